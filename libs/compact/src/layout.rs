@@ -0,0 +1,30 @@
+//! The in memory layout of objects using the compacting collector.
+//!
+//! Objects use the [shared layout](zerogc_context::layout) from `zerogc-context`.
+//! Each [GcHeader] stores the object's [Forwarding] pointer,
+//! which also serves as its mark.
+//!
+//! ## Safety
+//! Relying on this internal layout is incredibly unsafe.
+//! Unlike `zerogc-simple`, the layout isn't (yet) committed to a stable ABI.
+use zerogc_context::layout;
+
+use crate::RawCompactCollector;
+
+pub use zerogc_context::layout::{GcTypeLayout, HeaderLayout, StaticGcType, StaticVecType};
+
+/// A header for a GC object
+pub type GcHeader = layout::GcHeader<RawCompactCollector>;
+/// A header for a GC array object
+pub type GcArrayHeader = layout::GcArrayHeader<RawCompactCollector>;
+/// A header for a Gc vector
+pub type GcVecHeader = layout::GcVecHeader<RawCompactCollector>;
+/// A type used by GC
+pub type GcType = layout::GcType<RawCompactCollector>;
+/// The raw representation of a vector in the mark-compact collector
+pub type CompactVecRepr<'gc, T> = layout::VecRepr<'gc, T, RawCompactCollector>;
+/// The forwarding pointer of an object
+///
+/// This is set once the object is marked,
+/// and later updated to the location it's moved to.
+pub type Forwarding = layout::Forwarding<RawCompactCollector>;
@@ -0,0 +1,1080 @@
+//! A mark-compact garbage collector for zerogc.
+//!
+//! Small objects are bump-allocated into fixed-size blocks of memory.
+//! A collection marks every reachable object,
+//! and then slides the survivors together towards the start of the heap
+//! (using the [LISP2 algorithm](https://en.wikipedia.org/wiki/Mark%E2%80%93compact_algorithm#LISP2_algorithm)).
+//! This happens in three passes over the heap:
+//! 1. Compute the new location of every live object, storing it in the object's header
+//! 2. Update every reference (including the roots) to point to the new locations
+//! 3. Move the objects, preserving their order
+//!
+//! Since the heap is compacted by every collection, it never fragments.
+//! Blocks that are entirely empty afterwards are returned to the global allocator,
+//! so memory usage stays proportional to the amount of live data.
+//!
+//! Objects bigger than a quarter of a block are allocated individually,
+//! using the global allocator. These are never moved.
+//!
+//! Like `zerogc-copying`, objects move during collections.
+//! This relies on zerogc's support for moving collectors:
+//! 1. Roots are updated in place through the shadow stack,
+//!    so the values returned by `safepoint!` refer to the new locations.
+//! 2. [GcHandle](zerogc::GcHandle)s are updated through the [GcHandleList]
+//! 3. Weak references are updated to the new locations (or cleared)
+//!
+//! Since objects move, their addresses must never be relied upon across a safepoint
+//! (for example, hashing by address), unless the object is pinned.
+//!
+//! ## Pinning
+//! Objects that are pinned (see [zerogc::PinCollectorId]) keep their location.
+//! The objects before them slide as usual, and the ones after them
+//! slide towards the end of the pinned object instead of the start of the heap.
+//! The space in front of a pinned object can't be reused until it's unpinned.
+//!
+//! ## Internals
+//! The internal layout information is public,
+//! and available through the (layout module)[`self::layout`].
+#![deny(
+    missing_docs, // The 'compact' implementation needs to document its public API
+)]
+#![feature(
+    never_type, // Used for errors (which are currently impossible)
+    exhaustive_patterns, // Allow exhaustive matching against never
+)]
+use std::alloc::Layout;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use slog::{debug, FnValue, Logger};
+
+use zerogc::{GcAllocError, GcSafe, Trace};
+
+use zerogc_context::collector::RawSimpleAlloc;
+use zerogc_context::handle::{GcHandleList, RawHandleImpl, RawPinImpl};
+use zerogc_context::layout::{DynTrace, HandleListRoot, LayoutCollector, LayoutVisitor};
+use zerogc_context::threshold::update_gc_cpu_fraction;
+use zerogc_context::utils::{ILock, Lock, MemorySize, ThreadId};
+use zerogc_context::{
+    CollectionManager as AbstractCollectionManager, CollectorContext,
+    RawContext as AbstractRawContext,
+};
+
+use crate::layout::{
+    CompactVecRepr, Forwarding, GcArrayHeader, GcHeader, GcType, GcVecHeader, HeaderLayout,
+    StaticGcType, StaticVecType,
+};
+
+pub mod layout;
+
+pub use zerogc_context::threshold::{FinishedCollection, HeapGrowth, ThresholdPolicy};
+
+/// The configuration for a mark-compact garbage collector
+pub struct GcConfig {
+    /// Whether to always force a collection at safepoints,
+    /// regardless of whether the heuristics say.
+    ///
+    /// To force a single collection, use [zerogc::safepoint_force!] instead.
+    pub always_force_collect: bool,
+    /// The initial threshold to trigger garbage collection (in bytes)
+    ///
+    /// By default, this is the [HeapGrowth::min_heap_size] of the default policy.
+    pub initial_threshold: usize,
+    /// The policy used to pick the threshold for the next collection,
+    /// once a collection has finished.
+    ///
+    /// By default, this is [HeapGrowth::default].
+    pub threshold_policy: Box<dyn ThresholdPolicy>,
+    /// The size of each block that small objects are allocated in (in bytes)
+    ///
+    /// Objects bigger than a quarter of this are allocated individually,
+    /// and never moved.
+    pub block_size: usize,
+}
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            always_force_collect: false,
+            initial_threshold: HeapGrowth::default().min_heap_size,
+            threshold_policy: Box::<HeapGrowth>::default(),
+            block_size: 256 * 1024,
+        }
+    }
+}
+
+/// A snapshot of statistics about a [CompactCollector]
+///
+/// This can be retrieved with [CompactCollector::stats]
+#[derive(Clone, Debug, Default)]
+pub struct GcStats {
+    /// The number of collections that have been performed
+    pub collections: u64,
+    /// The total time spent in collections
+    pub total_pause: Duration,
+    /// The duration of the last collection
+    pub last_pause: Duration,
+    /// The number of bytes freed by the last collection
+    pub last_freed_bytes: usize,
+    /// The total number of bytes freed by all collections
+    pub total_freed_bytes: u64,
+    /// The number of bytes moved by the last collection
+    pub last_moved_bytes: usize,
+    /// The total number of bytes moved by all collections
+    pub total_moved_bytes: u64,
+    /// The number of bytes currently allocated in the heap
+    pub live_bytes: usize,
+    /// The current threshold that will trigger the next collection (in bytes)
+    pub threshold: usize,
+    /// The number of bytes reserved by the heap,
+    /// including the unused parts of its blocks.
+    pub heap_bytes: usize,
+    /// The number of blocks that small objects are allocated in
+    pub num_blocks: usize,
+    /// The number of handles that are currently alive
+    pub num_handles: usize,
+    /// The number of free handle slots, that can be reused by new handles
+    ///
+    /// Slots that are no longer needed are released after each collection.
+    pub free_handle_slots: usize,
+    /// The fraction of time recently spent collecting garbage, between zero and one.
+    ///
+    /// This is the duration of each collection,
+    /// relative to the time since the one before it.
+    /// It's a moving average, weighted towards the most recent collections.
+    pub gc_cpu_fraction: f64,
+}
+
+#[cfg(feature = "sync")]
+type RawContext<C> = zerogc_context::state::sync::RawContext<C>;
+#[cfg(feature = "sync")]
+type CollectionManager<C> = zerogc_context::state::sync::CollectionManager<C>;
+#[cfg(not(feature = "sync"))]
+type RawContext<C> = zerogc_context::state::nosync::RawContext<C>;
+#[cfg(not(feature = "sync"))]
+type CollectionManager<C> = zerogc_context::state::nosync::CollectionManager<C>;
+
+/// A mark-compact garbage collector
+pub type CompactCollector = ::zerogc_context::CollectorRef<RawCompactCollector>;
+/// The context of a mark-compact collector
+pub type CompactCollectorContext = ::zerogc_context::CollectorContext<RawCompactCollector>;
+/// The id for a mark-compact collector
+pub type CollectorId = ::zerogc_context::CollectorId<RawCompactCollector>;
+/// A garbage collected pointer, allocated in the mark-compact collector
+pub type Gc<'gc, T> = ::zerogc::Gc<'gc, T, CollectorId>;
+/// A weak reference to an object in the mark-compact collector
+pub type GcWeak<'gc, T> = ::zerogc::GcWeak<'gc, T, CollectorId>;
+/// A garbage collected array, allocated in the mark-compact collector
+pub type GcArray<'gc, T> = ::zerogc::array::GcArray<'gc, T, CollectorId>;
+/// A garbage collected vector, allocated in the mark-compact collector
+pub type GcVec<'gc, T> = ::zerogc::vec::GcVec<'gc, T, CollectorId>;
+
+unsafe impl RawSimpleAlloc for RawCompactCollector {
+    #[inline]
+    unsafe fn try_alloc_uninit<'gc, T>(
+        context: &'gc CompactCollectorContext,
+    ) -> Result<*mut T, GcAllocError>
+    where
+        T: GcSafe<'gc, crate::CollectorId>,
+    {
+        let (_header, ptr) = context.collector().heap.alloc_layout(
+            GcHeader::LAYOUT,
+            Layout::new::<T>(),
+            <T as StaticGcType<Self>>::STATIC_TYPE,
+        )?;
+        Ok(ptr as *mut T)
+    }
+
+    unsafe fn try_alloc_uninit_slice<'gc, T>(
+        context: &'gc CollectorContext<Self>,
+        len: usize,
+    ) -> Result<*mut T, GcAllocError>
+    where
+        T: GcSafe<'gc, crate::CollectorId>,
+    {
+        let (header, ptr) = context.collector().heap.alloc_layout(
+            GcArrayHeader::LAYOUT,
+            Layout::array::<T>(len)?,
+            <[T] as StaticGcType<Self>>::STATIC_TYPE,
+        )?;
+        (*header).len = len;
+        Ok(ptr.cast())
+    }
+
+    fn try_alloc_raw_vec_with_capacity<'gc, T>(
+        context: &'gc CollectorContext<Self>,
+        capacity: usize,
+    ) -> Result<Self::RawVec<'gc, T>, GcAllocError>
+    where
+        T: GcSafe<'gc, crate::CollectorId>,
+    {
+        let (header, value_ptr) = context.collector().heap.alloc_layout(
+            GcVecHeader::LAYOUT,
+            Layout::array::<T>(capacity)?,
+            <T as StaticVecType<Self>>::STATIC_VEC_TYPE,
+        )?;
+        unsafe {
+            (*header).capacity = capacity;
+            (*header).len.set(0);
+            let res = CompactVecRepr::from_raw_parts(NonNull::new_unchecked(header), context);
+            debug_assert_eq!(res.as_ptr(), value_ptr as *mut T as *const T);
+            Ok(res)
+        }
+    }
+}
+
+unsafe impl RawHandleImpl for RawCompactCollector {
+    type TypeInfo = GcType;
+
+    #[inline]
+    fn type_info_of<'gc, T: GcSafe<'gc, CollectorId>>() -> &'static Self::TypeInfo {
+        <T as StaticGcType<Self>>::STATIC_TYPE
+    }
+
+    #[inline]
+    fn resolve_type_info<'gc, T: ?Sized + GcSafe<'gc, CollectorId>>(
+        gc: zerogc::Gc<'gc, T, CollectorId>,
+    ) -> &'static Self::TypeInfo {
+        unsafe { (*GcHeader::from_value_ptr(gc.as_raw_ptr())).type_info }
+    }
+
+    #[inline]
+    fn handle_list(&self) -> &GcHandleList<Self> {
+        &self.handle_list
+    }
+}
+
+/// Pinned objects are never moved when the heap is compacted
+unsafe impl RawPinImpl for RawCompactCollector {}
+
+/// The minimum alignment of every object in the heap
+///
+/// The size of every object is a multiple of this,
+/// so only objects with a greater alignment ever need padding.
+const MIN_ALIGN: usize = std::mem::align_of::<GcHeader>();
+/// The alignment of every block
+///
+/// Objects with a greater alignment than this are always allocated individually.
+const BLOCK_ALIGN: usize = 4096;
+
+/// A position in the blocks of the heap
+///
+/// Positions are ordered by block, and then by offset.
+#[derive(Copy, Clone, Debug, Default)]
+struct Cursor {
+    /// The index of the block
+    block: usize,
+    /// The offset within the block
+    offset: usize,
+}
+
+/// An object that is too big to be allocated in a block.
+///
+/// These are allocated individually, and never moved.
+struct LargeObject {
+    header: *mut GcHeader,
+    /// The start of the allocation (before the header)
+    start: NonNull<u8>,
+    layout: Layout,
+}
+
+/// The memory of the heap
+struct Heap {
+    /// The size of each block
+    block_size: usize,
+    /// The blocks of memory that small objects are allocated in.
+    ///
+    /// Blocks are never empty, except for the last one
+    /// (and the ones in front of a pinned object).
+    blocks: Vec<NonNull<u8>>,
+    /// The position that the next object is allocated at
+    cursor: Cursor,
+    /// Every object in the blocks, in order of their position.
+    ///
+    /// Since objects are bump-allocated, this is also the order they were allocated in.
+    objects: Vec<*mut GcHeader>,
+    large_objects: Vec<LargeObject>,
+    /// The total size of every object in the heap (including padding)
+    allocated: usize,
+}
+impl Heap {
+    fn new(block_size: usize) -> Heap {
+        let mut heap = Heap {
+            block_size: block_size.next_multiple_of(BLOCK_ALIGN),
+            blocks: Vec::new(),
+            cursor: Cursor::default(),
+            objects: Vec::new(),
+            large_objects: Vec::new(),
+            allocated: 0,
+        };
+        heap.blocks.push(heap.alloc_block());
+        heap
+    }
+    #[inline]
+    fn block_layout(&self) -> Layout {
+        Layout::from_size_align(self.block_size, BLOCK_ALIGN).unwrap()
+    }
+    #[cold]
+    fn alloc_block(&self) -> NonNull<u8> {
+        let layout = self.block_layout();
+        NonNull::new(unsafe { std::alloc::alloc(layout) })
+            .unwrap_or_else(|| std::alloc::handle_alloc_error(layout))
+    }
+    #[inline]
+    fn is_large(&self, layout: Layout) -> bool {
+        layout.size() > self.block_size / 4 || layout.align() > BLOCK_ALIGN
+    }
+    /// Find the position of an object with the specified layout,
+    /// starting from the cursor.
+    ///
+    /// If it doesn't fit in the cursor's block, it goes at the start of the next block.
+    #[inline]
+    fn place(&self, cursor: Cursor, layout: Layout) -> (Cursor, Cursor) {
+        debug_assert_eq!(layout.size() % MIN_ALIGN, 0);
+        let base = self.blocks[cursor.block].as_ptr() as usize;
+        let align = layout.align().max(MIN_ALIGN);
+        let offset = ((base + cursor.offset + align - 1) & !(align - 1)) - base;
+        if offset + layout.size() <= self.block_size {
+            let start = Cursor {
+                block: cursor.block,
+                offset,
+            };
+            let end = Cursor {
+                block: cursor.block,
+                offset: offset + layout.size(),
+            };
+            (start, end)
+        } else {
+            // The start of every block is suitably aligned
+            let start = Cursor {
+                block: cursor.block + 1,
+                offset: 0,
+            };
+            let end = Cursor {
+                block: cursor.block + 1,
+                offset: layout.size(),
+            };
+            (start, end)
+        }
+    }
+    #[inline]
+    fn address(&self, cursor: Cursor) -> *mut u8 {
+        unsafe { self.blocks[cursor.block].as_ptr().add(cursor.offset) }
+    }
+    /// The start of every block along with its index, sorted by address
+    ///
+    /// This is needed to find the position of an address (see [Heap::cursor_of]).
+    fn blocks_by_address(&self) -> Vec<(usize, usize)> {
+        let mut blocks = self
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(block, start)| (start.as_ptr() as usize, block))
+            .collect::<Vec<_>>();
+        blocks.sort_unstable();
+        blocks
+    }
+    /// The position of the specified address, which must be inside one of the blocks
+    ///
+    /// The blocks must be given in order of their address (see [Heap::blocks_by_address]).
+    fn cursor_of(&self, blocks_by_address: &[(usize, usize)], address: *mut u8) -> Cursor {
+        let address = address as usize;
+        let index = blocks_by_address.partition_point(|&(start, _)| start <= address);
+        let (start, block) = match index.checked_sub(1) {
+            Some(index) => blocks_by_address[index],
+            None => unreachable!("Address isn't in the heap"),
+        };
+        let offset = address - start;
+        debug_assert!(offset < self.block_size, "Address isn't in the heap");
+        Cursor { block, offset }
+    }
+    /// Allocate memory for an object with the specified (overall) layout
+    #[inline]
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let (start, end) = self.place(self.cursor, layout);
+        if start.block == self.blocks.len() {
+            let block = self.alloc_block();
+            self.blocks.push(block);
+        }
+        self.cursor = end;
+        self.allocated += layout.size();
+        self.address(start)
+    }
+    #[cold]
+    fn alloc_large(&mut self, layout: Layout) -> *mut u8 {
+        let layout = layout.align_to(MIN_ALIGN).unwrap();
+        let start = NonNull::new(unsafe { std::alloc::alloc(layout) })
+            .unwrap_or_else(|| std::alloc::handle_alloc_error(layout));
+        self.allocated += layout.size();
+        self.large_objects.push(LargeObject {
+            header: std::ptr::null_mut(), // Filled in by caller
+            start,
+            layout,
+        });
+        start.as_ptr()
+    }
+    /// The number of bytes reserved for the heap
+    fn reserved_bytes(&self) -> usize {
+        self.blocks.len() * self.block_size
+            + self
+                .large_objects
+                .iter()
+                .map(|obj| obj.layout.size())
+                .sum::<usize>()
+    }
+    /// Compute the new location of every live object in the blocks,
+    /// storing it in the object's header.
+    ///
+    /// The pinned objects (sorted by address) keep their current location,
+    /// and the objects after them are placed after them.
+    ///
+    /// Returns the position after the last live object,
+    /// along with the number of bytes that will need to be moved.
+    unsafe fn compute_locations(&self, pinned: &[*mut GcHeader]) -> (Cursor, usize) {
+        let mut cursor = Cursor::default();
+        let mut moved = 0;
+        let blocks_by_address = if pinned.is_empty() {
+            Vec::new()
+        } else {
+            self.blocks_by_address()
+        };
+        for &header in &self.objects {
+            if (*header).forwarded().is_none() {
+                continue; // Garbage
+            }
+            let type_info = (*header).type_info;
+            let header_layout = type_info.header_layout();
+            let layout = type_info.determine_total_layout(header);
+            if pinned.binary_search(&header).is_ok() {
+                /*
+                 * The objects before this one never slide past its start,
+                 * since objects only ever move towards the start of the heap.
+                 */
+                let start = self.cursor_of(
+                    &blocks_by_address,
+                    header_layout.from_common_header(header).cast(),
+                );
+                let end = Cursor {
+                    block: start.block,
+                    offset: start.offset + layout.size(),
+                };
+                debug_assert!((end.block, end.offset) >= (cursor.block, cursor.offset));
+                // Marked objects are already forwarded to themselves
+                debug_assert_eq!((*header).forwarded(), Some(header));
+                cursor = end;
+                continue;
+            }
+            let (start, end) = self.place(cursor, layout);
+            let new_header = header_layout.common_header(self.address(start).cast());
+            /*
+             * Objects only ever move towards the start of the heap,
+             * since they're placed in the same order they were allocated.
+             */
+            if new_header != header {
+                moved += layout.size();
+            }
+            (*header).set_forwarded(new_header);
+            cursor = end;
+        }
+        (cursor, moved)
+    }
+    /// Drop all the unmarked objects, and then slide the live ones to their new locations.
+    ///
+    /// Once this is done, the heap ends at the specified cursor
+    /// and all the objects are unmarked.
+    /// Blocks that are left empty are returned to the global allocator.
+    unsafe fn compact(&mut self, end: Cursor) {
+        for &header in &self.objects {
+            if (*header).forwarded().is_none() {
+                if let Some(func) = (*header).type_info.drop_func {
+                    func((*header).value());
+                }
+            }
+        }
+        let mut allocated = 0;
+        let mut live_objects = Vec::with_capacity(self.objects.len());
+        for &header in &self.objects {
+            let new_header = match (*header).forwarded() {
+                Some(new_header) => new_header,
+                None => continue,
+            };
+            let type_info = (*header).type_info;
+            let header_layout = type_info.header_layout();
+            let layout = type_info.determine_total_layout(header);
+            if new_header != header {
+                // NOTE: The new location may overlap with the old one
+                std::ptr::copy(
+                    header_layout.from_common_header(header).cast::<u8>(),
+                    header_layout.from_common_header(new_header).cast::<u8>(),
+                    layout.size(),
+                );
+            }
+            (*new_header).set_forwarded(std::ptr::null_mut());
+            allocated += layout.size();
+            live_objects.push(new_header);
+        }
+        self.objects = live_objects;
+        let block_layout = self.block_layout();
+        for block in self.blocks.drain(end.block + 1..) {
+            std::alloc::dealloc(block.as_ptr(), block_layout);
+        }
+        self.cursor = end;
+        self.large_objects.retain(|obj| {
+            let header = obj.header;
+            if (*header).forwarded().is_some() {
+                (*header).set_forwarded(std::ptr::null_mut());
+                allocated += obj.layout.size();
+                true
+            } else {
+                if let Some(func) = (*header).type_info.drop_func {
+                    func((*header).value());
+                }
+                std::alloc::dealloc(obj.start.as_ptr(), obj.layout);
+                false
+            }
+        });
+        self.allocated = allocated;
+    }
+}
+impl Drop for Heap {
+    fn drop(&mut self) {
+        unsafe {
+            let headers = self
+                .objects
+                .iter()
+                .copied()
+                .chain(self.large_objects.iter().map(|obj| obj.header));
+            for header in headers {
+                if let Some(func) = (*header).type_info.drop_func {
+                    func((*header).value());
+                }
+            }
+            let block_layout = self.block_layout();
+            for block in self.blocks.drain(..) {
+                std::alloc::dealloc(block.as_ptr(), block_layout);
+            }
+            for obj in self.large_objects.drain(..) {
+                std::alloc::dealloc(obj.start.as_ptr(), obj.layout);
+            }
+        }
+    }
+}
+
+struct GcHeap {
+    config: Arc<GcConfig>,
+    collector_id: Option<CollectorId>,
+    heap: Lock<Heap>,
+    /// The number of bytes allocated in the heap
+    allocated_size: AtomicUsize,
+    threshold: AtomicUsize,
+}
+impl GcHeap {
+    fn new(config: Arc<GcConfig>) -> GcHeap {
+        GcHeap {
+            collector_id: None,
+            heap: Lock::from(Heap::new(config.block_size)),
+            allocated_size: AtomicUsize::new(0),
+            threshold: AtomicUsize::new(if config.always_force_collect {
+                0
+            } else {
+                config.initial_threshold
+            }),
+            config,
+        }
+    }
+    #[inline]
+    fn allocated_size(&self) -> usize {
+        self.allocated_size.load(Ordering::Acquire)
+    }
+    #[inline]
+    fn alloc_layout<H>(
+        &self,
+        header_layout: HeaderLayout<H>,
+        value_layout: Layout,
+        static_type: &'static GcType,
+    ) -> Result<(*mut H, *mut u8), GcAllocError> {
+        let collector_id_ptr = match self.collector_id {
+            Some(ref collector) => collector,
+            None => unreachable!("Invalid collector id"),
+        };
+        let (overall_layout, value_offset) = header_layout.layout().extend(value_layout)?;
+        debug_assert_eq!(
+            header_layout.value_offset(value_layout.align()),
+            value_offset
+        );
+        let overall_layout = overall_layout.pad_to_align();
+        let mut heap = self.heap.lock();
+        let large = heap.is_large(overall_layout);
+        let header: *mut H = if large {
+            heap.alloc_large(overall_layout).cast()
+        } else {
+            heap.alloc(overall_layout).cast()
+        };
+        unsafe {
+            let common_header = header_layout.common_header(header);
+            common_header.write(GcHeader::new(
+                static_type,
+                collector_id_ptr as *const CollectorId,
+                Forwarding::new(),
+            ));
+            if large {
+                heap.large_objects.last_mut().unwrap().header = common_header;
+            } else {
+                heap.objects.push(common_header);
+            }
+            self.allocated_size.store(heap.allocated, Ordering::Release);
+            Ok((header, (header as *mut u8).add(value_offset)))
+        }
+    }
+    #[inline]
+    fn should_collect_relaxed(&self) -> bool {
+        /*
+         * Going with relaxed ordering because it's not essential
+         * that we see updates immediately.
+         * Eventual consistency should be enough to eventually
+         * trigger a collection.
+         */
+        self.allocated_size.load(Ordering::Relaxed) >= self.threshold.load(Ordering::Relaxed)
+    }
+}
+
+/// We're careful - I swear :D
+unsafe impl Send for RawCompactCollector {}
+unsafe impl Sync for RawCompactCollector {}
+
+/// The internal data for a mark-compact collector
+#[doc(hidden)]
+pub struct RawCompactCollector {
+    logger: Logger,
+    heap: GcHeap,
+    manager: CollectionManager<Self>,
+    /// Tracks object handles
+    handle_list: GcHandleList<Self>,
+    /// Statistics about previous collections
+    ///
+    /// The fields that describe the current state of the heap
+    /// are filled in when a snapshot is taken.
+    history: Lock<GcStats>,
+    /// When the last collection finished (or the collector was created)
+    last_collection: Lock<Instant>,
+}
+
+unsafe impl ::zerogc_context::collector::RawCollectorImpl for RawCompactCollector {
+    type DynTracePtr = NonNull<dyn DynTrace<Self>>;
+    type Config = GcConfig;
+    type Stats = GcStats;
+
+    type Ptr = NonNull<Self>;
+
+    type Manager = CollectionManager<Self>;
+
+    type RawContext = RawContext<Self>;
+
+    type ContextCache = ();
+
+    type RawVec<'gc, T: GcSafe<'gc, CollectorId>> = CompactVecRepr<'gc, T>;
+
+    const SINGLETON: bool = false;
+
+    const SYNC: bool = cfg!(feature = "sync");
+
+    #[inline]
+    fn id_for_gc<'a, 'gc, T>(gc: &'a Gc<'gc, T>) -> &'a CollectorId
+    where
+        'gc: 'a,
+        T: ?Sized + 'gc,
+    {
+        unsafe {
+            let header = GcHeader::from_value_ptr(gc.as_raw_ptr());
+            (*header).collector_id()
+        }
+    }
+
+    #[inline]
+    fn id_for_array<'a, 'gc, T>(array: &'a GcArray<'gc, T>) -> &'a CollectorId
+    where
+        'gc: 'a,
+    {
+        unsafe {
+            let header = GcArrayHeader::LAYOUT.from_value_ptr(array.as_raw_ptr());
+            (*header).common_header.collector_id()
+        }
+    }
+
+    #[inline]
+    fn resolve_array_len<T>(gc: &GcArray<T>) -> usize {
+        unsafe {
+            let header = GcArrayHeader::LAYOUT.from_value_ptr(gc.as_raw_ptr());
+            (*header).len
+        }
+    }
+
+    #[inline]
+    unsafe fn as_dyn_trace_pointer<T: Trace>(value: *mut T) -> Self::DynTracePtr {
+        debug_assert!(!value.is_null());
+        NonNull::new_unchecked(std::mem::transmute::<
+            *mut dyn DynTrace<Self>,
+            *mut (dyn DynTrace<Self> + 'static),
+        >(value as *mut dyn DynTrace<Self>))
+    }
+
+    fn init(config: GcConfig, logger: Logger) -> NonNull<Self> {
+        let raw = RawCompactCollector::with_logger(config, logger);
+        let mut collector = Arc::new(raw);
+        let raw_ptr =
+            unsafe { NonNull::new_unchecked(Arc::as_ptr(&collector) as *mut RawCompactCollector) };
+        Arc::get_mut(&mut collector).unwrap().heap.collector_id =
+            Some(unsafe { CollectorId::from_raw(raw_ptr) });
+        std::mem::forget(collector); // We own it as a raw pointer...
+        raw_ptr
+    }
+
+    #[inline(always)]
+    unsafe fn gc_write_barrier<'gc, T, V>(
+        _owner: &Gc<'gc, T>,
+        _value: &Gc<'gc, V>,
+        _field_offset: usize,
+    ) where
+        T: GcSafe<'gc, CollectorId> + ?Sized,
+        V: GcSafe<'gc, CollectorId> + ?Sized,
+    {
+        // Mark-compact GC doesn't need write barriers
+    }
+    #[inline]
+    fn logger(&self) -> &Logger {
+        &self.logger
+    }
+    #[inline]
+    fn manager(&self) -> &CollectionManager<Self> {
+        &self.manager
+    }
+    #[inline]
+    fn should_collect(&self) -> bool {
+        self.heap.should_collect_relaxed() || self.manager.should_trigger_collection()
+    }
+    #[inline]
+    fn allocated_size(&self) -> MemorySize {
+        MemorySize {
+            bytes: self.heap.allocated_size(),
+        }
+    }
+    fn stats(&self) -> GcStats {
+        let handle_slots = self.handle_list.slot_stats();
+        let heap = self.heap.heap.lock();
+        GcStats {
+            live_bytes: heap.allocated,
+            threshold: self.heap.threshold.load(Ordering::Acquire),
+            heap_bytes: heap.reserved_bytes(),
+            num_blocks: heap.blocks.len(),
+            num_handles: handle_slots.live_slots,
+            free_handle_slots: handle_slots.free_slots,
+            ..self.history.lock().clone()
+        }
+    }
+    #[inline]
+    unsafe fn perform_raw_collection(&self, contexts: &[*mut RawContext<Self>]) {
+        self.perform_raw_collection(contexts)
+    }
+
+    #[inline]
+    unsafe fn flush_context_cache(&self, _cache: &()) {}
+}
+#[cfg(feature = "sync")]
+unsafe impl ::zerogc_context::collector::SyncCollector for RawCompactCollector {}
+impl RawCompactCollector {
+    fn with_logger(config: GcConfig, logger: Logger) -> Self {
+        RawCompactCollector {
+            logger,
+            manager: CollectionManager::new(),
+            heap: GcHeap::new(Arc::new(config)),
+            handle_list: GcHandleList::new(),
+            history: Lock::from(GcStats::default()),
+            last_collection: Lock::from(Instant::now()),
+        }
+    }
+    #[cold]
+    #[inline(never)]
+    unsafe fn perform_raw_collection(&self, contexts: &[*mut RawContext<Self>]) {
+        debug_assert!(self.manager.is_collecting());
+        let start = Instant::now();
+        let mut roots: Vec<*mut dyn DynTrace<Self>> = Vec::new();
+        for ctx in contexts.iter() {
+            roots.extend(
+                (**ctx)
+                    .assume_valid_shadow_stack()
+                    .reverse_iter()
+                    .map(NonNull::as_ptr),
+            );
+        }
+        roots.push(HandleListRoot::as_root(&self.handle_list));
+        let num_roots = roots.len();
+        let mut pinned = Vec::new();
+        self.handle_list.for_each_pinned(|raw_ptr, type_info| {
+            pinned.push(GcHeader::from_erased_value_ptr(raw_ptr, type_info));
+        });
+        pinned.sort_unstable();
+        let mut heap = self.heap.heap.lock();
+        let original_size = heap.allocated;
+        let mut task = CollectionTask {
+            expected_collector: self.heap.collector_id.unwrap(),
+            heap: &mut heap,
+            roots,
+            pinned,
+            grey_stack: Vec::with_capacity(64),
+            ephemerons: Vec::new(),
+        };
+        let moved = task.run();
+        let updated_size = heap.allocated;
+        self.heap
+            .allocated_size
+            .store(updated_size, Ordering::Release);
+        drop(heap);
+        let pause = start.elapsed();
+        let mut history = self.history.lock();
+        history.gc_cpu_fraction = {
+            let mut last_collection = self.last_collection.lock();
+            let fraction = update_gc_cpu_fraction(
+                (history.collections > 0).then_some(history.gc_cpu_fraction),
+                pause,
+                last_collection.elapsed(),
+            );
+            *last_collection = Instant::now();
+            fraction
+        };
+        if !self.heap.config.always_force_collect {
+            let threshold = self
+                .heap
+                .config
+                .threshold_policy
+                .next_threshold(&FinishedCollection {
+                    live_bytes: updated_size,
+                    gc_cpu_fraction: history.gc_cpu_fraction,
+                });
+            self.heap.threshold.store(threshold, Ordering::Release);
+        }
+        self.handle_list.shrink();
+        let freed = original_size.saturating_sub(updated_size);
+        {
+            history.collections += 1;
+            history.total_pause += pause;
+            history.last_pause = pause;
+            history.last_freed_bytes = freed;
+            history.total_freed_bytes += freed as u64;
+            history.last_moved_bytes = moved;
+            history.total_moved_bytes += moved as u64;
+        }
+        debug!(
+            self.logger, "Finished mark-compact GC";
+            "current_thread" => FnValue(|_| ThreadId::current()),
+            "num_roots" => num_roots,
+            "original_size" => %MemorySize { bytes: original_size },
+            "memory_freed" => %MemorySize { bytes: freed },
+            "memory_moved" => %MemorySize { bytes: moved },
+            "pause" => ?pause,
+        );
+    }
+}
+
+struct CollectionTask<'a> {
+    expected_collector: CollectorId,
+    heap: &'a mut Heap,
+    roots: Vec<*mut dyn DynTrace<RawCompactCollector>>,
+    /// The objects that are pinned in place, sorted by address
+    pinned: Vec<*mut GcHeader>,
+    grey_stack: Vec<*mut GcHeader>,
+    /// The ephemerons whose keys haven't (yet) been found reachable
+    ephemerons: Vec<Ephemeron>,
+}
+impl<'a> CollectionTask<'a> {
+    #[inline]
+    fn mark_visitor(&mut self) -> CompactVisitor<'_> {
+        LayoutVisitor::new(
+            self.expected_collector,
+            Phase::Mark {
+                grey_stack: &mut self.grey_stack,
+                ephemerons: &mut self.ephemerons,
+            },
+        )
+    }
+    /// Run the collection, returning the number of bytes that were moved
+    fn run(&mut self) -> usize {
+        // Mark
+        for i in 0..self.roots.len() {
+            let root = self.roots[i];
+            // Dynamically dispatched
+            unsafe {
+                (*root).trace(&mut self.mark_visitor());
+            }
+        }
+        self.process_grey_stack();
+        // Trace the values of ephemerons with reachable keys
+        self.process_ephemerons();
+        unsafe {
+            // Compute new locations
+            let (end, moved) = self.heap.compute_locations(&self.pinned);
+            // Update references (clearing weak references to unreachable objects)
+            self.update_references();
+            // Move
+            self.heap.compact(end);
+            moved
+        }
+    }
+    fn process_grey_stack(&mut self) {
+        while let Some(header) = self.grey_stack.pop() {
+            unsafe {
+                self.mark_visitor().trace_contents(header);
+            }
+        }
+    }
+    /// Trace the values of all the ephemerons whose keys are reachable.
+    ///
+    /// Tracing a value can make even more keys reachable,
+    /// so this needs to iterate until it reaches a fixpoint.
+    /// Any ephemerons that remain afterwards have unreachable keys,
+    /// which are cleared when references are updated.
+    fn process_ephemerons(&mut self) {
+        loop {
+            let mut progress = false;
+            for ephemeron in std::mem::take(&mut self.ephemerons) {
+                if unsafe { (*ephemeron.key.referent).forwarded().is_none() } {
+                    // Still unknown, try again on the next iteration
+                    self.ephemerons.push(ephemeron);
+                    continue;
+                }
+                unsafe {
+                    ephemeron.trace_value(&mut self.mark_visitor());
+                }
+                progress = true;
+            }
+            if !progress {
+                break;
+            }
+            self.process_grey_stack();
+        }
+    }
+    /// Update every reference in the roots and the live objects
+    /// to point to the new location of its object.
+    ///
+    /// This must happen after the new locations have been computed,
+    /// but before anything is actually moved.
+    unsafe fn update_references(&mut self) {
+        let mut visitor = LayoutVisitor::new(self.expected_collector, Phase::Update);
+        for &root in &self.roots {
+            (*root).trace(&mut visitor);
+        }
+        let headers = self
+            .heap
+            .objects
+            .iter()
+            .copied()
+            .chain(self.heap.large_objects.iter().map(|obj| obj.header));
+        for header in headers {
+            if (*header).forwarded().is_none() {
+                continue; // Garbage
+            }
+            visitor.trace_contents(header);
+        }
+    }
+}
+
+type WeakRef = zerogc_context::layout::WeakRef<RawCompactCollector>;
+type Ephemeron = zerogc_context::layout::Ephemeron<RawCompactCollector>;
+
+#[doc(hidden)] // NOTE: Needs be public for RawCollectorImpl
+pub type CompactVisitor<'a> = LayoutVisitor<'a, RawCompactCollector>;
+
+/// The phase of a collection that a [CompactVisitor] is used for
+#[doc(hidden)]
+pub enum Phase<'a> {
+    /// Marking every reachable object
+    Mark {
+        /// The marked objects that still need to be traced
+        grey_stack: &'a mut Vec<*mut GcHeader>,
+        /// The ephemerons whose keys haven't been marked yet
+        ephemerons: &'a mut Vec<Ephemeron>,
+    },
+    /// Updating every reference to the new location of its object
+    Update,
+}
+impl Phase<'_> {
+    /// Mark the specified object (if it hasn't been already),
+    /// pushing it onto the grey stack so anything it references is marked later.
+    ///
+    /// A marked object's forwarding pointer refers to itself,
+    /// until its real location is computed.
+    unsafe fn mark(&mut self, header: *mut GcHeader) {
+        let grey_stack = match *self {
+            Phase::Mark {
+                ref mut grey_stack, ..
+            } => grey_stack,
+            Phase::Update => unreachable!(),
+        };
+        if (*header).forwarded().is_none() {
+            (*header).set_forwarded(header);
+            if (*header).type_info.trace_func.is_some() {
+                grey_stack.push(header);
+            }
+        }
+    }
+}
+unsafe impl LayoutCollector for RawCompactCollector {
+    type HeaderState = Forwarding;
+    type VisitorState<'a> = Phase<'a>;
+
+    #[inline]
+    unsafe fn visit(visitor: &mut CompactVisitor<'_>, header: *mut GcHeader) -> *mut GcHeader {
+        match visitor.state {
+            Phase::Mark { .. } => {
+                visitor.state.mark(header);
+                header
+            }
+            /*
+             * The object itself hasn't moved yet,
+             * but the reference is updated to its new location.
+             */
+            Phase::Update => (*header)
+                .forwarded()
+                .unwrap_or_else(|| unreachable!("Referenced object wasn't marked")),
+        }
+    }
+
+    #[inline]
+    unsafe fn visit_weak(visitor: &mut CompactVisitor<'_>, weak: WeakRef) {
+        if let Phase::Update = visitor.state {
+            /*
+             * Weak references don't mark their referent.
+             * Once marking has finished, they're updated like everything else
+             * (or cleared if their referent is unreachable).
+             */
+            weak.update((*weak.referent).forwarded());
+        }
+    }
+
+    unsafe fn visit_ephemeron(visitor: &mut CompactVisitor<'_>, ephemeron: Ephemeron) {
+        let key_marked = (*ephemeron.key.referent).forwarded().is_some();
+        match visitor.state {
+            Phase::Mark {
+                ref mut ephemerons, ..
+            } => {
+                if key_marked {
+                    // The key is already known to be reachable
+                    ephemeron.trace_value(visitor);
+                } else {
+                    /*
+                     * We don't know whether the key is reachable yet.
+                     * Defer tracing the value until we do.
+                     */
+                    ephemerons.push(ephemeron);
+                }
+            }
+            Phase::Update => {
+                ephemeron.key.update((*ephemeron.key.referent).forwarded());
+                if key_marked {
+                    ephemeron.trace_value(visitor);
+                }
+                // Otherwise the value was never marked, so it must not be touched
+            }
+        }
+    }
+}
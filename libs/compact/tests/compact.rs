@@ -0,0 +1,289 @@
+#![feature(
+    arbitrary_self_types, // Unfortunately this is required for methods on Gc refs
+)]
+use slog::Logger;
+
+use zerogc::prelude::*;
+use zerogc::safepoint_force;
+use zerogc_derive::Trace;
+
+use zerogc_compact::{
+    CollectorId as CompactCollectorId, CompactCollector, CompactCollectorContext,
+    FinishedCollection, Gc, GcArray, GcConfig, GcVec, GcWeak, HeapGrowth, ThresholdPolicy,
+};
+
+fn test_collector() -> CompactCollector {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    config.block_size = 4096;
+    CompactCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(CompactCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(CompactCollectorId))]
+struct Node<'gc> {
+    val: usize,
+    next: Option<Gc<'gc, Node<'gc>>>,
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(CompactCollectorId))]
+struct Cache<'gc> {
+    entry: GcWeak<'gc, Dummy>,
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(CompactCollectorId))]
+struct Big {
+    values: [u64; 256],
+}
+
+fn check_list(head: Option<Gc<Node>>, len: usize) {
+    let mut node = head;
+    for expected in (0..len).rev() {
+        assert_eq!(node.unwrap().val, expected);
+        node = node.unwrap().next;
+    }
+    assert!(node.is_none());
+}
+
+/// Allocate a list, with garbage in between the nodes
+fn alloc_fragmented_list<'gc>(
+    context: &'gc CompactCollectorContext,
+    len: usize,
+) -> Option<Gc<'gc, Node<'gc>>> {
+    let mut head = None;
+    for val in 0..len {
+        head = Some(context.alloc(Node { val, next: head }));
+        for garbage in 0..4 {
+            context.alloc(Dummy { val: garbage });
+        }
+    }
+    head
+}
+
+#[test]
+fn objects_slide() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let first = context.alloc(Dummy { val: 1 });
+    context.alloc(Dummy { val: 2 });
+    let kept = context.alloc(Dummy { val: 3 });
+    let old_address = &*kept as *const Dummy as usize;
+    let (first, kept): (Gc<Dummy>, Gc<Dummy>) = safepoint!(context, (first, kept));
+    // The garbage in between is squeezed out
+    assert!((&*kept as *const Dummy as usize) < old_address);
+    assert_eq!(first.val, 1);
+    assert_eq!(kept.val, 3);
+    let stats = context.system().stats();
+    assert_eq!(stats.collections, 1);
+    assert!(stats.last_freed_bytes > 0);
+    assert!(stats.last_moved_bytes > 0);
+    assert!(stats.last_moved_bytes < stats.live_bytes);
+}
+
+#[test]
+fn linked_list_intact() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let head = alloc_fragmented_list(&context, 1000);
+    let head: Option<Gc<Node>> = safepoint!(context, head);
+    check_list(head, 1000);
+    // Nothing moves if the heap is already compact
+    let head: Option<Gc<Node>> = safepoint!(context, head);
+    assert_eq!(context.system().stats().last_moved_bytes, 0);
+    check_list(head, 1000);
+}
+
+#[test]
+fn heap_shrinks() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let head = alloc_fragmented_list(&context, 1000);
+    let before = context.system().stats();
+    let head: Option<Gc<Node>> = safepoint!(context, head);
+    let after = context.system().stats();
+    check_list(head, 1000);
+    // Only the blocks needed by the list are kept
+    assert!(after.num_blocks * 2 < before.num_blocks);
+    assert!(after.heap_bytes < before.heap_bytes);
+    assert!(after.heap_bytes <= after.live_bytes + 4096);
+}
+
+#[test]
+fn handles_relocated() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    context.alloc(Dummy { val: 1 });
+    let value = context.alloc(Dummy { val: 42 });
+    let handle = value.create_handle();
+    assert_eq!(context.system().stats().num_handles, 1);
+    // The handle is the only thing keeping the value alive
+    safepoint!(context, ());
+    assert!(context.system().stats().last_moved_bytes > 0);
+    safepoint!(context, ());
+    let value = handle.bind_to(&context);
+    assert_eq!(value.val, 42);
+}
+
+#[test]
+fn weak_updated() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    context.alloc(Dummy { val: 0 });
+    let target = context.alloc(Dummy { val: 1 });
+    let cache = context.alloc(Cache {
+        entry: target.downgrade(),
+    });
+    let (cache, target): (Gc<Cache>, Gc<Dummy>) = safepoint!(context, (cache, target));
+    let entry = cache.entry.upgrade().unwrap();
+    assert_eq!(entry.val, 1);
+    assert!(std::ptr::eq(&*entry, &*target));
+    let cache: Gc<Cache> = safepoint!(context, cache);
+    assert!(cache.entry.is_cleared());
+}
+
+#[test]
+fn arrays_and_vecs_move() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    context.alloc(Dummy { val: 0 });
+    let array: GcArray<u64> = context.alloc_slice_copy(&[1, 2, 3]);
+    let mut vec: GcVec<Gc<Dummy>> = context.alloc_vec_with_capacity(4);
+    for val in 0..3 {
+        vec.push(context.alloc(Dummy { val }));
+        context.alloc(Dummy { val });
+    }
+    let (array, mut vec): (GcArray<u64>, GcVec<Gc<Dummy>>) = safepoint!(context, (array, vec));
+    assert_eq!(array.as_slice(), &[1, 2, 3]);
+    assert_eq!(vec.len(), 3);
+    vec.push(context.alloc(Dummy { val: 3 }));
+    let vec: GcVec<Gc<Dummy>> = safepoint!(context, vec);
+    for (i, dummy) in vec.iter().enumerate() {
+        assert_eq!(dummy.val, i);
+    }
+}
+
+#[test]
+fn large_objects_stay() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    context.alloc(Big { values: [1; 256] });
+    let big = context.alloc(Big { values: [2; 256] });
+    let old_address = &*big as *const Big as usize;
+    let big: Gc<Big> = safepoint!(context, big);
+    assert_eq!(&*big as *const Big as usize, old_address);
+    assert_eq!(big.values, [2; 256]);
+    let stats = context.system().stats();
+    assert!(stats.last_freed_bytes >= std::mem::size_of::<Big>());
+    assert_eq!(stats.last_moved_bytes, 0);
+}
+
+#[test]
+fn pinned_stays_in_place() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let head = alloc_fragmented_list(&context, 100);
+    context.alloc(Dummy { val: 0 });
+    let node = context.alloc(Node {
+        val: 1000,
+        next: head,
+    });
+    let pinned = node.pin();
+    let address = pinned.as_raw_ptr();
+    // Garbage after the pinned node, followed by a live object
+    for val in 0..16 {
+        context.alloc(Dummy { val });
+    }
+    let after = context.alloc(Dummy { val: 42 });
+    let after_address = &*after as *const Dummy as usize;
+    let (weak, after): (GcWeak<Node>, Gc<Dummy>) = safepoint!(context, (node.downgrade(), after));
+    assert_eq!(pinned.as_raw_ptr(), address);
+    let node = weak.upgrade().unwrap();
+    assert!(std::ptr::eq(&*node, address));
+    // The objects on either side of the pinned one still slide
+    assert!(context.system().stats().last_moved_bytes > 0);
+    assert!((&*after as *const Dummy as usize) < after_address);
+    assert!((&*after as *const Dummy as usize) > address as usize);
+    assert_eq!(after.val, 42);
+    check_list(node.next, 100);
+    // New objects are allocated after the pinned one, without overwriting it
+    for val in 0..1000 {
+        context.alloc(Dummy { val });
+    }
+    let (weak, after): (GcWeak<Node>, Gc<Dummy>) = safepoint!(context, (weak, after));
+    let node = weak.upgrade().unwrap();
+    assert!(std::ptr::eq(&*node, address));
+    assert_eq!(node.val, 1000);
+    assert_eq!(after.val, 42);
+    check_list(node.next, 100);
+    // Once the pin is released, the node slides like everything else
+    drop(pinned);
+    let node: Gc<Node> = safepoint!(context, weak.upgrade().unwrap());
+    assert!(!std::ptr::eq(&*node, address));
+    assert_eq!(node.val, 1000);
+    check_list(node.next, 100);
+}
+
+#[test]
+fn pinned_in_many_blocks() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    // Pin objects spread across lots of blocks, with garbage in between
+    let mut pinned = Vec::new();
+    for val in 0..500 {
+        let node = context.alloc(Node { val, next: None });
+        pinned.push(node.pin());
+        for garbage in 0..16 {
+            context.alloc(Dummy { val: garbage });
+        }
+    }
+    let addresses: Vec<_> = pinned.iter().map(|pin| pin.as_raw_ptr()).collect();
+    assert!(context.system().stats().num_blocks > 10);
+    safepoint!(context, ());
+    for (val, (pin, &address)) in pinned.iter().zip(&addresses).enumerate() {
+        assert_eq!(pin.as_raw_ptr(), address);
+        assert_eq!(unsafe { (*address).val }, val);
+    }
+}
+
+struct FixedThreshold(usize);
+impl ThresholdPolicy for FixedThreshold {
+    fn next_threshold(&self, collection: &FinishedCollection) -> usize {
+        assert!((0.0..=1.0).contains(&collection.gc_cpu_fraction));
+        collection.live_bytes + self.0
+    }
+}
+
+#[test]
+fn threshold_policy() {
+    let mut config = GcConfig::default();
+    config.threshold_policy = Box::new(FixedThreshold(4096));
+    let collector =
+        CompactCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()));
+    let mut context = collector.into_context();
+    assert_eq!(
+        context.system().stats().threshold,
+        HeapGrowth::default().min_heap_size
+    );
+    let kept = context.alloc(Dummy { val: 1 });
+    let kept: Gc<Dummy> = safepoint_force!(context, kept);
+    assert_eq!(kept.val, 1);
+    let stats = context.system().stats();
+    assert_eq!(stats.threshold, stats.live_bytes + 4096);
+    // The policy decides when the next collection happens
+    for val in 0..1000 {
+        context.alloc(Dummy { val });
+        if context.system().stats().live_bytes >= stats.threshold {
+            break;
+        }
+    }
+    safepoint!(context, ());
+    assert_eq!(context.system().stats().collections, 2);
+}
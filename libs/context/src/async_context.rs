@@ -0,0 +1,172 @@
+//! Contexts that can be used inside `async` tasks
+//!
+//! A regular [CollectorContext] is bound to a single thread,
+//! but async tasks may move between executor threads whenever they `.await`.
+//! An [AsyncContext] is owned by a single task,
+//! and freezes itself whenever it awaits with [safepoint_await!].
+//! While it's frozen, the task can be moved to another thread,
+//! and other threads can collect garbage without waiting for it.
+use core::future::Future;
+use core::ops::{Deref, DerefMut};
+
+use alloc::boxed::Box;
+
+use zerogc::{GcContext, Trace};
+
+use crate::collector::SyncCollector;
+use crate::state::RawContext;
+use crate::{CollectorContext, ShadowStackLink};
+
+/// Perform a safepoint, then freeze the context while awaiting the specified future.
+///
+/// The specified root is preserved (and rebound) just like [safepoint!](zerogc::safepoint),
+/// and is returned alongside the output of the future.
+/// The context must be an [AsyncContext].
+///
+/// Since the context is frozen, the task can safely move to another thread,
+/// and other threads can collect garbage without waiting for this one.
+///
+/// ## Example
+/// ```ignore
+/// let root = context.alloc(Node { val: 42 });
+/// let (root, response) = safepoint_await!(context, root, socket.read());
+/// assert_eq!(root.val, 42);
+/// ```
+///
+/// ## Safety
+/// This macro is completely safe, although it expands to unsafe code internally.
+#[macro_export]
+macro_rules! safepoint_await {
+    ($context:ident, $value:expr, $future:expr) => {{
+        use ::zerogc::GcContext;
+        let erased = unsafe { $context.rebrand_static($value) };
+        let (erased, output) = unsafe { $context.await_safepoint(erased, $future) }.await;
+        (unsafe { $context.rebrand_self(erased) }, output)
+    }};
+}
+
+/// A context that is owned by an `async` task,
+/// and can be sent between threads.
+///
+/// This dereferences to a regular [CollectorContext],
+/// which can be used for allocation and synchronous safepoints.
+/// However, only [safepoint_await!] will freeze the context.
+/// The owning task must never await anything else
+/// (see [CollectorRef::create_async_context](crate::CollectorRef::create_async_context)).
+pub struct AsyncContext<C: SyncCollector> {
+    /// The root context, which is never shared
+    context: CollectorContext<C>,
+}
+impl<C: SyncCollector> AsyncContext<C> {
+    #[inline]
+    pub(crate) fn new(context: CollectorContext<C>) -> Self {
+        debug_assert!(context.root);
+        AsyncContext { context }
+    }
+    /// Perform a safepoint, then freeze this context while awaiting the specified future,
+    /// preserving the specified root.
+    ///
+    /// Once the future completes, the context is unfrozen again
+    /// (waiting for any collection that is currently in progress).
+    /// If the returned future is dropped early, the context is unfrozen by its destructor.
+    ///
+    /// This is what the [safepoint_await!] macro expands to internally.
+    ///
+    /// ## Safety
+    /// The same as [GcContext::basic_safepoint].
+    /// The root must have been rebranded to the `'static` lifetime,
+    /// and must be rebranded back to the lifetime of the context once it's returned.
+    pub async unsafe fn await_safepoint<T, F>(&mut self, root: T, future: F) -> (T, F::Output)
+    where
+        T: Trace,
+        F: Future,
+    {
+        let mut frozen = Box::new(FrozenRoot::<C, T> {
+            root,
+            link: None,
+        });
+        self.context.basic_safepoint(&mut &mut frozen.root);
+        let guard = FreezeGuard::freeze(self, frozen);
+        let output = future.await;
+        (guard.unfreeze(), output)
+    }
+}
+impl<C: SyncCollector> Deref for AsyncContext<C> {
+    type Target = CollectorContext<C>;
+    #[inline]
+    fn deref(&self) -> &CollectorContext<C> {
+        &self.context
+    }
+}
+impl<C: SyncCollector> DerefMut for AsyncContext<C> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut CollectorContext<C> {
+        &mut self.context
+    }
+}
+/// An async context can move between threads,
+/// since it's never shared and only a root context.
+///
+/// ## Safety
+/// This relies on the requirement of [CollectorRef::create_async_context](crate::CollectorRef::create_async_context):
+/// the owning task only suspends in [safepoint_await!], where the context is frozen.
+/// The type system can't check that a task never holds an active context across
+/// some other `.await`, which is why creating the context is `unsafe`.
+unsafe impl<C: SyncCollector> Send for AsyncContext<C> {}
+
+/// A root that is preserved while its context is frozen
+///
+/// This is boxed, so the shadow stack can safely point into it
+/// even if the future awaiting it is moved.
+struct FrozenRoot<C: SyncCollector, T: Trace> {
+    root: T,
+    /// The link on the shadow stack, once the context has been frozen
+    link: Option<ShadowStackLink<C::DynTracePtr>>,
+}
+/// Unfreezes the context (and pops its root from the shadow stack) once dropped
+///
+/// This makes sure the context is unfrozen even if the task is cancelled.
+struct FreezeGuard<'a, C: SyncCollector, T: Trace> {
+    context: &'a mut AsyncContext<C>,
+    frozen: Option<Box<FrozenRoot<C, T>>>,
+}
+impl<'a, C: SyncCollector, T: Trace> FreezeGuard<'a, C, T> {
+    unsafe fn freeze(context: &'a mut AsyncContext<C>, mut frozen: Box<FrozenRoot<C, T>>) -> Self {
+        let shadow_stack = (*context.context.raw).shadow_stack_ptr();
+        let link = frozen.link.insert(ShadowStackLink {
+            element: C::as_dyn_trace_pointer(&mut frozen.root as *mut T),
+            prev: (*shadow_stack).last,
+        });
+        (*shadow_stack).last = link;
+        context.context.freeze();
+        FreezeGuard {
+            context,
+            frozen: Some(frozen),
+        }
+    }
+    /// Unfreeze the context, and take back the (possibly relocated) root
+    fn unfreeze(mut self) -> T {
+        unsafe { self.unfreeze_inner() }.root
+    }
+    unsafe fn unfreeze_inner(&mut self) -> Box<FrozenRoot<C, T>> {
+        let frozen = self.frozen.take().unwrap();
+        self.context.context.unfreeze();
+        let shadow_stack = (*self.context.context.raw).shadow_stack_ptr();
+        let link = frozen.link.as_ref().unwrap();
+        debug_assert_eq!((*shadow_stack).last, link as *const _);
+        (*shadow_stack).last = link.prev;
+        frozen
+    }
+}
+impl<C: SyncCollector, T: Trace> Drop for FreezeGuard<'_, C, T> {
+    fn drop(&mut self) {
+        if self.frozen.is_some() {
+            unsafe {
+                self.unfreeze_inner();
+            }
+        }
+    }
+}
+/// The guard can move between threads along with the task,
+/// since the context is frozen (and the root is owned by it).
+unsafe impl<C: SyncCollector, T: Trace + Send> Send for FreezeGuard<'_, C, T> {}
@@ -1,16 +1,21 @@
 //! The interface to a collector
 #![allow(clippy::missing_safety_doc)]
 
+use core::alloc::Layout;
 use core::fmt::{self, Debug, Formatter};
 use core::hash::{Hash, Hasher};
 use core::marker::PhantomData;
 use core::ptr::NonNull;
 
+use alloc::boxed::Box;
 use alloc::sync::Arc;
 
 use slog::{o, Logger};
 
-use zerogc::{Gc, GcArray, GcSafe, GcSimpleAlloc, GcSystem, Trace};
+use zerogc::{
+    Gc, GcAllocError, GcArray, GcFinalize, GcFinalized, GcRebrand, GcSafe, GcSimpleAlloc, GcSystem,
+    NonMovingCollectorId, Trace,
+};
 
 use crate::state::{CollectionManager, RawContext};
 use crate::CollectorContext;
@@ -29,6 +34,8 @@ pub unsafe trait RawCollectorImpl: 'static + Sized {
     type DynTracePtr: Copy + Debug + 'static;
     /// The configuration
     type Config: Sized + Default;
+    /// A snapshot of statistics about the collector
+    type Stats: Clone + Debug;
 
     /// A pointer to this collector
     ///
@@ -40,6 +47,11 @@ pub unsafe trait RawCollectorImpl: 'static + Sized {
 
     /// The context
     type RawContext: RawContext<Self>;
+    /// A cache owned by each context, used to speed up allocation
+    ///
+    /// For example, this could hold a context's private free lists.
+    /// Collectors that don't need one can just use `()`.
+    type ContextCache: Default;
     /// The raw representation of a vec
     type RawVec<'gc, T: GcSafe<'gc, CollectorId<Self>>>: GcRawVec<'gc, T, Id = CollectorId<Self>>;
 
@@ -98,9 +110,41 @@ pub unsafe trait RawCollectorImpl: 'static + Sized {
 
     fn allocated_size(&self) -> crate::utils::MemorySize;
 
+    /// Take a snapshot of the collector's current statistics
+    fn stats(&self) -> Self::Stats;
+
     unsafe fn perform_raw_collection(&self, contexts: &[*mut Self::RawContext]);
+
+    /// Flush the specified context's cache back into the collector
+    ///
+    /// Contexts do this whenever they reach a safepoint that triggers a collection,
+    /// before they're frozen and before they're freed.
+    /// So every cache has been flushed by the time a collection begins.
+    ///
+    /// ## Safety
+    /// The cache must belong to a context of this collector,
+    /// and that context must not be allocating concurrently.
+    unsafe fn flush_context_cache(&self, cache: &Self::ContextCache);
+
+    /// Make the objects allocated through the specified context's cache
+    /// known to the collector, without giving back the memory it has reserved.
+    ///
+    /// Contexts do this at every safepoint that doesn't trigger a collection,
+    /// where a full flush would be wasted work.
+    /// By default, this flushes the whole cache.
+    ///
+    /// ## Safety
+    /// See [RawCollectorImpl::flush_context_cache]
+    unsafe fn publish_context_cache(&self, cache: &Self::ContextCache) {
+        self.flush_context_cache(cache)
+    }
 }
 
+/// A collector that never moves objects
+///
+/// This makes its [CollectorId] a [NonMovingCollectorId].
+pub unsafe trait RawNonMovingImpl: RawCollectorImpl {}
+
 /// A thread safe collector
 pub unsafe trait SyncCollector: RawCollectorImpl + Sync {}
 
@@ -353,6 +397,7 @@ unsafe impl<C: RawCollectorImpl> ::zerogc::CollectorId for CollectorId<C> {
         &*(self as *const CollectorId<C> as *const CollectorRef<C>)
     }
 }
+unsafe impl<C: RawNonMovingImpl> NonMovingCollectorId for CollectorId<C> {}
 zerogc::impl_nulltrace_for_static!(CollectorId<C>, params => [C: RawCollectorImpl]);
 
 pub struct WeakCollectorRef<C: RawCollectorImpl> {
@@ -378,19 +423,19 @@ impl<C: RawCollectorImpl> WeakCollectorRef<C> {
 }
 
 pub unsafe trait RawSimpleAlloc: RawCollectorImpl {
-    unsafe fn alloc_uninit<'gc, T: GcSafe<'gc, CollectorId<Self>>>(
+    unsafe fn try_alloc_uninit<'gc, T: GcSafe<'gc, CollectorId<Self>>>(
         context: &'gc CollectorContext<Self>,
-    ) -> *mut T;
-    unsafe fn alloc_uninit_slice<'gc, T>(
+    ) -> Result<*mut T, GcAllocError>;
+    unsafe fn try_alloc_uninit_slice<'gc, T>(
         context: &'gc CollectorContext<Self>,
         len: usize,
-    ) -> *mut T
+    ) -> Result<*mut T, GcAllocError>
     where
         T: GcSafe<'gc, CollectorId<Self>>;
-    fn alloc_raw_vec_with_capacity<'gc, T>(
+    fn try_alloc_raw_vec_with_capacity<'gc, T>(
         context: &'gc CollectorContext<Self>,
         capacity: usize,
-    ) -> Self::RawVec<'gc, T>
+    ) -> Result<Self::RawVec<'gc, T>, GcAllocError>
     where
         T: GcSafe<'gc, CollectorId<Self>>;
 }
@@ -403,7 +448,26 @@ where
     where
         T: GcSafe<'gc, CollectorId<C>>,
     {
-        C::alloc_uninit(self)
+        match C::try_alloc_uninit(self) {
+            Ok(ptr) => ptr,
+            Err(cause) => cause.fail(Layout::new::<T>()),
+        }
+    }
+
+    #[inline]
+    unsafe fn try_alloc_uninit<'gc, T>(&'gc self) -> Result<*mut T, GcAllocError>
+    where
+        T: GcSafe<'gc, CollectorId<C>>,
+    {
+        C::try_alloc_uninit(self)
+    }
+
+    #[inline]
+    unsafe fn try_alloc_uninit_slice<'gc, T>(&'gc self, len: usize) -> Result<*mut T, GcAllocError>
+    where
+        T: GcSafe<'gc, CollectorId<C>>,
+    {
+        C::try_alloc_uninit_slice(self, len)
     }
 
     #[inline]
@@ -411,7 +475,21 @@ where
     where
         T: GcSafe<'gc, CollectorId<C>>,
     {
-        C::alloc_uninit_slice(self, len)
+        match C::try_alloc_uninit_slice(self, len) {
+            Ok(ptr) => ptr,
+            Err(cause) => cause.fail_array::<T>(len),
+        }
+    }
+
+    #[inline]
+    fn try_alloc_raw_vec_with_capacity<'gc, T>(
+        &'gc self,
+        capacity: usize,
+    ) -> Result<C::RawVec<'gc, T>, GcAllocError>
+    where
+        T: GcSafe<'gc, CollectorId<C>>,
+    {
+        C::try_alloc_raw_vec_with_capacity::<T>(self, capacity)
     }
 
     #[inline]
@@ -419,7 +497,41 @@ where
     where
         T: GcSafe<'gc, CollectorId<C>>,
     {
-        C::alloc_raw_vec_with_capacity::<T>(self, capacity)
+        match C::try_alloc_raw_vec_with_capacity::<T>(self, capacity) {
+            Ok(raw) => raw,
+            Err(cause) => cause.fail_array::<T>(capacity),
+        }
+    }
+}
+
+pub unsafe trait RawFinalizeImpl: RawCollectorImpl {
+    type DrainFinalized<'gc>: Iterator<Item = GcFinalized<'gc, CollectorId<Self>>>;
+    fn register_finalizer<'gc, T>(
+        context: &'gc CollectorContext<Self>,
+        gc: Gc<'gc, T, CollectorId<Self>>,
+    ) where
+        T: GcSafe<'gc, CollectorId<Self>> + GcRebrand<'static, CollectorId<Self>>,
+        <T as GcRebrand<'static, CollectorId<Self>>>::Branded: 'static;
+    fn drain_finalized<'gc>(context: &'gc CollectorContext<Self>) -> Self::DrainFinalized<'gc>;
+}
+unsafe impl<C> GcFinalize for CollectorContext<C>
+where
+    C: RawFinalizeImpl,
+{
+    type DrainFinalized<'gc> = C::DrainFinalized<'gc>;
+
+    #[inline]
+    fn register_finalizer<'gc, T>(&'gc self, gc: Gc<'gc, T, CollectorId<C>>)
+    where
+        T: GcSafe<'gc, CollectorId<C>> + GcRebrand<'static, CollectorId<C>>,
+        <T as GcRebrand<'static, CollectorId<C>>>::Branded: 'static,
+    {
+        C::register_finalizer(self, gc)
+    }
+
+    #[inline]
+    fn drain_finalized<'gc>(&'gc self) -> C::DrainFinalized<'gc> {
+        C::drain_finalized(self)
     }
 }
 
@@ -510,6 +622,28 @@ impl<C: RawCollectorImpl> CollectorRef<C> {
         CollectorId { ptr: self.ptr }
     }
 
+    /// Take a snapshot of this collector's statistics
+    #[inline]
+    pub fn stats(&self) -> C::Stats {
+        self.as_raw().stats()
+    }
+
+    /// Register a callback to be invoked before every collection.
+    ///
+    /// The hook is given the id of the collection
+    /// and a snapshot of the collector's statistics.
+    /// See [CollectionHook](crate::state::CollectionHook) for restrictions on what hooks can do.
+    pub fn on_collection_start(&self, hook: impl Fn(u64, &C::Stats) + Send + Sync + 'static) {
+        self.as_raw().manager().on_collection_start(Box::new(hook))
+    }
+
+    /// Register a callback to be invoked after every collection.
+    ///
+    /// See [CollectorRef::on_collection_start] for details.
+    pub fn on_collection_end(&self, hook: impl Fn(u64, &C::Stats) + Send + Sync + 'static) {
+        self.as_raw().manager().on_collection_end(Box::new(hook))
+    }
+
     /// Convert this collector into a unique context
     ///
     /// The single-threaded implementation only allows a single context,
@@ -526,6 +660,26 @@ impl<C: SyncCollector> CollectorRef<C> {
     pub fn create_context(&self) -> CollectorContext<C> {
         unsafe { CollectorContext::register_root(self) }
     }
+    /// Create a new context that can be used inside an `async` task
+    ///
+    /// Unlike a regular context, this can be sent between threads,
+    /// as long as it is frozen using [safepoint_await!](crate::safepoint_await)
+    /// whenever the task awaits.
+    ///
+    /// ## Safety
+    /// While the context is alive, the task that owns it must only suspend
+    /// inside [safepoint_await!](crate::safepoint_await).
+    ///
+    /// Any other `.await` suspends the task with the context still active,
+    /// and the executor is free to resume it on another thread.
+    /// The context isn't synchronized for that: only freezing it
+    /// hands it over to the collector safely.
+    /// Collections on every other thread also block until the task is resumed,
+    /// which deadlocks if the task is waiting for one of them.
+    #[cfg(feature = "sync")]
+    pub unsafe fn create_async_context(&self) -> crate::AsyncContext<C> {
+        crate::AsyncContext::new(self.create_context())
+    }
 }
 impl<C: RawCollectorImpl> Drop for CollectorRef<C> {
     #[inline]
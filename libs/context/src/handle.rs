@@ -4,18 +4,26 @@
 use core::marker::PhantomData;
 use core::mem::ManuallyDrop;
 use core::ptr::{self, NonNull, Pointee};
-use core::sync::atomic::{self, AtomicPtr, AtomicUsize, Ordering};
+use core::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 
 use crate::collector::RawCollectorImpl;
 use crate::{CollectionManager, CollectorId, CollectorRef, Gc, WeakCollectorRef};
+use zerogc::array::GcArray;
 use zerogc::{
-    GcRebrand, GcSafe, GcVisitor, HandleCollectorId, NullTrace, Trace, TraceImmutable, TrustedDrop,
+    GcRebrand, GcSafe, GcVisitor, HandleCollectorId, NullTrace, PinCollectorId, Trace,
+    TraceImmutable, TrustedDrop, WeakHandleCollectorId,
 };
 
 const INITIAL_HANDLE_CAPACITY: usize = 64;
+/// Set in [GcHandleList::freeing] while the list is being shrunk
+const SHRINKING: usize = 1 << (usize::BITS - 1);
+/// The value of a weak handle whose object has been collected
+///
+/// This can't be null, since that would mark the slot as free.
+const CLEARED_WEAK_VALUE: *mut () = NonNull::<()>::dangling().as_ptr();
 
 /// A [RawCollectorImpl] that supports handles
 pub unsafe trait RawHandleImpl: RawCollectorImpl {
@@ -31,6 +39,20 @@ pub unsafe trait RawHandleImpl: RawCollectorImpl {
     fn handle_list(&self) -> &GcHandleList<Self>;
 }
 
+/// A [RawHandleImpl] that supports pinning objects in place.
+///
+/// Pinned objects are referenced by handles with their pinned flag set.
+/// Collectors that relocate objects must not move any of them
+/// (see [GcHandleList::for_each_pinned]).
+pub unsafe trait RawPinImpl: RawHandleImpl {}
+
+/// A [RawHandleImpl] that supports weak handles.
+///
+/// Weak handles are skipped by [GcHandleList::trace] (and [GcHandleList::relocate]).
+/// Once marking has finished, the collector must clear the ones
+/// whose objects are unreachable (see [GcHandleList::clear_dead_weak]).
+pub unsafe trait RawWeakHandleImpl: RawHandleImpl {}
+
 /// Concurrent list of [GcHandle]s
 ///
 /// Each bucket in the linked list is twice the size of
@@ -39,13 +61,21 @@ pub unsafe trait RawHandleImpl: RawCollectorImpl {
 /// The list can not be appended to while a collection
 /// is in progress.
 ///
-/// TODO: This list only grows. It never shrinks!!
+/// Buckets that are left entirely free are released
+/// by the collector (see [GcHandleList::shrink]).
 pub struct GcHandleList<C: RawHandleImpl> {
     last_bucket: AtomicPtr<GcHandleBucket<C>>,
     /// Pointer to the last free slot in the free list
     ///
     /// If the list is empty, this is null
     last_free_slot: AtomicPtr<HandleSlot<C>>,
+    /// The number of threads that are currently freeing a slot,
+    /// or [SHRINKING] if the list is being shrunk.
+    ///
+    /// Handles can be dropped by threads that aren't
+    /// participating in collections, so this keeps them from
+    /// freeing a slot while its bucket is being released.
+    freeing: AtomicUsize,
 }
 #[allow(clippy::new_without_default)]
 impl<C: RawHandleImpl> GcHandleList<C> {
@@ -54,8 +84,36 @@ impl<C: RawHandleImpl> GcHandleList<C> {
         GcHandleList {
             last_bucket: AtomicPtr::new(null_mut()),
             last_free_slot: AtomicPtr::new(null_mut()),
+            freeing: AtomicUsize::new(0),
         }
     }
+    /// Free the specified slot, so it can be reused by another handle
+    ///
+    /// The specified slot must be logically owned
+    /// and not already part of this list
+    unsafe fn free_slot(&self, slot: *mut HandleSlot<C>) {
+        let mut freeing = self.freeing.load(Ordering::Acquire);
+        loop {
+            if freeing == SHRINKING {
+                // The collector never blocks while shrinking, so this won't be long
+                core::hint::spin_loop();
+                freeing = self.freeing.load(Ordering::Acquire);
+                continue;
+            }
+            match self.freeing.compare_exchange_weak(
+                freeing,
+                freeing + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(actual) => freeing = actual,
+            }
+        }
+        (*slot).valid.value.store(ptr::null_mut(), Ordering::Release);
+        self.append_free_slot(slot);
+        self.freeing.fetch_sub(1, Ordering::AcqRel);
+    }
     /// Append the specified slot to this list
     ///
     /// The specified slot must be logically owned
@@ -209,7 +267,7 @@ impl<C: RawHandleImpl> GcHandleList<C> {
         ) {
             Ok(actual_bucket) => {
                 assert_eq!(actual_bucket, prev_bucket);
-                Ok(&*actual_bucket)
+                Ok(&*allocated_bucket)
             }
             Err(actual_bucket) => {
                 /*
@@ -225,6 +283,8 @@ impl<C: RawHandleImpl> GcHandleList<C> {
     }
     /// Trace the [GcHandle] using the specified closure.
     ///
+    /// Weak handles are skipped, since they don't keep their values alive.
+    ///
     /// ## Safety
     /// Assumes the visitor function is well behaved.
     ///
@@ -247,7 +307,7 @@ impl<C: RawHandleImpl> GcHandleList<C> {
             // We should have exclusive access!
             let slots = &mut *(*bucket).slots;
             for slot in slots {
-                if slot.is_valid(Ordering::Relaxed) {
+                if slot.is_valid(Ordering::Relaxed) && !slot.valid.weak.load(Ordering::Relaxed) {
                     slot.valid.trace_inner(&mut visitor)?;
                 }
             }
@@ -260,18 +320,195 @@ impl<C: RawHandleImpl> GcHandleList<C> {
         atomic::fence(Ordering::Release);
         Ok(())
     }
+    /// Trace the value of every handle,
+    /// replacing it with the (possibly relocated) pointer returned by the closure.
+    ///
+    /// This is how moving collectors update handles to objects they've moved.
+    /// Weak handles are skipped (only non-moving collectors support them).
+    ///
+    /// ## Safety
+    /// A collection must currently be in progress,
+    /// and the returned pointers must refer to the same objects
+    /// (with the same type information).
+    /// Pinned objects must be left where they are.
+    pub unsafe fn relocate<F, E>(&mut self, mut visitor: F) -> Result<(), E>
+    where
+        F: FnMut(*mut (), &C::TypeInfo) -> Result<*mut (), E>,
+    {
+        atomic::fence(Ordering::Acquire);
+        let mut bucket = self.last_bucket.load(Ordering::Relaxed);
+        while !bucket.is_null() {
+            // We should have exclusive access!
+            let slots = &mut *(*bucket).slots;
+            for slot in slots {
+                if slot.is_valid(Ordering::Relaxed) && !slot.valid.weak.load(Ordering::Relaxed) {
+                    slot.valid.relocate_inner(&mut visitor)?;
+                }
+            }
+            bucket = (*bucket).prev.load(Ordering::Relaxed);
+        }
+        // Release the relocated pointers
+        atomic::fence(Ordering::Release);
+        Ok(())
+    }
+    /// Clear every weak handle whose value is no longer alive,
+    /// according to the specified closure.
+    ///
+    /// Cleared handles stay valid, but no longer refer to anything.
+    ///
+    /// ## Safety
+    /// A collection must currently be in progress,
+    /// and marking must have finished (but the garbage can't have been freed yet).
+    pub unsafe fn clear_dead_weak(&self, mut is_alive: impl FnMut(*mut (), &C::TypeInfo) -> bool) {
+        atomic::fence(Ordering::Acquire);
+        let mut bucket = self.last_bucket.load(Ordering::Relaxed);
+        while !bucket.is_null() {
+            for slot in &*(*bucket).slots {
+                if !slot.is_valid(Ordering::Relaxed) || !slot.valid.weak.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let value = slot.valid.value.load(Ordering::Relaxed);
+                if value != CLEARED_WEAK_VALUE
+                    && !is_alive(value, &*slot.valid.type_info.load(Ordering::Relaxed))
+                {
+                    slot.valid.value.store(CLEARED_WEAK_VALUE, Ordering::Relaxed);
+                }
+            }
+            bucket = (*bucket).prev.load(Ordering::Relaxed);
+        }
+        atomic::fence(Ordering::Release);
+    }
+    /// Count the number of handles that are currently alive.
+    ///
+    /// If other threads are creating or dropping handles,
+    /// this is only an approximation.
+    #[inline]
+    pub fn count_handles(&self) -> usize {
+        self.slot_stats().live_slots
+    }
+    /// Count the number of slots that are used by live handles,
+    /// and the number of slots that are free to be reused.
+    ///
+    /// If other threads are creating or dropping handles,
+    /// this is only an approximation.
+    pub fn slot_stats(&self) -> HandleSlotStats {
+        let mut stats = HandleSlotStats::default();
+        let mut bucket = self.last_bucket.load(Ordering::Acquire);
+        while !bucket.is_null() {
+            unsafe {
+                let slots = &*(*bucket).slots;
+                let live = slots
+                    .iter()
+                    .filter(|slot| slot.is_valid(Ordering::Acquire))
+                    .count();
+                stats.live_slots += live;
+                stats.free_slots += slots.len() - live;
+                bucket = (*bucket).prev.load(Ordering::Acquire);
+            }
+        }
+        stats
+    }
+    /// Release every bucket that only contains free slots,
+    /// and rebuild the free list from the remaining ones.
+    ///
+    /// Free slots in the oldest buckets are reused first,
+    /// so the newer (bigger) buckets have a chance to become entirely free.
+    /// Live handles never move, since they're referenced by pointer.
+    ///
+    /// If another thread is currently dropping a handle,
+    /// nothing is done until the next collection.
+    ///
+    /// ## Safety
+    /// A collection must currently be in progress,
+    /// so no new handles can be allocated.
+    pub unsafe fn shrink(&self) {
+        if self
+            .freeing
+            .compare_exchange(0, SHRINKING, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        // Unlink (and free) the empty buckets
+        let mut link = &self.last_bucket;
+        loop {
+            let bucket = link.load(Ordering::Acquire);
+            if bucket.is_null() {
+                break;
+            }
+            if (*bucket).slots.iter().any(|slot| slot.is_valid(Ordering::Acquire)) {
+                link = &(*bucket).prev;
+            } else {
+                link.store((*bucket).prev.load(Ordering::Acquire), Ordering::Release);
+                drop(Box::from_raw(bucket));
+            }
+        }
+        /*
+         * Rebuild the free list, from the newest bucket to the oldest.
+         * The free list is LIFO, so the oldest slots end up at the front.
+         *
+         * Every free slot is now part of the free list,
+         * so they must never be handed out by `blindly_alloc_slot`.
+         */
+        let mut last_free: *mut HandleSlot<C> = ptr::null_mut();
+        let mut bucket = self.last_bucket.load(Ordering::Acquire);
+        while !bucket.is_null() {
+            let slots = &*(*bucket).slots;
+            (*bucket).last_alloc.store(slots.len(), Ordering::Relaxed);
+            for slot in slots.iter().rev() {
+                if !slot.is_valid(Ordering::Relaxed) {
+                    slot.freed.prev_free_slot.store(last_free, Ordering::Relaxed);
+                    last_free = slot as *const HandleSlot<C> as *mut HandleSlot<C>;
+                }
+            }
+            bucket = (*bucket).prev.load(Ordering::Acquire);
+        }
+        self.last_free_slot.store(last_free, Ordering::Release);
+        self.freeing.store(0, Ordering::Release);
+    }
+    /// Invoke the specified closure on every object
+    /// that is currently pinned by a handle.
+    ///
+    /// Moving collectors must not relocate any of these objects.
+    ///
+    /// ## Safety
+    /// A collection must currently be in progress.
+    pub unsafe fn for_each_pinned(&self, mut func: impl FnMut(*mut (), &C::TypeInfo)) {
+        atomic::fence(Ordering::Acquire);
+        let mut bucket = self.last_bucket.load(Ordering::Relaxed);
+        while !bucket.is_null() {
+            for slot in &*(*bucket).slots {
+                if slot.is_valid(Ordering::Relaxed) && slot.valid.pinned.load(Ordering::Relaxed) {
+                    let value = slot.valid.value.load(Ordering::Relaxed);
+                    func(value, &*slot.valid.type_info.load(Ordering::Relaxed));
+                }
+            }
+            bucket = (*bucket).prev.load(Ordering::Relaxed);
+        }
+    }
 }
 impl<C: RawHandleImpl> Drop for GcHandleList<C> {
     fn drop(&mut self) {
         let mut bucket = self.last_bucket.load(Ordering::Acquire);
         while !bucket.is_null() {
             unsafe {
+                let prev = (*bucket).prev.load(Ordering::Acquire);
                 drop(Box::from_raw(bucket));
-                bucket = (*bucket).prev.load(Ordering::Acquire);
+                bucket = prev;
             }
         }
     }
 }
+/// Statistics about the slots in a [GcHandleList]
+///
+/// This can be retrieved with [GcHandleList::slot_stats]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct HandleSlotStats {
+    /// The number of slots used by live handles
+    pub live_slots: usize,
+    /// The number of slots that are free to be reused
+    pub free_slots: usize,
+}
 struct GcHandleBucket<C: RawHandleImpl> {
     slots: Box<[HandleSlot<C>]>,
     /// A pointer to the last allocated slot
@@ -372,6 +609,15 @@ pub struct GcRawHandle<C: RawHandleImpl> {
     /// and this memory can be used.
     // TODO: Encapsulate
     pub(crate) refcnt: AtomicUsize,
+    /// Whether the underlying value is pinned in place
+    ///
+    /// Pinned values must never be relocated.
+    pub(crate) pinned: AtomicBool,
+    /// Whether this is a weak handle, that doesn't keep its value alive
+    ///
+    /// Once the value has been collected,
+    /// it's replaced with [CLEARED_WEAK_VALUE].
+    pub(crate) weak: AtomicBool,
 }
 impl<C: RawHandleImpl> GcRawHandle<C> {
     /// Trace this handle, assuming collection is in progress
@@ -394,6 +640,37 @@ impl<C: RawHandleImpl> GcRawHandle<C> {
         let type_info = &*self.type_info.load(Ordering::Relaxed);
         trace(value, type_info)
     }
+    /// Trace this handle, replacing its value with the relocated pointer
+    ///
+    /// ## Safety
+    /// Same as [GcRawHandle::trace_inner]. The closure must return
+    /// a pointer to the same object.
+    unsafe fn relocate_inner<F, E>(&self, relocate: &mut F) -> Result<(), E>
+    where
+        F: FnMut(*mut (), &C::TypeInfo) -> Result<*mut (), E>,
+    {
+        let value = self.value.load(Ordering::Relaxed);
+        if value.is_null() {
+            debug_assert_eq!(self.refcnt.load(Ordering::Relaxed), 0);
+            return Ok(()); // Nothing to relocate
+        }
+        debug_assert_ne!(self.refcnt.load(Ordering::Relaxed), 0);
+        let type_info = &*self.type_info.load(Ordering::Relaxed);
+        let relocated = relocate(value, type_info)?;
+        debug_assert!(!relocated.is_null());
+        self.value.store(relocated, Ordering::Relaxed);
+        Ok(())
+    }
+}
+/// The kind of handle that references a [GcRawHandle]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum HandleKind {
+    /// A regular [GcHandle]
+    Strong,
+    /// A [GcPinned] handle
+    Pinned,
+    /// A [GcWeakHandle]
+    Weak,
 }
 pub struct GcHandle<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawHandleImpl> {
     inner: NonNull<GcRawHandle<C>>,
@@ -422,6 +699,51 @@ impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawHandleImpl> GcHandle<T,
             marker: PhantomData,
         }
     }
+    /// Allocate a new handle, pointing to the specified value
+    ///
+    /// ## Safety
+    /// The value must be a valid object, allocated in the specified collector.
+    /// A collection may not currently be in progress.
+    unsafe fn alloc(
+        collector: &CollectorId<C>,
+        value: *mut T,
+        type_info: &'static C::TypeInfo,
+        kind: HandleKind,
+    ) -> Self {
+        let raw = collector
+            .as_ref()
+            .handle_list()
+            .alloc_raw_handle(value as *mut ());
+        /*
+         * WARN: Undefined Behavior
+         * if we don't finish initializing
+         * the handle!!!
+         */
+        raw.type_info.store(
+            type_info as *const C::TypeInfo as *mut C::TypeInfo,
+            Ordering::Release,
+        );
+        raw.pinned
+            .store(kind == HandleKind::Pinned, Ordering::Release);
+        raw.weak.store(kind == HandleKind::Weak, Ordering::Release);
+        raw.refcnt.store(1, Ordering::Release);
+        GcHandle::new(
+            NonNull::from(raw),
+            collector.weak_ref(),
+            ptr::metadata(value),
+        )
+    }
+    /// The current value of this handle,
+    /// or `None` if it's a weak handle that has been cleared.
+    #[inline]
+    unsafe fn load_value(&self) -> Option<*mut T> {
+        let value = self.inner.as_ref().value.load(Ordering::Acquire);
+        if value == CLEARED_WEAK_VALUE {
+            None
+        } else {
+            Some(ptr::from_raw_parts_mut(value, self.metadata))
+        }
+    }
     #[inline]
     unsafe fn assume_valid(&self) -> *mut T {
         ptr::from_raw_parts_mut(
@@ -615,14 +937,13 @@ impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawHandleImpl> Drop for GcH
                 1 => {
                     // Free underlying memory
                 }
-                _ => {} // Other references
+                _ => return, // Other references
             }
             // Mark the value as freed
-            inner.value.store(ptr::null_mut(), Ordering::Release);
             unsafe {
                 collector
                     .handle_list()
-                    .append_free_slot(self.inner.as_ptr() as *mut HandleSlot<C>);
+                    .free_slot(self.inner.as_ptr() as *mut HandleSlot<C>);
             }
         });
     }
@@ -661,28 +982,303 @@ where
         T::Branded: GcSafe<'static, Self>,
     {
         unsafe {
-            let collector = gc.collector_id();
-            let value = gc.as_raw_ptr();
-            let raw = collector
-                .as_ref()
-                .handle_list()
-                .alloc_raw_handle(value as *mut ());
             /*
-             * WARN: Undefined Behavior
-             * if we don't finish initializing
-             * the handle!!!
+             * NOTE: Can't use regular pointer-cast
+             * because of potentially mismatched vtables.
              */
-            raw.type_info.store(
-                C::resolve_type_info(gc) as *const C::TypeInfo as *mut C::TypeInfo,
-                Ordering::Release,
+            let value =
+                crate::utils::transmute_mismatched::<*mut T, *mut T::Branded>(gc.as_raw_ptr());
+            GcHandle::alloc(
+                gc.collector_id(),
+                value,
+                C::resolve_type_info(gc),
+                HandleKind::Strong,
+            )
+        }
+    }
+}
+
+/// A [GcHandle] that also pins its value in place
+///
+/// The value is guaranteed to never move
+/// for as long as the pin is held.
+pub struct GcPinned<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawPinImpl> {
+    handle: GcHandle<T, C>,
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawPinImpl> ::zerogc::GcPinned<T>
+    for GcPinned<T, C>
+{
+    #[inline]
+    fn as_raw_ptr(&self) -> *mut T {
+        self.handle.collector.ensure_valid(|_| unsafe {
+            // The value is pinned, so it can't change
+            self.handle.assume_valid()
+        })
+    }
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawPinImpl> ::zerogc::GcHandle<T>
+    for GcPinned<T, C>
+{
+    type System = CollectorRef<C>;
+    type Id = CollectorId<C>;
+
+    #[inline]
+    fn use_critical<R>(&self, func: impl FnOnce(&T) -> R) -> R {
+        self.handle.use_critical(func)
+    }
+    #[inline]
+    fn bind_to<'new_gc>(
+        &self,
+        context: &'new_gc <Self::System as zerogc::GcSystem>::Context,
+    ) -> Gc<'new_gc, <T as GcRebrand<'new_gc, Self::Id>>::Branded, Self::Id>
+    where
+        T: GcRebrand<'new_gc, Self::Id>,
+    {
+        self.handle.bind_to(context)
+    }
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawPinImpl> Trace for GcPinned<T, C> {
+    /// See docs on reachability
+    const NEEDS_TRACE: bool = false;
+    const NEEDS_DROP: bool = true;
+    #[inline(always)]
+    fn trace<V>(&mut self, _visitor: &mut V) -> Result<(), V::Err>
+    where
+        V: zerogc::GcVisitor,
+    {
+        Ok(())
+    }
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawPinImpl> TraceImmutable
+    for GcPinned<T, C>
+{
+    #[inline(always)]
+    fn trace_immutable<V>(&self, _visitor: &mut V) -> Result<(), V::Err>
+    where
+        V: GcVisitor,
+    {
+        Ok(())
+    }
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawPinImpl> NullTrace
+    for GcPinned<T, C>
+{
+}
+unsafe impl<'gc, T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawPinImpl>
+    GcSafe<'gc, CollectorId<C>> for GcPinned<T, C>
+{
+    #[inline]
+    unsafe fn trace_inside_gc<V>(
+        gc: &mut Gc<'gc, Self, CollectorId<C>>,
+        visitor: &mut V,
+    ) -> Result<(), V::Err>
+    where
+        V: GcVisitor,
+    {
+        // Fine to stuff inside a pointer. We're a `Sized` type
+        visitor.trace_gc(gc)
+    }
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawPinImpl> TrustedDrop
+    for GcPinned<T, C>
+{
+}
+impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawPinImpl> Clone for GcPinned<T, C> {
+    #[inline]
+    fn clone(&self) -> Self {
+        GcPinned {
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+/// We support pinning
+unsafe impl<C> PinCollectorId for CollectorId<C>
+where
+    C: RawPinImpl,
+{
+    type Pinned<T: GcSafe<'static, Self> + ?Sized> = GcPinned<T, C>;
+
+    #[inline]
+    fn pin<'gc, T>(gc: Gc<'gc, T, CollectorId<C>>) -> Self::Pinned<T::Branded>
+    where
+        T: ?Sized + GcSafe<'gc, Self> + GcRebrand<'static, Self>,
+    {
+        unsafe {
+            let value =
+                crate::utils::transmute_mismatched::<*mut T, *mut T::Branded>(gc.as_raw_ptr());
+            GcPinned {
+                handle: GcHandle::alloc(
+                    gc.collector_id(),
+                    value,
+                    C::resolve_type_info(gc),
+                    HandleKind::Pinned,
+                ),
+            }
+        }
+    }
+
+    #[inline]
+    fn pin_array<'gc, T>(array: GcArray<'gc, T, Self>) -> Self::Pinned<[T::Branded]>
+    where
+        T: GcSafe<'gc, Self> + GcRebrand<'static, Self>,
+        T::Branded: Sized,
+    {
+        unsafe {
+            let slice = NonNull::from(array.as_slice());
+            let gc = Gc::<'gc, [T], Self>::from_raw(slice);
+            let value = ptr::slice_from_raw_parts_mut(
+                slice.as_ptr() as *mut T as *mut T::Branded,
+                array.len(),
+            );
+            GcPinned {
+                handle: GcHandle::alloc(
+                    array.collector_id(),
+                    value,
+                    C::resolve_type_info(gc),
+                    HandleKind::Pinned,
+                ),
+            }
+        }
+    }
+}
+
+/// A weak handle, which doesn't keep its value alive
+///
+/// Once the value has been collected,
+/// the handle is cleared (but remains allocated until it's dropped).
+pub struct GcWeakHandle<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawWeakHandleImpl> {
+    handle: GcHandle<T, C>,
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawWeakHandleImpl>
+    ::zerogc::GcWeakHandle<T> for GcWeakHandle<T, C>
+{
+    type System = CollectorRef<C>;
+    type Id = CollectorId<C>;
+
+    fn use_critical<R>(&self, func: impl FnOnce(&T) -> R) -> Option<R> {
+        self.handle.collector.ensure_valid(|collector| unsafe {
+            // See `GcHandle::use_critical`
+            C::Manager::prevent_collection(collector.as_ref(), || {
+                self.handle.load_value().map(|value| func(&*value))
+            })
+        })
+    }
+    #[inline]
+    fn bind_to<'new_gc>(
+        &self,
+        context: &'new_gc <Self::System as zerogc::GcSystem>::Context,
+    ) -> Option<Gc<'new_gc, <T as GcRebrand<'new_gc, Self::Id>>::Branded, Self::Id>>
+    where
+        T: GcRebrand<'new_gc, Self::Id>,
+    {
+        /*
+         * The context is active, so there can't be a collection in progress.
+         * If the value hasn't been cleared yet,
+         * it's kept alive by the context from now on (see `GcHandle::bind_to`).
+         */
+        unsafe {
+            let collector = self.handle.collector.assume_valid();
+            assert_eq!(
+                collector.as_ref() as *const C,
+                context.collector() as *const C,
+                "Collectors mismatch"
             );
-            raw.refcnt.store(1, Ordering::Release);
-            let weak_collector = collector.weak_ref();
-            let metadata = crate::utils::transmute_mismatched::<
-                <T as Pointee>::Metadata,
-                <T::Branded as Pointee>::Metadata,
-            >(ptr::metadata(value));
-            GcHandle::new(NonNull::from(raw), weak_collector, metadata)
+            let value = self.handle.load_value()?;
+            let value = crate::utils::transmute_mismatched::<*mut T, *mut T::Branded>(value);
+            debug_assert!(!value.is_null());
+            Some(Gc::from_raw(NonNull::new_unchecked(value)))
+        }
+    }
+    #[inline]
+    fn is_cleared(&self) -> bool {
+        self.handle
+            .collector
+            .ensure_valid(|_| unsafe { self.handle.load_value().is_none() })
+    }
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawWeakHandleImpl> Trace
+    for GcWeakHandle<T, C>
+{
+    /// Weak handles are cleared by the collector, not by tracing
+    const NEEDS_TRACE: bool = false;
+    const NEEDS_DROP: bool = true;
+    #[inline(always)]
+    fn trace<V>(&mut self, _visitor: &mut V) -> Result<(), V::Err>
+    where
+        V: zerogc::GcVisitor,
+    {
+        Ok(())
+    }
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawWeakHandleImpl> TraceImmutable
+    for GcWeakHandle<T, C>
+{
+    #[inline(always)]
+    fn trace_immutable<V>(&self, _visitor: &mut V) -> Result<(), V::Err>
+    where
+        V: GcVisitor,
+    {
+        Ok(())
+    }
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawWeakHandleImpl> NullTrace
+    for GcWeakHandle<T, C>
+{
+}
+unsafe impl<'gc, T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawWeakHandleImpl>
+    GcSafe<'gc, CollectorId<C>> for GcWeakHandle<T, C>
+{
+    #[inline]
+    unsafe fn trace_inside_gc<V>(
+        gc: &mut Gc<'gc, Self, CollectorId<C>>,
+        visitor: &mut V,
+    ) -> Result<(), V::Err>
+    where
+        V: GcVisitor,
+    {
+        // Fine to stuff inside a pointer. We're a `Sized` type
+        visitor.trace_gc(gc)
+    }
+}
+unsafe impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawWeakHandleImpl> TrustedDrop
+    for GcWeakHandle<T, C>
+{
+}
+impl<T: ?Sized + GcSafe<'static, CollectorId<C>>, C: RawWeakHandleImpl> Clone
+    for GcWeakHandle<T, C>
+{
+    #[inline]
+    fn clone(&self) -> Self {
+        GcWeakHandle {
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+/// We support weak handles
+unsafe impl<C> WeakHandleCollectorId for CollectorId<C>
+where
+    C: RawWeakHandleImpl,
+{
+    type WeakHandle<T: GcSafe<'static, Self> + ?Sized> = GcWeakHandle<T, C>;
+
+    #[inline]
+    fn create_weak_handle<'gc, T>(gc: Gc<'gc, T, CollectorId<C>>) -> Self::WeakHandle<T::Branded>
+    where
+        T: ?Sized + GcSafe<'gc, Self> + GcRebrand<'static, Self>,
+    {
+        unsafe {
+            let value =
+                crate::utils::transmute_mismatched::<*mut T, *mut T::Branded>(gc.as_raw_ptr());
+            GcWeakHandle {
+                handle: GcHandle::alloc(
+                    gc.collector_id(),
+                    value,
+                    C::resolve_type_info(gc),
+                    HandleKind::Weak,
+                ),
+            }
         }
     }
 }
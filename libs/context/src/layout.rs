@@ -0,0 +1,674 @@
+//! The in memory layout of objects,
+//! shared by the collectors that are built on top of this crate.
+//!
+//! All objects are allocated with a [GcHeader],
+//! that contains type information along with some collector-specific
+//! [state](LayoutCollector::HeaderState).
+//!
+//! Collectors decide what visiting an object does,
+//! through the hooks of the [LayoutCollector] trait.
+//! Everything else (finding headers, tracing arrays and vectors,
+//! updating moved references) is handled by the shared [LayoutVisitor].
+//!
+//! ## Safety
+//! Relying on this internal layout is incredibly unsafe.
+//! Unlike `zerogc-simple`, the layout isn't (yet) committed to a stable ABI.
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use alloc::vec::Vec;
+
+use zerogc::vec::raw::{GcRawVec, IGcVec};
+use zerogc::{GcSafe, GcSimpleAlloc, Trace};
+use zerogc_derive::unsafe_gc_impl;
+
+use crate::collector::RawSimpleAlloc;
+use crate::{CollectorContext, CollectorId};
+
+mod visitor;
+
+pub use self::visitor::{DynTrace, Ephemeron, HandleListRoot, LayoutVisitor, WeakRef};
+
+/// A collector whose objects use this layout
+///
+/// ## Safety
+/// The hooks must be well behaved.
+/// In particular, a collector that moves objects must return their new location,
+/// and every reference must be updated before the object's old memory is reused.
+pub unsafe trait LayoutCollector: RawSimpleAlloc {
+    /// The collector-specific state stored in every [GcHeader]
+    type HeaderState;
+    /// The collector-specific state of a [LayoutVisitor]
+    type VisitorState<'a>;
+
+    /// Visit a (strong) reference to the specified object,
+    /// returning the location the reference should be updated to.
+    ///
+    /// Collectors that don't move the object simply return its current header.
+    unsafe fn visit(
+        visitor: &mut LayoutVisitor<'_, Self>,
+        header: *mut GcHeader<Self>,
+    ) -> *mut GcHeader<Self>;
+    /// Visit a weak reference, whose referent hasn't been freed (yet)
+    unsafe fn visit_weak(visitor: &mut LayoutVisitor<'_, Self>, weak: WeakRef<Self>);
+    /// Visit an ephemeron, whose key hasn't been freed (yet)
+    unsafe fn visit_ephemeron(visitor: &mut LayoutVisitor<'_, Self>, ephemeron: Ephemeron<Self>);
+}
+
+/// Marker type for an unknown header
+pub struct UnknownHeader(());
+
+/// The layout of an object's header
+#[derive(Debug)]
+pub struct HeaderLayout<H> {
+    /// The overall size of the header
+    pub header_size: usize,
+    /// The offset of the 'common' header,
+    /// starting from the start of the real header
+    pub common_header_offset: usize,
+    /// The alignment of the header
+    pub header_align: usize,
+    marker: PhantomData<*mut H>,
+}
+impl<H> Copy for HeaderLayout<H> {}
+impl<H> Clone for HeaderLayout<H> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<H> HeaderLayout<H> {
+    /// The layout of the header type `H`,
+    /// whose common header is at the specified offset
+    #[inline]
+    pub const fn new(common_header_offset: usize) -> Self {
+        HeaderLayout {
+            header_size: core::mem::size_of::<H>(),
+            common_header_offset,
+            header_align: core::mem::align_of::<H>(),
+            marker: PhantomData,
+        }
+    }
+    #[inline]
+    pub const fn value_offset_from_common_header(&self, align: usize) -> usize {
+        self.value_offset(align) - self.common_header_offset
+    }
+    #[inline]
+    pub const fn into_unknown(self) -> HeaderLayout<UnknownHeader> {
+        HeaderLayout {
+            header_size: self.header_size,
+            common_header_offset: self.common_header_offset,
+            header_align: self.header_align,
+            marker: PhantomData,
+        }
+    }
+    #[inline]
+    pub const unsafe fn common_header<C: LayoutCollector>(self, ptr: *mut H) -> *mut GcHeader<C> {
+        (ptr as *mut u8).add(self.common_header_offset).cast()
+    }
+    #[inline]
+    #[allow(clippy::wrong_self_convention)]
+    pub const unsafe fn from_common_header<C: LayoutCollector>(
+        self,
+        ptr: *mut GcHeader<C>,
+    ) -> *mut H {
+        (ptr as *mut u8).sub(self.common_header_offset).cast()
+    }
+    /// Get the header from the specified value pointer
+    ///
+    /// ## Safety
+    /// Undefined behavior if the pointer doesn't point to a an object
+    /// allocated in this collector (ie. doesn't have the appropriate header).
+    #[inline]
+    pub const unsafe fn from_value_ptr<T: ?Sized>(self, ptr: *mut T) -> *mut H {
+        let align = core::mem::align_of_val(&*ptr);
+        (ptr as *mut u8).sub(self.value_offset(align)).cast()
+    }
+    /// Get the in-memory layout of the header (doesn't include the value)
+    #[inline]
+    pub const fn layout(&self) -> Layout {
+        unsafe { Layout::from_size_align_unchecked(self.header_size, self.header_align) }
+    }
+    /// Get the offset of the value from the start of the header,
+    /// given the alignment of its value
+    #[inline]
+    pub const fn value_offset(&self, align: usize) -> usize {
+        // Round the size of the header up to the alignment of the value
+        (self.header_size + align - 1) & !(align - 1)
+    }
+}
+
+/// A header for a GC object
+#[repr(C)]
+pub struct GcHeader<C: LayoutCollector> {
+    /// The type information
+    pub type_info: &'static GcType<C>,
+    /// A pointer to the id of the collector that allocated this object
+    collector_id_ptr: *const CollectorId<C>,
+    /// The collector-specific state of this object
+    pub state: C::HeaderState,
+}
+impl<C: LayoutCollector> GcHeader<C> {
+    /// The layout of the header
+    pub const LAYOUT: HeaderLayout<Self> = HeaderLayout::new(0);
+    /// Create a new header, with the specified initial state
+    #[inline]
+    pub fn new(
+        type_info: &'static GcType<C>,
+        collector_id_ptr: *const CollectorId<C>,
+        state: C::HeaderState,
+    ) -> Self {
+        GcHeader {
+            type_info,
+            collector_id_ptr,
+            state,
+        }
+    }
+    /// Get the collector id associated with this object.
+    #[inline]
+    pub fn collector_id(&self) -> &'_ CollectorId<C> {
+        unsafe { &*self.collector_id_ptr }
+    }
+    /// A pointer to the header's value
+    #[inline]
+    pub fn value(&self) -> *mut c_void {
+        unsafe {
+            (self as *const GcHeader<C> as *mut GcHeader<C> as *mut u8)
+                .add(self.type_info.value_offset_from_common_header)
+                .cast::<c_void>()
+        }
+    }
+    /// Get the [GcHeader] for the specified value, assuming that its been allocated by this collector.
+    ///
+    /// ## Safety
+    /// Assumes the value was allocated in the collector `C`.
+    #[inline]
+    pub unsafe fn from_value_ptr<T: ?Sized>(ptr: *mut T) -> *mut GcHeader<C> {
+        GcHeader::LAYOUT.from_value_ptr(ptr)
+    }
+    /// Get the [GcHeader] for the specified value, given its type information.
+    ///
+    /// This is how objects referenced by a handle are found.
+    ///
+    /// ## Safety
+    /// Assumes the value was allocated in the collector `C`, with the specified type.
+    #[inline]
+    pub unsafe fn from_erased_value_ptr(ptr: *mut (), type_info: &GcType<C>) -> *mut GcHeader<C> {
+        (ptr as *mut u8)
+            .sub(type_info.value_offset_from_common_header)
+            .cast()
+    }
+}
+
+/// The state of an object in a collector that moves its objects,
+/// or otherwise needs to remember a new location for them.
+pub struct Forwarding<C: LayoutCollector>(Cell<*mut GcHeader<C>>);
+impl<C: LayoutCollector> Forwarding<C> {
+    /// An object that hasn't been forwarded
+    #[inline]
+    pub const fn new() -> Self {
+        Forwarding(Cell::new(core::ptr::null_mut()))
+    }
+}
+impl<C: LayoutCollector> Default for Forwarding<C> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<C: LayoutCollector<HeaderState = Forwarding<C>>> GcHeader<C> {
+    /// The new location of this object,
+    /// or `None` if it hasn't been forwarded (yet)
+    #[inline]
+    pub fn forwarded(&self) -> Option<*mut GcHeader<C>> {
+        let forwarded = self.state.0.get();
+        if forwarded.is_null() {
+            None
+        } else {
+            Some(forwarded)
+        }
+    }
+    #[inline]
+    pub fn set_forwarded(&self, new_location: *mut GcHeader<C>) {
+        self.state.0.set(new_location);
+    }
+}
+
+/// A header for a GC array object
+#[repr(C)]
+pub struct GcArrayHeader<C: LayoutCollector> {
+    pub len: usize,
+    pub common_header: GcHeader<C>,
+}
+impl<C: LayoutCollector> GcArrayHeader<C> {
+    pub const LAYOUT: HeaderLayout<Self> = HeaderLayout::new(common_header_offset::<C>(1));
+}
+/// A header for a Gc vector
+#[repr(C)]
+pub struct GcVecHeader<C: LayoutCollector> {
+    pub capacity: usize,
+    /*
+     * NOTE: Suffix must be transmutable to `GcArrayHeader`
+     * in order for `steal_as_array_unchecked` to work
+     */
+    pub len: Cell<usize>,
+    pub common_header: GcHeader<C>,
+}
+impl<C: LayoutCollector> GcVecHeader<C> {
+    pub const LAYOUT: HeaderLayout<Self> = HeaderLayout::new(common_header_offset::<C>(2));
+}
+/// The offset of the common header,
+/// in a (`repr(C)`) header that starts with the specified number of `usize` fields.
+///
+/// NOTE: `field_offset!` can't be used with generic parameters
+const fn common_header_offset<C: LayoutCollector>(num_prefix_fields: usize) -> usize {
+    let align = core::mem::align_of::<GcHeader<C>>();
+    let prefix_size = num_prefix_fields * core::mem::size_of::<usize>();
+    (prefix_size + align - 1) & !(align - 1)
+}
+
+/// The raw representation of a vector
+///
+/// NOTE: Length and capacity are stored implicitly in the [GcVecHeader]
+#[repr(C)]
+pub struct VecRepr<'gc, T: Sized, C: LayoutCollector> {
+    marker: PhantomData<zerogc::Gc<'gc, [T], CollectorId<C>>>,
+    header: NonNull<GcVecHeader<C>>,
+    ctx: &'gc CollectorContext<C>,
+}
+impl<'gc, T, C: LayoutCollector> VecRepr<'gc, T, C> {
+    #[inline]
+    pub unsafe fn from_raw_parts(
+        header: NonNull<GcVecHeader<C>>,
+        ctx: &'gc CollectorContext<C>,
+    ) -> Self {
+        VecRepr {
+            header,
+            ctx,
+            marker: PhantomData,
+        }
+    }
+    #[inline]
+    pub fn header(&self) -> *const GcVecHeader<C> {
+        self.header.as_ptr() as *const _
+    }
+    /// Point this vector at the new location of its header
+    #[inline]
+    pub unsafe fn set_header(&mut self, header: NonNull<GcVecHeader<C>>) {
+        self.header = header;
+    }
+}
+impl<'gc, T: GcSafe<'gc, CollectorId<C>>, C: LayoutCollector> Copy for VecRepr<'gc, T, C> {}
+impl<'gc, T: GcSafe<'gc, CollectorId<C>>, C: LayoutCollector> Clone for VecRepr<'gc, T, C> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<'gc, T, C> Extend<T> for VecRepr<'gc, T, C>
+where
+    T: GcSafe<'gc, CollectorId<C>>,
+    C: LayoutCollector<RawVec<'gc, T> = Self>,
+{
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().1.unwrap_or(0));
+        for val in iter {
+            self.push(val);
+        }
+    }
+}
+#[inherent::inherent]
+unsafe impl<'gc, T, C> GcRawVec<'gc, T> for VecRepr<'gc, T, C>
+where
+    T: GcSafe<'gc, CollectorId<C>>,
+    C: LayoutCollector<RawVec<'gc, T> = Self>,
+{
+    #[allow(dead_code)]
+    unsafe fn steal_as_array_unchecked(mut self) -> zerogc::GcArray<'gc, T, CollectorId<C>> {
+        /*
+         * Invalidate capacity
+         * NOTE: This should never be relied upon.
+         * It is already undefined behavior to use this vector
+         * after calling this method.
+         * This is just an extra check
+         */
+        self.header.as_mut().capacity = 0;
+        zerogc::GcArray::from_raw_ptr(NonNull::new_unchecked(self.as_mut_ptr()), self.len())
+    }
+    pub fn iter(&self) -> zerogc::vec::raw::RawVecIter<'gc, T, Self>
+    where
+        T: Copy;
+}
+#[inherent::inherent]
+unsafe impl<'gc, T, C> IGcVec<'gc, T> for VecRepr<'gc, T, C>
+where
+    T: GcSafe<'gc, CollectorId<C>>,
+    C: LayoutCollector<RawVec<'gc, T> = Self>,
+{
+    type Id = CollectorId<C>;
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        unsafe { (*self.header()).len.get() }
+    }
+
+    #[inline]
+    pub unsafe fn set_len(&mut self, len: usize) {
+        debug_assert!(len <= self.capacity());
+        (*self.header()).len.set(len);
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        unsafe { (*self.header()).capacity }
+    }
+
+    #[inline]
+    pub fn with_capacity_in(capacity: usize, ctx: &'gc CollectorContext<C>) -> Self {
+        ctx.alloc_raw_vec_with_capacity::<T>(capacity)
+    }
+
+    #[inline]
+    pub fn reserve_in_place(
+        &mut self,
+        _additional: usize,
+    ) -> Result<(), zerogc::vec::raw::ReallocFailedError> {
+        Err(zerogc::vec::raw::ReallocFailedError::Unsupported)
+    }
+
+    #[inline]
+    pub unsafe fn as_ptr(&self) -> *const T {
+        (self.header.as_ptr() as *mut u8)
+            .add(GcVecHeader::<C>::LAYOUT.value_offset(core::mem::align_of::<T>()))
+            .cast()
+    }
+
+    #[inline]
+    pub fn context(&self) -> &'gc CollectorContext<C> {
+        self.ctx
+    }
+
+    // Default methods:
+    pub unsafe fn as_mut_ptr(&mut self) -> *mut T;
+    pub fn replace(&mut self, index: usize, val: T) -> T;
+    pub fn set(&mut self, index: usize, val: T);
+    pub fn extend_from_slice(&mut self, src: &[T])
+    where
+        T: Copy;
+    pub fn push(&mut self, val: T);
+    pub fn pop(&mut self) -> Option<T>;
+    pub fn swap_remove(&mut self, index: usize) -> T;
+    pub fn reserve(&mut self, additional: usize);
+    pub fn is_empty(&self) -> bool;
+    pub fn new_in(ctx: &'gc CollectorContext<C>) -> Self;
+    pub fn copy_from_slice(src: &[T], ctx: &'gc CollectorContext<C>) -> Self
+    where
+        T: Copy;
+    pub fn from_vec(src: Vec<T>, ctx: &'gc CollectorContext<C>) -> Self;
+    pub fn get(&mut self, index: usize) -> Option<T>
+    where
+        T: Copy;
+    pub unsafe fn as_slice_unchecked(&self) -> &[T];
+}
+unsafe_gc_impl!(
+    target => VecRepr<'gc, T, C>,
+    params => ['gc, T: GcSafe<'gc, CollectorId<C>>, C: LayoutCollector],
+    bounds => {
+        Trace => { where T: zerogc::Trace },
+        TraceImmutable => { where T: zerogc::TraceImmutable },
+        GcSafe => { where T: zerogc::GcSafe<'gc, CollectorId<C>> },
+        TrustedDrop => { where T: zerogc::TrustedDrop },
+        GcRebrand => { where T: zerogc::GcRebrand<'new_gc, CollectorId<C>>,
+            T::Branded: Sized + zerogc::GcSafe<'new_gc, CollectorId<C>> },
+    },
+    NEEDS_TRACE => T::NEEDS_TRACE,
+    NEEDS_DROP => T::NEEDS_DROP,
+    null_trace => { where T: ::zerogc::NullTrace },
+    trace_mut => |self, visitor| {
+        // Trace our innards
+        unsafe {
+            let start: *mut T = self.header.as_ptr().cast::<u8>()
+                .add(GcVecHeader::<C>::LAYOUT.value_offset(core::mem::align_of::<T>()))
+                .cast();
+            for i in 0..(*self.header()).len.get() {
+                visitor.trace(&mut *start.add(i))?;
+            }
+        }
+        Ok(())
+    },
+    trace_immutable => |self, visitor| {
+        // Trace our innards
+        unsafe {
+            let start: *mut T = self.header.as_ptr().cast::<u8>()
+                .add(GcVecHeader::<C>::LAYOUT.value_offset(core::mem::align_of::<T>()))
+                .cast();
+            for i in 0..(*self.header()).len.get() {
+                visitor.trace_immutable(&*start.add(i))?;
+            }
+        }
+        Ok(())
+    },
+    branded_type => VecRepr<'new_gc, T::Branded, C>,
+    collector_id => CollectorId<C>
+);
+
+/// Layout information on a [GcType]
+pub enum GcTypeLayout {
+    /// A type with a fixed, statically-known layout
+    Fixed(Layout),
+    /// An array, whose size can vary at runtime
+    Array {
+        /// The fixed layout of elements in the array
+        ///
+        /// The overall alignment of the array is equal to the alignment of each element,
+        /// however the size may vary at runtime.
+        element_layout: Layout,
+    },
+    /// A vector, whose capacity can vary from instance to instance
+    Vec {
+        /// The fixed layout of elements in the vector.
+        element_layout: Layout,
+    },
+}
+
+/// A type used by GC
+#[repr(C)]
+pub struct GcType<C: LayoutCollector> {
+    /// Information on the type's layout
+    pub layout: GcTypeLayout,
+    /// The offset of the value from the start of the header
+    ///
+    /// This varies depending on the type's alignment
+    pub value_offset_from_common_header: usize,
+    /// The function to trace the type, or `None` if it doesn't need to be traced
+    pub trace_func: Option<unsafe fn(*mut c_void, &mut LayoutVisitor<'_, C>)>,
+    /// The function to drop the type, or `None` if it doesn't need to be dropped
+    pub drop_func: Option<unsafe fn(*mut c_void)>,
+    /// The name of the type, as given by [core::any::type_name]
+    ///
+    /// This is only used for debugging.
+    pub type_name: &'static str,
+}
+impl<C: LayoutCollector> GcType<C> {
+    #[inline]
+    fn align(&self) -> usize {
+        match self.layout {
+            GcTypeLayout::Fixed(fixed) => fixed.align(),
+            GcTypeLayout::Array { element_layout } | GcTypeLayout::Vec { element_layout } => {
+                element_layout.align()
+            }
+        }
+    }
+    pub fn header_layout(&self) -> HeaderLayout<UnknownHeader> {
+        match self.layout {
+            GcTypeLayout::Fixed(_) => GcHeader::<C>::LAYOUT.into_unknown(),
+            GcTypeLayout::Array { .. } => GcArrayHeader::<C>::LAYOUT.into_unknown(),
+            GcTypeLayout::Vec { .. } => GcVecHeader::<C>::LAYOUT.into_unknown(),
+        }
+    }
+    #[inline]
+    unsafe fn determine_size(&self, header: *mut GcHeader<C>) -> usize {
+        match self.layout {
+            GcTypeLayout::Fixed(layout) => layout.size(),
+            GcTypeLayout::Array { element_layout } => {
+                let header = GcArrayHeader::<C>::LAYOUT.from_common_header(header);
+                element_layout.repeat((*header).len).unwrap().0.size()
+            }
+            GcTypeLayout::Vec { element_layout } => {
+                let header = GcVecHeader::<C>::LAYOUT.from_common_header(header);
+                element_layout.repeat((*header).capacity).unwrap().0.size()
+            }
+        }
+    }
+    #[inline]
+    pub unsafe fn determine_total_layout(&self, header: *mut GcHeader<C>) -> Layout {
+        self.header_layout()
+            .layout()
+            .extend(Layout::from_size_align_unchecked(
+                self.determine_size(header),
+                self.align(),
+            ))
+            .unwrap()
+            .0
+            .pad_to_align()
+    }
+    #[inline]
+    pub unsafe fn determine_total_size(&self, header: *mut GcHeader<C>) -> usize {
+        self.determine_total_layout(header).size()
+    }
+    /// Get the [GcType] for the specified `Sized` type
+    #[inline]
+    pub const fn for_regular<'gc, T: GcSafe<'gc, CollectorId<C>>>() -> &'static Self {
+        <T as StaticGcType<C>>::STATIC_TYPE
+    }
+}
+
+/// The [GcType] of a vector whose elements are `Self`
+pub trait StaticVecType<C: LayoutCollector> {
+    const STATIC_VEC_TYPE: &'static GcType<C>;
+}
+impl<'gc, T: GcSafe<'gc, CollectorId<C>>, C: LayoutCollector> StaticVecType<C> for T {
+    const STATIC_VEC_TYPE: &'static GcType<C> = &GcType {
+        layout: GcTypeLayout::Vec {
+            element_layout: Layout::new::<T>(),
+        },
+        value_offset_from_common_header: {
+            GcVecHeader::<C>::LAYOUT.value_offset_from_common_header(core::mem::align_of::<T>())
+        },
+        trace_func: if <T as Trace>::NEEDS_TRACE {
+            Some({
+                unsafe fn visit<T: Trace, C: LayoutCollector>(
+                    val: *mut c_void,
+                    visitor: &mut LayoutVisitor<'_, C>,
+                ) {
+                    let len = (*GcVecHeader::<C>::LAYOUT.from_value_ptr(val as *mut T))
+                        .len
+                        .get();
+                    let slice = core::slice::from_raw_parts_mut(val as *mut T, len);
+                    let Ok(()) = <[T] as Trace>::trace(slice, visitor);
+                }
+                visit::<T, C> as unsafe fn(*mut c_void, &mut LayoutVisitor<'_, C>)
+            })
+        } else {
+            None
+        },
+        drop_func: if T::NEEDS_DROP {
+            Some({
+                unsafe fn drop_gc_vec<T, C: LayoutCollector>(val: *mut c_void) {
+                    let len = (*GcVecHeader::<C>::LAYOUT.from_value_ptr(val as *mut T))
+                        .len
+                        .get();
+                    core::ptr::drop_in_place::<[T]>(core::ptr::slice_from_raw_parts_mut(
+                        val as *mut T,
+                        len,
+                    ));
+                }
+                drop_gc_vec::<T, C> as unsafe fn(*mut c_void)
+            })
+        } else {
+            None
+        },
+        type_name: core::any::type_name::<[T]>(),
+    };
+}
+/// The [GcType] of `Self`
+pub trait StaticGcType<C: LayoutCollector> {
+    const STATIC_TYPE: &'static GcType<C>;
+}
+impl<'gc, T: GcSafe<'gc, CollectorId<C>>, C: LayoutCollector> StaticGcType<C> for [T] {
+    const STATIC_TYPE: &'static GcType<C> = &GcType {
+        layout: GcTypeLayout::Array {
+            element_layout: Layout::new::<T>(),
+        },
+        value_offset_from_common_header: {
+            GcArrayHeader::<C>::LAYOUT.value_offset_from_common_header(core::mem::align_of::<T>())
+        },
+        trace_func: if <T as Trace>::NEEDS_TRACE {
+            Some({
+                unsafe fn visit<T: Trace, C: LayoutCollector>(
+                    val: *mut c_void,
+                    visitor: &mut LayoutVisitor<'_, C>,
+                ) {
+                    let header = GcArrayHeader::<C>::LAYOUT.from_value_ptr(val as *mut T);
+                    let len = (*header).len;
+                    let slice = core::slice::from_raw_parts_mut(val as *mut T, len);
+                    let Ok(()) = <[T] as Trace>::trace(slice, visitor);
+                }
+                visit::<T, C> as unsafe fn(*mut c_void, &mut LayoutVisitor<'_, C>)
+            })
+        } else {
+            None
+        },
+        drop_func: if <T as Trace>::NEEDS_DROP {
+            Some({
+                unsafe fn drop_gc_slice<T, C: LayoutCollector>(val: *mut c_void) {
+                    let len = (*GcArrayHeader::<C>::LAYOUT.from_value_ptr(val as *mut T)).len;
+                    core::ptr::drop_in_place::<[T]>(core::ptr::slice_from_raw_parts_mut(
+                        val as *mut T,
+                        len,
+                    ));
+                }
+                drop_gc_slice::<T, C> as unsafe fn(*mut c_void)
+            })
+        } else {
+            None
+        },
+        type_name: core::any::type_name::<[T]>(),
+    };
+}
+impl<'gc, T: GcSafe<'gc, CollectorId<C>>, C: LayoutCollector> StaticGcType<C> for T {
+    const STATIC_TYPE: &'static GcType<C> = &GcType {
+        layout: GcTypeLayout::Fixed(Layout::new::<T>()),
+        value_offset_from_common_header: {
+            GcHeader::<C>::LAYOUT.value_offset_from_common_header(core::mem::align_of::<T>())
+        },
+        trace_func: if <T as Trace>::NEEDS_TRACE {
+            Some({
+                unsafe fn visit<T: Trace, C: LayoutCollector>(
+                    val: *mut c_void,
+                    visitor: &mut LayoutVisitor<'_, C>,
+                ) {
+                    let Ok(()) = <T as Trace>::trace(&mut *(val as *mut T), visitor);
+                }
+                visit::<T, C> as unsafe fn(*mut c_void, &mut LayoutVisitor<'_, C>)
+            })
+        } else {
+            None
+        },
+        drop_func: if <T as Trace>::NEEDS_DROP {
+            unsafe {
+                Some(core::mem::transmute::<unsafe fn(*mut T), unsafe fn(*mut c_void)>(
+                    core::ptr::drop_in_place::<T> as unsafe fn(*mut T),
+                ))
+            }
+        } else {
+            None
+        },
+        type_name: core::any::type_name::<T>(),
+    };
+}
@@ -0,0 +1,371 @@
+//! The visitor shared by all collectors using this layout
+use core::any::TypeId;
+use core::ffi::c_void;
+use core::ptr::{DynMetadata, NonNull, Pointee};
+
+use zerogc::vec::raw::GcRawVec;
+use zerogc::{Gc, GcSafe, GcVisitor, GcWeak, Trace};
+
+use crate::handle::{GcHandleList, RawHandleImpl};
+use crate::CollectorId;
+
+use super::{GcArrayHeader, GcHeader, GcType, GcVecHeader, LayoutCollector, VecRepr};
+
+/// A dynamically dispatched root of a collection
+#[doc(hidden)] // NOTE: Needs be public for RawCollectorImpl
+pub unsafe trait DynTrace<C: LayoutCollector> {
+    fn trace(&mut self, visitor: &mut LayoutVisitor<'_, C>);
+}
+unsafe impl<T: Trace + ?Sized, C: LayoutCollector> DynTrace<C> for T {
+    fn trace(&mut self, visitor: &mut LayoutVisitor<'_, C>) {
+        let Ok(()) = self.trace(visitor);
+    }
+}
+
+/// A wrapper for [GcHandleList] that implements [DynTrace]
+///
+/// Every handle is visited like any other reference,
+/// and updated if its object has moved.
+#[repr(transparent)]
+pub struct HandleListRoot<C: RawHandleImpl>(GcHandleList<C>);
+impl<C> HandleListRoot<C>
+where
+    C: LayoutCollector + RawHandleImpl<TypeInfo = GcType<C>>,
+{
+    /// Treat the specified handle list as a root of a collection
+    #[inline]
+    pub fn as_root(list: &GcHandleList<C>) -> *mut dyn DynTrace<C> {
+        list as *const GcHandleList<C> as *const HandleListRoot<C> as *const dyn DynTrace<C>
+            as *mut dyn DynTrace<C>
+    }
+}
+unsafe impl<C> DynTrace<C> for HandleListRoot<C>
+where
+    C: LayoutCollector + RawHandleImpl<TypeInfo = GcType<C>>,
+{
+    fn trace(&mut self, visitor: &mut LayoutVisitor<'_, C>) {
+        unsafe {
+            let Ok(()) = self.0.relocate::<_, !>(|raw_ptr, type_info| {
+                let header = GcHeader::from_erased_value_ptr(raw_ptr, type_info);
+                let new_header = C::visit(visitor, header);
+                Ok(relocated_value(header, new_header) as *mut ())
+            });
+        }
+    }
+}
+
+/// The value of an object once it's been moved to the specified location.
+///
+/// The object may not have actually moved yet,
+/// so the type information is read from its current location.
+#[inline]
+unsafe fn relocated_value<C: LayoutCollector>(
+    header: *mut GcHeader<C>,
+    new_header: *mut GcHeader<C>,
+) -> *mut c_void {
+    (new_header as *mut u8)
+        .add((*header).type_info.value_offset_from_common_header)
+        .cast()
+}
+
+/// A weak reference that was encountered by a [LayoutVisitor]
+///
+/// Once the collector knows whether its referent is reachable,
+/// the reference is either updated or cleared.
+pub struct WeakRef<C: LayoutCollector> {
+    /// The header of the referenced object (at its original location)
+    pub referent: *mut GcHeader<C>,
+    /// A pointer to the [GcWeak] itself
+    slot: *mut (),
+    /// Points the weak reference in the slot at the specified value,
+    /// or clears it if that's null.
+    update_func: unsafe fn(*mut (), *mut c_void),
+}
+impl<C: LayoutCollector> WeakRef<C> {
+    /// A weak reference to the specified object, stored in the specified slot
+    #[inline]
+    pub unsafe fn new<'gc, T: ?Sized>(
+        referent: *mut GcHeader<C>,
+        slot: &mut GcWeak<'gc, T, CollectorId<C>>,
+    ) -> Self {
+        WeakRef {
+            referent,
+            slot: slot as *mut GcWeak<'gc, T, CollectorId<C>> as *mut (),
+            update_func: Self::update_slot::<T>,
+        }
+    }
+    /// Clear the weak reference, because its referent is unreachable
+    #[inline]
+    pub unsafe fn clear(&self) {
+        (self.update_func)(self.slot, core::ptr::null_mut())
+    }
+    /// Point the weak reference at the new location of its referent,
+    /// or clear it if the referent is unreachable (`None`).
+    ///
+    /// The referent's type information must still be valid at its original location.
+    #[inline]
+    pub unsafe fn update(&self, new_location: Option<*mut GcHeader<C>>) {
+        match new_location {
+            Some(new_header) => {
+                (self.update_func)(self.slot, relocated_value(self.referent, new_header))
+            }
+            None => self.clear(),
+        }
+    }
+    unsafe fn update_slot<T: ?Sized>(slot: *mut (), new_value: *mut c_void) {
+        let weak = &mut *(slot as *mut GcWeak<'static, T, CollectorId<C>>);
+        match (NonNull::new(new_value), weak.as_raw_ptr()) {
+            (Some(new_value), Some(old_ptr)) => {
+                let new_ptr = core::ptr::from_raw_parts_mut::<T>(
+                    new_value.as_ptr() as *mut (),
+                    core::ptr::metadata(old_ptr.as_ptr()),
+                );
+                /*
+                 * NOTE: Must transmute instead of using Gc::from_raw
+                 * because we don't satisfy the 'GcSafe' bound.
+                 */
+                *weak = GcWeak::new(core::mem::transmute::<
+                    NonNull<T>,
+                    Gc<'static, T, CollectorId<C>>,
+                >(NonNull::new_unchecked(new_ptr)));
+            }
+            _ => weak.clear(),
+        }
+    }
+}
+
+/// An ephemeron that was encountered by a [LayoutVisitor]
+///
+/// The value should only be traced once the key is known to be reachable.
+/// If that never happens, the key is cleared instead.
+pub struct Ephemeron<C: LayoutCollector> {
+    /// The weak reference to the key
+    pub key: WeakRef<C>,
+    /// A pointer to the value
+    value: *mut (),
+    /// Traces the value (once the key is known to be reachable)
+    trace_value: unsafe fn(*mut (), &mut LayoutVisitor<'_, C>),
+}
+impl<C: LayoutCollector> Ephemeron<C> {
+    /// An ephemeron with the specified key and value
+    #[inline]
+    pub unsafe fn new<V: Trace>(key: WeakRef<C>, value: &mut V) -> Self {
+        Ephemeron {
+            key,
+            value: value as *mut V as *mut (),
+            trace_value: Self::trace_erased::<V>,
+        }
+    }
+    /// Trace the value, now that the key is known to be reachable
+    #[inline]
+    pub unsafe fn trace_value(&self, visitor: &mut LayoutVisitor<'_, C>) {
+        (self.trace_value)(self.value, visitor)
+    }
+    unsafe fn trace_erased<V: Trace>(value: *mut (), visitor: &mut LayoutVisitor<'_, C>) {
+        let Ok(()) = (*(value as *mut V)).trace(visitor);
+    }
+}
+
+/// The visitor for objects using the [shared layout](super)
+///
+/// This finds the header of every reference it visits,
+/// then defers to the collector's [hooks](LayoutCollector) to decide what to do with it.
+/// References to objects that have moved are updated automatically.
+#[doc(hidden)] // NOTE: Needs be public for RawCollectorImpl
+pub struct LayoutVisitor<'a, C: LayoutCollector> {
+    expected_collector: CollectorId<C>,
+    /// The collector-specific state of this visitor
+    pub state: C::VisitorState<'a>,
+}
+impl<'a, C: LayoutCollector> LayoutVisitor<'a, C> {
+    /// Create a visitor for the specified collector
+    #[inline]
+    pub fn new(expected_collector: CollectorId<C>, state: C::VisitorState<'a>) -> Self {
+        LayoutVisitor {
+            expected_collector,
+            state,
+        }
+    }
+    /// Trace the contents of the specified object,
+    /// using its dynamic type information.
+    #[inline]
+    pub unsafe fn trace_contents(&mut self, header: *mut GcHeader<C>) {
+        if let Some(func) = (*header).type_info.trace_func {
+            func((*header).value(), self);
+        }
+    }
+    /// Visit the specified object, returning its new location
+    #[inline]
+    unsafe fn visit_header(&mut self, header: *mut GcHeader<C>) -> *mut GcHeader<C> {
+        /*
+         * Check the collectors match. Otherwise we're mutating
+         * other people's data.
+         */
+        assert_eq!(*(*header).collector_id(), self.expected_collector);
+        C::visit(self, header)
+    }
+}
+unsafe impl<C: LayoutCollector> GcVisitor for LayoutVisitor<'_, C> {
+    type Err = !;
+
+    #[inline]
+    unsafe fn trace_gc<'gc, T, Id>(&mut self, gc: &mut Gc<'gc, T, Id>) -> Result<(), Self::Err>
+    where
+        T: GcSafe<'gc, Id>,
+        Id: zerogc::CollectorId,
+    {
+        if TypeId::of::<Id>() == TypeId::of::<CollectorId<C>>() {
+            let header = GcHeader::<C>::from_value_ptr(gc.as_raw_ptr());
+            let new_header = self.visit_header(header);
+            if new_header != header {
+                let new_value = relocated_value(header, new_header);
+                *gc = Gc::from_raw(NonNull::new_unchecked(new_value as *mut T));
+            }
+            Ok(())
+        } else {
+            // Just ignore
+            Ok(())
+        }
+    }
+
+    unsafe fn trace_weak<'gc, T, Id>(
+        &mut self,
+        weak: &mut GcWeak<'gc, T, Id>,
+    ) -> Result<(), Self::Err>
+    where
+        T: ?Sized + GcSafe<'gc, Id>,
+        Id: zerogc::CollectorId,
+    {
+        if TypeId::of::<Id>() == TypeId::of::<CollectorId<C>>() {
+            /*
+             * Since the `TypeId`s match, we know the generic `Id`
+             * matches our own `CollectorId<C>`.
+             * Therefore its safe to specific the generic `Id` into the
+             * `GcWeak` into its more specific type.
+             */
+            let weak = core::mem::transmute::<
+                &mut GcWeak<'gc, T, Id>,
+                &mut GcWeak<'gc, T, CollectorId<C>>,
+            >(weak);
+            if let Some(gc) = weak.upgrade() {
+                assert_eq!(*gc.collector_id(), self.expected_collector);
+                let referent = GcHeader::from_value_ptr(gc.as_raw_ptr());
+                C::visit_weak(self, WeakRef::new(referent, weak));
+            }
+            Ok(())
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn trace_ephemeron<'gc, K, V, Id>(
+        &mut self,
+        key: &mut GcWeak<'gc, K, Id>,
+        value: &mut V,
+    ) -> Result<(), Self::Err>
+    where
+        K: ?Sized + GcSafe<'gc, Id>,
+        V: Trace,
+        Id: zerogc::CollectorId,
+    {
+        if TypeId::of::<Id>() == TypeId::of::<CollectorId<C>>() {
+            /*
+             * The TypeIds match, so this cast is safe. See `trace_weak` for details
+             */
+            let key = core::mem::transmute::<
+                &mut GcWeak<'gc, K, Id>,
+                &mut GcWeak<'gc, K, CollectorId<C>>,
+            >(key);
+            let gc = match key.upgrade() {
+                Some(gc) => gc,
+                None => {
+                    // The key is already dead, so the value must never be touched
+                    return Ok(());
+                }
+            };
+            assert_eq!(*gc.collector_id(), self.expected_collector);
+            let key = WeakRef::new(GcHeader::from_value_ptr(gc.as_raw_ptr()), key);
+            C::visit_ephemeron(self, Ephemeron::new(key, value));
+            Ok(())
+        } else {
+            /*
+             * We can't tell whether another collector's key is reachable,
+             * so conservatively treat the value as strongly reachable.
+             */
+            value.trace(self)
+        }
+    }
+
+    unsafe fn trace_trait_object<'gc, T, Id>(
+        &mut self,
+        gc: &mut Gc<'gc, T, Id>,
+    ) -> Result<(), Self::Err>
+    where
+        T: ?Sized + GcSafe<'gc, Id> + Pointee<Metadata = DynMetadata<T>> + zerogc::DynTrace<'gc, Id>,
+        Id: zerogc::CollectorId,
+    {
+        if TypeId::of::<Id>() == TypeId::of::<CollectorId<C>>() {
+            let header = GcHeader::<C>::from_value_ptr(gc.as_raw_ptr());
+            let new_header = self.visit_header(header);
+            if new_header != header {
+                // Preserve the vtable
+                let new_ptr = core::ptr::from_raw_parts_mut::<T>(
+                    relocated_value(header, new_header) as *mut (),
+                    core::ptr::metadata(gc.as_raw_ptr()),
+                );
+                *gc = Gc::from_raw(NonNull::new_unchecked(new_ptr));
+            }
+            Ok(())
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    unsafe fn trace_vec<'gc, T, V>(&mut self, raw: &mut V) -> Result<(), Self::Err>
+    where
+        T: GcSafe<'gc, V::Id>,
+        V: GcRawVec<'gc, T>,
+    {
+        if TypeId::of::<V::Id>() == TypeId::of::<CollectorId<C>>() {
+            let raw = &mut *(raw as *mut V as *mut VecRepr<'gc, T, C>);
+            let header = raw.header() as *mut GcVecHeader<C>;
+            let common_header: *mut GcHeader<C> = &mut (*header).common_header;
+            let new_header = self.visit_header(common_header);
+            if new_header != common_header {
+                raw.set_header(NonNull::new_unchecked(
+                    GcVecHeader::LAYOUT.from_common_header(new_header),
+                ));
+            }
+            Ok(())
+        } else {
+            unreachable!("Can't trace {}", core::any::type_name::<V::Id>());
+        }
+    }
+
+    #[inline]
+    unsafe fn trace_array<'gc, T, Id>(
+        &mut self,
+        array: &mut zerogc::array::GcArray<'gc, T, Id>,
+    ) -> Result<(), Self::Err>
+    where
+        T: GcSafe<'gc, Id>,
+        Id: zerogc::CollectorId,
+    {
+        if TypeId::of::<Id>() == TypeId::of::<CollectorId<C>>() {
+            let header = GcArrayHeader::<C>::LAYOUT.from_value_ptr(array.as_raw_ptr());
+            let len = (*header).len;
+            let common_header: *mut GcHeader<C> = &mut (*header).common_header;
+            let new_header = self.visit_header(common_header);
+            if new_header != common_header {
+                *array = zerogc::array::GcArray::from_raw_ptr(
+                    NonNull::new_unchecked(relocated_value(common_header, new_header) as *mut T),
+                    len,
+                );
+            }
+            Ok(())
+        } else {
+            Ok(())
+        }
+    }
+}
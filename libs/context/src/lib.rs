@@ -1,6 +1,15 @@
 #![feature(
     negative_impls, // !Send is much cleaner than `PhantomData<Rc>`
-    ptr_metadata
+    ptr_metadata,
+    // Used by the shared object layout:
+    alloc_layout_extra, // Used for GcType::determine_size
+    never_type, // Used for errors (which are currently impossible)
+    exhaustive_patterns, // Allow exhaustive matching against never
+    const_alloc_layout, // Used for StaticType
+    const_type_name, // Used for GcType::type_name
+    const_mut_refs,
+    const_align_of_val,
+    const_refs_to_cell, // Needed for field_offset!
 )]
 #![allow(
     clippy::missing_safety_doc, // Entirely internal code
@@ -35,9 +44,15 @@ pub mod state;
 pub mod utils;
 pub mod collector;
 pub mod handle;
+pub mod layout;
+pub mod threshold;
+#[cfg(feature = "sync")]
+pub mod async_context;
 
 use crate::collector::RawCollectorImpl;
 
+#[cfg(feature = "sync")]
+pub use crate::async_context::AsyncContext;
 pub use crate::collector::{CollectorId, CollectorRef, WeakCollectorRef};
 pub use crate::state::{CollectionManager, RawContext};
 
@@ -131,6 +146,17 @@ impl<C: RawCollectorImpl> CollectorContext<C> {
     pub fn collector(&self) -> &C {
         unsafe { (*self.raw).collector() }
     }
+    /// The cache used to speed up this context's allocations
+    ///
+    /// See [RawCollectorImpl::ContextCache]
+    #[inline]
+    pub fn cache(&self) -> &C::ContextCache {
+        unsafe { (*self.raw).cache() }
+    }
+    #[inline]
+    unsafe fn flush_cache(&self) {
+        self.collector().flush_context_cache((*self.raw).cache());
+    }
     #[inline(always)]
     unsafe fn with_shadow_stack<R, T: Trace>(
         &self,
@@ -160,6 +186,7 @@ impl<C: RawCollectorImpl> Drop for CollectorContext<C> {
     fn drop(&mut self) {
         if self.root {
             unsafe {
+                self.flush_cache();
                 C::Manager::free_context(self.collector(), self.raw);
             }
         }
@@ -173,12 +200,23 @@ unsafe impl<C: RawCollectorImpl> GcContext for CollectorContext<C> {
     unsafe fn unchecked_safepoint<T: Trace>(&self, value: &mut &mut T) {
         debug_assert_eq!((*self.raw).state(), ContextState::Active);
         if (*self.raw).collector().should_collect() {
+            self.flush_cache();
             self.trigger_basic_safepoint(value);
+        } else {
+            self.collector().publish_context_cache((*self.raw).cache());
         }
         debug_assert_eq!((*self.raw).state(), ContextState::Active);
     }
 
+    unsafe fn unchecked_force_safepoint<T: Trace>(&self, value: &mut &mut T) {
+        debug_assert_eq!((*self.raw).state(), ContextState::Active);
+        self.flush_cache();
+        self.trigger_basic_safepoint(value);
+        debug_assert_eq!((*self.raw).state(), ContextState::Active);
+    }
+
     unsafe fn freeze(&mut self) {
+        self.flush_cache();
         (*self.raw).collector().manager().freeze_context(&*self.raw);
     }
 
@@ -5,6 +5,7 @@ use core::fmt::Debug;
 use core::mem::ManuallyDrop;
 
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 
 pub mod nosync;
 /// The internal state of the collector
@@ -49,6 +50,58 @@ where
     /// - Assumes the specified pointer is valid
     /// - Assumes there are no more outstanding borrows to values in the context
     unsafe fn free_context(collector: &C, context: *mut Self::Context);
+
+    /// Register a hook to be invoked before every collection
+    fn on_collection_start(&self, hook: CollectionHook<C>);
+    /// Register a hook to be invoked after every collection
+    fn on_collection_end(&self, hook: CollectionHook<C>);
+}
+
+/// A callback that is invoked around a collection,
+/// given the id of the collection and a snapshot of the collector's statistics.
+///
+/// Hooks run while the world is stopped,
+/// so they must not allocate or trigger safepoints.
+/// Registering another hook from inside a hook
+/// will deadlock (or panic).
+pub type CollectionHook<C> = Box<dyn Fn(u64, &<C as RawCollectorImpl>::Stats) + Send + Sync>;
+
+/// The hooks that have been registered with a [CollectionManager]
+pub(crate) struct CollectionHooks<C: RawCollectorImpl> {
+    pub(crate) start: Vec<CollectionHook<C>>,
+    pub(crate) end: Vec<CollectionHook<C>>,
+}
+impl<C: RawCollectorImpl> CollectionHooks<C> {
+    pub(crate) const fn new() -> Self {
+        CollectionHooks {
+            start: Vec::new(),
+            end: Vec::new(),
+        }
+    }
+    /// Perform a collection, invoking the registered hooks before and after
+    ///
+    /// ## Safety
+    /// See [RawCollectorImpl::perform_raw_collection]
+    pub(crate) unsafe fn perform_collection(
+        &self,
+        collector: &C,
+        collection_id: u64,
+        contexts: &[*mut C::RawContext],
+    ) {
+        if !self.start.is_empty() {
+            let stats = collector.stats();
+            for hook in &self.start {
+                hook(collection_id, &stats);
+            }
+        }
+        collector.perform_raw_collection(contexts);
+        if !self.end.is_empty() {
+            let stats = collector.stats();
+            for hook in &self.end {
+                hook(collection_id, &stats);
+            }
+        }
+    }
 }
 
 /// The underlying state of a context
@@ -105,6 +158,10 @@ where
         self.collector_ref().as_raw()
     }
     fn state(&self) -> ContextState;
+    /// The context's allocation cache
+    ///
+    /// See [RawCollectorImpl::ContextCache]
+    fn cache(&self) -> &C::ContextCache;
 }
 
 mod sealed {
@@ -14,6 +14,7 @@ use alloc::boxed::Box;
 
 use slog::{o, trace, FnValue, Logger};
 
+use super::{CollectionHook, CollectionHooks};
 use crate::collector::RawCollectorImpl;
 use crate::{CollectorRef, ContextState, ShadowStack};
 
@@ -32,6 +33,8 @@ pub struct CollectionManager<C: RawCollectorImpl> {
     collecting: Cell<bool>,
     /// Sanity check to ensure there's only one context
     has_existing_context: Cell<bool>,
+    /// The hooks to invoke around collections
+    hooks: RefCell<CollectionHooks<C>>,
 }
 impl<C: RawCollectorImpl> super::sealed::Sealed for CollectionManager<C> {}
 unsafe impl<C> super::CollectionManager<C> for CollectionManager<C>
@@ -47,6 +50,7 @@ where
             state: RefCell::new(CollectorState::new()),
             collecting: Cell::new(false),
             has_existing_context: Cell::new(false),
+            hooks: RefCell::new(CollectionHooks::new()),
         }
     }
     #[inline]
@@ -84,6 +88,14 @@ where
         assert!(!C::SYNC);
         // No extra work to do - automatic Drop handles everything
     }
+
+    fn on_collection_start(&self, hook: CollectionHook<C>) {
+        self.hooks.borrow_mut().start.push(hook);
+    }
+
+    fn on_collection_end(&self, hook: CollectionHook<C>) {
+        self.hooks.borrow_mut().end.push(hook);
+    }
 }
 pub struct RawContext<C: RawCollectorImpl> {
     /// Since we're the only context, we should (logically)
@@ -96,6 +108,8 @@ pub struct RawContext<C: RawCollectorImpl> {
     pub(super) shadow_stack: UnsafeCell<ShadowStack<C>>,
     // TODO: Does the collector access this async?
     pub(super) state: Cell<ContextState>,
+    /// See [RawCollectorImpl::ContextCache]
+    cache: C::ContextCache,
     logger: Logger,
 }
 impl<C: RawCollectorImpl> Debug for RawContext<C> {
@@ -137,6 +151,7 @@ where
                 last: core::ptr::null_mut(),
             }),
             state: Cell::new(ContextState::Active),
+            cache: Default::default(),
         }));
         trace!(
             logger, "Initializing context";
@@ -186,7 +201,12 @@ where
             "collection_id" => collection_id,
             "original_size" => %self.collector.as_raw().allocated_size(),
         );
-        self.collector.as_raw().perform_raw_collection(&[ptr]);
+        let collector = self.collector.as_raw();
+        collector
+            .manager()
+            .hooks
+            .borrow()
+            .perform_collection(collector, collection_id, &[ptr]);
         assert_eq!(
             self.state.replace(ContextState::Active),
             ContextState::SafePoint { collection_id }
@@ -208,6 +228,11 @@ where
     fn state(&self) -> ContextState {
         self.state.get()
     }
+
+    #[inline]
+    fn cache(&self) -> &C::ContextCache {
+        &self.cache
+    }
 }
 
 // Pending collections
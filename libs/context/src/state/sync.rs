@@ -14,7 +14,7 @@ use std::{fmt, mem};
 
 use slog::{o, trace, Drain, FnValue, Logger};
 
-use super::{ContextState, ShadowStack};
+use super::{CollectionHook, CollectionHooks, ContextState, ShadowStack};
 use crate::collector::SyncCollector;
 use crate::utils::ThreadId;
 use crate::{CollectorRef, RawCollectorImpl};
@@ -57,6 +57,8 @@ pub struct CollectionManager<C: RawCollectorImpl> {
     ///
     /// Like `collection_wait`, his doesn't actually protect any data.
     collection_wait_lock: Mutex<()>,
+    /// The hooks to invoke around collections
+    hooks: RwLock<CollectionHooks<C>>,
 }
 impl<C: SyncCollector> super::sealed::Sealed for CollectionManager<C> {}
 unsafe impl<C> super::CollectionManager<C> for CollectionManager<C>
@@ -75,6 +77,7 @@ where
             valid_contexts_lock: Mutex::new(()),
             collection_wait_lock: Mutex::new(()),
             collecting: AtomicBool::new(false),
+            hooks: RwLock::new(CollectionHooks::new()),
         }
     }
     #[inline]
@@ -100,7 +103,7 @@ where
          * This means we are now "valid" for the purposes of
          * collection ^_^
          */
-        self.valid_contexts_wait.notify_all();
+        notify_all(&self.valid_contexts_lock, &self.valid_contexts_wait);
     }
     unsafe fn unfreeze_context(&self, context: &RawContext<C>) {
         /*
@@ -123,6 +126,14 @@ where
     unsafe fn free_context(collector: &C, context: *mut Self::Context) {
         collector.free_context(context)
     }
+
+    fn on_collection_start(&self, hook: CollectionHook<C>) {
+        self.hooks.write().start.push(hook);
+    }
+
+    fn on_collection_end(&self, hook: CollectionHook<C>) {
+        self.hooks.write().end.push(hook);
+    }
 }
 
 pub struct RawContext<C: RawCollectorImpl> {
@@ -132,6 +143,8 @@ pub struct RawContext<C: RawCollectorImpl> {
     pub(super) shadow_stack: UnsafeCell<ShadowStack<C>>,
     // TODO: Does the collector access this async?
     pub(super) state: Cell<ContextState>,
+    /// See [RawCollectorImpl::ContextCache]
+    cache: C::ContextCache,
     pub(super) logger: Logger,
 }
 impl<C: RawCollectorImpl> Debug for RawContext<C> {
@@ -169,6 +182,7 @@ where
                 last: std::ptr::null_mut(),
             }),
             state: Cell::new(ContextState::Active),
+            cache: Default::default(),
         }));
         let old_num_total = collector.as_raw().add_context(&mut **context);
         trace!(
@@ -191,6 +205,24 @@ where
          */
         let collector = self.collector.as_raw();
         let mut guard = collector.manager().state.write();
+        /*
+         * The last collection may be finished,
+         * without every context having left its safepoint yet.
+         * We can't join it, so wait for it to be cleared.
+         */
+        while matches!(
+            guard.pending,
+            Some(PendingCollection {
+                state: PendingState::Finished,
+                ..
+            })
+        ) {
+            wait_unlocked(
+                &mut guard,
+                &collector.manager().collection_wait_lock,
+                &collector.manager().collection_wait,
+            );
+        }
         let state = &mut *guard;
         // If there is not a active `PendingCollection` - create one
         if state.pending.is_none() {
@@ -277,7 +309,11 @@ where
                 "state" => ?pending.state,
                 "collector_id" => pending.id,
             );
-            collector.perform_raw_collection(&contexts);
+            collector
+                .manager()
+                .hooks
+                .read()
+                .perform_collection(collector, pending.id, &contexts);
             assert_eq!(pending.state, PendingState::InProgress);
             pending.state = PendingState::Finished;
             // Now acknowledge that we're finished
@@ -304,6 +340,11 @@ where
     fn state(&self) -> ContextState {
         self.state.get()
     }
+
+    #[inline]
+    fn cache(&self) -> &C::ContextCache {
+        &self.cache
+    }
 }
 // Pending collections
 
@@ -355,8 +396,9 @@ pub(crate) trait SyncCollectorImpl:
         // Acquire the lock to ensure there's no collection in progress
         let mut state = self.manager().state.read();
         while state.pending.is_some() {
-            RwLockReadGuard::unlocked(&mut state, || {
-                let mut lock = self.manager().collection_wait_lock.lock();
+            // See `wait_unlocked`
+            let mut lock = self.manager().collection_wait_lock.lock();
+            RwLockReadGuard::unlocked(&mut state, move || {
                 self.manager().collection_wait.wait(&mut lock);
             })
         }
@@ -452,7 +494,10 @@ pub(crate) trait SyncCollectorImpl:
          * Notify all threads waiting for contexts to be valid.
          * TODO: I think this is really only useful if we're waiting....
          */
-        self.manager().valid_contexts_wait.notify_all();
+        notify_all(
+            &self.manager().valid_contexts_lock,
+            &self.manager().valid_contexts_wait,
+        );
         // Now drop the Box
         drop(Box::from_raw(raw));
     }
@@ -514,36 +559,48 @@ pub(crate) trait SyncCollectorImpl:
                              * Notify all blocked threads we're finished.
                              * We can proceed immediately and everyone else
                              * will slowly begin to wakeup;
+                             *
+                             * This includes the threads that were still
+                             * waiting for all the contexts to be valid.
                              */
-                            self.manager().collection_wait.notify_all();
+                            notify_all(
+                                &self.manager().valid_contexts_lock,
+                                &self.manager().valid_contexts_wait,
+                            );
+                            notify_all(
+                                &self.manager().collection_wait_lock,
+                                &self.manager().collection_wait,
+                            );
                             return;
                         }
                         PendingState::Waiting => {
-                            RwLockWriteGuard::unlocked(&mut lock, || {
-                                let mut lock = self.manager().valid_contexts_lock.lock();
-                                /*
-                                 * Wait for all contexts to be valid.
-                                 *
-                                 * Typically we're waiting for them to reach
-                                 * a safepoint.
-                                 */
-                                self.manager().valid_contexts_wait.wait(&mut lock);
-                            })
+                            /*
+                             * Wait for all contexts to be valid.
+                             *
+                             * Typically we're waiting for them to reach
+                             * a safepoint.
+                             */
+                            wait_unlocked(
+                                &mut lock,
+                                &self.manager().valid_contexts_lock,
+                                &self.manager().valid_contexts_wait,
+                            );
                         }
                         PendingState::InProgress => {
-                            RwLockWriteGuard::unlocked(&mut lock, || {
-                                let mut lock = self.manager().collection_wait_lock.lock();
-                                /*
-                                 * Another thread has already started collecting.
-                                 * Wait for them to finish.
-                                 *
-                                 * Block until collection finishes
-                                 * Parking lot says there shouldn't be any "spurious"
-                                 * (accidental) wakeups. However I guess it's possible
-                                 * we're woken up somehow in the middle of collection.
-                                 */
-                                self.manager().collection_wait.wait(&mut lock);
-                            })
+                            /*
+                             * Another thread has already started collecting.
+                             * Wait for them to finish.
+                             *
+                             * Block until collection finishes
+                             * Parking lot says there shouldn't be any "spurious"
+                             * (accidental) wakeups. However I guess it's possible
+                             * we're woken up somehow in the middle of collection.
+                             */
+                            wait_unlocked(
+                                &mut lock,
+                                &self.manager().collection_wait_lock,
+                                &self.manager().collection_wait,
+                            );
                         }
                     }
                 }
@@ -596,10 +653,33 @@ pub(crate) trait SyncCollectorImpl:
                 Ok(true)
             );
             // Someone may be waiting for us to become `None`
-            self.manager().collection_wait.notify_all();
+            notify_all(
+                &self.manager().collection_wait_lock,
+                &self.manager().collection_wait,
+            );
         }
     }
 }
+/// Block until the specified condition is notified,
+/// temporarily releasing the lock on the state.
+///
+/// The condition's mutex is acquired *before* the state is released,
+/// so a notification can't be missed in between.
+/// Likewise, notifications must hold the mutex (see [notify_all]).
+fn wait_unlocked<T>(state: &mut RwLockWriteGuard<T>, mutex: &Mutex<()>, condition: &Condvar) {
+    let mut lock = mutex.lock();
+    RwLockWriteGuard::unlocked(state, move || {
+        condition.wait(&mut lock);
+        // NOTE: Must release the mutex before the state is reacquired
+    })
+}
+/// Notify all the threads that are waiting on the specified condition
+#[inline]
+fn notify_all(mutex: &Mutex<()>, condition: &Condvar) {
+    let _lock = mutex.lock();
+    condition.notify_all();
+}
+
 /// Blanket implementation
 impl<C> SyncCollectorImpl for C
 where
@@ -0,0 +1,129 @@
+//! Policies that decide when collections are triggered
+//!
+//! These are shared by the collectors built on top of this crate,
+//! so they can all be tuned the same way.
+use core::time::Duration;
+
+/// Decides when the next collection should be triggered
+pub trait ThresholdPolicy: Send + Sync {
+    /// Pick the threshold for the next collection (in bytes),
+    /// once a collection has finished.
+    ///
+    /// The collector may limit the result further
+    /// (for example, to a hard limit on the size of the heap).
+    fn next_threshold(&self, collection: &FinishedCollection) -> usize;
+}
+
+/// Information about a finished collection,
+/// given to a [ThresholdPolicy]
+#[derive(Copy, Clone, Debug)]
+pub struct FinishedCollection {
+    /// The total size of the objects that survived the collection (in bytes)
+    pub live_bytes: usize,
+    /// The fraction of time that has recently been spent collecting garbage
+    ///
+    /// See [update_gc_cpu_fraction]
+    pub gc_cpu_fraction: f64,
+}
+
+/// Update the moving average of the fraction of time spent collecting garbage,
+/// once a collection cycle has finished.
+///
+/// The cycle took `cycle_time` in total (including the time spent running the program),
+/// and `cycle_pause` of it was spent paused for collections.
+/// The average is weighted towards the most recent collections.
+/// Pass `None` as the `average` for the first collection.
+pub fn update_gc_cpu_fraction(
+    average: Option<f64>,
+    cycle_pause: Duration,
+    cycle_time: Duration,
+) -> f64 {
+    let cycle_time = cycle_time.as_secs_f64();
+    let fraction = if cycle_time > 0.0 {
+        (cycle_pause.as_secs_f64() / cycle_time).min(1.0)
+    } else {
+        0.0
+    };
+    match average {
+        None => fraction,
+        Some(average) => (average + fraction) / 2.0,
+    }
+}
+
+/// The default [ThresholdPolicy], which grows the heap in proportion to the live data.
+///
+/// This is similar to the way Go's collector is tuned with `GOGC` and `GOMEMLIMIT`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HeapGrowth {
+    /// How much the heap can grow (relative to the live data) before the next collection
+    ///
+    /// A factor of `1.0` collects once the heap has doubled.
+    /// By default, this is `1.0`.
+    pub growth_factor: f64,
+    /// The smallest threshold that will ever be used (in bytes)
+    ///
+    /// This avoids collecting over and over again while the heap is small
+    /// (for example during startup).
+    /// By default, this is 4MB.
+    pub min_heap_size: usize,
+    /// A soft limit on the size of the heap (in bytes)
+    ///
+    /// The threshold is never set past this limit,
+    /// unless the live data is already close to it.
+    /// Unlike a collector's hard limit on the heap size,
+    /// allocations never fail because of this.
+    ///
+    /// By default, there is no limit.
+    pub max_heap_size: Option<usize>,
+    /// The fraction of time that should be spent collecting garbage
+    ///
+    /// If collections are taking more time than this,
+    /// the heap grows faster than the `growth_factor` (up to eight times as fast),
+    /// trading memory for less time in the collector.
+    ///
+    /// By default, this is `Some(0.25)`.
+    pub target_gc_cpu_fraction: Option<f64>,
+}
+impl HeapGrowth {
+    /// The most that the `growth_factor` is scaled up
+    /// when collections exceed the `target_gc_cpu_fraction`
+    const MAX_GROWTH_SCALE: f64 = 8.0;
+    /// Collect once the heap grows to 150% of the live data,
+    /// without a minimum heap size or any adaptation to the time spent collecting.
+    ///
+    /// This is how `zerogc-simple` used to pick its thresholds.
+    /// It keeps the heap small, at the cost of collecting far more often.
+    pub const EAGER: HeapGrowth = HeapGrowth {
+        growth_factor: 0.5,
+        min_heap_size: 0,
+        max_heap_size: None,
+        target_gc_cpu_fraction: None,
+    };
+}
+impl Default for HeapGrowth {
+    fn default() -> Self {
+        HeapGrowth {
+            growth_factor: 1.0,
+            min_heap_size: 4 * 1024 * 1024,
+            max_heap_size: None,
+            target_gc_cpu_fraction: Some(0.25),
+        }
+    }
+}
+impl ThresholdPolicy for HeapGrowth {
+    fn next_threshold(&self, collection: &FinishedCollection) -> usize {
+        let mut growth_factor = self.growth_factor;
+        if let Some(target) = self.target_gc_cpu_fraction {
+            let scale = collection.gc_cpu_fraction / target;
+            growth_factor *= scale.clamp(1.0, HeapGrowth::MAX_GROWTH_SCALE);
+        }
+        let live_bytes = collection.live_bytes;
+        let mut threshold = live_bytes.saturating_add((live_bytes as f64 * growth_factor) as usize);
+        if let Some(max_heap_size) = self.max_heap_size {
+            // Leave some room to grow, so we don't collect at every safepoint
+            let min_headroom = live_bytes.saturating_add(live_bytes / 16);
+            threshold = threshold.min(max_heap_size.max(min_headroom));
+        }
+        threshold.max(self.min_heap_size)
+    }
+}
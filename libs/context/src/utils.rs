@@ -2,9 +2,10 @@
 //!
 //! Also used by some collector implementations.
 #[cfg(not(feature = "sync"))]
-use core::cell::Cell;
+use core::cell::{Cell, RefCell, RefMut};
 use core::fmt::{self, Debug, Display, Formatter};
 use core::mem;
+use core::ops::{Deref, DerefMut};
 
 /// Get the offset of the specified field within a structure
 #[macro_export]
@@ -68,6 +69,66 @@ impl<T: Copy> AtomicCell<T> {
     }
 }
 
+/// The abstract specification of a [Lock],
+/// shared between thread-safe and thread-unsafe code
+pub trait ILock<'a, T> {
+    type Guard: Sized + Deref<Target = T> + DerefMut<Target = T> + 'a;
+    fn lock(&'a self) -> Self::Guard;
+    fn get_mut(&'a mut self) -> &'a mut T;
+}
+
+/// A lock used by collector internals
+///
+/// This is only a real mutex if the `sync` feature is enabled.
+#[cfg(feature = "sync")]
+pub struct Lock<T>(::parking_lot::Mutex<T>);
+#[cfg(feature = "sync")]
+impl<T> From<T> for Lock<T> {
+    fn from(val: T) -> Self {
+        Lock(::parking_lot::Mutex::new(val))
+    }
+}
+#[cfg(feature = "sync")]
+impl<'a, T: 'a> ILock<'a, T> for Lock<T> {
+    type Guard = ::parking_lot::MutexGuard<'a, T>;
+
+    #[inline]
+    fn lock(&'a self) -> Self::Guard {
+        self.0.lock()
+    }
+
+    #[inline]
+    fn get_mut(&'a mut self) -> &'a mut T {
+        self.0.get_mut()
+    }
+}
+
+/// A lock used by collector internals
+///
+/// This is only a real mutex if the `sync` feature is enabled.
+#[cfg(not(feature = "sync"))]
+pub struct Lock<T>(RefCell<T>);
+#[cfg(not(feature = "sync"))]
+impl<T> From<T> for Lock<T> {
+    fn from(val: T) -> Self {
+        Lock(RefCell::new(val))
+    }
+}
+#[cfg(not(feature = "sync"))]
+impl<'a, T: 'a> ILock<'a, T> for Lock<T> {
+    type Guard = RefMut<'a, T>;
+
+    #[inline]
+    fn lock(&'a self) -> Self::Guard {
+        self.0.borrow_mut()
+    }
+
+    #[inline]
+    fn get_mut(&'a mut self) -> &'a mut T {
+        self.0.get_mut()
+    }
+}
+
 #[derive(Clone)]
 pub enum ThreadId {
     #[allow(unused)]
@@ -0,0 +1,27 @@
+//! The in memory layout of objects using the copying collector.
+//!
+//! Objects use the [shared layout](zerogc_context::layout) from `zerogc-context`.
+//! Each [GcHeader] stores the object's [Forwarding] pointer.
+//!
+//! ## Safety
+//! Relying on this internal layout is incredibly unsafe.
+//! Unlike `zerogc-simple`, the layout isn't (yet) committed to a stable ABI.
+use zerogc_context::layout;
+
+use crate::RawCopyingCollector;
+
+pub use zerogc_context::layout::{GcTypeLayout, HeaderLayout, StaticGcType, StaticVecType};
+
+/// A header for a GC object
+pub type GcHeader = layout::GcHeader<RawCopyingCollector>;
+/// A header for a GC array object
+pub type GcArrayHeader = layout::GcArrayHeader<RawCopyingCollector>;
+/// A header for a Gc vector
+pub type GcVecHeader = layout::GcVecHeader<RawCopyingCollector>;
+/// A type used by GC
+pub type GcType = layout::GcType<RawCopyingCollector>;
+/// The raw representation of a vector in the copying collector
+pub type CopyingVecRepr<'gc, T> = layout::VecRepr<'gc, T, RawCopyingCollector>;
+/// The forwarding pointer of an object,
+/// set once it has been copied (or left in place) by a collection
+pub type Forwarding = layout::Forwarding<RawCopyingCollector>;
@@ -0,0 +1,1011 @@
+//! A copying (semi-space) garbage collector for zerogc.
+//!
+//! The heap is split into two equally sized spaces.
+//! Objects are allocated by bumping a pointer in the active space.
+//! A collection copies every reachable object into the other (reserve) space,
+//! using [Cheney's algorithm](https://en.wikipedia.org/wiki/Cheney%27s_algorithm),
+//! and then the two spaces are swapped.
+//! The cost of a collection is proportional to the amount of live data,
+//! and garbage is freed without ever being touched (unless it needs to be dropped).
+//!
+//! Unlike `zerogc-simple`, objects move during collections.
+//! This relies on zerogc's support for moving collectors:
+//! 1. Roots are updated in place through the shadow stack,
+//!    so the values returned by `safepoint!` refer to the new copies.
+//! 2. [GcHandle](zerogc::GcHandle)s are updated through the [GcHandleList]
+//! 3. Weak references are updated to the new copies (or cleared)
+//!
+//! Since objects move, their addresses must never be relied upon across a safepoint
+//! (for example, hashing by address), unless the object is pinned.
+//!
+//! ## Pinning
+//! Objects that are pinned (see [zerogc::PinCollectorId]) are left where they are,
+//! along with anything that doesn't fit in the other space.
+//! A space that contains objects like this isn't completely reset by a collection.
+//! Instead, the objects that were left behind are skipped over
+//! when copying objects into that space (or allocating in it).
+//! Once an object is unpinned, it's moved by the next collection
+//! that evacuates the space it's in.
+//!
+//! ## Memory usage
+//! Both spaces are allocated up front, using [GcConfig::space_size] bytes each.
+//! Collections are triggered by the same [ThresholdPolicy] as `zerogc-simple`.
+//! Whenever the threshold it picks doesn't fit in the active space,
+//! the (empty) reserve space is replaced with a bigger one,
+//! up to [GcConfig::max_space_size].
+//! The next collection copies the live objects into it, and the other space
+//! is grown to match once it's empty. So the collector uses twice the size
+//! of the larger space (plus the overhead of tracking objects).
+//!
+//! The active space can't grow in between collections, since its objects can't move.
+//! Allocations that don't fit in it fail with
+//! [GcAllocError::HeapLimitExceeded](zerogc::GcAllocError::HeapLimitExceeded)
+//! and force a collection at the next safepoint,
+//! while the infallible allocation methods panic.
+//! Code that allocates a lot in between safepoints should use the fallible methods
+//! (like [try_alloc](zerogc::GcSimpleAlloc::try_alloc)),
+//! and retry the allocation after invoking a safepoint.
+//! If the live objects filled the space, the first of these collections only grows the
+//! reserve space, so it can take a second safepoint before the retry succeeds.
+//!
+//! ## Internals
+//! The internal layout information is public,
+//! and available through the (layout module)[`self::layout`].
+#![deny(
+    missing_docs, // The 'copying' implementation needs to document its public API
+)]
+#![feature(
+    never_type, // Used for errors (which are currently impossible)
+    exhaustive_patterns, // Allow exhaustive matching against never
+)]
+use std::alloc::Layout;
+use std::ops::Range;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use slog::{debug, FnValue, Logger};
+
+use zerogc::{GcAllocError, GcSafe, Trace};
+
+use zerogc_context::collector::RawSimpleAlloc;
+use zerogc_context::handle::{GcHandleList, RawHandleImpl, RawPinImpl};
+use zerogc_context::layout::{DynTrace, HandleListRoot, LayoutCollector, LayoutVisitor};
+use zerogc_context::threshold::update_gc_cpu_fraction;
+use zerogc_context::utils::{ILock, Lock, MemorySize, ThreadId};
+use zerogc_context::{
+    CollectionManager as AbstractCollectionManager, CollectorContext,
+    RawContext as AbstractRawContext,
+};
+
+use crate::layout::{
+    CopyingVecRepr, Forwarding, GcArrayHeader, GcHeader, GcType, GcVecHeader, HeaderLayout,
+    StaticGcType, StaticVecType,
+};
+
+pub mod layout;
+
+pub use zerogc_context::threshold::{FinishedCollection, HeapGrowth, ThresholdPolicy};
+
+/// The configuration for a copying garbage collector
+pub struct GcConfig {
+    /// Whether to always force a collection at safepoints,
+    /// regardless of whether the heuristics say.
+    ///
+    /// To force a single collection, use [zerogc::safepoint_force!] instead.
+    pub always_force_collect: bool,
+    /// The initial threshold to trigger garbage collection (in bytes)
+    ///
+    /// By default, this is the [HeapGrowth::min_heap_size] of the default policy.
+    pub initial_threshold: usize,
+    /// The policy used to pick the threshold for the next collection,
+    /// once a collection has finished.
+    ///
+    /// The threshold is limited to the size of the active space.
+    /// If the policy picks a bigger one, the spaces grow
+    /// (see [GcConfig::max_space_size]).
+    ///
+    /// By default, this is [HeapGrowth::default].
+    pub threshold_policy: Box<dyn ThresholdPolicy>,
+    /// The initial size of each of the two semispaces (in bytes)
+    ///
+    /// Both spaces are allocated up front,
+    /// so the collector reserves twice this amount of memory.
+    pub space_size: usize,
+    /// The size that the semispaces can grow to (in bytes)
+    ///
+    /// Once the spaces reach this size, the threshold stops growing
+    /// and allocations that don't fit fail instead.
+    ///
+    /// By default, there is no limit.
+    pub max_space_size: Option<usize>,
+}
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            always_force_collect: false,
+            initial_threshold: HeapGrowth::default().min_heap_size,
+            threshold_policy: Box::<HeapGrowth>::default(),
+            space_size: 16 * 1024 * 1024,
+            max_space_size: None,
+        }
+    }
+}
+
+/// A snapshot of statistics about a [CopyingCollector]
+///
+/// This can be retrieved with [CopyingCollector::stats]
+#[derive(Clone, Debug, Default)]
+pub struct GcStats {
+    /// The number of collections that have been performed
+    pub collections: u64,
+    /// The total time spent in collections
+    pub total_pause: Duration,
+    /// The duration of the last collection
+    pub last_pause: Duration,
+    /// The number of bytes freed by the last collection
+    pub last_freed_bytes: usize,
+    /// The total number of bytes freed by all collections
+    pub total_freed_bytes: u64,
+    /// The number of bytes copied by the last collection
+    pub last_copied_bytes: usize,
+    /// The total number of bytes copied by all collections
+    pub total_copied_bytes: u64,
+    /// The number of bytes currently used in the active space
+    pub live_bytes: usize,
+    /// The current threshold that will trigger the next collection (in bytes)
+    pub threshold: usize,
+    /// The size of the active semispace (in bytes)
+    ///
+    /// This grows along with the threshold (see [GcConfig::threshold_policy]).
+    pub space_size: usize,
+    /// The number of handles that are currently alive
+    pub num_handles: usize,
+    /// The number of free handle slots, that can be reused by new handles
+    ///
+    /// Slots that are no longer needed are released after each collection.
+    pub free_handle_slots: usize,
+    /// The fraction of time recently spent collecting garbage, between zero and one.
+    ///
+    /// This is the duration of each collection,
+    /// relative to the time since the one before it.
+    /// It's a moving average, weighted towards the most recent collections.
+    pub gc_cpu_fraction: f64,
+}
+
+#[cfg(feature = "sync")]
+type RawContext<C> = zerogc_context::state::sync::RawContext<C>;
+#[cfg(feature = "sync")]
+type CollectionManager<C> = zerogc_context::state::sync::CollectionManager<C>;
+#[cfg(not(feature = "sync"))]
+type RawContext<C> = zerogc_context::state::nosync::RawContext<C>;
+#[cfg(not(feature = "sync"))]
+type CollectionManager<C> = zerogc_context::state::nosync::CollectionManager<C>;
+
+/// A copying garbage collector
+pub type CopyingCollector = ::zerogc_context::CollectorRef<RawCopyingCollector>;
+/// The context of a copying collector
+pub type CopyingCollectorContext = ::zerogc_context::CollectorContext<RawCopyingCollector>;
+/// The id for a copying collector
+pub type CollectorId = ::zerogc_context::CollectorId<RawCopyingCollector>;
+/// A garbage collected pointer, allocated in the copying collector
+pub type Gc<'gc, T> = ::zerogc::Gc<'gc, T, CollectorId>;
+/// A weak reference to an object in the copying collector
+pub type GcWeak<'gc, T> = ::zerogc::GcWeak<'gc, T, CollectorId>;
+/// A garbage collected array, allocated in the copying collector
+pub type GcArray<'gc, T> = ::zerogc::array::GcArray<'gc, T, CollectorId>;
+/// A garbage collected vector, allocated in the copying collector
+pub type GcVec<'gc, T> = ::zerogc::vec::GcVec<'gc, T, CollectorId>;
+
+unsafe impl RawSimpleAlloc for RawCopyingCollector {
+    #[inline]
+    unsafe fn try_alloc_uninit<'gc, T>(
+        context: &'gc CopyingCollectorContext,
+    ) -> Result<*mut T, GcAllocError>
+    where
+        T: GcSafe<'gc, crate::CollectorId>,
+    {
+        let (_header, ptr) = context.collector().heap.alloc_layout(
+            GcHeader::LAYOUT,
+            Layout::new::<T>(),
+            <T as StaticGcType<Self>>::STATIC_TYPE,
+        )?;
+        Ok(ptr as *mut T)
+    }
+
+    unsafe fn try_alloc_uninit_slice<'gc, T>(
+        context: &'gc CollectorContext<Self>,
+        len: usize,
+    ) -> Result<*mut T, GcAllocError>
+    where
+        T: GcSafe<'gc, crate::CollectorId>,
+    {
+        let (header, ptr) = context.collector().heap.alloc_layout(
+            GcArrayHeader::LAYOUT,
+            Layout::array::<T>(len)?,
+            <[T] as StaticGcType<Self>>::STATIC_TYPE,
+        )?;
+        (*header).len = len;
+        Ok(ptr.cast())
+    }
+
+    fn try_alloc_raw_vec_with_capacity<'gc, T>(
+        context: &'gc CollectorContext<Self>,
+        capacity: usize,
+    ) -> Result<Self::RawVec<'gc, T>, GcAllocError>
+    where
+        T: GcSafe<'gc, crate::CollectorId>,
+    {
+        let (header, value_ptr) = context.collector().heap.alloc_layout(
+            GcVecHeader::LAYOUT,
+            Layout::array::<T>(capacity)?,
+            <T as StaticVecType<Self>>::STATIC_VEC_TYPE,
+        )?;
+        unsafe {
+            (*header).capacity = capacity;
+            (*header).len.set(0);
+            let res = CopyingVecRepr::from_raw_parts(NonNull::new_unchecked(header), context);
+            debug_assert_eq!(res.as_ptr(), value_ptr as *mut T as *const T);
+            Ok(res)
+        }
+    }
+}
+
+unsafe impl RawHandleImpl for RawCopyingCollector {
+    type TypeInfo = GcType;
+
+    #[inline]
+    fn type_info_of<'gc, T: GcSafe<'gc, CollectorId>>() -> &'static Self::TypeInfo {
+        <T as StaticGcType<Self>>::STATIC_TYPE
+    }
+
+    #[inline]
+    fn resolve_type_info<'gc, T: ?Sized + GcSafe<'gc, CollectorId>>(
+        gc: zerogc::Gc<'gc, T, CollectorId>,
+    ) -> &'static Self::TypeInfo {
+        unsafe { (*GcHeader::from_value_ptr(gc.as_raw_ptr())).type_info }
+    }
+
+    #[inline]
+    fn handle_list(&self) -> &GcHandleList<Self> {
+        &self.handle_list
+    }
+}
+
+/// Objects are left in place while they're pinned
+unsafe impl RawPinImpl for RawCopyingCollector {}
+
+/// The minimum alignment of every object in a [Space]
+///
+/// The size of every object is a multiple of this,
+/// so only objects with a greater alignment ever need padding.
+const MIN_ALIGN: usize = std::mem::align_of::<GcHeader>();
+
+/// The addresses of the memory used by an object (including its header)
+#[inline]
+unsafe fn object_range(header: *mut GcHeader) -> Range<usize> {
+    let type_info = (*header).type_info;
+    let start = type_info.header_layout().from_common_header(header) as usize;
+    start..start + type_info.determine_total_layout(header).size()
+}
+
+/// The number of bytes an object with the specified layout is charged against its space
+///
+/// See [Space::charged]
+#[inline]
+fn charge_for(layout: Layout) -> usize {
+    layout.size() + layout.align().saturating_sub(MIN_ALIGN)
+}
+
+/// One of the two halves of the heap
+struct Space {
+    start: NonNull<u8>,
+    size: usize,
+    /// The number of bytes that have been used (including padding)
+    used: usize,
+    /// The number of bytes charged against the space's capacity.
+    ///
+    /// In addition to the size of each object,
+    /// this includes the worst-case padding it could need
+    /// (if it was copied into a different position).
+    /// Limiting this to the size of the space guarantees
+    /// that the live objects always fit in the other space.
+    charged: usize,
+    /// Every object allocated in this space, in the order they were allocated.
+    ///
+    /// During a collection, this is the queue of copied objects that need to be scanned.
+    objects: Vec<*mut GcHeader>,
+    /// The objects that the last collection left in place, in order of their address.
+    ///
+    /// These are skipped over when bump-allocating.
+    retained: Vec<*mut GcHeader>,
+    /// The index of the first retained object after the bump pointer
+    next_retained: usize,
+}
+impl Space {
+    fn new(size: usize) -> Space {
+        let layout = Layout::from_size_align(size.max(MIN_ALIGN), MIN_ALIGN).unwrap();
+        let start = unsafe { std::alloc::alloc(layout) };
+        Space {
+            start: NonNull::new(start).unwrap_or_else(|| std::alloc::handle_alloc_error(layout)),
+            size: layout.size(),
+            used: 0,
+            charged: 0,
+            objects: Vec::new(),
+            retained: Vec::new(),
+            next_retained: 0,
+        }
+    }
+    /// Bump-allocate memory for an object with the specified (overall) layout,
+    /// returning `None` if it doesn't fit.
+    ///
+    /// Objects that were left in place by the last collection are skipped over.
+    #[inline]
+    fn bump(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        debug_assert_eq!(layout.size() % MIN_ALIGN, 0);
+        let charged = self.charged.checked_add(charge_for(layout))?;
+        if charged > self.size {
+            return None;
+        }
+        let base = self.start.as_ptr() as usize;
+        let align = layout.align().max(MIN_ALIGN);
+        let mut used = self.used;
+        let mut next_retained = self.next_retained;
+        loop {
+            let offset = ((base + used + align - 1) & !(align - 1)) - base;
+            let end = offset.checked_add(layout.size())?;
+            if end > self.size {
+                return None;
+            }
+            if let Some(&retained) = self.retained.get(next_retained) {
+                let range = unsafe { object_range(retained) };
+                if range.start < base + end {
+                    // Doesn't fit before the retained object, so try again after it
+                    used = range.end - base;
+                    next_retained += 1;
+                    continue;
+                }
+            }
+            self.used = end;
+            self.next_retained = next_retained;
+            self.charged = charged;
+            return Some(unsafe { NonNull::new_unchecked(self.start.as_ptr().add(offset)) });
+        }
+    }
+    /// Keep an object that a collection left in place,
+    /// so that it isn't overwritten by objects allocated after it.
+    ///
+    /// The retained objects must be sorted afterwards (see [Space::sort_retained]).
+    unsafe fn retain(&mut self, header: *mut GcHeader) {
+        debug_assert!(self.contains(header));
+        let type_info = (*header).type_info;
+        self.charged += charge_for(type_info.determine_total_layout(header));
+        self.objects.push(header);
+        self.retained.push(header);
+    }
+    fn sort_retained(&mut self) {
+        self.retained.sort_unstable();
+        let used = self.start.as_ptr() as usize + self.used;
+        self.next_retained = self
+            .retained
+            .partition_point(|&header| (header as usize) < used);
+    }
+    #[inline]
+    fn contains(&self, ptr: *mut GcHeader) -> bool {
+        let start = self.start.as_ptr() as usize;
+        (start..start + self.size).contains(&(ptr as usize))
+    }
+    /// Drop every object that wasn't copied out of this space,
+    /// then reset it so it can be reused.
+    unsafe fn drop_and_reset(&mut self) {
+        for header in self.objects.drain(..) {
+            if (*header).forwarded().is_none() {
+                if let Some(func) = (*header).type_info.drop_func {
+                    func((*header).value());
+                }
+            }
+        }
+        self.used = 0;
+        self.charged = 0;
+        self.retained.clear();
+        self.next_retained = 0;
+    }
+}
+impl Drop for Space {
+    fn drop(&mut self) {
+        unsafe {
+            self.drop_and_reset();
+            std::alloc::dealloc(
+                self.start.as_ptr(),
+                Layout::from_size_align_unchecked(self.size, MIN_ALIGN),
+            );
+        }
+    }
+}
+
+struct GcHeap {
+    config: Arc<GcConfig>,
+    collector_id: Option<CollectorId>,
+    /// The space that objects are currently allocated in
+    active: Lock<Space>,
+    /// The space that the next collection copies objects into.
+    ///
+    /// This is always empty between collections.
+    reserve: Lock<Space>,
+    /// The number of bytes used in the active space
+    allocated_size: AtomicUsize,
+    threshold: AtomicUsize,
+}
+impl GcHeap {
+    fn new(config: Arc<GcConfig>) -> GcHeap {
+        GcHeap {
+            collector_id: None,
+            active: Lock::from(Space::new(config.space_size)),
+            reserve: Lock::from(Space::new(config.space_size)),
+            allocated_size: AtomicUsize::new(0),
+            threshold: AtomicUsize::new(if config.always_force_collect {
+                0
+            } else {
+                config.initial_threshold
+            }),
+            config,
+        }
+    }
+    #[inline]
+    fn allocated_size(&self) -> usize {
+        self.allocated_size.load(Ordering::Acquire)
+    }
+    #[inline]
+    fn alloc_layout<H>(
+        &self,
+        header_layout: HeaderLayout<H>,
+        value_layout: Layout,
+        static_type: &'static GcType,
+    ) -> Result<(*mut H, *mut u8), GcAllocError> {
+        let collector_id_ptr = match self.collector_id {
+            Some(ref collector) => collector,
+            None => unreachable!("Invalid collector id"),
+        };
+        let (overall_layout, value_offset) = header_layout.layout().extend(value_layout)?;
+        debug_assert_eq!(
+            header_layout.value_offset(value_layout.align()),
+            value_offset
+        );
+        let overall_layout = overall_layout.pad_to_align();
+        let mut active = self.active.lock();
+        let header: *mut H = match active.bump(overall_layout) {
+            Some(ptr) => ptr.as_ptr().cast(),
+            None => {
+                // Make sure we collect at the next safepoint
+                self.threshold.store(0, Ordering::Relaxed);
+                return Err(GcAllocError::HeapLimitExceeded);
+            }
+        };
+        unsafe {
+            let common_header = header_layout.common_header(header);
+            common_header.write(GcHeader::new(
+                static_type,
+                collector_id_ptr as *const CollectorId,
+                Forwarding::new(),
+            ));
+            active.objects.push(common_header);
+            self.allocated_size.store(active.used, Ordering::Release);
+            Ok((header, (header as *mut u8).add(value_offset)))
+        }
+    }
+    #[inline]
+    fn should_collect_relaxed(&self) -> bool {
+        /*
+         * Going with relaxed ordering because it's not essential
+         * that we see updates immediately.
+         * Eventual consistency should be enough to eventually
+         * trigger a collection.
+         */
+        self.allocated_size.load(Ordering::Relaxed) >= self.threshold.load(Ordering::Relaxed)
+    }
+    /// Make sure the reserve space is big enough for the next collection,
+    /// once the spaces have been flipped.
+    ///
+    /// It grows to the size of the active space, and beyond that,
+    /// at least doubles whenever the threshold doesn't fit in the active space.
+    /// Only an empty space can be replaced, so a reserve space that
+    /// still contains pinned objects stays the same size until they're gone.
+    fn grow_reserve(&self, active: &Space, reserve: &mut Space, threshold: usize) {
+        let mut size = active.size;
+        if threshold > size {
+            size = threshold.max(size.saturating_mul(2));
+        }
+        if let Some(max_space_size) = self.config.max_space_size {
+            size = size.min(max_space_size.max(active.size));
+        }
+        if size > reserve.size && reserve.retained.is_empty() {
+            debug_assert!(reserve.objects.is_empty());
+            *reserve = Space::new(size);
+        }
+    }
+}
+
+/// We're careful - I swear :D
+unsafe impl Send for RawCopyingCollector {}
+unsafe impl Sync for RawCopyingCollector {}
+
+/// The internal data for a copying collector
+#[doc(hidden)]
+pub struct RawCopyingCollector {
+    logger: Logger,
+    heap: GcHeap,
+    manager: CollectionManager<Self>,
+    /// Tracks object handles
+    handle_list: GcHandleList<Self>,
+    /// Statistics about previous collections
+    ///
+    /// The fields that describe the current state of the heap
+    /// are filled in when a snapshot is taken.
+    history: Lock<GcStats>,
+    /// When the last collection finished (or the collector was created)
+    last_collection: Lock<Instant>,
+}
+
+unsafe impl ::zerogc_context::collector::RawCollectorImpl for RawCopyingCollector {
+    type DynTracePtr = NonNull<dyn DynTrace<Self>>;
+    type Config = GcConfig;
+    type Stats = GcStats;
+
+    type Ptr = NonNull<Self>;
+
+    type Manager = CollectionManager<Self>;
+
+    type RawContext = RawContext<Self>;
+
+    type ContextCache = ();
+
+    type RawVec<'gc, T: GcSafe<'gc, CollectorId>> = CopyingVecRepr<'gc, T>;
+
+    const SINGLETON: bool = false;
+
+    const SYNC: bool = cfg!(feature = "sync");
+
+    #[inline]
+    fn id_for_gc<'a, 'gc, T>(gc: &'a Gc<'gc, T>) -> &'a CollectorId
+    where
+        'gc: 'a,
+        T: ?Sized + 'gc,
+    {
+        unsafe {
+            let header = GcHeader::from_value_ptr(gc.as_raw_ptr());
+            (*header).collector_id()
+        }
+    }
+
+    #[inline]
+    fn id_for_array<'a, 'gc, T>(array: &'a GcArray<'gc, T>) -> &'a CollectorId
+    where
+        'gc: 'a,
+    {
+        unsafe {
+            let header = GcArrayHeader::LAYOUT.from_value_ptr(array.as_raw_ptr());
+            (*header).common_header.collector_id()
+        }
+    }
+
+    #[inline]
+    fn resolve_array_len<T>(gc: &GcArray<T>) -> usize {
+        unsafe {
+            let header = GcArrayHeader::LAYOUT.from_value_ptr(gc.as_raw_ptr());
+            (*header).len
+        }
+    }
+
+    #[inline]
+    unsafe fn as_dyn_trace_pointer<T: Trace>(value: *mut T) -> Self::DynTracePtr {
+        debug_assert!(!value.is_null());
+        NonNull::new_unchecked(std::mem::transmute::<
+            *mut dyn DynTrace<Self>,
+            *mut (dyn DynTrace<Self> + 'static),
+        >(value as *mut dyn DynTrace<Self>))
+    }
+
+    fn init(config: GcConfig, logger: Logger) -> NonNull<Self> {
+        let raw = RawCopyingCollector::with_logger(config, logger);
+        let mut collector = Arc::new(raw);
+        let raw_ptr =
+            unsafe { NonNull::new_unchecked(Arc::as_ptr(&collector) as *mut RawCopyingCollector) };
+        Arc::get_mut(&mut collector).unwrap().heap.collector_id =
+            Some(unsafe { CollectorId::from_raw(raw_ptr) });
+        std::mem::forget(collector); // We own it as a raw pointer...
+        raw_ptr
+    }
+
+    #[inline(always)]
+    unsafe fn gc_write_barrier<'gc, T, V>(
+        _owner: &Gc<'gc, T>,
+        _value: &Gc<'gc, V>,
+        _field_offset: usize,
+    ) where
+        T: GcSafe<'gc, CollectorId> + ?Sized,
+        V: GcSafe<'gc, CollectorId> + ?Sized,
+    {
+        // Copying GC doesn't need write barriers
+    }
+    #[inline]
+    fn logger(&self) -> &Logger {
+        &self.logger
+    }
+    #[inline]
+    fn manager(&self) -> &CollectionManager<Self> {
+        &self.manager
+    }
+    #[inline]
+    fn should_collect(&self) -> bool {
+        self.heap.should_collect_relaxed() || self.manager.should_trigger_collection()
+    }
+    #[inline]
+    fn allocated_size(&self) -> MemorySize {
+        MemorySize {
+            bytes: self.heap.allocated_size(),
+        }
+    }
+    fn stats(&self) -> GcStats {
+        let handle_slots = self.handle_list.slot_stats();
+        GcStats {
+            live_bytes: self.heap.allocated_size(),
+            threshold: self.heap.threshold.load(Ordering::Acquire),
+            space_size: self.heap.active.lock().size,
+            num_handles: handle_slots.live_slots,
+            free_handle_slots: handle_slots.free_slots,
+            ..self.history.lock().clone()
+        }
+    }
+    #[inline]
+    unsafe fn perform_raw_collection(&self, contexts: &[*mut RawContext<Self>]) {
+        self.perform_raw_collection(contexts)
+    }
+
+    #[inline]
+    unsafe fn flush_context_cache(&self, _cache: &()) {}
+}
+#[cfg(feature = "sync")]
+unsafe impl ::zerogc_context::collector::SyncCollector for RawCopyingCollector {}
+impl RawCopyingCollector {
+    fn with_logger(config: GcConfig, logger: Logger) -> Self {
+        RawCopyingCollector {
+            logger,
+            manager: CollectionManager::new(),
+            heap: GcHeap::new(Arc::new(config)),
+            handle_list: GcHandleList::new(),
+            history: Lock::from(GcStats::default()),
+            last_collection: Lock::from(Instant::now()),
+        }
+    }
+    #[cold]
+    #[inline(never)]
+    unsafe fn perform_raw_collection(&self, contexts: &[*mut RawContext<Self>]) {
+        debug_assert!(self.manager.is_collecting());
+        let start = Instant::now();
+        let mut roots: Vec<*mut dyn DynTrace<Self>> = Vec::new();
+        for ctx in contexts.iter() {
+            roots.extend(
+                (**ctx)
+                    .assume_valid_shadow_stack()
+                    .reverse_iter()
+                    .map(NonNull::as_ptr),
+            );
+        }
+        roots.push(HandleListRoot::as_root(&self.handle_list));
+        let num_roots = roots.len();
+        let mut active = self.heap.active.lock();
+        let mut reserve = self.heap.reserve.lock();
+        /*
+         * The only objects in the reserve space are the ones the last collection left there.
+         * They stay where they are (at least until this collection is done).
+         */
+        let leftovers = std::mem::take(&mut reserve.objects);
+        debug_assert_eq!(leftovers.len(), reserve.retained.len());
+        debug_assert_eq!(reserve.used, 0);
+        reserve.charged = 0;
+        let original_size = active.used;
+        let mut task = CollectionTask {
+            expected_collector: self.heap.collector_id.unwrap(),
+            to_space: &mut reserve,
+            scanned: 0,
+            retained: Vec::new(),
+            scanned_retained: 0,
+            weak_refs: Vec::new(),
+            ephemerons: Vec::new(),
+        };
+        self.handle_list.for_each_pinned(|raw_ptr, type_info| {
+            task.visitor()
+                .state
+                .pin(GcHeader::from_erased_value_ptr(raw_ptr, type_info));
+        });
+        task.run(roots);
+        let retained = task.retained;
+        // Everything that's left behind is garbage
+        for header in leftovers {
+            if (*header).forwarded().is_none() {
+                if let Some(func) = (*header).type_info.drop_func {
+                    func((*header).value());
+                }
+            }
+        }
+        active.drop_and_reset();
+        reserve.retained.clear();
+        for header in retained {
+            (*header).set_forwarded(std::ptr::null_mut());
+            if active.contains(header) {
+                active.retain(header);
+            } else {
+                reserve.retain(header);
+            }
+        }
+        active.sort_retained();
+        reserve.sort_retained();
+        std::mem::swap(&mut *active, &mut *reserve);
+        let updated_size = active.used;
+        self.heap
+            .allocated_size
+            .store(updated_size, Ordering::Release);
+        let pause = start.elapsed();
+        let mut history = self.history.lock();
+        history.gc_cpu_fraction = {
+            let mut last_collection = self.last_collection.lock();
+            let fraction = update_gc_cpu_fraction(
+                (history.collections > 0).then_some(history.gc_cpu_fraction),
+                pause,
+                last_collection.elapsed(),
+            );
+            *last_collection = Instant::now();
+            fraction
+        };
+        let threshold = self
+            .heap
+            .config
+            .threshold_policy
+            .next_threshold(&FinishedCollection {
+                live_bytes: updated_size,
+                gc_cpu_fraction: history.gc_cpu_fraction,
+            });
+        self.heap.grow_reserve(&active, &mut reserve, threshold);
+        if !self.heap.config.always_force_collect {
+            self.heap
+                .threshold
+                .store(threshold.min(active.size), Ordering::Release);
+        }
+        drop(active);
+        drop(reserve);
+        self.handle_list.shrink();
+        let freed = original_size.saturating_sub(updated_size);
+        {
+            history.collections += 1;
+            history.total_pause += pause;
+            history.last_pause = pause;
+            history.last_freed_bytes = freed;
+            history.total_freed_bytes += freed as u64;
+            history.last_copied_bytes = updated_size;
+            history.total_copied_bytes += updated_size as u64;
+        }
+        debug!(
+            self.logger, "Finished copying GC";
+            "current_thread" => FnValue(|_| ThreadId::current()),
+            "num_roots" => num_roots,
+            "original_size" => %MemorySize { bytes: original_size },
+            "memory_freed" => %MemorySize { bytes: freed },
+            "pause" => ?pause,
+        );
+    }
+}
+
+struct CollectionTask<'a> {
+    expected_collector: CollectorId,
+    /// The space that objects are being copied into
+    to_space: &'a mut Space,
+    /// The number of copied objects that have been scanned
+    ///
+    /// Objects after this in `to_space.objects` still need to be scanned
+    /// (they're implicitly in the grey set).
+    scanned: usize,
+    /// The objects that are being left in place (in either space)
+    retained: Vec<*mut GcHeader>,
+    /// The number of retained objects that have been scanned
+    scanned_retained: usize,
+    /// The weak references encountered while copying
+    weak_refs: Vec<WeakRef>,
+    /// The ephemerons whose keys haven't (yet) been found reachable
+    ephemerons: Vec<Ephemeron>,
+}
+impl<'a> CollectionTask<'a> {
+    #[inline]
+    fn visitor(&mut self) -> CopyVisitor<'_> {
+        LayoutVisitor::new(
+            self.expected_collector,
+            Copying {
+                to_space: self.to_space,
+                retained: &mut self.retained,
+                weak_refs: &mut self.weak_refs,
+                ephemerons: &mut self.ephemerons,
+            },
+        )
+    }
+    fn run(&mut self, roots: Vec<*mut dyn DynTrace<RawCopyingCollector>>) {
+        // Copy everything directly referenced by the roots, updating them in place
+        for root in roots {
+            // Dynamically dispatched
+            unsafe {
+                (*root).trace(&mut self.visitor());
+            }
+        }
+        self.process_scan_queue();
+        // Trace the values of ephemerons with reachable keys
+        self.process_ephemerons();
+        // Update weak references (and ephemeron keys), clearing the unreachable ones
+        self.process_weak_refs();
+    }
+    /// Scan all the copied objects that haven't been scanned yet,
+    /// copying everything they reference.
+    ///
+    /// This is a breadth-first traversal, using the to-space itself as the queue.
+    /// Objects that are left in place are scanned as well.
+    fn process_scan_queue(&mut self) {
+        loop {
+            let header = if self.scanned < self.to_space.objects.len() {
+                self.scanned += 1;
+                self.to_space.objects[self.scanned - 1]
+            } else if self.scanned_retained < self.retained.len() {
+                self.scanned_retained += 1;
+                self.retained[self.scanned_retained - 1]
+            } else {
+                break;
+            };
+            unsafe {
+                self.visitor().trace_contents(header);
+            }
+        }
+    }
+    /// Trace the values of all the ephemerons whose keys are reachable.
+    ///
+    /// Tracing a value can make even more keys reachable,
+    /// so this needs to iterate until it reaches a fixpoint.
+    /// Any ephemerons that remain afterwards have (currently) unreachable keys.
+    /// Those are cleared by [CollectionTask::process_weak_refs].
+    fn process_ephemerons(&mut self) {
+        loop {
+            let mut progress = false;
+            for ephemeron in std::mem::take(&mut self.ephemerons) {
+                if unsafe { (*ephemeron.key.referent).forwarded().is_none() } {
+                    // Still unknown, try again on the next iteration
+                    self.ephemerons.push(ephemeron);
+                    continue;
+                }
+                unsafe {
+                    ephemeron.trace_value(&mut self.visitor());
+                }
+                self.weak_refs.push(ephemeron.key);
+                progress = true;
+            }
+            if !progress {
+                break;
+            }
+            self.process_scan_queue();
+        }
+    }
+    /// Update all weak references to point to the new copies of their referents,
+    /// clearing the ones whose referents weren't copied.
+    /// Ephemerons whose keys are still unreachable are cleared as well.
+    ///
+    /// This must happen after copying has finished,
+    /// but before the old space is reset.
+    fn process_weak_refs(&mut self) {
+        for ephemeron in self.ephemerons.drain(..) {
+            unsafe { ephemeron.key.clear() }
+        }
+        for weak in self.weak_refs.drain(..) {
+            unsafe { weak.update((*weak.referent).forwarded()) }
+        }
+    }
+}
+
+type WeakRef = zerogc_context::layout::WeakRef<RawCopyingCollector>;
+type Ephemeron = zerogc_context::layout::Ephemeron<RawCopyingCollector>;
+
+#[doc(hidden)] // NOTE: Needs be public for RawCollectorImpl
+pub type CopyVisitor<'a> = LayoutVisitor<'a, RawCopyingCollector>;
+
+/// The state of the [CopyVisitor] while copying
+#[doc(hidden)]
+pub struct Copying<'a> {
+    /// The space that objects are being copied into
+    to_space: &'a mut Space,
+    /// The objects that are being left in place
+    retained: &'a mut Vec<*mut GcHeader>,
+    /// The weak references that need to be updated after copying
+    weak_refs: &'a mut Vec<WeakRef>,
+    /// The ephemerons whose keys haven't been copied yet
+    ephemerons: &'a mut Vec<Ephemeron>,
+}
+impl Copying<'_> {
+    /// Copy the specified object into the to-space (if it hasn't been already),
+    /// returning the header of the new copy.
+    ///
+    /// The copy is pushed onto the scan queue,
+    /// so anything it references will be copied later.
+    unsafe fn forward(&mut self, header: *mut GcHeader) -> *mut GcHeader {
+        if let Some(forwarded) = (*header).forwarded() {
+            return forwarded;
+        } else if self.to_space.contains(header) {
+            if self.to_space.retained.binary_search(&header).is_ok() {
+                // Left here by the last collection, and not visited yet
+                self.retain(header);
+            }
+            // Otherwise, this reference has already been updated
+            return header;
+        }
+        let type_info = (*header).type_info;
+        let header_layout = type_info.header_layout();
+        let layout = type_info.determine_total_layout(header);
+        let old_start = header_layout.from_common_header(header) as *mut u8;
+        /*
+         * The amount charged to the old space accounts for the worst-case padding,
+         * so this can only fail if there are objects that were left in the to-space.
+         * In that case, this object is left where it is as well.
+         */
+        let new_start = match self.to_space.bump(layout) {
+            Some(new_start) => new_start,
+            None => {
+                self.retain(header);
+                return header;
+            }
+        };
+        std::ptr::copy_nonoverlapping(old_start, new_start.as_ptr(), layout.size());
+        let new_header = header_layout.common_header(new_start.as_ptr().cast());
+        (*header).set_forwarded(new_header);
+        self.to_space.objects.push(new_header);
+        new_header
+    }
+    /// Leave a pinned object where it is (if it hasn't been visited already)
+    unsafe fn pin(&mut self, header: *mut GcHeader) {
+        if (*header).forwarded().is_none() {
+            self.retain(header);
+        }
+    }
+    /// Leave an object where it is, instead of copying it.
+    ///
+    /// The object is forwarded to itself (until the collection is done),
+    /// and pushed onto the scan queue.
+    unsafe fn retain(&mut self, header: *mut GcHeader) {
+        (*header).set_forwarded(header);
+        self.retained.push(header);
+    }
+}
+unsafe impl LayoutCollector for RawCopyingCollector {
+    type HeaderState = Forwarding;
+    type VisitorState<'a> = Copying<'a>;
+
+    #[inline]
+    unsafe fn visit(visitor: &mut CopyVisitor<'_>, header: *mut GcHeader) -> *mut GcHeader {
+        visitor.state.forward(header)
+    }
+
+    #[inline]
+    unsafe fn visit_weak(visitor: &mut CopyVisitor<'_>, weak: WeakRef) {
+        /*
+         * Don't copy the referent. Just remember the reference,
+         * so it can be updated once copying has finished.
+         */
+        visitor.state.weak_refs.push(weak);
+    }
+
+    unsafe fn visit_ephemeron(visitor: &mut CopyVisitor<'_>, ephemeron: Ephemeron) {
+        if (*ephemeron.key.referent).forwarded().is_some() {
+            // The key is already known to be reachable
+            ephemeron.trace_value(visitor);
+            visitor.state.weak_refs.push(ephemeron.key);
+        } else {
+            /*
+             * We don't know whether the key is reachable yet.
+             * Defer tracing the value until we do.
+             */
+            visitor.state.ephemerons.push(ephemeron);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+#![feature(
+    arbitrary_self_types, // Unfortunately this is required for methods on Gc refs
+)]
+use slog::Logger;
+
+use zerogc::prelude::*;
+use zerogc::GcAllocError;
+use zerogc_derive::Trace;
+
+use zerogc_copying::{
+    CollectorId as CopyingCollectorId, CopyingCollector, Gc, GcArray, GcConfig, GcVec, GcWeak,
+};
+
+fn test_collector() -> CopyingCollector {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    CopyingCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(CopyingCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(CopyingCollectorId))]
+struct Node<'gc> {
+    val: usize,
+    next: Option<Gc<'gc, Node<'gc>>>,
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(CopyingCollectorId))]
+struct Cache<'gc> {
+    entry: GcWeak<'gc, Dummy>,
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(CopyingCollectorId))]
+struct Big {
+    values: [u64; 16],
+}
+
+#[test]
+fn objects_move() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    context.alloc(Dummy { val: 1 });
+    let kept = context.alloc(Dummy { val: 2 });
+    let old_address = &*kept as *const Dummy as usize;
+    let kept: Gc<Dummy> = safepoint!(context, kept);
+    assert_ne!(&*kept as *const Dummy as usize, old_address);
+    assert_eq!(kept.val, 2);
+    let stats = context.system().stats();
+    assert_eq!(stats.collections, 1);
+    assert!(stats.last_freed_bytes > 0);
+    assert_eq!(stats.last_copied_bytes, stats.live_bytes);
+}
+
+#[test]
+fn linked_list_intact() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let mut head: Option<Gc<Node>> = None;
+    for val in 0..100 {
+        head = Some(context.alloc(Node { val, next: head }));
+        // Garbage in between the nodes
+        context.alloc(Dummy { val });
+    }
+    let mut head: Option<Gc<Node>> = safepoint!(context, head);
+    let mut expected = 100;
+    while let Some(node) = head {
+        expected -= 1;
+        assert_eq!(node.val, expected);
+        head = node.next;
+    }
+    assert_eq!(expected, 0);
+}
+
+#[test]
+fn handles_relocated() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let value = context.alloc(Dummy { val: 42 });
+    let handle = value.create_handle();
+    assert_eq!(context.system().stats().num_handles, 1);
+    // The handle is the only thing keeping the value alive
+    safepoint!(context, ());
+    safepoint!(context, ());
+    let value = handle.bind_to(&context);
+    assert_eq!(value.val, 42);
+}
+
+#[test]
+fn weak_updated() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let target = context.alloc(Dummy { val: 1 });
+    let cache = context.alloc(Cache {
+        entry: target.downgrade(),
+    });
+    let (cache, target): (Gc<Cache>, Gc<Dummy>) = safepoint!(context, (cache, target));
+    let entry = cache.entry.upgrade().unwrap();
+    assert_eq!(entry.val, 1);
+    assert!(std::ptr::eq(&*entry, &*target));
+    let cache: Gc<Cache> = safepoint!(context, cache);
+    assert!(cache.entry.is_cleared());
+}
+
+#[test]
+fn arrays_and_vecs_move() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let array: GcArray<u64> = context.alloc_slice_copy(&[1, 2, 3]);
+    let mut vec: GcVec<Gc<Dummy>> = context.alloc_vec_with_capacity(4);
+    for val in 0..3 {
+        vec.push(context.alloc(Dummy { val }));
+    }
+    let (array, mut vec): (GcArray<u64>, GcVec<Gc<Dummy>>) = safepoint!(context, (array, vec));
+    assert_eq!(array.as_slice(), &[1, 2, 3]);
+    assert_eq!(vec.len(), 3);
+    vec.push(context.alloc(Dummy { val: 3 }));
+    let vec: GcVec<Gc<Dummy>> = safepoint!(context, vec);
+    for (i, dummy) in vec.iter().enumerate() {
+        assert_eq!(dummy.val, i);
+    }
+}
+
+#[test]
+fn space_exhausted() {
+    let mut config = GcConfig::default();
+    config.space_size = 1024;
+    let collector =
+        CopyingCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()));
+    let mut context = collector.into_context();
+    let mut last = None;
+    loop {
+        match context.try_alloc(Big { values: [7; 16] }) {
+            Ok(big) => last = Some(big),
+            Err(cause) => {
+                assert_eq!(cause, GcAllocError::HeapLimitExceeded);
+                break;
+            }
+        }
+    }
+    // Only the last object survives, so a collection frees enough room
+    let last: Gc<Big> = safepoint!(context, last.unwrap());
+    assert_eq!(last.values, [7; 16]);
+    assert_eq!(context.system().stats().collections, 1);
+    let big = context.try_alloc(Big { values: [3; 16] }).unwrap();
+    assert_eq!(big.values, [3; 16]);
+}
+
+#[test]
+fn spaces_grow() {
+    let mut config = GcConfig::default();
+    config.space_size = 1024;
+    let collector =
+        CopyingCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()));
+    let mut context = collector.into_context();
+    let mut list: Option<Gc<Node>> = None;
+    for val in 0..1000 {
+        // Invoke a safepoint and retry whenever the space is full
+        loop {
+            match context.try_alloc(Node { val, next: list }) {
+                Ok(node) => {
+                    list = Some(node);
+                    break;
+                }
+                Err(cause) => {
+                    assert_eq!(cause, GcAllocError::HeapLimitExceeded);
+                    list = safepoint!(context, list);
+                }
+            }
+        }
+    }
+    let stats = context.system().stats();
+    assert!(stats.collections > 0);
+    assert!(stats.space_size > 1024);
+    let mut expected = 1000;
+    while let Some(node) = list {
+        expected -= 1;
+        assert_eq!(node.val, expected);
+        list = node.next;
+    }
+    assert_eq!(expected, 0);
+}
+
+#[test]
+fn max_space_size() {
+    let mut config = GcConfig::default();
+    config.space_size = 1024;
+    config.max_space_size = Some(2048);
+    let collector =
+        CopyingCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()));
+    let mut context = collector.into_context();
+    let mut live = Vec::new();
+    for _ in 0..4 {
+        while let Ok(big) = context.try_alloc(Big { values: [7; 16] }) {
+            live.push(big.create_handle());
+        }
+        safepoint!(context, ());
+    }
+    let stats = context.system().stats();
+    assert_eq!(stats.space_size, 2048);
+    assert!(stats.threshold <= 2048);
+    // Nothing was freed, so the space is still full
+    assert_eq!(
+        context.try_alloc(Big { values: [3; 16] }).unwrap_err(),
+        GcAllocError::HeapLimitExceeded
+    );
+    for handle in &live {
+        assert_eq!(handle.bind_to(&context).values, [7; 16]);
+    }
+}
+
+#[test]
+fn array_size_overflow() {
+    let collector = test_collector();
+    let context = collector.into_context();
+    assert_eq!(
+        context
+            .try_alloc_vec_with_capacity::<u64>(usize::MAX)
+            .unwrap_err(),
+        GcAllocError::SizeOverflow
+    );
+}
+
+#[test]
+fn pinned_stays_in_place() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    context.alloc(Dummy { val: 0 });
+    let tail = context.alloc(Node { val: 2, next: None });
+    let node = context.alloc(Node {
+        val: 1,
+        next: Some(tail),
+    });
+    let pinned = node.pin();
+    let address = pinned.as_raw_ptr();
+    let weak = node.downgrade();
+    // Collect several times, so both spaces are used as the to-space
+    let mut weak: GcWeak<Node> = safepoint!(context, weak);
+    for val in 0..4 {
+        for _ in 0..32 {
+            context.alloc(Big { values: [val; 16] });
+        }
+        weak = safepoint!(context, weak);
+        assert_eq!(pinned.as_raw_ptr(), address);
+        let node = weak.upgrade().unwrap();
+        assert!(std::ptr::eq(&*node, address));
+        assert_eq!(node.val, 1);
+        // Only the pinned node references the tail, which still moves
+        assert_eq!(node.next.unwrap().val, 2);
+    }
+    /*
+     * Once the pin is released, the node moves like everything else.
+     * If it was left in the to-space, that has to wait until the space is evacuated.
+     */
+    drop(pinned);
+    let node: Gc<Node> = safepoint!(context, weak.upgrade().unwrap());
+    let node: Gc<Node> = safepoint!(context, node);
+    assert!(!std::ptr::eq(&*node, address));
+    assert_eq!(node.val, 1);
+    assert_eq!(node.next.unwrap().val, 2);
+    let weak: GcWeak<Node> = safepoint!(context, node.downgrade());
+    assert!(weak.is_cleared());
+}
+
+#[test]
+fn allocations_skip_pinned() {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true;
+    config.space_size = 4096;
+    config.max_space_size = Some(4096);
+    let collector =
+        CopyingCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()));
+    let mut context = collector.into_context();
+    let mut live: Vec<_> = (0..8)
+        .map(|val| context.alloc(Big { values: [val; 16] }).pin())
+        .collect();
+    // Unpin every other object, leaving holes in between the pinned ones
+    for i in (0..8).rev().step_by(2) {
+        live.remove(i);
+    }
+    safepoint!(context, ());
+    let mut vec: GcVec<Gc<Big>> = context.alloc_vec_with_capacity(64);
+    while let Ok(big) = context.try_alloc(Big { values: [100; 16] }) {
+        vec.push(big);
+        vec = safepoint!(context, vec);
+    }
+    assert!(!vec.is_empty());
+    for big in vec.iter() {
+        assert_eq!(big.values, [100; 16]);
+    }
+    for (i, pinned) in live.iter().enumerate() {
+        let values = unsafe { (*pinned.as_raw_ptr()).values };
+        assert_eq!(values, [(i * 2) as u64; 16]);
+    }
+}
@@ -0,0 +1,94 @@
+//! Implementation of the `#[dyn_trace]` attribute
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_quote, Error, GenericParam, ItemTrait, Lifetime, Path, Token, TypeParamBound};
+
+use crate::zerogc_crate;
+
+/// The arguments to `#[dyn_trace(collector_id = Id)]`
+pub struct DynTraceArgs {
+    collector_id: Path,
+}
+impl Parse for DynTraceArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key = input.parse::<syn::Ident>()?;
+        if key != "collector_id" {
+            return Err(Error::new(key.span(), "Expected `collector_id = ...`"));
+        }
+        input.parse::<Token![=]>()?;
+        let collector_id = input.parse::<Path>()?;
+        if !input.is_empty() {
+            return Err(input.error("Unexpected tokens after collector_id"));
+        }
+        Ok(DynTraceArgs { collector_id })
+    }
+}
+
+pub fn expand(args: DynTraceArgs, mut input: ItemTrait) -> Result<TokenStream, Error> {
+    let zerogc_crate = zerogc_crate();
+    let collector_id = &args.collector_id;
+    let gc_lt = Lifetime::new("'gc", Span::call_site());
+    if !input
+        .generics
+        .lifetimes()
+        .any(|def| def.lifetime.ident == "gc")
+    {
+        return Err(Error::new(
+            input.ident.span(),
+            "The trait must have a `'gc` lifetime parameter",
+        ));
+    }
+    input.supertraits.push(TypeParamBound::Trait(
+        parse_quote!(#zerogc_crate::DynTrace<#gc_lt, #collector_id>),
+    ));
+    let ident = &input.ident;
+    let mut lifetimes = Vec::new();
+    let mut params = Vec::new();
+    let mut target_args = Vec::new();
+    let mut branded_args = Vec::new();
+    for param in &input.generics.params {
+        match *param {
+            GenericParam::Lifetime(ref def) => {
+                let lt = &def.lifetime;
+                lifetimes.push(lt.clone());
+                target_args.push(quote!(#lt));
+                if lt.ident == "gc" {
+                    branded_args.push(quote!('new_gc));
+                } else {
+                    branded_args.push(quote!(#lt));
+                }
+            }
+            GenericParam::Type(ref tp) => {
+                let param = &tp.ident;
+                params.push(param.clone());
+                target_args.push(quote!(#param));
+                branded_args.push(quote!(#param));
+            }
+            GenericParam::Const(ref c) => {
+                return Err(Error::new_spanned(
+                    c,
+                    "Const parameters are not supported by #[dyn_trace]",
+                ));
+            }
+        }
+    }
+    let generics = crate::move_bounds_to_where_clause(input.generics.clone());
+    let where_clause = generics
+        .where_clause
+        .as_ref()
+        .map(|clause| {
+            let predicates = &clause.predicates;
+            quote!({ where #predicates })
+        })
+        .unwrap_or_default();
+    Ok(quote! {
+        #input
+        #zerogc_crate::trait_object_trace!(
+            impl<#(#lifetimes,)* #(#params),*> Trace for dyn #ident<#(#target_args),*> #where_clause;
+            Branded<'new_gc> => (dyn #ident<#(#branded_args),*> + 'new_gc),
+            collector_id => #collector_id,
+            gc_lifetime => #gc_lt
+        );
+    })
+}
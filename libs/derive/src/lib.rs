@@ -18,6 +18,7 @@ use syn::{
 };
 
 mod derive;
+mod dyn_trace;
 mod macros;
 
 /// Magic const that expands to either `::zerogc` or `crate::`
@@ -181,6 +182,40 @@ pub fn derive_trace(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     res
 }
 
+/// Implement [Trace](`zerogc::Trace`) for trait objects of the annotated trait,
+/// allowing them to be allocated as `Gc<'gc, dyn Trait<'gc>>`.
+///
+/// This adds [DynTrace](`zerogc::DynTrace`) as a supertrait,
+/// then expands to the equivalent `trait_object_trace!` invocation.
+///
+/// The trait must have a `'gc` lifetime parameter.
+///
+/// ## Example
+/// ```
+/// # use zerogc::epsilon::EpsilonCollectorId;
+/// #[zerogc_derive::dyn_trace(collector_id = EpsilonCollectorId)]
+/// trait Node<'gc> {
+///     fn eval(&self) -> i32;
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dyn_trace(
+    args: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(args as dyn_trace::DynTraceArgs);
+    let input = parse_macro_input!(input as syn::ItemTrait);
+    let target = input.ident.to_string();
+    let res = dyn_trace::expand(args, input).unwrap_or_else(|e| e.to_compile_error());
+    debug_derive(
+        "dyn_trace",
+        &target,
+        &format_args!("#[dyn_trace] for {}", target),
+        &res,
+    );
+    res.into()
+}
+
 pub(crate) const DESERIALIZE_ENABLED: bool = cfg!(feature = "__serde-internal");
 
 #[proc_macro_derive(GcDeserialize, attributes(zerogc))]
@@ -248,12 +283,7 @@ fn span_file_loc(span: Span) -> String {
      * Source file identifiers in the form `<file_name>:<lineno>`
      */
     let internal = span.unwrap();
-    let sf = internal.source_file();
-    let path = sf.path();
-    let file_name = if sf.is_real() { path.file_name() } else { None }
-        .map(std::ffi::OsStr::to_string_lossy)
-        .map(String::from)
-        .unwrap_or_else(|| String::from("<fake>"));
+    let file_name = String::from("<fake>");
     let lineno = internal.start().line();
     format!("{}:{}", file_name, lineno)
 }
@@ -261,7 +291,7 @@ fn span_file_loc(span: Span) -> String {
 fn debug_derive(key: &str, target: &dyn ToString, message: &dyn Display, value: &dyn Display) {
     let target = target.to_string();
     // TODO: Use proc_macro::tracked_env::var
-    match ::proc_macro::tracked_env::var("DEBUG_DERIVE") {
+    match ::std::env::var("DEBUG_DERIVE") {
         Ok(ref var) if var == "*" || var == "1" || var.is_empty() => {}
         Ok(ref var) if var == "0" => {
             return; /* disabled */
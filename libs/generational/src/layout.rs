@@ -0,0 +1,95 @@
+//! The in memory layout of objects using the generational collector.
+//!
+//! Objects use the [shared layout](zerogc_context::layout) from `zerogc-context`.
+//! Each [GcHeader] stores the object's generation and mark bits in its [ObjectState].
+//!
+//! ## Safety
+//! Relying on this internal layout is incredibly unsafe.
+//! Unlike `zerogc-simple`, the layout isn't (yet) committed to a stable ABI.
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use zerogc_context::layout;
+
+use crate::{MarkState, RawGenerationalCollector};
+
+pub use zerogc_context::layout::{GcTypeLayout, HeaderLayout, StaticGcType, StaticVecType};
+
+/// A header for a GC object
+pub type GcHeader = layout::GcHeader<RawGenerationalCollector>;
+/// A header for a GC array object
+pub type GcArrayHeader = layout::GcArrayHeader<RawGenerationalCollector>;
+/// A header for a Gc vector
+pub type GcVecHeader = layout::GcVecHeader<RawGenerationalCollector>;
+/// A type used by GC
+pub type GcType = layout::GcType<RawGenerationalCollector>;
+/// The raw representation of a vector in the generational collector
+pub type GenerationalVecRepr<'gc, T> = layout::VecRepr<'gc, T, RawGenerationalCollector>;
+
+/// The bits of an object's state that store its [MarkState]
+const MARK_MASK: u8 = 0b11;
+/// Set if the object has been promoted to the old generation
+const OLD_FLAG: u8 = 0b100;
+/// Set if the object is currently in the remembered set
+const REMEMBERED_FLAG: u8 = 0b1000;
+
+/// The generation an object belongs to
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Generation {
+    /// The object is in the nursery.
+    ///
+    /// Young objects are collected by every (minor) collection.
+    Young,
+    /// The object has been promoted to the old generation,
+    /// and is only collected by a major collection.
+    Old,
+}
+
+/// The object's mark state, generation and remembered flag
+///
+/// This is atomic because write barriers can be triggered
+/// by multiple threads at once.
+pub struct ObjectState(AtomicU8);
+impl ObjectState {
+    /// The state of a new (white) object in the specified generation
+    #[inline]
+    pub(crate) fn new(generation: Generation) -> Self {
+        let generation_bits = match generation {
+            Generation::Young => 0,
+            Generation::Old => OLD_FLAG,
+        };
+        ObjectState(AtomicU8::new(MarkState::White as u8 | generation_bits))
+    }
+    /// The generation this object currently belongs to
+    #[inline]
+    pub fn generation(&self) -> Generation {
+        if self.0.load(Ordering::Relaxed) & OLD_FLAG != 0 {
+            Generation::Old
+        } else {
+            Generation::Young
+        }
+    }
+    /// Move this object into the old generation
+    #[inline]
+    pub(crate) fn promote(&self) {
+        self.0.fetch_or(OLD_FLAG, Ordering::Relaxed);
+    }
+    #[inline]
+    pub(crate) fn mark_state(&self) -> MarkState {
+        MarkState::from_bits(self.0.load(Ordering::Relaxed) & MARK_MASK)
+    }
+    #[inline]
+    pub(crate) fn update_mark_state(&self, state: MarkState) {
+        let old = self.0.load(Ordering::Relaxed);
+        self.0.store((old & !MARK_MASK) | state as u8, Ordering::Relaxed);
+    }
+    /// Set the remembered flag,
+    /// returning `true` if it wasn't already set.
+    #[inline]
+    pub(crate) fn try_remember(&self) -> bool {
+        self.0.fetch_or(REMEMBERED_FLAG, Ordering::AcqRel) & REMEMBERED_FLAG == 0
+    }
+    #[inline]
+    pub(crate) fn clear_remembered(&self) {
+        self.0.fetch_and(!REMEMBERED_FLAG, Ordering::Relaxed);
+    }
+}
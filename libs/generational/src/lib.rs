@@ -0,0 +1,932 @@
+//! A generational garbage collector for zerogc.
+//!
+//! Most objects die young, so re-marking the entire heap every collection
+//! wastes a lot of time on objects that are known to be long-lived.
+//! This collector splits the heap into two generations:
+//! 1. The young generation (nursery), where all new objects are allocated
+//! 2. The old generation, containing all objects that have survived a collection
+//!
+//! A minor collection only marks and sweeps the nursery, which is cheap
+//! no matter how large the old generation grows.
+//! Any young object that survives a collection is immediately promoted
+//! to the old generation, so the nursery is always empty afterwards.
+//!
+//! Once the old generation grows past its threshold,
+//! a major collection marks and sweeps the entire heap (just like `zerogc-simple`).
+//!
+//! Objects are never moved. Promotion only changes a flag in the [GcHeader].
+//!
+//! ## Write barriers
+//! References from the old generation into the nursery must be treated as roots
+//! by a minor collection, or it could free a young object that's still reachable.
+//! These are tracked in a remembered set, which is maintained by the write barrier
+//! ([zerogc::CollectorId::gc_write_barrier]).
+//!
+//! The write barrier is triggered by the setters generated by `#[zerogc(mutable)]`.
+//! [GcCell::set](zerogc::cell::GcCell::set) only accepts [NullTrace](zerogc::NullTrace) values,
+//! so it never needs a barrier.
+//!
+//! Vectors don't (yet) have write barriers. To compensate, [GcVec]s are always
+//! allocated directly in the old generation, and are re-scanned by every minor collection.
+//!
+//! Weak references go through the same barrier.
+//! Storing a [GcWeak](zerogc::GcWeak) to a young object into an old one
+//! adds the old object to the remembered set. The next minor collection re-scans it,
+//! clearing the weak reference if the young object turns out to be unreachable.
+//! Unsafe code that stores a weak reference without triggering the barrier
+//! would leave it dangling once the young object is freed.
+//!
+//! ## Internals
+//! The internal layout information is public,
+//! and available through the (layout module)[`self::layout`].
+#![deny(
+    missing_docs, // The 'generational' implementation needs to document its public API
+)]
+#![feature(
+    never_type, // Used for errors (which are currently impossible)
+    exhaustive_patterns, // Allow exhaustive matching against never
+)]
+use std::alloc::Layout;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use slog::{debug, FnValue, Logger};
+
+use zerogc::{GcAllocError, GcSafe, Trace};
+
+use zerogc_context::collector::{RawNonMovingImpl, RawSimpleAlloc};
+use zerogc_context::handle::{GcHandleList, RawHandleImpl, RawPinImpl};
+use zerogc_context::layout::{DynTrace, HandleListRoot, LayoutCollector, LayoutVisitor};
+use zerogc_context::utils::{ILock, Lock, MemorySize, ThreadId};
+use zerogc_context::{
+    CollectionManager as AbstractCollectionManager, CollectorContext,
+    RawContext as AbstractRawContext,
+};
+
+use crate::layout::{
+    GcArrayHeader, GcHeader, GcType, GcVecHeader, Generation, GenerationalVecRepr, HeaderLayout,
+    ObjectState, StaticGcType, StaticVecType,
+};
+
+pub mod layout;
+
+/// The configuration for a generational garbage collector
+pub struct GcConfig {
+    /// Whether to always force a (minor) collection at safepoints,
+    /// regardless of whether the heuristics say.
+    ///
+    /// To force a single collection, use [zerogc::safepoint_force!] instead.
+    pub always_force_collect: bool,
+    /// The number of bytes that can be allocated in the nursery
+    /// before triggering a minor collection
+    pub nursery_size: usize,
+    /// The initial size of the old generation (in bytes)
+    /// that triggers a major collection.
+    ///
+    /// After each major collection, the threshold is
+    /// set to twice the size of the surviving old generation
+    /// (but never less than this).
+    pub initial_old_threshold: usize,
+}
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            always_force_collect: false,
+            nursery_size: 64 * 1024,
+            initial_old_threshold: 1024 * 1024,
+        }
+    }
+}
+
+/// A snapshot of statistics about a [GenerationalCollector]
+///
+/// This can be retrieved with [GenerationalCollector::stats]
+#[derive(Clone, Debug, Default)]
+pub struct GcStats {
+    /// The number of minor collections (of only the nursery) that have been performed
+    pub minor_collections: u64,
+    /// The number of major collections (of the entire heap) that have been performed
+    pub major_collections: u64,
+    /// The total time spent in collections
+    pub total_pause: Duration,
+    /// The duration of the last collection
+    pub last_pause: Duration,
+    /// The number of bytes freed by the last collection
+    pub last_freed_bytes: usize,
+    /// The total number of bytes freed by all collections
+    pub total_freed_bytes: u64,
+    /// The total number of bytes promoted from the nursery to the old generation
+    pub promoted_bytes: u64,
+    /// The number of bytes currently allocated in the nursery
+    pub young_bytes: usize,
+    /// The number of bytes currently allocated in the old generation
+    pub old_bytes: usize,
+    /// The current size of the old generation that will trigger the next major collection
+    pub old_threshold: usize,
+    /// The number of old objects currently in the remembered set
+    pub remembered_objects: usize,
+    /// The number of handles that are currently alive
+    pub num_handles: usize,
+    /// The number of free handle slots, that can be reused by new handles
+    ///
+    /// Slots that are no longer needed are released after each collection.
+    pub free_handle_slots: usize,
+}
+
+#[cfg(feature = "sync")]
+type RawContext<C> = zerogc_context::state::sync::RawContext<C>;
+#[cfg(feature = "sync")]
+type CollectionManager<C> = zerogc_context::state::sync::CollectionManager<C>;
+#[cfg(not(feature = "sync"))]
+type RawContext<C> = zerogc_context::state::nosync::RawContext<C>;
+#[cfg(not(feature = "sync"))]
+type CollectionManager<C> = zerogc_context::state::nosync::CollectionManager<C>;
+
+/// A generational garbage collector
+pub type GenerationalCollector = ::zerogc_context::CollectorRef<RawGenerationalCollector>;
+/// The context of a generational collector
+pub type GenerationalCollectorContext =
+    ::zerogc_context::CollectorContext<RawGenerationalCollector>;
+/// The id for a generational collector
+pub type CollectorId = ::zerogc_context::CollectorId<RawGenerationalCollector>;
+/// A garbage collected pointer, allocated in the generational collector
+pub type Gc<'gc, T> = ::zerogc::Gc<'gc, T, CollectorId>;
+/// A weak reference to an object in the generational collector
+pub type GcWeak<'gc, T> = ::zerogc::GcWeak<'gc, T, CollectorId>;
+/// A garbage collected array, allocated in the generational collector
+pub type GcArray<'gc, T> = ::zerogc::array::GcArray<'gc, T, CollectorId>;
+/// A garbage collected vector, allocated in the generational collector
+pub type GcVec<'gc, T> = ::zerogc::vec::GcVec<'gc, T, CollectorId>;
+
+/// Determine which generation the specified object currently belongs to.
+#[inline]
+pub fn generation_of<'gc, T: ?Sized + GcSafe<'gc, CollectorId>>(gc: Gc<'gc, T>) -> Generation {
+    unsafe { (*GcHeader::from_value_ptr(gc.as_raw_ptr())).state.generation() }
+}
+
+unsafe impl RawSimpleAlloc for RawGenerationalCollector {
+    #[inline]
+    unsafe fn try_alloc_uninit<'gc, T>(
+        context: &'gc GenerationalCollectorContext,
+    ) -> Result<*mut T, GcAllocError>
+    where
+        T: GcSafe<'gc, crate::CollectorId>,
+    {
+        let (_header, ptr) = context.collector().heap.alloc_layout(
+            GcHeader::LAYOUT,
+            Layout::new::<T>(),
+            <T as StaticGcType<Self>>::STATIC_TYPE,
+            Generation::Young,
+        )?;
+        Ok(ptr as *mut T)
+    }
+
+    unsafe fn try_alloc_uninit_slice<'gc, T>(
+        context: &'gc CollectorContext<Self>,
+        len: usize,
+    ) -> Result<*mut T, GcAllocError>
+    where
+        T: GcSafe<'gc, crate::CollectorId>,
+    {
+        let (header, ptr) = context.collector().heap.alloc_layout(
+            GcArrayHeader::LAYOUT,
+            Layout::array::<T>(len)?,
+            <[T] as StaticGcType<Self>>::STATIC_TYPE,
+            Generation::Young,
+        )?;
+        (*header).len = len;
+        Ok(ptr.cast())
+    }
+
+    fn try_alloc_raw_vec_with_capacity<'gc, T>(
+        context: &'gc CollectorContext<Self>,
+        capacity: usize,
+    ) -> Result<Self::RawVec<'gc, T>, GcAllocError>
+    where
+        T: GcSafe<'gc, crate::CollectorId>,
+    {
+        /*
+         * Vectors don't have write barriers,
+         * so they're pretenured into the old generation
+         * and re-scanned by every minor collection.
+         */
+        let heap = &context.collector().heap;
+        let (header, value_ptr) = heap.alloc_layout(
+            GcVecHeader::LAYOUT,
+            Layout::array::<T>(capacity)?,
+            <T as StaticVecType<Self>>::STATIC_VEC_TYPE,
+            Generation::Old,
+        )?;
+        unsafe {
+            (*header).capacity = capacity;
+            (*header).len.set(0);
+            heap.old_vecs.lock().push(&mut (*header).common_header);
+            let res = GenerationalVecRepr::from_raw_parts(NonNull::new_unchecked(header), context);
+            debug_assert_eq!(res.as_ptr(), value_ptr as *mut T as *const T);
+            Ok(res)
+        }
+    }
+}
+
+unsafe impl RawHandleImpl for RawGenerationalCollector {
+    type TypeInfo = GcType;
+
+    #[inline]
+    fn type_info_of<'gc, T: GcSafe<'gc, CollectorId>>() -> &'static Self::TypeInfo {
+        <T as StaticGcType<Self>>::STATIC_TYPE
+    }
+
+    #[inline]
+    fn resolve_type_info<'gc, T: ?Sized + GcSafe<'gc, CollectorId>>(
+        gc: zerogc::Gc<'gc, T, CollectorId>,
+    ) -> &'static Self::TypeInfo {
+        unsafe { (*GcHeader::from_value_ptr(gc.as_raw_ptr())).type_info }
+    }
+
+    #[inline]
+    fn handle_list(&self) -> &GcHandleList<Self> {
+        &self.handle_list
+    }
+}
+
+/// The generational collector never moves objects (even when promoting them),
+/// so pinned objects only need to be kept alive (just like any other handle).
+unsafe impl RawPinImpl for RawGenerationalCollector {}
+
+unsafe impl RawNonMovingImpl for RawGenerationalCollector {}
+
+struct GcHeap {
+    config: Arc<GcConfig>,
+    collector_id: Option<CollectorId>,
+    /// The objects in the nursery
+    young_objects: Lock<Vec<*mut GcHeader>>,
+    /// The objects in the old generation (including vectors)
+    old_objects: Lock<Vec<*mut GcHeader>>,
+    /// The vectors in the old generation,
+    /// which need to be re-scanned by every minor collection
+    old_vecs: Lock<Vec<*mut GcHeader>>,
+    /// Old objects that may reference young ones
+    remembered: Lock<Vec<*mut GcHeader>>,
+    young_size: AtomicUsize,
+    old_size: AtomicUsize,
+    old_threshold: AtomicUsize,
+}
+impl GcHeap {
+    fn new(config: Arc<GcConfig>) -> GcHeap {
+        GcHeap {
+            collector_id: None,
+            young_objects: Lock::from(Vec::new()),
+            old_objects: Lock::from(Vec::new()),
+            old_vecs: Lock::from(Vec::new()),
+            remembered: Lock::from(Vec::new()),
+            young_size: AtomicUsize::new(0),
+            old_size: AtomicUsize::new(0),
+            old_threshold: AtomicUsize::new(config.initial_old_threshold),
+            config,
+        }
+    }
+    #[inline]
+    fn young_size(&self) -> usize {
+        self.young_size.load(Ordering::Acquire)
+    }
+    #[inline]
+    fn old_size(&self) -> usize {
+        self.old_size.load(Ordering::Acquire)
+    }
+    #[inline]
+    fn alloc_layout<H>(
+        &self,
+        header_layout: HeaderLayout<H>,
+        value_layout: Layout,
+        static_type: &'static GcType,
+        generation: Generation,
+    ) -> Result<(*mut H, *mut u8), GcAllocError> {
+        let collector_id_ptr = match self.collector_id {
+            Some(ref collector) => collector,
+            None => unreachable!("Invalid collector id"),
+        };
+        let (overall_layout, value_offset) = header_layout.layout().extend(value_layout)?;
+        debug_assert_eq!(
+            header_layout.value_offset(value_layout.align()),
+            value_offset
+        );
+        let overall_layout = overall_layout.pad_to_align();
+        let header: *mut H = unsafe { std::alloc::alloc(overall_layout).cast() };
+        if header.is_null() {
+            return Err(GcAllocError::OutOfMemory);
+        }
+        unsafe {
+            let common_header = header_layout.common_header(header);
+            common_header.write(GcHeader::new(
+                static_type,
+                collector_id_ptr as *const CollectorId,
+                ObjectState::new(generation),
+            ));
+            match generation {
+                Generation::Young => {
+                    self.young_objects.lock().push(common_header);
+                    self.young_size
+                        .fetch_add(overall_layout.size(), Ordering::AcqRel);
+                }
+                Generation::Old => {
+                    self.old_objects.lock().push(common_header);
+                    self.old_size
+                        .fetch_add(overall_layout.size(), Ordering::AcqRel);
+                }
+            }
+            Ok((header, (header as *mut u8).add(value_offset)))
+        }
+    }
+    /// Add an old object to the remembered set (if it isn't already there)
+    #[cold]
+    fn remember(&self, header: &GcHeader) {
+        debug_assert_eq!(header.state.generation(), Generation::Old);
+        if header.state.try_remember() {
+            self.remembered
+                .lock()
+                .push(header as *const GcHeader as *mut GcHeader);
+        }
+    }
+    /// Remove every object from the remembered set
+    ///
+    /// This must happen before any of them can be freed.
+    unsafe fn clear_remembered(&self) {
+        for header in self.remembered.lock().drain(..) {
+            (*header).state.clear_remembered();
+        }
+    }
+    /// Drop and deallocate the specified object
+    unsafe fn free_object(header: *mut GcHeader) {
+        let type_info = (*header).type_info;
+        if let Some(func) = type_info.drop_func {
+            func((*header).value());
+        }
+        let layout = type_info.determine_total_layout(header);
+        let start = type_info.header_layout().from_common_header(header);
+        std::alloc::dealloc(start.cast(), layout);
+    }
+    /// Sweep the nursery, freeing unreachable objects
+    /// and promoting all the survivors into the old generation.
+    ///
+    /// Returns the number of bytes that were freed and promoted (respectively).
+    unsafe fn sweep_young(&self) -> (usize, usize) {
+        let mut freed = 0;
+        let mut promoted = 0;
+        let young = std::mem::take(&mut *self.young_objects.lock());
+        let mut old = self.old_objects.lock();
+        for header in young {
+            let size = (*header).type_info.determine_total_size(header);
+            match (*header).state.mark_state() {
+                MarkState::White => {
+                    freed += size;
+                    Self::free_object(header);
+                }
+                MarkState::Grey => panic!("All grey objects should've been processed"),
+                MarkState::Black => {
+                    (*header).state.update_mark_state(MarkState::White);
+                    (*header).state.promote();
+                    promoted += size;
+                    old.push(header);
+                }
+            }
+        }
+        debug_assert_eq!(freed + promoted, self.young_size());
+        self.young_size.store(0, Ordering::Release);
+        self.old_size.fetch_add(promoted, Ordering::AcqRel);
+        (freed, promoted)
+    }
+    /// Sweep the old generation, freeing unreachable objects.
+    ///
+    /// This must only be done after marking the entire heap.
+    /// Returns the number of bytes that were freed.
+    unsafe fn sweep_old(&self) -> usize {
+        let mut freed = 0;
+        let mut retained = 0;
+        // Forget about any vectors that are about to be freed
+        self.old_vecs
+            .lock()
+            .retain(|&header| (*header).state.mark_state() == MarkState::Black);
+        self.old_objects.lock().retain(|&header| {
+            let size = (*header).type_info.determine_total_size(header);
+            match (*header).state.mark_state() {
+                MarkState::White => {
+                    freed += size;
+                    Self::free_object(header);
+                    false
+                }
+                MarkState::Grey => panic!("All grey objects should've been processed"),
+                MarkState::Black => {
+                    (*header).state.update_mark_state(MarkState::White);
+                    retained += size;
+                    true
+                }
+            }
+        });
+        debug_assert_eq!(freed + retained, self.old_size());
+        self.old_size.store(retained, Ordering::Release);
+        freed
+    }
+    #[inline]
+    fn should_collect_relaxed(&self) -> bool {
+        /*
+         * Going with relaxed ordering because it's not essential
+         * that we see updates immediately.
+         * Eventual consistency should be enough to eventually
+         * trigger a collection.
+         */
+        self.config.always_force_collect
+            || self.young_size.load(Ordering::Relaxed) >= self.config.nursery_size
+            || self.old_size.load(Ordering::Relaxed) >= self.old_threshold.load(Ordering::Relaxed)
+    }
+}
+impl Drop for GcHeap {
+    fn drop(&mut self) {
+        unsafe {
+            for &header in self.young_objects.get_mut().iter() {
+                Self::free_object(header);
+            }
+            for &header in self.old_objects.get_mut().iter() {
+                Self::free_object(header);
+            }
+        }
+    }
+}
+
+/// We're careful - I swear :D
+unsafe impl Send for RawGenerationalCollector {}
+unsafe impl Sync for RawGenerationalCollector {}
+
+/// The internal data for a generational collector
+#[doc(hidden)]
+pub struct RawGenerationalCollector {
+    logger: Logger,
+    heap: GcHeap,
+    manager: CollectionManager<Self>,
+    /// Tracks object handles
+    handle_list: GcHandleList<Self>,
+    /// Statistics about previous collections
+    ///
+    /// The fields that describe the current state of the heap
+    /// are filled in when a snapshot is taken.
+    history: Lock<GcStats>,
+}
+
+unsafe impl ::zerogc_context::collector::RawCollectorImpl for RawGenerationalCollector {
+    type DynTracePtr = NonNull<dyn DynTrace<Self>>;
+    type Config = GcConfig;
+    type Stats = GcStats;
+
+    type Ptr = NonNull<Self>;
+
+    type Manager = CollectionManager<Self>;
+
+    type RawContext = RawContext<Self>;
+
+    type ContextCache = ();
+
+    type RawVec<'gc, T: GcSafe<'gc, CollectorId>> = GenerationalVecRepr<'gc, T>;
+
+    const SINGLETON: bool = false;
+
+    const SYNC: bool = cfg!(feature = "sync");
+
+    #[inline]
+    fn id_for_gc<'a, 'gc, T>(gc: &'a Gc<'gc, T>) -> &'a CollectorId
+    where
+        'gc: 'a,
+        T: ?Sized + 'gc,
+    {
+        unsafe {
+            let header = GcHeader::from_value_ptr(gc.as_raw_ptr());
+            (*header).collector_id()
+        }
+    }
+
+    #[inline]
+    fn id_for_array<'a, 'gc, T>(array: &'a GcArray<'gc, T>) -> &'a CollectorId
+    where
+        'gc: 'a,
+    {
+        unsafe {
+            let header = GcArrayHeader::LAYOUT.from_value_ptr(array.as_raw_ptr());
+            (*header).common_header.collector_id()
+        }
+    }
+
+    #[inline]
+    fn resolve_array_len<T>(gc: &GcArray<T>) -> usize {
+        unsafe {
+            let header = GcArrayHeader::LAYOUT.from_value_ptr(gc.as_raw_ptr());
+            (*header).len
+        }
+    }
+
+    #[inline]
+    unsafe fn as_dyn_trace_pointer<T: Trace>(value: *mut T) -> Self::DynTracePtr {
+        debug_assert!(!value.is_null());
+        NonNull::new_unchecked(std::mem::transmute::<
+            *mut dyn DynTrace<Self>,
+            *mut (dyn DynTrace<Self> + 'static),
+        >(value as *mut dyn DynTrace<Self>))
+    }
+
+    fn init(config: GcConfig, logger: Logger) -> NonNull<Self> {
+        let raw = RawGenerationalCollector::with_logger(config, logger);
+        let mut collector = Arc::new(raw);
+        let raw_ptr = unsafe {
+            NonNull::new_unchecked(Arc::as_ptr(&collector) as *mut RawGenerationalCollector)
+        };
+        Arc::get_mut(&mut collector).unwrap().heap.collector_id =
+            Some(unsafe { CollectorId::from_raw(raw_ptr) });
+        std::mem::forget(collector); // We own it as a raw pointer...
+        raw_ptr
+    }
+
+    #[inline]
+    unsafe fn gc_write_barrier<'gc, T, V>(
+        owner: &Gc<'gc, T>,
+        value: &Gc<'gc, V>,
+        _field_offset: usize,
+    ) where
+        T: GcSafe<'gc, CollectorId> + ?Sized,
+        V: GcSafe<'gc, CollectorId> + ?Sized,
+    {
+        let owner = &*GcHeader::from_value_ptr(owner.as_raw_ptr());
+        let value = &*GcHeader::from_value_ptr(value.as_raw_ptr());
+        /*
+         * We only need to remember references from the old generation
+         * into the nursery. Everything else is found by tracing.
+         */
+        if owner.state.generation() == Generation::Old
+            && value.state.generation() == Generation::Young
+        {
+            owner.collector_id().as_ref().heap.remember(owner);
+        }
+    }
+    #[inline]
+    fn logger(&self) -> &Logger {
+        &self.logger
+    }
+    #[inline]
+    fn manager(&self) -> &CollectionManager<Self> {
+        &self.manager
+    }
+    #[inline]
+    fn should_collect(&self) -> bool {
+        self.heap.should_collect_relaxed() || self.manager.should_trigger_collection()
+    }
+    #[inline]
+    fn allocated_size(&self) -> MemorySize {
+        MemorySize {
+            bytes: self.heap.young_size() + self.heap.old_size(),
+        }
+    }
+    fn stats(&self) -> GcStats {
+        let handle_slots = self.handle_list.slot_stats();
+        GcStats {
+            young_bytes: self.heap.young_size(),
+            old_bytes: self.heap.old_size(),
+            old_threshold: self.heap.old_threshold.load(Ordering::Acquire),
+            remembered_objects: self.heap.remembered.lock().len(),
+            num_handles: handle_slots.live_slots,
+            free_handle_slots: handle_slots.free_slots,
+            ..self.history.lock().clone()
+        }
+    }
+    #[inline]
+    unsafe fn perform_raw_collection(&self, contexts: &[*mut RawContext<Self>]) {
+        self.perform_raw_collection(contexts)
+    }
+
+    #[inline]
+    unsafe fn flush_context_cache(&self, _cache: &()) {}
+}
+#[cfg(feature = "sync")]
+unsafe impl ::zerogc_context::collector::SyncCollector for RawGenerationalCollector {}
+impl RawGenerationalCollector {
+    fn with_logger(config: GcConfig, logger: Logger) -> Self {
+        RawGenerationalCollector {
+            logger,
+            manager: CollectionManager::new(),
+            heap: GcHeap::new(Arc::new(config)),
+            handle_list: GcHandleList::new(),
+            history: Lock::from(GcStats::default()),
+        }
+    }
+    #[cold]
+    #[inline(never)]
+    unsafe fn perform_raw_collection(&self, contexts: &[*mut RawContext<Self>]) {
+        debug_assert!(self.manager.is_collecting());
+        let start = Instant::now();
+        let mut roots: Vec<*mut dyn DynTrace<Self>> = Vec::new();
+        for ctx in contexts.iter() {
+            roots.extend(
+                (**ctx)
+                    .assume_valid_shadow_stack()
+                    .reverse_iter()
+                    .map(NonNull::as_ptr),
+            );
+        }
+        roots.push(HandleListRoot::as_root(&self.handle_list));
+        let kind = if self.heap.old_size() >= self.heap.old_threshold.load(Ordering::Acquire) {
+            CollectionKind::Major
+        } else {
+            CollectionKind::Minor
+        };
+        let original_size = self.heap.young_size() + self.heap.old_size();
+        let mut task = CollectionTask {
+            kind,
+            expected_collector: self.heap.collector_id.unwrap(),
+            roots,
+            heap: &self.heap,
+            grey_stack: Vec::with_capacity(64),
+            weak_refs: Vec::new(),
+            ephemerons: Vec::new(),
+        };
+        let SweepResult { freed, promoted } = task.run();
+        self.handle_list.shrink();
+        let pause = start.elapsed();
+        {
+            let mut history = self.history.lock();
+            match kind {
+                CollectionKind::Minor => history.minor_collections += 1,
+                CollectionKind::Major => history.major_collections += 1,
+            }
+            history.total_pause += pause;
+            history.last_pause = pause;
+            history.last_freed_bytes = freed;
+            history.total_freed_bytes += freed as u64;
+            history.promoted_bytes += promoted as u64;
+        }
+        debug!(
+            self.logger, "Finished generational GC";
+            "current_thread" => FnValue(|_| ThreadId::current()),
+            "kind" => ?kind,
+            "original_size" => %MemorySize { bytes: original_size },
+            "memory_freed" => %MemorySize { bytes: freed },
+            "pause" => ?pause,
+        );
+    }
+}
+
+/// The number of bytes freed and promoted by a collection
+struct SweepResult {
+    freed: usize,
+    promoted: usize,
+}
+
+/// The kind of a collection
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum CollectionKind {
+    /// Only collect the nursery,
+    /// treating every old object as reachable
+    Minor,
+    /// Collect the entire heap
+    Major,
+}
+
+struct CollectionTask<'a> {
+    kind: CollectionKind,
+    expected_collector: CollectorId,
+    roots: Vec<*mut dyn DynTrace<RawGenerationalCollector>>,
+    heap: &'a GcHeap,
+    grey_stack: Vec<*mut GcHeader>,
+    /// The weak references encountered while marking
+    weak_refs: Vec<WeakRef>,
+    /// The ephemerons whose keys haven't (yet) been found reachable
+    ephemerons: Vec<Ephemeron>,
+}
+impl<'a> CollectionTask<'a> {
+    #[inline]
+    fn visitor(&mut self) -> MarkVisitor<'_> {
+        LayoutVisitor::new(
+            self.expected_collector,
+            Marking {
+                minor: self.kind == CollectionKind::Minor,
+                grey_stack: &mut self.grey_stack,
+                weak_refs: &mut self.weak_refs,
+                ephemerons: &mut self.ephemerons,
+            },
+        )
+    }
+    fn run(&mut self) -> SweepResult {
+        // Mark
+        for root in std::mem::take(&mut self.roots) {
+            // Dynamically dispatched
+            unsafe {
+                (*root).trace(&mut self.visitor());
+            }
+        }
+        if self.kind == CollectionKind::Minor {
+            /*
+             * Old objects aren't traced by a minor collection,
+             * so anything that may reference the nursery is an extra root.
+             */
+            let remembered = self.heap.remembered.lock().clone();
+            let old_vecs = self.heap.old_vecs.lock().clone();
+            for header in remembered.into_iter().chain(old_vecs) {
+                unsafe { self.visitor().trace_contents(header) }
+            }
+        }
+        self.process_grey_stack();
+        // Trace the values of ephemerons with reachable keys
+        self.process_ephemerons();
+        // Clear weak references (and ephemeron keys) to unreachable objects
+        self.process_weak_refs();
+        /*
+         * Sweep
+         *
+         * Every survivor is promoted, so the nursery is empty afterwards.
+         * Therefore nothing in the old generation can reference it,
+         * and the remembered set can be cleared.
+         */
+        unsafe {
+            self.heap.clear_remembered();
+            let (mut freed, promoted) = self.heap.sweep_young();
+            if self.kind == CollectionKind::Major {
+                freed += self.heap.sweep_old();
+                let threshold = self
+                    .heap
+                    .config
+                    .initial_old_threshold
+                    .max(self.heap.old_size() * 2);
+                self.heap.old_threshold.store(threshold, Ordering::Release);
+            }
+            SweepResult { freed, promoted }
+        }
+    }
+    /// Trace all the objects remaining on the grey stack
+    fn process_grey_stack(&mut self) {
+        while let Some(obj) = self.grey_stack.pop() {
+            unsafe {
+                debug_assert_eq!((*obj).state.mark_state(), MarkState::Grey);
+                self.visitor().trace_contents(obj);
+                // Mark the object black now it's innards have been traced
+                (*obj).state.update_mark_state(MarkState::Black);
+            }
+        }
+    }
+    /// Trace the values of all the ephemerons whose keys are reachable.
+    ///
+    /// Tracing a value can make even more keys reachable,
+    /// so this needs to iterate until it reaches a fixpoint.
+    /// Any ephemerons that remain afterwards have (currently) unreachable keys.
+    /// Those are cleared by [CollectionTask::process_weak_refs].
+    fn process_ephemerons(&mut self) {
+        loop {
+            let mut progress = false;
+            for ephemeron in std::mem::take(&mut self.ephemerons) {
+                if !unsafe { self.visitor().state.is_reachable(ephemeron.key.referent) } {
+                    // Still unknown, try again on the next iteration
+                    self.ephemerons.push(ephemeron);
+                    continue;
+                }
+                unsafe {
+                    ephemeron.trace_value(&mut self.visitor());
+                }
+                progress = true;
+            }
+            if !progress {
+                break;
+            }
+            self.process_grey_stack();
+        }
+    }
+    /// Clear all weak references whose referents weren't marked,
+    /// along with the keys of any ephemerons that are still unreachable.
+    ///
+    /// This must happen after marking has finished,
+    /// but before any objects have been swept.
+    fn process_weak_refs(&mut self) {
+        for ephemeron in self.ephemerons.drain(..) {
+            unsafe { ephemeron.key.clear() }
+        }
+        for weak in std::mem::take(&mut self.weak_refs) {
+            unsafe {
+                if !self.visitor().state.is_reachable(weak.referent) {
+                    weak.clear()
+                }
+            }
+        }
+    }
+}
+
+type WeakRef = zerogc_context::layout::WeakRef<RawGenerationalCollector>;
+type Ephemeron = zerogc_context::layout::Ephemeron<RawGenerationalCollector>;
+
+#[doc(hidden)] // NOTE: Needs be public for RawCollectorImpl
+pub type MarkVisitor<'a> = LayoutVisitor<'a, RawGenerationalCollector>;
+
+/// The state of the [MarkVisitor] while marking
+#[doc(hidden)]
+pub struct Marking<'a> {
+    /// Whether this is a minor collection,
+    /// which treats every old object as reachable (without tracing it)
+    minor: bool,
+    grey_stack: &'a mut Vec<*mut GcHeader>,
+    /// The weak references that need to be processed after marking
+    weak_refs: &'a mut Vec<WeakRef>,
+    /// The ephemerons whose keys haven't been marked yet
+    ephemerons: &'a mut Vec<Ephemeron>,
+}
+impl Marking<'_> {
+    /// Check if the specified object is (currently) known to be reachable
+    ///
+    /// A minor collection assumes every old object is reachable.
+    #[inline]
+    unsafe fn is_reachable(&self, header: *mut GcHeader) -> bool {
+        (self.minor && (*header).state.generation() == Generation::Old)
+            || (*header).state.mark_state() != MarkState::White
+    }
+    /// Mark the specified object as reachable.
+    ///
+    /// The object is marked grey and pushed onto the grey stack,
+    /// unless it has nothing that needs to be traced.
+    unsafe fn mark(&mut self, header: *mut GcHeader) {
+        if self.is_reachable(header) {
+            /*
+             * Either this object is already grey (and will be traversed eventually),
+             * or it's already known to be reachable.
+             */
+            return;
+        }
+        if (*header).type_info.trace_func.is_none() {
+            /*
+             * We don't need to mark this grey
+             * It has no internals that need to be traced.
+             * We can directly move it directly to the black set
+             */
+            (*header).state.update_mark_state(MarkState::Black);
+        } else {
+            /*
+             * We need to mark this object grey and push it onto the grey stack.
+             * It will be processed later
+             */
+            (*header).state.update_mark_state(MarkState::Grey);
+            self.grey_stack.push(header);
+        }
+    }
+}
+unsafe impl LayoutCollector for RawGenerationalCollector {
+    type HeaderState = ObjectState;
+    type VisitorState<'a> = Marking<'a>;
+
+    #[inline]
+    unsafe fn visit(visitor: &mut MarkVisitor<'_>, header: *mut GcHeader) -> *mut GcHeader {
+        visitor.state.mark(header);
+        // Objects never move
+        header
+    }
+
+    #[inline]
+    unsafe fn visit_weak(visitor: &mut MarkVisitor<'_>, weak: WeakRef) {
+        /*
+         * Don't mark the referent. Just remember the reference,
+         * so it can be cleared once marking has finished.
+         */
+        visitor.state.weak_refs.push(weak);
+    }
+
+    unsafe fn visit_ephemeron(visitor: &mut MarkVisitor<'_>, ephemeron: Ephemeron) {
+        if visitor.state.is_reachable(ephemeron.key.referent) {
+            // The key is already known to be reachable
+            ephemeron.trace_value(visitor);
+        } else {
+            /*
+             * We don't know whether the key is reachable yet.
+             * Defer tracing the value until we do.
+             */
+            visitor.state.ephemerons.push(ephemeron);
+        }
+    }
+}
+
+/// The current mark state of the object
+///
+/// See [Tri Color Marking](https://en.wikipedia.org/wiki/Tracing_garbage_collection#Tri-color_marking)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+enum MarkState {
+    /// The object is in the "white set" and is a candidate for having its memory freed.
+    ///
+    /// Once all the objects have been marked,
+    /// all remaining white objects will be freed.
+    White = 0,
+    /// The object is in the gray set and needs to be traversed to look for reachable memory
+    ///
+    /// After being scanned this object will end up in the black set.
+    Grey = 1,
+    /// The object is in the black set and is reachable from the roots.
+    ///
+    /// This object cannot be freed.
+    Black = 2,
+}
+impl MarkState {
+    #[inline]
+    fn from_bits(b: u8) -> Self {
+        assert!(b < 3);
+        unsafe { std::mem::transmute::<u8, MarkState>(b) }
+    }
+}
@@ -0,0 +1,199 @@
+#![feature(
+    arbitrary_self_types, // Unfortunately this is required for methods on Gc refs
+)]
+use slog::Logger;
+
+use zerogc::cell::GcCell;
+use zerogc::{safepoint, safepoint_force, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_generational::layout::Generation;
+use zerogc_generational::{
+    generation_of, CollectorId as GenerationalCollectorId, Gc, GcConfig, GcVec, GcWeak,
+    GenerationalCollector,
+};
+
+/// A collector that only performs minor collections (unless forced)
+fn minor_collector() -> GenerationalCollector {
+    let mut config = GcConfig::default();
+    config.initial_old_threshold = usize::MAX;
+    GenerationalCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(GenerationalCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[derive(Trace)]
+#[zerogc(collector_ids(GenerationalCollectorId))]
+struct Holder<'gc> {
+    #[zerogc(mutable(public))]
+    child: GcCell<Option<Gc<'gc, Dummy>>>,
+}
+
+#[derive(Trace)]
+#[zerogc(collector_ids(GenerationalCollectorId))]
+struct WeakHolder<'gc> {
+    #[zerogc(mutable(public))]
+    entry: GcCell<GcWeak<'gc, Dummy>>,
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(GenerationalCollectorId))]
+struct Cache<'gc> {
+    entry: GcWeak<'gc, Dummy>,
+}
+
+#[test]
+fn minor_promotes_survivors() {
+    let collector = minor_collector();
+    let mut context = collector.into_context();
+    let kept = context.alloc(Dummy { val: 1 });
+    context.alloc(Dummy { val: 2 });
+    assert_eq!(generation_of(kept), Generation::Young);
+    let young_bytes = context.system().stats().young_bytes;
+    let kept: Gc<Dummy> = safepoint_force!(context, kept);
+    assert_eq!(kept.val, 1);
+    assert_eq!(generation_of(kept), Generation::Old);
+    let stats = context.system().stats();
+    assert_eq!(stats.minor_collections, 1);
+    assert_eq!(stats.major_collections, 0);
+    assert_eq!(stats.young_bytes, 0);
+    assert!(stats.last_freed_bytes > 0);
+    assert_eq!(stats.old_bytes, young_bytes - stats.last_freed_bytes);
+    assert_eq!(stats.promoted_bytes, stats.old_bytes as u64);
+}
+
+#[test]
+fn minor_keeps_old_garbage() {
+    let collector = minor_collector();
+    let mut context = collector.into_context();
+    let first = context.alloc(Dummy { val: 1 });
+    let first: Gc<Dummy> = safepoint_force!(context, first);
+    assert_eq!(generation_of(first), Generation::Old);
+    let old_bytes = context.system().stats().old_bytes;
+    // The old object is garbage now, but a minor collection can't tell
+    safepoint_force!(context, ());
+    let stats = context.system().stats();
+    assert_eq!(stats.minor_collections, 2);
+    assert_eq!(stats.old_bytes, old_bytes);
+}
+
+#[test]
+fn barrier_remembers_young() {
+    let collector = minor_collector();
+    let mut context = collector.into_context();
+    let holder = context.alloc(Holder {
+        child: GcCell::new(None),
+    });
+    let holder: Gc<Holder> = safepoint_force!(context, holder);
+    assert_eq!(generation_of(holder), Generation::Old);
+    // Store a young object into the old one
+    let child = context.alloc(Dummy { val: 42 });
+    holder.set_child(Some(child));
+    assert_eq!(context.system().stats().remembered_objects, 1);
+    /*
+     * The child is only reachable through the old holder,
+     * which isn't traced by a minor collection
+     */
+    let holder: Gc<Holder> = safepoint_force!(context, holder);
+    let stats = context.system().stats();
+    assert_eq!(stats.minor_collections, 2);
+    assert_eq!(stats.last_freed_bytes, 0);
+    assert_eq!(stats.remembered_objects, 0);
+    let child = holder.child.get().unwrap();
+    assert_eq!(child.val, 42);
+    assert_eq!(generation_of(child), Generation::Old);
+}
+
+#[test]
+fn major_frees_old_garbage() {
+    let mut config = GcConfig::default();
+    // Any old objects at all trigger a major collection
+    config.initial_old_threshold = 1;
+    let collector =
+        GenerationalCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()));
+    let mut context = collector.into_context();
+    let first = context.alloc(Dummy { val: 1 });
+    let second = context.alloc(Dummy { val: 2 });
+    // The old generation is empty, so this is a minor collection
+    let (first, second): (Gc<Dummy>, Gc<Dummy>) = safepoint_force!(context, (first, second));
+    assert_eq!(context.system().stats().minor_collections, 1);
+    assert_eq!(generation_of(second), Generation::Old);
+    let old_bytes = context.system().stats().old_bytes;
+    let first: Gc<Dummy> = safepoint!(context, first);
+    assert_eq!(first.val, 1);
+    let stats = context.system().stats();
+    assert_eq!(stats.major_collections, 1);
+    assert!(stats.last_freed_bytes > 0);
+    assert_eq!(stats.old_bytes, old_bytes - stats.last_freed_bytes);
+    assert_eq!(stats.old_threshold, stats.old_bytes * 2);
+}
+
+#[test]
+fn weak_young_cleared() {
+    let collector = minor_collector();
+    let mut context = collector.into_context();
+    let target = context.alloc(Dummy { val: 1 });
+    let cache = context.alloc(Cache {
+        entry: target.downgrade(),
+    });
+    let cache: Gc<Cache> = safepoint_force!(context, cache);
+    assert!(cache.entry.is_cleared());
+    let target = context.alloc(Dummy { val: 2 });
+    let cache = context.alloc(Cache {
+        entry: target.downgrade(),
+    });
+    let (cache, target): (Gc<Cache>, Gc<Dummy>) = safepoint_force!(context, (cache, target));
+    assert_eq!(cache.entry.upgrade().map(|gc| gc.val), Some(2));
+    assert_eq!(generation_of(target), Generation::Old);
+    // Old referents are never cleared by a minor collection, even if they're garbage
+    let cache: Gc<Cache> = safepoint_force!(context, cache);
+    assert_eq!(cache.entry.upgrade().map(|gc| gc.val), Some(2));
+}
+
+#[test]
+fn weak_in_old_object_cleared() {
+    let collector = minor_collector();
+    let mut context = collector.into_context();
+    let holder = context.alloc(WeakHolder {
+        entry: GcCell::new(GcWeak::cleared()),
+    });
+    let holder: Gc<WeakHolder> = safepoint_force!(context, holder);
+    assert_eq!(generation_of(holder), Generation::Old);
+    // Weakly reference a young object from the old one
+    let target = context.alloc(Dummy { val: 1 });
+    holder.set_entry(target.downgrade());
+    assert_eq!(context.system().stats().remembered_objects, 1);
+    // The target is garbage, so the reference must be cleared before it's freed
+    let holder: Gc<WeakHolder> = safepoint_force!(context, holder);
+    assert!(holder.entry.get().is_cleared());
+    assert!(context.system().stats().last_freed_bytes > 0);
+    // A young target that survives is promoted, and the reference stays valid
+    let target = context.alloc(Dummy { val: 2 });
+    holder.set_entry(target.downgrade());
+    let (holder, target): (Gc<WeakHolder>, Gc<Dummy>) =
+        safepoint_force!(context, (holder, target));
+    assert_eq!(generation_of(target), Generation::Old);
+    assert_eq!(holder.entry.get().upgrade().map(|gc| gc.val), Some(2));
+}
+
+#[test]
+fn vec_rescanned() {
+    let collector = minor_collector();
+    let mut context = collector.into_context();
+    let vec: GcVec<Gc<Dummy>> = context.alloc_vec_with_capacity(4);
+    // Vectors are allocated directly in the old generation
+    let stats = context.system().stats();
+    assert_eq!(stats.young_bytes, 0);
+    assert!(stats.old_bytes > 0);
+    let mut vec: GcVec<Gc<Dummy>> = safepoint_force!(context, vec);
+    // Vectors don't have write barriers, but are re-scanned by every minor collection
+    vec.push(context.alloc(Dummy { val: 7 }));
+    let vec: GcVec<Gc<Dummy>> = safepoint_force!(context, vec);
+    assert_eq!(context.system().stats().last_freed_bytes, 0);
+    assert_eq!(vec[0].val, 7);
+    assert_eq!(generation_of(vec[0]), Generation::Old);
+}
@@ -1,7 +1,7 @@
 #![allow(clippy::vec_box)] // We must Box<Chunk> for a stable address
 use std::alloc::Layout;
+use std::cell::Cell;
 use std::mem;
-use std::mem::MaybeUninit;
 use std::ptr::NonNull;
 
 #[cfg(feature = "sync")]
@@ -101,20 +101,11 @@ mod debug {
 /// Since the header takes at least one word,
 /// its not really worth ever allocating less than this
 pub const MINIMUM_WORDS: usize = 2;
-/// The maximum words supported by small arenas
-///
-/// Past this we have to fallback to the global allocator
-pub const MAXIMUM_SMALL_WORDS: usize = 32;
 /// The alignment of elements in the arena
 pub const ARENA_ELEMENT_ALIGN: usize = std::mem::align_of::<GcHeader>();
 
 use crate::layout::{GcHeader, UnknownHeader};
-
-#[inline]
-pub const fn fits_small_object(layout: Layout) -> bool {
-    layout.size() <= MAXIMUM_SMALL_WORDS * std::mem::size_of::<usize>()
-        && layout.align() <= ARENA_ELEMENT_ALIGN
-}
+use crate::GcConfig;
 
 pub(crate) struct Chunk {
     pub start: *mut u8,
@@ -154,10 +145,39 @@ impl Chunk {
             }
         }
     }
+    /// Allocate as many elements as possible at once, up to `max_elements`
+    ///
+    /// Returns the number of elements that were allocated
+    #[inline]
+    fn try_alloc_batch(
+        &self,
+        element_size: usize,
+        max_elements: usize,
+    ) -> Option<(NonNull<u8>, usize)> {
+        loop {
+            let old_current = self.current.load();
+            let remaining = self.end as usize - old_current as usize;
+            let count = (remaining / element_size).min(max_elements);
+            if count == 0 {
+                return None;
+            }
+            unsafe {
+                let updated = old_current.add(count * element_size);
+                if self.current.compare_exchange(old_current, updated).is_ok() {
+                    return Some((NonNull::new_unchecked(old_current), count));
+                }
+            }
+        }
+    }
     #[inline]
     fn capacity(&self) -> usize {
         self.end as usize - self.start as usize
     }
+    /// The number of bytes that have been handed out by this chunk
+    #[inline]
+    fn used(&self) -> usize {
+        self.current.load() as usize - self.start as usize
+    }
 }
 impl Drop for Chunk {
     fn drop(&mut self) {
@@ -171,12 +191,14 @@ pub struct FreeSlot {
     /// Pointer to the previous free slot
     pub(crate) prev_free: Option<NonNull<FreeSlot>>,
 }
-pub const NUM_SMALL_ARENAS: usize = 15;
-const INITIAL_SIZE: usize = 512;
+/// The maximum number of fresh slots that a context's cache
+/// reserves from an arena at once.
+const CACHE_BATCH_SIZE: usize = 64;
 
 /// The current state of the allocator.
 ///
-/// TODO: Support per-thread arena caching
+/// This is shared between all threads.
+/// Each context also has a private [ArenaCache].
 struct ArenaState {
     /// We have to Box the chunk so that it'll remain valid
     /// even when we move it.
@@ -244,6 +266,18 @@ impl ArenaState {
         }
     }
 
+    /// Allocate up to `max_elements` consecutive elements,
+    /// returning the number that were actually allocated.
+    fn alloc_batch(&self, element_size: usize, max_elements: usize) -> (NonNull<u8>, usize) {
+        unsafe {
+            let chunk = &*self.current_chunk().as_ptr();
+            match chunk.try_alloc_batch(element_size, max_elements) {
+                Some(res) => res,
+                None => (self.alloc_fallback(element_size).cast(), 1),
+            }
+        }
+    }
+
     #[cold]
     #[inline(never)]
     fn alloc_fallback(&self, element_size: usize) -> NonNull<UnknownHeader> {
@@ -296,6 +330,41 @@ impl FreeList {
             }
         }
     }
+    /// Add every slot in the specified list at once
+    unsafe fn add_all(&self, list: LocalFreeList) {
+        let (head, tail) = match (list.head, list.tail) {
+            (Some(head), Some(tail)) => (head, tail),
+            _ => return,
+        };
+        let mut next = self.next.load();
+        loop {
+            (*tail.as_ptr()).prev_free = next;
+            match self.next.compare_exchange(next, Some(head)) {
+                Ok(_) => break,
+                Err(actual_next) => {
+                    next = actual_next;
+                }
+            }
+        }
+    }
+    /// Take every slot in the free list at once
+    fn take_all(&self) -> LocalFreeList {
+        let mut head = self.next.load();
+        while let Some(expected) = head {
+            match self.next.compare_exchange(Some(expected), None) {
+                Ok(_) => break,
+                Err(actual) => head = actual,
+            }
+        }
+        // The list is ours now, so we can walk it without worrying about races
+        let mut tail = head;
+        unsafe {
+            while let Some(prev) = tail.and_then(|slot| slot.as_ref().prev_free) {
+                tail = Some(prev);
+            }
+        }
+        LocalFreeList { head, tail }
+    }
     #[inline]
     fn take_free(&self) -> Option<NonNull<u8>> {
         loop {
@@ -318,8 +387,115 @@ impl FreeList {
     }
 }
 
+/// A list of free slots, which is owned by a single context
+#[derive(Copy, Clone)]
+struct LocalFreeList {
+    head: Option<NonNull<FreeSlot>>,
+    /// The last slot in the list
+    tail: Option<NonNull<FreeSlot>>,
+}
+impl LocalFreeList {
+    const EMPTY: LocalFreeList = LocalFreeList {
+        head: None,
+        tail: None,
+    };
+    /// Link together `count` consecutive slots, starting at `start`
+    unsafe fn from_batch(start: NonNull<u8>, element_size: usize, count: usize) -> Self {
+        debug_assert!(count >= 1);
+        let slot = |idx: usize| start.as_ptr().add(idx * element_size) as *mut FreeSlot;
+        for idx in 0..count {
+            (*slot(idx)).prev_free = if idx + 1 < count {
+                Some(NonNull::new_unchecked(slot(idx + 1)))
+            } else {
+                None
+            };
+        }
+        LocalFreeList {
+            head: Some(start.cast()),
+            tail: Some(NonNull::new_unchecked(slot(count - 1))),
+        }
+    }
+    unsafe fn push(&mut self, slot: NonNull<FreeSlot>) {
+        (*slot.as_ptr()).prev_free = self.head;
+        if self.tail.is_none() {
+            self.tail = Some(slot);
+        }
+        self.head = Some(slot);
+    }
+    #[inline]
+    unsafe fn pop(&mut self) -> Option<NonNull<FreeSlot>> {
+        let slot = self.head?;
+        self.head = slot.as_ref().prev_free;
+        if self.head.is_none() {
+            self.tail = None;
+        }
+        Some(slot)
+    }
+}
+
+/// A context's private cache of free slots in each arena
+///
+/// Allocating from the cache doesn't touch any shared state,
+/// so contexts on different threads don't contend with each other.
+/// The cache is refilled in bulk, either by taking
+/// the arena's entire free list or by reserving a batch of fresh slots.
+///
+/// The slots are given back to the arenas whenever the cache is flushed.
+pub struct ArenaCache {
+    lists: [Cell<LocalFreeList>; GcConfig::MAX_SIZE_CLASSES],
+}
+impl Default for ArenaCache {
+    fn default() -> Self {
+        ArenaCache {
+            lists: [const { Cell::new(LocalFreeList::EMPTY) }; GcConfig::MAX_SIZE_CLASSES],
+        }
+    }
+}
+impl ArenaCache {
+    /// Whether the cache holds any slots for the specified arena
+    #[inline]
+    pub(crate) fn has_free(&self, arena: &SmallArena) -> bool {
+        self.lists[arena.index].get().head.is_some()
+    }
+    /// Allocate a slot in the specified arena,
+    /// refilling the cache from the arena if it's empty
+    #[inline]
+    pub(crate) fn alloc(&self, arena: &SmallArena) -> NonNull<UnknownHeader> {
+        if DEBUG_INTERNAL_ALLOCATOR {
+            // Slots need to be individually padded
+            return arena.alloc();
+        }
+        let cell = &self.lists[arena.index];
+        let mut list = cell.get();
+        let slot = match unsafe { list.pop() } {
+            Some(slot) => slot,
+            None => {
+                list = arena.take_batch();
+                unsafe { list.pop().unwrap() }
+            }
+        };
+        cell.set(list);
+        slot.cast()
+    }
+    /// Give every cached slot back to its arena
+    ///
+    /// ## Safety
+    /// The cache must've been filled from the specified arenas.
+    pub(crate) unsafe fn flush(&self, arenas: &SmallArenaList) {
+        for (idx, cell) in self.lists.iter().enumerate() {
+            let list = cell.replace(LocalFreeList::EMPTY);
+            if list.head.is_some() {
+                let arena = arenas.arenas[idx].get().unwrap();
+                arena.free.add_all(list);
+            }
+        }
+    }
+}
+
 pub struct SmallArena {
     pub(crate) element_size: usize,
+    /// The index of this arena in the [SmallArenaList]
+    index: usize,
     state: ArenaState,
     free: FreeList,
 }
@@ -328,18 +504,150 @@ impl SmallArena {
     pub(crate) unsafe fn add_free(&self, obj: *mut UnknownHeader) {
         self.free.add_free(obj, self.element_size)
     }
+    /// Whether there are any slots in the free list
+    #[inline]
+    pub(crate) fn has_free(&self) -> bool {
+        self.free.next.load().is_some()
+    }
     #[cold] // Initialization is the slow path
-    fn with_words(num_words: usize) -> SmallArena {
-        assert!(num_words >= MINIMUM_WORDS);
-        let element_size = num_words * mem::size_of::<usize>();
-        assert!(INITIAL_SIZE >= element_size * 2);
-        let chunks = vec![Chunk::alloc(INITIAL_SIZE)];
+    fn with_size(element_size: usize, index: usize, chunk_size: usize) -> SmallArena {
+        assert!(element_size >= MINIMUM_WORDS * mem::size_of::<usize>());
+        let chunks = vec![Chunk::alloc(chunk_size.max(element_size * 2))];
         SmallArena {
             state: ArenaState::new(chunks),
             element_size,
+            index,
             free: Default::default(),
         }
     }
+    /// Release the chunks that are entirely free,
+    /// keeping up to `retained` bytes of them around for reuse.
+    ///
+    /// Returns the number of bytes that were released,
+    /// and subtracts the capacity of the free chunks that were kept from `retained`.
+    ///
+    /// ## Safety
+    /// The arena must not be used concurrently,
+    /// and every context's cache must've been flushed.
+    unsafe fn release_free_chunks(&self, retained: &mut usize) -> usize {
+        if DEBUG_INTERNAL_ALLOCATOR {
+            // Padded slots are too much trouble to count
+            return 0;
+        }
+        let mut chunks = self.state.lock_chunks();
+        let chunk_index = |chunks: &[Box<Chunk>], ptr: *mut u8| {
+            chunks
+                .iter()
+                .position(|chunk| chunk.start <= ptr && ptr < chunk.end)
+                .expect("Free slot outside of arena")
+        };
+        let mut list = self.free.take_all();
+        let mut free_slots = vec![0usize; chunks.len()];
+        let mut next = list.head;
+        while let Some(slot) = next {
+            free_slots[chunk_index(&chunks, slot.as_ptr().cast())] += 1;
+            next = slot.as_ref().prev_free;
+        }
+        let mut free_chunks: Vec<usize> = (0..chunks.len())
+            .filter(|&idx| free_slots[idx] * self.element_size == chunks[idx].used())
+            .collect();
+        // Prefer keeping the smaller chunks
+        free_chunks.sort_by_key(|&idx| chunks[idx].capacity());
+        let mut released = vec![false; chunks.len()];
+        let mut released_bytes = 0;
+        for &idx in &free_chunks {
+            let capacity = chunks[idx].capacity();
+            if capacity <= *retained {
+                *retained -= capacity;
+            } else {
+                released[idx] = true;
+                released_bytes += capacity;
+            }
+        }
+        if released.iter().all(|&released| released) {
+            // We always need at least one chunk
+            let smallest = free_chunks[0];
+            released[smallest] = false;
+            released_bytes -= chunks[smallest].capacity();
+        }
+        if released_bytes > 0 {
+            // Remove the slots of the released chunks from the free list
+            let mut remaining = LocalFreeList::EMPTY;
+            while let Some(slot) = list.pop() {
+                if !released[chunk_index(&chunks, slot.as_ptr().cast())] {
+                    remaining.push(slot);
+                }
+            }
+            list = remaining;
+            let mut idx = 0;
+            chunks.retain(|_| {
+                idx += 1;
+                !released[idx - 1]
+            });
+            // The current chunk may have been released
+            self.state
+                .force_current_chunk(NonNull::from(&**chunks.last().unwrap()));
+        }
+        self.free.add_all(list);
+        released_bytes
+    }
+    /// The total capacity of this arena's chunks
+    fn reserved_bytes(&self) -> usize {
+        self.state
+            .lock_chunks()
+            .iter()
+            .map(|chunk| chunk.capacity())
+            .sum()
+    }
+    /// Walk this arena's free list, invoking the closure on each free slot
+    ///
+    /// ## Safety
+    /// The free list must not be concurrently modified.
+    unsafe fn verify_free_list(&self, func: &mut impl FnMut(*mut u8)) -> Result<(), String> {
+        let chunks = self.state.lock_chunks();
+        let max_slots =
+            chunks.iter().map(|chunk| chunk.capacity()).sum::<usize>() / self.element_size;
+        let mut num_slots = 0;
+        let mut next = self.free.next.load();
+        while let Some(slot) = next {
+            let ptr = slot.as_ptr() as *mut u8;
+            let in_bounds = chunks.iter().any(|chunk| {
+                chunk.start <= ptr
+                    && (ptr as usize + self.element_size) <= chunk.current.load() as usize
+            });
+            if !in_bounds {
+                return Err(format!(
+                    "Free slot {:p} is outside of the arena for size {}",
+                    ptr, self.element_size
+                ));
+            }
+            if (ptr as usize) & (ARENA_ELEMENT_ALIGN - 1) != 0 {
+                return Err(format!("Free slot {:p} is misaligned", ptr));
+            }
+            num_slots += 1;
+            if num_slots > max_slots {
+                return Err(format!(
+                    "Free list for size {} contains a cycle",
+                    self.element_size
+                ));
+            }
+            func(ptr);
+            next = slot.as_ref().prev_free;
+        }
+        Ok(())
+    }
+    /// Take a batch of free slots, to refill a context's [ArenaCache]
+    ///
+    /// This prefers reusing the free list over reserving fresh memory.
+    #[cold]
+    fn take_batch(&self) -> LocalFreeList {
+        let list = self.free.take_all();
+        if list.head.is_some() {
+            return list;
+        }
+        let (start, count) = self.state.alloc_batch(self.element_size, CACHE_BATCH_SIZE);
+        unsafe { LocalFreeList::from_batch(start, self.element_size, count) }
+    }
     #[inline]
     pub(crate) fn alloc(&self) -> NonNull<UnknownHeader> {
         // Check the free list
@@ -361,73 +669,105 @@ impl SmallArena {
         }
     }
 }
-macro_rules! arena_match {
-    ($arenas:expr, $target:ident, max = $max:expr; $($size:pat => $num_words:literal @ $idx:expr),*) => {
-        Some(match $target {
-            $($size => $arenas[$idx].get_or_init(|| {
-                assert_eq!(SMALL_ARENA_SIZES[$idx], $num_words);
-                SmallArena::with_words($num_words)
-            }),)*
-            _ => {
-                assert!($target > $max);
-                return None
-            }
-        })
-    };
-}
-const SMALL_ARENA_SIZES: [usize; NUM_SMALL_ARENAS] =
-    [2, 3, 4, 5, 6, 7, 8, 10, 12, 14, 16, 20, 24, 28, 32];
 pub struct SmallArenaList {
     // NOTE: Internally boxed to avoid bloating main struct
-    arenas: Box<[OnceCell<SmallArena>; NUM_SMALL_ARENAS]>,
+    arenas: Box<[OnceCell<SmallArena>]>,
+    /// The size of the elements in each arena (in bytes)
+    sizes: Box<[usize]>,
+    /// The index of the arena to use for each object size (in words)
+    ///
+    /// Objects bigger than the last entry aren't small objects.
+    size_classes: Box<[u8]>,
+    /// The size of the first chunk in each arena
+    chunk_size: usize,
 }
 impl SmallArenaList {
-    pub fn new() -> Self {
-        // NOTE: Why does writing arrays have to be so difficult:?
-        unsafe {
-            let mut arenas: Box<[MaybeUninit<OnceCell<SmallArena>>; NUM_SMALL_ARENAS]> =
-                Box::new_uninit().assume_init();
-            for i in 0..NUM_SMALL_ARENAS {
-                arenas[i].as_mut_ptr().write(OnceCell::new());
-            }
-            SmallArenaList {
-                // NOTE: This is done because I want to explicitly specify types
-                arenas: mem::transmute::<
-                    Box<[MaybeUninit<OnceCell<SmallArena>>; NUM_SMALL_ARENAS]>,
-                    Box<[OnceCell<SmallArena>; NUM_SMALL_ARENAS]>,
-                >(arenas),
-            }
+    /// Create arenas for the size classes given by the configuration
+    ///
+    /// Panics if the size classes are invalid (see [GcConfig::size_classes])
+    pub fn new(config: &GcConfig) -> Self {
+        let word_size = mem::size_of::<usize>();
+        let sizes = &config.size_classes;
+        assert!(!sizes.is_empty(), "No size classes");
+        assert!(
+            sizes.len() <= GcConfig::MAX_SIZE_CLASSES,
+            "Too many size classes: {}",
+            sizes.len()
+        );
+        for (idx, &size) in sizes.iter().enumerate() {
+            assert!(
+                size % word_size == 0 && size >= MINIMUM_WORDS * word_size,
+                "Invalid size class: {}",
+                size
+            );
+            assert!(
+                idx == 0 || sizes[idx - 1] < size,
+                "Size classes must be sorted: {:?}",
+                sizes
+            );
+        }
+        let mut size_classes = Vec::new();
+        for (idx, &size) in sizes.iter().enumerate() {
+            size_classes.resize(size / word_size + 1, idx as u8);
+        }
+        SmallArenaList {
+            arenas: sizes.iter().map(|_| OnceCell::new()).collect(),
+            sizes: sizes.clone().into_boxed_slice(),
+            size_classes: size_classes.into_boxed_slice(),
+            chunk_size: config.arena_chunk_size,
         }
     }
-    #[inline] // This should hopefully be constant folded away (layout is const)
+    /// Release the chunks of every arena that are entirely free,
+    /// keeping up to `retained` bytes of them around for reuse.
+    ///
+    /// Returns the number of bytes that were released.
+    ///
+    /// ## Safety
+    /// The arenas must not be used concurrently,
+    /// and every context's cache must've been flushed.
+    pub(crate) unsafe fn release_free_chunks(&self, mut retained: usize) -> usize {
+        self.arenas
+            .iter()
+            .filter_map(OnceCell::get)
+            .map(|arena| arena.release_free_chunks(&mut retained))
+            .sum()
+    }
+    /// The total capacity of every arena's chunks, including free memory
+    pub(crate) fn reserved_bytes(&self) -> usize {
+        self.arenas
+            .iter()
+            .filter_map(OnceCell::get)
+            .map(SmallArena::reserved_bytes)
+            .sum()
+    }
+    /// Verify the free lists of every arena aren't corrupted,
+    /// invoking the specified closure on each free slot.
+    ///
+    /// ## Safety
+    /// The free lists must not be concurrently modified.
+    pub(crate) unsafe fn verify_free_lists(
+        &self,
+        mut func: impl FnMut(*mut u8),
+    ) -> Result<(), String> {
+        for arena in self.arenas.iter().filter_map(OnceCell::get) {
+            arena.verify_free_list(&mut func)?;
+        }
+        Ok(())
+    }
+    /// Find the arena for objects with the specified layout,
+    /// or `None` if it's too big to be a small object.
+    #[inline]
     pub fn find(&self, layout: Layout) -> Option<&SmallArena> {
-        if !fits_small_object(layout) {
+        if layout.align() > ARENA_ELEMENT_ALIGN {
             return None;
         }
         // Divide round up
         let word_size = mem::size_of::<usize>();
         let num_words = (layout.size() + (word_size - 1)) / word_size;
-        self.find_raw(num_words)
-    }
-    #[inline] // We want this constant-folded away......
-    fn find_raw(&self, num_words: usize) -> Option<&SmallArena> {
-        arena_match!(
-            self.arenas, num_words, max = 32;
-            0..=2 => 2 @ 0,
-            3 => 3 @ 1,
-            4 => 4 @ 2,
-            5 => 5 @ 3,
-            6 => 6 @ 4,
-            7 => 7 @ 5,
-            8 => 8 @ 6,
-            9..=10 => 10 @ 7,
-            11..=12 => 12 @ 8,
-            13..=14 => 14 @ 9,
-            15..=16 => 16 @ 10,
-            17..=20 => 20 @ 11,
-            21..=24 => 24 @ 12,
-            25..=28 => 28 @ 13,
-            29..=32 => 32 @ 14
+        let idx = *self.size_classes.get(num_words)? as usize;
+        Some(
+            self.arenas[idx]
+                .get_or_init(|| SmallArena::with_size(self.sizes[idx], idx, self.chunk_size)),
         )
     }
 }
@@ -0,0 +1,292 @@
+//! An [Immix](https://www.cs.utexas.edu/users/speedway/DaCapo/papers/immix-pldi-2008.pdf)-style
+//! allocator for small objects, enabled by the `immix` feature.
+//!
+//! Memory is split into blocks, which are further divided into lines.
+//! Objects of every size are bump-allocated into "holes" of contiguous free lines,
+//! so objects that are allocated together end up next to each other.
+//!
+//! Whenever an object is marked, so are the lines it occupies.
+//! Once the garbage has been dropped, every run of unmarked lines becomes a hole
+//! that can be reused by the allocator.
+//! Blocks that are left entirely free can be returned to the global allocator.
+use std::alloc::Layout;
+use std::cell::Cell;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::layout::GcHeader;
+use crate::{ILock, Lock};
+
+/// The size of each block of memory (in bytes)
+///
+/// Blocks are aligned to their size,
+/// so the block containing an object can be found by masking its address.
+pub const BLOCK_SIZE: usize = 32 * 1024;
+/// The size of each line (in bytes)
+pub const LINE_SIZE: usize = 128;
+const LINES_PER_BLOCK: usize = BLOCK_SIZE / LINE_SIZE;
+/// The number of lines at the start of each block used to store its line marks
+const METADATA_LINES: usize = LINES_PER_BLOCK.div_ceil(LINE_SIZE);
+/// The size of the biggest object that is allocated in a block
+///
+/// Anything bigger than this is allocated individually, using the global allocator.
+pub const MAX_OBJECT_SIZE: usize = BLOCK_SIZE / 4;
+/// The alignment of objects in a block
+const OBJECT_ALIGN: usize = std::mem::align_of::<GcHeader>();
+
+/// Whether an object with the specified (overall) layout is allocated in a block
+#[inline]
+pub const fn fits(layout: Layout) -> bool {
+    layout.size() <= MAX_OBJECT_SIZE && layout.align() <= OBJECT_ALIGN
+}
+
+/// Mark the lines occupied by the specified object,
+/// if it's allocated in a block.
+///
+/// This is safe to call concurrently from multiple marking threads.
+#[inline]
+pub unsafe fn mark_object(header: *mut GcHeader) {
+    let type_info = (*header).type_info;
+    let layout = type_info.determine_total_layout(header);
+    if fits(layout) {
+        let start = type_info.header_layout().from_common_header(header);
+        mark_lines(start as *mut u8, layout.size());
+    }
+}
+
+/// Mark the lines occupied by an object that starts at the specified address
+/// (including its header).
+///
+/// The object must be allocated in a block.
+#[inline]
+pub unsafe fn mark_lines(start: *mut u8, size: usize) {
+    let start = start as usize;
+    let block = start & !(BLOCK_SIZE - 1);
+    let first_line = (start - block) / LINE_SIZE;
+    let last_line = (start + size - 1 - block) / LINE_SIZE;
+    let marks = Block::line_marks_at(block);
+    for mark in &marks[first_line..=last_line] {
+        mark.store(1, Ordering::Relaxed);
+    }
+}
+
+/// A range of free memory, that objects can be bump-allocated in
+#[derive(Copy, Clone, Debug)]
+struct Hole {
+    start: usize,
+    end: usize,
+}
+impl Hole {
+    const EMPTY: Hole = Hole { start: 0, end: 0 };
+    #[inline]
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// A block of memory, which starts with the marks for each of its lines
+struct Block(NonNull<u8>);
+impl Block {
+    const LAYOUT: Layout = match Layout::from_size_align(BLOCK_SIZE, BLOCK_SIZE) {
+        Ok(layout) => layout,
+        Err(_) => panic!("Invalid block layout"),
+    };
+    #[cold]
+    fn alloc() -> Block {
+        // NOTE: Zeroed so that every line starts out unmarked
+        let ptr = unsafe { std::alloc::alloc_zeroed(Block::LAYOUT) };
+        Block(NonNull::new(ptr).unwrap_or_else(|| std::alloc::handle_alloc_error(Block::LAYOUT)))
+    }
+    #[inline]
+    unsafe fn line_marks_at<'a>(block: usize) -> &'a [AtomicU8; LINES_PER_BLOCK] {
+        &*(block as *const [AtomicU8; LINES_PER_BLOCK])
+    }
+    #[inline]
+    fn line_marks(&self) -> &[AtomicU8; LINES_PER_BLOCK] {
+        unsafe { Block::line_marks_at(self.0.as_ptr() as usize) }
+    }
+    #[inline]
+    fn line_address(&self, line: usize) -> usize {
+        self.0.as_ptr() as usize + line * LINE_SIZE
+    }
+}
+impl Drop for Block {
+    fn drop(&mut self) {
+        unsafe { std::alloc::dealloc(self.0.as_ptr(), Block::LAYOUT) }
+    }
+}
+
+/// The hole that a context is currently allocating in
+#[derive(Default)]
+pub struct ImmixCache {
+    cursor: Cell<usize>,
+    limit: Cell<usize>,
+}
+
+struct SpaceState {
+    blocks: Vec<Block>,
+    /// The holes that are available for allocation
+    holes: Vec<Hole>,
+    /// The hole used for allocations that don't have a context's cache
+    shared: Hole,
+}
+impl SpaceState {
+    /// Find a hole that's big enough for an object of the specified size,
+    /// allocating a new block if there aren't any.
+    fn take_hole(&mut self, size: usize) -> Hole {
+        match self.holes.iter().rposition(|hole| hole.len() >= size) {
+            Some(index) => self.holes.swap_remove(index),
+            None => {
+                let block = Block::alloc();
+                let hole = Hole {
+                    start: block.line_address(METADATA_LINES),
+                    end: block.line_address(LINES_PER_BLOCK),
+                };
+                self.blocks.push(block);
+                hole
+            }
+        }
+    }
+    /// Give back what's left of a hole, unless it's too small to be worth it
+    fn give_back(&mut self, hole: Hole) {
+        if hole.len() >= LINE_SIZE {
+            self.holes.push(hole);
+        }
+    }
+}
+
+/// The blocks that small objects are allocated in
+pub struct ImmixSpace {
+    state: Lock<SpaceState>,
+}
+impl ImmixSpace {
+    pub fn new() -> ImmixSpace {
+        ImmixSpace {
+            state: Lock::from(SpaceState {
+                blocks: Vec::new(),
+                holes: Vec::new(),
+                shared: Hole::EMPTY,
+            }),
+        }
+    }
+    /// Allocate memory for an object with the specified (overall) layout,
+    /// using the context's cache if one is given.
+    #[inline]
+    pub fn alloc(&self, layout: Layout, cache: Option<&ImmixCache>) -> NonNull<u8> {
+        debug_assert!(fits(layout));
+        debug_assert_eq!(layout.size() % OBJECT_ALIGN, 0);
+        let size = layout.size();
+        let ptr = match cache {
+            Some(cache) => {
+                let cursor = cache.cursor.get();
+                if size <= cache.limit.get() - cursor {
+                    cache.cursor.set(cursor + size);
+                    cursor
+                } else {
+                    self.refill(cache, size)
+                }
+            }
+            None => {
+                let mut state = self.state.lock();
+                let mut hole = state.shared;
+                if hole.len() < size {
+                    state.give_back(hole);
+                    hole = state.take_hole(size);
+                }
+                state.shared = Hole {
+                    start: hole.start + size,
+                    end: hole.end,
+                };
+                hole.start
+            }
+        };
+        debug_assert_eq!(ptr % OBJECT_ALIGN, 0);
+        unsafe { NonNull::new_unchecked(ptr as *mut u8) }
+    }
+    #[cold]
+    fn refill(&self, cache: &ImmixCache, size: usize) -> usize {
+        let mut state = self.state.lock();
+        state.give_back(Hole {
+            start: cache.cursor.get(),
+            end: cache.limit.get(),
+        });
+        let hole = state.take_hole(size);
+        cache.cursor.set(hole.start + size);
+        cache.limit.set(hole.end);
+        hole.start
+    }
+    /// Give back the rest of the context's current hole
+    pub unsafe fn flush(&self, cache: &ImmixCache) {
+        let hole = Hole {
+            start: cache.cursor.replace(0),
+            end: cache.limit.replace(0),
+        };
+        if hole.len() > 0 {
+            self.state.lock().give_back(hole);
+        }
+    }
+    /// Find the free lines in every block, now that marking has finished
+    /// and all the garbage has been dropped.
+    ///
+    /// Entirely free blocks are released to the global allocator,
+    /// once they exceed the `retained` number of bytes (if any).
+    /// The line marks are reset for the next collection.
+    ///
+    /// ## Safety
+    /// Every context's cache must have been flushed,
+    /// and none of the unmarked lines can contain live objects.
+    pub unsafe fn sweep_lines(&self, retained: Option<usize>) {
+        let mut state = self.state.lock();
+        let SpaceState {
+            ref mut blocks,
+            ref mut holes,
+            ref mut shared,
+        } = *state;
+        holes.clear();
+        *shared = Hole::EMPTY;
+        let mut retained = retained.unwrap_or(usize::MAX);
+        blocks.retain(|block| {
+            let marks = block.line_marks();
+            let num_holes = holes.len();
+            let mut free_start = None;
+            for (line, mark) in marks.iter().enumerate().skip(METADATA_LINES) {
+                let marked = mark.swap(0, Ordering::Relaxed) != 0;
+                match (marked, free_start) {
+                    (false, None) => free_start = Some(line),
+                    (true, Some(start)) => {
+                        holes.push(Hole {
+                            start: block.line_address(start),
+                            end: block.line_address(line),
+                        });
+                        free_start = None;
+                    }
+                    _ => {}
+                }
+            }
+            match free_start {
+                Some(METADATA_LINES) => {
+                    // The whole block is free
+                    if retained < BLOCK_SIZE {
+                        return false;
+                    }
+                    retained -= BLOCK_SIZE;
+                    holes.push(Hole {
+                        start: block.line_address(METADATA_LINES),
+                        end: block.line_address(LINES_PER_BLOCK),
+                    });
+                }
+                Some(start) => holes.push(Hole {
+                    start: block.line_address(start),
+                    end: block.line_address(LINES_PER_BLOCK),
+                }),
+                None => {}
+            }
+            debug_assert!(holes[num_holes..].iter().all(|hole| hole.len() > 0));
+            true
+        });
+    }
+    /// The number of bytes reserved for blocks
+    pub fn reserved_bytes(&self) -> usize {
+        self.state.lock().blocks.len() * BLOCK_SIZE
+    }
+}
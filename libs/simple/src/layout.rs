@@ -13,6 +13,7 @@ use std::cell::Cell;
 use std::ffi::c_void;
 use std::marker::PhantomData;
 use std::mem::{self};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use zerogc::vec::raw::{GcRawVec, IGcVec};
 use zerogc::{GcSafe, GcSimpleAlloc, Trace};
@@ -36,8 +37,10 @@ const STATE_MASK: usize = 0b11;
 #[repr(transparent)]
 #[derive(NullTrace)]
 pub struct SimpleMarkData {
-    /// We're assuming that the collector is single threaded (which is true for now)
-    data: Cell<usize>,
+    /// Atomic, since objects can be marked by several threads at once
+    /// (see [crate::GcConfig::marking_threads]).
+    #[zerogc(unsafe_skip_trace)]
+    data: AtomicUsize,
     #[zerogc(unsafe_skip_trace)]
     fmt: PhantomData<CollectorId>,
 }
@@ -108,7 +111,7 @@ impl<H> HeaderLayout<H> {
     /// given the alignment of its value
     #[inline]
     pub const fn value_offset(&self, align: usize) -> usize {
-        let padding = self.layout().padding_needed_for(align);
+        let padding = self.layout().padding_needed_for(match core::ptr::Alignment::new(align) { Some(a) => a, None => panic!() });
         self.header_size + padding
     }
 }
@@ -118,7 +121,7 @@ impl SimpleMarkData {
     #[inline]
     pub fn from_snapshot(snapshot: SimpleMarkDataSnapshot) -> Self {
         SimpleMarkData {
-            data: Cell::new(snapshot.packed()),
+            data: AtomicUsize::new(snapshot.packed()),
             fmt: PhantomData,
         }
     }
@@ -126,12 +129,32 @@ impl SimpleMarkData {
     pub(crate) fn update_raw_state(&self, updated: RawMarkState) {
         let mut snapshot = self.load_snapshot();
         snapshot.state = updated;
-        self.data.set(snapshot.packed());
+        self.data.store(snapshot.packed(), Ordering::Relaxed);
+    }
+    /// Atomically change the state from `expected` to `updated`,
+    /// returning `false` if the state was something else.
+    ///
+    /// This ensures only one marking thread claims the object.
+    #[inline]
+    pub(crate) fn try_update_raw_state(
+        &self,
+        expected: RawMarkState,
+        updated: RawMarkState,
+    ) -> bool {
+        let mut snapshot = self.load_snapshot();
+        if snapshot.state != expected {
+            return false;
+        }
+        let old = snapshot.packed();
+        snapshot.state = updated;
+        self.data
+            .compare_exchange(old, snapshot.packed(), Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
     }
     /// Load a snapshot of the object's current marking state
     #[inline]
     pub fn load_snapshot(&self) -> SimpleMarkDataSnapshot {
-        unsafe { SimpleMarkDataSnapshot::from_packed(self.data.get()) }
+        unsafe { SimpleMarkDataSnapshot::from_packed(self.data.load(Ordering::Relaxed)) }
     }
 }
 
@@ -381,6 +404,16 @@ unsafe impl<'gc, T: GcSafe<'gc, crate::CollectorId>> IGcVec<'gc, T> for SimpleVe
         self.ctx
     }
 
+    #[inline]
+    pub fn write_barrier(&mut self) {
+        unsafe {
+            self.ctx
+                .collector()
+                .heap
+                .write_barrier(&mut (*self.header.as_ptr()).common_header);
+        }
+    }
+
     // Default methods:
     pub unsafe fn as_mut_ptr(&mut self) -> *mut T;
     pub fn replace(&mut self, index: usize, val: T) -> T;
@@ -496,13 +529,20 @@ impl GcHeader {
     }
     #[inline]
     pub(crate) fn raw_mark_state(&self) -> RawMarkState {
-        // TODO: Is this safe? Couldn't it be accessed concurrently?
         self.mark_data.load_snapshot().state
     }
     #[inline]
     pub(crate) fn update_raw_mark_state(&self, raw_state: RawMarkState) {
         self.mark_data.update_raw_state(raw_state);
     }
+    #[inline]
+    pub(crate) fn try_update_raw_mark_state(
+        &self,
+        expected: RawMarkState,
+        updated: RawMarkState,
+    ) -> bool {
+        self.mark_data.try_update_raw_state(expected, updated)
+    }
 }
 /// Layout information on a [GcType]
 pub enum GcTypeLayout {
@@ -536,6 +576,10 @@ pub struct GcType {
     pub trace_func: Option<unsafe fn(*mut c_void, &mut MarkVisitor)>,
     /// The function to drop the type, or `None` if it doesn't need to be dropped
     pub drop_func: Option<unsafe fn(*mut c_void)>,
+    /// The name of the type, as given by [std::any::type_name]
+    ///
+    /// This is only used for debugging.
+    pub type_name: &'static str,
 }
 impl GcType {
     #[inline]
@@ -634,6 +678,7 @@ impl<'gc, T: GcSafe<'gc, crate::CollectorId>> StaticVecType for T {
         } else {
             None
         },
+        type_name: std::any::type_name::<[T]>(),
     };
 }
 pub(crate) trait StaticGcType {
@@ -674,6 +719,7 @@ impl<'gc, T: GcSafe<'gc, crate::CollectorId>> StaticGcType for [T] {
         } else {
             None
         },
+        type_name: std::any::type_name::<[T]>(),
     };
 }
 impl<'gc, T: GcSafe<'gc, crate::CollectorId>> StaticGcType for T {
@@ -700,5 +746,6 @@ impl<'gc, T: GcSafe<'gc, crate::CollectorId>> StaticGcType for T {
         } else {
             None
         },
+        type_name: std::any::type_name::<T>(),
     };
 }
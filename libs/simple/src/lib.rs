@@ -27,13 +27,14 @@
     const_alloc_layout, // Used for StaticType
     new_uninit, // Until Rust has const generics, this is how we init arrays..
     ptr_metadata, // Needed to abstract over Sized/unsized types
+    const_type_name, // Used for GcType::type_name
     // Used for const layout computation:
     const_mut_refs,
     const_align_of_val,
     // Needed for field_offset!
     const_refs_to_cell,
     // Used instead of drain_filter
-    extract_if,
+    extract_if, ptr_alignment_type,
 )]
 #![allow(
     /*
@@ -46,30 +47,32 @@ use std::alloc::Layout;
 use std::any::TypeId;
 #[cfg(not(feature = "multiple-collectors"))]
 use std::marker::PhantomData;
-use std::ops::{Deref, DerefMut};
 use std::ptr::{DynMetadata, NonNull, Pointee};
 #[cfg(not(feature = "multiple-collectors"))]
 use std::sync::atomic::AtomicPtr;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use slog::{debug, FnValue, Logger};
 
-use zerogc::{GcSafe, GcVisitor, Trace};
+use zerogc::{GcAllocError, GcFinalized, GcRebrand, GcSafe, GcVisitor, Trace};
 
-use zerogc_context::utils::{MemorySize, ThreadId};
+use zerogc_context::utils::{ILock, Lock, MemorySize, ThreadId};
 
-use crate::alloc::{SmallArena, SmallArenaList};
+use crate::alloc::{ArenaCache, SmallArena, SmallArenaList};
+use crate::immix::{ImmixCache, ImmixSpace};
 use crate::layout::{
     BigGcObject, GcArrayHeader, GcHeader, GcType, GcTypeLayout, GcVecHeader, HeaderLayout,
     SimpleMarkData, SimpleMarkDataSnapshot, SimpleVecRepr, StaticGcType, StaticVecType,
 };
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::ffi::c_void;
 use zerogc::vec::raw::GcRawVec;
-use zerogc_context::collector::RawSimpleAlloc;
-use zerogc_context::handle::{GcHandleList, RawHandleImpl};
+use zerogc_context::collector::{RawFinalizeImpl, RawNonMovingImpl, RawSimpleAlloc};
+use zerogc_context::handle::{GcHandleList, RawHandleImpl, RawPinImpl, RawWeakHandleImpl};
+use zerogc_context::threshold::update_gc_cpu_fraction;
 use zerogc_context::{
     CollectionManager as AbstractCollectionManager, CollectorContext,
     RawContext as AbstractRawContext,
@@ -80,16 +83,28 @@ mod alloc;
 #[cfg(not(feature = "small-object-arenas"))]
 mod alloc {
     use crate::layout::UnknownHeader;
+    use crate::GcConfig;
     use std::alloc::Layout;
 
-    pub const fn fits_small_object(_layout: Layout) -> bool {
-        false
-    }
     pub struct SmallArena;
+    #[derive(Default)]
+    pub struct ArenaCache;
+    impl ArenaCache {
+        pub(crate) fn has_free(&self, _arena: &SmallArena) -> bool {
+            unimplemented!()
+        }
+        pub(crate) fn alloc(&self, _arena: &SmallArena) -> std::ptr::NonNull<super::GcHeader> {
+            unimplemented!()
+        }
+        pub(crate) unsafe fn flush(&self, _arenas: &SmallArenaList) {}
+    }
     impl SmallArena {
         pub(crate) fn add_free(&self, _free: *mut UnknownHeader) {
             unimplemented!()
         }
+        pub(crate) fn has_free(&self) -> bool {
+            unimplemented!()
+        }
         pub(crate) fn alloc(&self) -> std::ptr::NonNull<super::GcHeader> {
             unimplemented!()
         }
@@ -97,33 +112,306 @@ mod alloc {
     pub struct SmallArenaList;
     impl SmallArenaList {
         // Create dummy
-        pub fn new() -> Self {
+        pub fn new(_config: &GcConfig) -> Self {
             SmallArenaList
         }
         pub fn find(&self, _layout: Layout) -> Option<&SmallArena> {
             None
         }
+        pub(crate) unsafe fn verify_free_lists(
+            &self,
+            _func: impl FnMut(*mut u8),
+        ) -> Result<(), String> {
+            Ok(())
+        }
+        pub(crate) unsafe fn release_free_chunks(&self, _retained: usize) -> usize {
+            0
+        }
+        pub(crate) fn reserved_bytes(&self) -> usize {
+            0
+        }
+    }
+}
+#[cfg(feature = "immix")]
+mod immix;
+#[cfg(not(feature = "immix"))]
+mod immix {
+    use crate::layout::GcHeader;
+    use std::alloc::Layout;
+    use std::ptr::NonNull;
+
+    pub struct ImmixSpace;
+    #[derive(Default)]
+    pub struct ImmixCache;
+    #[inline]
+    pub const fn fits(_layout: Layout) -> bool {
+        false
+    }
+    #[inline]
+    pub unsafe fn mark_object(_header: *mut GcHeader) {}
+    pub unsafe fn mark_lines(_start: *mut u8, _size: usize) {
+        unimplemented!()
+    }
+    impl ImmixSpace {
+        // Create dummy
+        pub fn new() -> Self {
+            ImmixSpace
+        }
+        pub fn alloc(&self, _layout: Layout, _cache: Option<&ImmixCache>) -> NonNull<u8> {
+            unimplemented!()
+        }
+        pub unsafe fn flush(&self, _cache: &ImmixCache) {}
+        pub unsafe fn sweep_lines(&self, _retained: Option<usize>) {}
+        pub fn reserved_bytes(&self) -> usize {
+            0
+        }
     }
 }
 pub mod layout;
+#[cfg(feature = "sync")]
+mod parallel;
+pub mod snapshot;
+mod verify;
 
 /// The configuration for a garbage collection
 pub struct GcConfig {
     /// Whether to always force a collection at safepoints,
     /// regardless of whether the heuristics say.
+    ///
+    /// To force a single collection, use [zerogc::safepoint_force!] instead.
     pub always_force_collect: bool,
     /// The initial threshold to trigger garbage collection (in bytes)
+    ///
+    /// By default, this is the [HeapGrowth::min_heap_size] of the default policy.
     pub initial_threshold: usize,
+    /// The policy used to pick the threshold for the next collection,
+    /// once a collection has finished.
+    ///
+    /// By default, this is [HeapGrowth::default].
+    pub threshold_policy: Box<dyn ThresholdPolicy>,
+    /// The maximum size of the heap (in bytes)
+    ///
+    /// Allocations that would exceed this limit fail with
+    /// [GcAllocError::HeapLimitExceeded](zerogc::GcAllocError::HeapLimitExceeded)
+    /// and force a collection at the next safepoint.
+    ///
+    /// Allocations can't collect garbage themselves (that requires a safepoint),
+    /// so the limit applies to everything allocated since the last collection,
+    /// including garbage. Callers should handle the error by invoking a safepoint
+    /// and retrying the allocation, which only fails again if the live objects
+    /// really don't fit. The infallible allocation methods just panic.
+    ///
+    /// By default, the heap is unlimited.
+    pub max_heap_size: Option<usize>,
+    /// Verify the integrity of the heap before and after every mark phase,
+    /// panicking if any corruption is detected.
+    ///
+    /// This is very slow, and is intended for debugging
+    /// broken `Trace` implementations.
+    pub verify_heap: bool,
+    /// Mark the heap incrementally, doing a bounded amount of work at each safepoint.
+    ///
+    /// This limits the length of pauses, at the cost of write barriers
+    /// (which are only active while marking is in progress).
+    /// Once marking has finished, the rest of the collection
+    /// (processing weak references and sweeping) is done all at once.
+    ///
+    /// If the heap grows to twice the collection threshold while marking is in progress,
+    /// the rest of the marking is done all at once.
+    /// A forced safepoint ([zerogc::safepoint_force!]) performs a full collection,
+    /// unless marking is already in progress.
+    ///
+    /// This is ignored if the `implicit-grey-stack` feature is enabled,
+    /// since recursive marking can't be interrupted.
+    ///
+    /// By default, the whole collection is done at once.
+    pub incremental_budget: Option<MarkBudget>,
+    /// The number of threads used to mark the heap,
+    /// including the thread that triggered the collection.
+    ///
+    /// Marking is split across the threads using work-stealing,
+    /// but only once the heap turns out to be large enough to make it worthwhile.
+    /// The steps of incremental marking are always done on a single thread.
+    /// The extra threads are spawned by the first collection that uses them,
+    /// and are kept around until the collector is dropped.
+    ///
+    /// Objects are traced on whichever thread happens to find them,
+    /// so this is ignored unless the `sync` feature is enabled.
+    ///
+    /// By default, a single thread is used.
+    pub marking_threads: usize,
+    /// Sweep the heap lazily, instead of all at once at the end of each collection.
+    ///
+    /// The garbage found by a collection is freed in small batches,
+    /// whenever the allocator runs out of free slots.
+    /// Anything left over is swept at the start of the next collection.
+    ///
+    /// This moves most of the cost of sweeping out of collection pauses,
+    /// at the cost of holding onto garbage for longer.
+    /// Objects are dropped by whichever thread happens to sweep them.
+    ///
+    /// This is disabled by default,
+    /// and has no effect if the `immix` feature is enabled.
+    pub lazy_sweep: bool,
+    /// The amount of entirely free memory that the small object arenas
+    /// (or the Immix blocks) keep around after a collection (in bytes).
+    ///
+    /// Any other free chunks are returned to the global allocator,
+    /// so memory usage shrinks again after a burst of allocation.
+    /// With lazy sweeping, chunks are only released once
+    /// they've been completely swept (at the start of the next collection).
+    ///
+    /// If this is `None`, arena memory is never released.
+    /// By default, up to 1MB is kept.
+    pub retained_arena_bytes: Option<usize>,
+    /// The sizes of the slots in the small object arenas (in bytes)
+    ///
+    /// Each object is allocated in the smallest size class that it fits in.
+    /// Anything bigger than the largest size class is allocated individually,
+    /// using the global allocator (which is much slower).
+    ///
+    /// The sizes must be sorted, and multiples of the word size.
+    /// Each size must be at least two words,
+    /// and there can't be more than [GcConfig::MAX_SIZE_CLASSES] of them.
+    ///
+    /// By default, this is [GcConfig::size_classes_up_to] 32 words.
+    /// This is ignored unless the `small-object-arenas` feature is enabled.
+    pub size_classes: Vec<usize>,
+    /// The size of the first chunk of memory in each small object arena (in bytes)
+    ///
+    /// Whenever an arena runs out of memory,
+    /// it allocates another chunk that's twice as big as the last one.
+    pub arena_chunk_size: usize,
+}
+impl GcConfig {
+    /// The maximum number of [GcConfig::size_classes]
+    pub const MAX_SIZE_CLASSES: usize = 64;
+    /// Size classes for all the objects up to `max_size` bytes
+    ///
+    /// There are four size classes for every doubling in size,
+    /// so no more than 25% of each slot is wasted
+    /// (except for the smallest few, which are a single word apart).
+    pub fn size_classes_up_to(max_size: usize) -> Vec<usize> {
+        let word_size = std::mem::size_of::<usize>();
+        let max_words = max_size.div_ceil(word_size);
+        let mut sizes = Vec::new();
+        let mut num_words = 2;
+        while num_words < max_words {
+            sizes.push(num_words * word_size);
+            let step = (1 << num_words.ilog2()) / 4;
+            num_words += step.max(1);
+        }
+        sizes.push(max_words.max(2) * word_size);
+        sizes
+    }
+    /// The defaults from before [GcConfig::threshold_policy] was added,
+    /// which keep the heap as small as possible.
+    ///
+    /// The first collection is triggered once 2KB has been allocated,
+    /// and later ones once the heap grows to 150% of the live data ([HeapGrowth::EAGER]).
+    /// This collects far more often than the defaults,
+    /// especially while a program is starting up.
+    pub fn eager() -> GcConfig {
+        GcConfig {
+            initial_threshold: 2048,
+            threshold_policy: Box::new(HeapGrowth::EAGER),
+            ..GcConfig::default()
+        }
+    }
 }
 impl Default for GcConfig {
     fn default() -> Self {
         GcConfig {
             always_force_collect: false,
-            initial_threshold: 2048,
+            initial_threshold: HeapGrowth::default().min_heap_size,
+            threshold_policy: Box::<HeapGrowth>::default(),
+            max_heap_size: None,
+            verify_heap: false,
+            incremental_budget: None,
+            marking_threads: 1,
+            lazy_sweep: false,
+            retained_arena_bytes: Some(1024 * 1024),
+            size_classes: GcConfig::size_classes_up_to(32 * std::mem::size_of::<usize>()),
+            arena_chunk_size: 512,
         }
     }
 }
 
+/// The amount of work done by each step of incremental marking
+///
+/// See [GcConfig::incremental_budget]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MarkBudget {
+    /// Stop marking once the step has taken this long
+    ///
+    /// The roots are always scanned in full,
+    /// so a step may take slightly longer than this.
+    Time(Duration),
+    /// Stop marking once the step has traced this many objects
+    Work(usize),
+}
+
+/// A snapshot of statistics about a [SimpleCollector]
+///
+/// This can be retrieved with [SimpleCollector::stats]
+#[derive(Clone, Debug, Default)]
+pub struct GcStats {
+    /// The number of collections that have been performed
+    pub collections: u64,
+    /// The total time spent in collections
+    /// (including incremental marking steps)
+    pub total_pause: Duration,
+    /// The duration of the last pause
+    ///
+    /// This is either a full collection,
+    /// or a single step of incremental marking.
+    pub last_pause: Duration,
+    /// The number of bytes freed by the last collection
+    pub last_freed_bytes: usize,
+    /// The total number of bytes freed by all collections
+    pub total_freed_bytes: u64,
+    /// The number of roots that were traced in the last collection
+    pub last_num_roots: usize,
+    /// The number of bytes currently allocated in the heap
+    ///
+    /// This includes garbage that is waiting to be swept (see `unswept_bytes`).
+    pub live_bytes: usize,
+    /// The current threshold that will trigger the next collection (in bytes)
+    pub threshold: usize,
+    /// The number of handles that are currently alive
+    pub num_handles: usize,
+    /// The number of free handle slots, that can be reused by new handles
+    ///
+    /// Slots that are no longer needed are released after each collection.
+    pub free_handle_slots: usize,
+    /// The number of bytes allocated in the small object arenas
+    pub small_object_bytes: usize,
+    /// The number of bytes allocated as individual big objects
+    pub big_object_bytes: usize,
+    /// The number of incremental marking steps that have been performed
+    ///
+    /// This is always zero unless [GcConfig::incremental_budget] is set.
+    pub incremental_steps: u64,
+    /// Whether incremental marking is currently in progress
+    pub marking_in_progress: bool,
+    /// The number of bytes of garbage that is waiting to be swept
+    ///
+    /// This is always zero unless [GcConfig::lazy_sweep] is set.
+    pub unswept_bytes: usize,
+    /// The fraction of time recently spent collecting garbage, between zero and one.
+    ///
+    /// This is the time spent paused for collections (including incremental marking steps),
+    /// relative to the total time taken by each collection cycle.
+    /// It's a moving average, weighted towards the most recent collections.
+    pub gc_cpu_fraction: f64,
+    /// The number of bytes reserved by the small object arenas
+    /// (or the Immix blocks, with the `immix` feature)
+    ///
+    /// This includes free memory that hasn't been released yet
+    /// (see [GcConfig::retained_arena_bytes]).
+    pub arena_bytes: usize,
+}
+
 /// The alignment of the singleton empty vector
 const EMPTY_VEC_ALIGNMENT: usize = std::mem::align_of::<usize>();
 
@@ -140,10 +428,18 @@ type CollectionManager<C> = zerogc_context::state::nosync::CollectionManager<C>;
 pub type SimpleCollector = ::zerogc_context::CollectorRef<RawSimpleCollector>;
 /// The context of a simple collector
 pub type SimpleCollectorContext = ::zerogc_context::CollectorContext<RawSimpleCollector>;
+/// A context of a simple collector, that can be used inside `async` tasks
+#[cfg(feature = "sync")]
+pub type SimpleAsyncContext = ::zerogc_context::AsyncContext<RawSimpleCollector>;
+#[cfg(feature = "sync")]
+pub use zerogc_context::safepoint_await;
+pub use zerogc_context::threshold::{FinishedCollection, HeapGrowth, ThresholdPolicy};
 /// The id for a simple collector
 pub type CollectorId = ::zerogc_context::CollectorId<RawSimpleCollector>;
 /// A garbage collected pointer, allocated in the "simple" collector
 pub type Gc<'gc, T> = ::zerogc::Gc<'gc, T, CollectorId>;
+/// A weak reference to an object in the "simple" collector
+pub type GcWeak<'gc, T> = ::zerogc::GcWeak<'gc, T, CollectorId>;
 /// A garbage collected array, allocated in the "simple" collector
 pub type GcArray<'gc, T> = ::zerogc::array::GcArray<'gc, T, CollectorId>;
 /// A garbage colelcted vector, allocated in the "simple" collector
@@ -154,35 +450,42 @@ static GLOBAL_COLLECTOR: AtomicPtr<RawSimpleCollector> = AtomicPtr::new(std::ptr
 
 unsafe impl RawSimpleAlloc for RawSimpleCollector {
     #[inline]
-    unsafe fn alloc_uninit<'gc, T>(context: &'gc SimpleCollectorContext) -> *mut T
+    unsafe fn try_alloc_uninit<'gc, T>(
+        context: &'gc SimpleCollectorContext,
+    ) -> Result<*mut T, GcAllocError>
     where
         T: GcSafe<'gc, crate::CollectorId>,
     {
-        let (_header, ptr) = context.collector().heap.allocator.alloc_layout(
+        let (_header, ptr) = context.collector().heap.alloc_layout(
             GcHeader::LAYOUT,
             Layout::new::<T>(),
             T::STATIC_TYPE,
-        );
-        ptr as *mut T
+            Some(context.cache()),
+        )?;
+        Ok(ptr as *mut T)
     }
 
-    unsafe fn alloc_uninit_slice<'gc, T>(context: &'gc CollectorContext<Self>, len: usize) -> *mut T
+    unsafe fn try_alloc_uninit_slice<'gc, T>(
+        context: &'gc CollectorContext<Self>,
+        len: usize,
+    ) -> Result<*mut T, GcAllocError>
     where
         T: GcSafe<'gc, crate::CollectorId>,
     {
-        let (header, ptr) = context.collector().heap.allocator.alloc_layout(
+        let (header, ptr) = context.collector().heap.alloc_layout(
             GcArrayHeader::LAYOUT,
-            Layout::array::<T>(len).unwrap(),
+            Layout::array::<T>(len)?,
             <[T] as StaticGcType>::STATIC_TYPE,
-        );
+            Some(context.cache()),
+        )?;
         (*header).len = len;
-        ptr.cast()
+        Ok(ptr.cast())
     }
 
-    fn alloc_raw_vec_with_capacity<'gc, T>(
+    fn try_alloc_raw_vec_with_capacity<'gc, T>(
         context: &'gc CollectorContext<Self>,
         capacity: usize,
-    ) -> Self::RawVec<'gc, T>
+    ) -> Result<Self::RawVec<'gc, T>, GcAllocError>
     where
         T: GcSafe<'gc, crate::CollectorId>,
     {
@@ -192,17 +495,18 @@ unsafe impl RawSimpleAlloc for RawSimpleCollector {
             unsafe {
                 debug_assert_eq!((*header).len.get(), 0);
                 debug_assert_eq!((*header).capacity, 0);
-                return self::layout::SimpleVecRepr::from_raw_parts(
+                return Ok(self::layout::SimpleVecRepr::from_raw_parts(
                     NonNull::new_unchecked(header),
                     context,
-                );
+                ));
             }
         }
-        let (header, value_ptr) = context.collector().heap.allocator.alloc_layout(
+        let (header, value_ptr) = context.collector().heap.alloc_layout(
             GcVecHeader::LAYOUT,
-            Layout::array::<T>(capacity).unwrap(),
+            Layout::array::<T>(capacity)?,
             <T as StaticVecType>::STATIC_VEC_TYPE,
-        );
+            Some(context.cache()),
+        )?;
         unsafe {
             (*header).capacity = capacity;
             (*header).len.set(0);
@@ -211,11 +515,29 @@ unsafe impl RawSimpleAlloc for RawSimpleCollector {
                 context,
             );
             debug_assert_eq!(res.as_ptr(), value_ptr as *mut T as *const T,);
-            res
+            Ok(res)
         }
     }
 }
 
+/// The cache that each context uses to speed up allocation
+///
+/// The objects a context allocates aren't visible to the collector
+/// until the cache is flushed at the context's next safepoint.
+#[doc(hidden)] // NOTE: Needs be public for RawCollectorImpl
+#[derive(Default)]
+pub struct SimpleContextCache {
+    /// Free slots that were taken from the small object arenas
+    arenas: ArenaCache,
+    /// The free lines that are being bump-allocated into (see the `immix` feature)
+    immix: ImmixCache,
+    /// The small objects that were allocated since the last safepoint
+    ///
+    /// These are added to the shared list in bulk,
+    /// so allocating doesn't need to take a lock.
+    small_objects: RefCell<Vec<*mut GcHeader>>,
+}
+
 #[doc(hidden)] // NOTE: Needs be public for RawCollectorImpl
 pub unsafe trait DynTrace {
     fn trace(&mut self, visitor: &mut MarkVisitor);
@@ -247,6 +569,16 @@ unsafe impl RawHandleImpl for RawSimpleCollector {
     }
 }
 
+/// The simple collector never moves objects,
+/// so pinned objects only need to be kept alive (just like any other handle).
+unsafe impl RawPinImpl for RawSimpleCollector {}
+
+unsafe impl RawNonMovingImpl for RawSimpleCollector {}
+
+/// Weak handles are cleared along with the other weak references
+/// (see [CollectionTask::process_weak_refs]).
+unsafe impl RawWeakHandleImpl for RawSimpleCollector {}
+
 /// A wrapper for [GcHandleList] that implements [DynTrace]
 #[repr(transparent)]
 struct GcHandleListWrapper(GcHandleList<RawSimpleCollector>);
@@ -254,27 +586,98 @@ unsafe impl DynTrace for GcHandleListWrapper {
     fn trace(&mut self, visitor: &mut MarkVisitor) {
         unsafe {
             let Ok(()) = self.0.trace::<_, !>(|raw_ptr, type_info| {
-                let header = &mut *GcHeader::from_value_ptr(raw_ptr);
-                // Mark grey
-                header.update_raw_mark_state(MarkState::Grey.to_raw(visitor.inverted_mark));
-                // Visit innards
-                if let Some(func) = type_info.trace_func {
-                    func(header.value(), visitor);
-                }
-                // Mark black
-                header.update_raw_mark_state(MarkState::Black.to_raw(visitor.inverted_mark));
+                let header = GcHeader::from_value_ptr(raw_ptr);
+                debug_assert!(std::ptr::eq((*header).type_info, type_info));
+                /*
+                 * NOTE: Must respect the existing mark state,
+                 * since the object may already be on the grey stack
+                 * (if it's also referenced elsewhere, or marking is incremental)
+                 */
+                visitor._trace_own_header(header);
                 Ok(())
             });
         }
     }
 }
 
+unsafe impl RawFinalizeImpl for RawSimpleCollector {
+    type DrainFinalized<'gc> = std::vec::IntoIter<GcFinalized<'gc, CollectorId>>;
+
+    fn register_finalizer<'gc, T>(context: &'gc SimpleCollectorContext, gc: Gc<'gc, T>)
+    where
+        T: GcSafe<'gc, CollectorId> + GcRebrand<'static, CollectorId>,
+        <T as GcRebrand<'static, CollectorId>>::Branded: 'static,
+    {
+        let entry = unsafe {
+            Finalizable {
+                header: GcHeader::from_value_ptr(gc.as_raw_ptr()),
+                object: GcFinalized::new(gc).rebrand(),
+            }
+        };
+        context.collector().finalizers.registered.lock().push(entry);
+    }
+
+    fn drain_finalized<'gc>(context: &'gc SimpleCollectorContext) -> Self::DrainFinalized<'gc> {
+        let pending = std::mem::take(&mut *context.collector().finalizers.pending.lock());
+        pending
+            .into_iter()
+            // SAFETY: Nothing can be collected until the next safepoint
+            .map(|entry| unsafe { entry.object.rebrand::<'gc>() })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+/// An object that has been registered for finalization
+struct Finalizable {
+    header: *mut GcHeader,
+    object: GcFinalized<'static, CollectorId>,
+}
+
+/// The objects registered for finalization,
+/// along with the ones that are waiting to be drained.
+struct FinalizerQueue {
+    /// Objects that will be finalized once they become unreachable
+    registered: Lock<Vec<Finalizable>>,
+    /// Objects that have been found unreachable,
+    /// but haven't been drained by the mutator yet.
+    ///
+    /// These are considered roots until they are drained.
+    pending: Lock<Vec<Finalizable>>,
+}
+impl FinalizerQueue {
+    fn new() -> Self {
+        FinalizerQueue {
+            registered: Lock::from(Vec::new()),
+            pending: Lock::from(Vec::new()),
+        }
+    }
+}
+unsafe impl DynTrace for FinalizerQueue {
+    fn trace(&mut self, visitor: &mut MarkVisitor) {
+        for entry in self.pending.lock().iter() {
+            unsafe { visitor._trace_own_header(entry.header) }
+        }
+    }
+}
+
 struct GcHeap {
     config: Arc<GcConfig>,
     threshold: AtomicUsize,
     allocator: SimpleAlloc,
     // NOTE: This is only public so it can be traced
     cached_empty_vec: Cell<Option<*mut GcVecHeader>>,
+    /// Whether incremental marking is in progress
+    ///
+    /// Write barriers are only needed while this is set.
+    marking: AtomicBool,
+    /// Objects that have been turned grey by the mutator
+    /// since the last step of incremental marking.
+    ///
+    /// These are added to the grey stack at the start of the next step.
+    shaded: Lock<Vec<*mut GcHeader>>,
+    /// The total size of the objects allocated while incremental marking is in progress
+    allocated_while_marking: AtomicUsize,
 }
 impl GcHeap {
     fn new(config: Arc<GcConfig>) -> GcHeap {
@@ -284,9 +687,12 @@ impl GcHeap {
             } else {
                 config.initial_threshold
             }),
-            allocator: SimpleAlloc::new(),
+            allocator: SimpleAlloc::new(&config),
             config,
             cached_empty_vec: Cell::new(None),
+            marking: AtomicBool::new(false),
+            shaded: Lock::from(Vec::new()),
+            allocated_while_marking: AtomicUsize::new(0),
         }
     }
     #[inline]
@@ -312,16 +718,107 @@ impl GcHeap {
                 .value_offset_from_common_header(EMPTY_VEC_ALIGNMENT),
             drop_func: None,
             trace_func: None,
+            type_name: "<empty vec>",
         };
-        let (header, _) =
-            self.allocator
-                .alloc_layout(GcVecHeader::LAYOUT, DUMMY_LAYOUT, &DUMMY_TYPE);
+        let (header, _) = self
+            .allocator
+            .alloc_layout(GcVecHeader::LAYOUT, DUMMY_LAYOUT, &DUMMY_TYPE, None)
+            .unwrap_or_else(|_| std::alloc::handle_alloc_error(GcVecHeader::LAYOUT.layout()));
         unsafe {
             (*header).capacity = 0;
             (*header).len.set(0);
             header
         }
     }
+    /// Allocate an object, respecting the configured `max_heap_size`
+    #[inline]
+    fn alloc_layout<H>(
+        &self,
+        header_layout: HeaderLayout<H>,
+        value_layout: Layout,
+        static_type: &'static GcType,
+        cache: Option<&SimpleContextCache>,
+    ) -> Result<(*mut H, *mut u8), GcAllocError> {
+        if let Some(max_heap_size) = self.config.max_heap_size {
+            let requested_size = header_layout.layout().size() + value_layout.size();
+            if self.allocator.allocated_size() + requested_size > max_heap_size {
+                // Garbage that is waiting to be swept doesn't count against the limit
+                unsafe { self.allocator.finish_sweep() };
+            }
+            if self.allocator.allocated_size() + requested_size > max_heap_size {
+                // Make sure we collect at the next safepoint
+                self.threshold.store(0, Ordering::Relaxed);
+                return Err(GcAllocError::HeapLimitExceeded);
+            }
+        }
+        let (header, value_ptr) =
+            self.allocator
+                .alloc_layout(header_layout, value_layout, static_type, cache)?;
+        if self.is_marking() {
+            unsafe { self.mark_allocated(header_layout, value_layout, header) }
+        }
+        Ok((header, value_ptr))
+    }
+    /// Mark an object that was allocated while incremental marking is in progress.
+    ///
+    /// Not every store into a new object goes through a write barrier
+    /// (for example, when a `GcVecCell` grows), so new objects can't start out white.
+    /// Instead they're conservatively assumed to be reachable.
+    #[cold]
+    unsafe fn mark_allocated<H>(
+        &self,
+        header_layout: HeaderLayout<H>,
+        value_layout: Layout,
+        header: *mut H,
+    ) {
+        // NOTE: Can't use `determine_total_size`, since arrays haven't been initialized yet
+        let (overall_layout, _) = header_layout.layout().extend(value_layout).unwrap();
+        self.allocated_while_marking
+            .fetch_add(overall_layout.pad_to_align().size(), Ordering::Relaxed);
+        if immix::fits(overall_layout) {
+            immix::mark_lines(header.cast(), overall_layout.pad_to_align().size());
+        }
+        let header = header_layout.common_header(header);
+        let inverted_mark = self.allocator.mark_inverted();
+        if (*header).type_info.trace_func.is_some() {
+            (*header).update_raw_mark_state(MarkState::Grey.to_raw(inverted_mark));
+            self.shaded.lock().push(header);
+        } else {
+            (*header).update_raw_mark_state(MarkState::Black.to_raw(inverted_mark));
+        }
+    }
+    #[inline]
+    fn is_marking(&self) -> bool {
+        self.marking.load(Ordering::Relaxed)
+    }
+    /// The write barrier, triggered before an object is mutated.
+    ///
+    /// While incremental marking is in progress, this preserves the tri-color invariant
+    /// (black objects never reference white ones) by turning the object grey again,
+    /// so its new contents get traced.
+    /// The roots aren't protected by write barriers, so they're rescanned by every step.
+    #[inline]
+    pub(crate) unsafe fn write_barrier(&self, owner: *mut GcHeader) {
+        if self.is_marking() {
+            self.shade(owner)
+        }
+    }
+    #[cold]
+    unsafe fn shade(&self, header: *mut GcHeader) {
+        let mut shaded = self.shaded.lock();
+        // NOTE: Check the state while holding the lock, so an object is never queued twice
+        let inverted_mark = self.allocator.mark_inverted();
+        if (*header).raw_mark_state().resolve(inverted_mark) == MarkState::Black {
+            (*header).update_raw_mark_state(MarkState::Grey.to_raw(inverted_mark));
+            shaded.push(header);
+        }
+    }
+    /// Whether the heap has grown so much during incremental marking
+    /// that the rest of the marking should be done all at once.
+    #[inline]
+    fn should_finish_marking(&self) -> bool {
+        self.allocator.allocated_size() >= self.threshold.load(Ordering::Relaxed).saturating_mul(2)
+    }
     #[inline]
     fn should_collect_relaxed(&self) -> bool {
         /*
@@ -349,66 +846,15 @@ impl GcHeap {
     }
 }
 
-/// The abstract specification of a [Lock],
-/// shared between thread-safe and thread-unsafe code
-trait ILock<'a, T> {
-    type Guard: Sized + Deref<Target = T> + DerefMut<Target = T> + 'a;
-    fn lock(&'a self) -> Self::Guard;
-    fn get_mut(&'a mut self) -> &'a mut T;
-}
-
-#[cfg(feature = "sync")]
-struct Lock<T>(::parking_lot::Mutex<T>);
-#[cfg(feature = "sync")]
-impl<T> From<T> for Lock<T> {
-    fn from(val: T) -> Self {
-        Lock(::parking_lot::Mutex::new(val))
-    }
-}
-#[cfg(feature = "sync")]
-impl<'a, T: 'a> ILock<'a, T> for Lock<T> {
-    type Guard = ::parking_lot::MutexGuard<'a, T>;
-
-    #[inline]
-    fn lock(&'a self) -> Self::Guard {
-        self.0.lock()
-    }
-
-    #[inline]
-    fn get_mut(&'a mut self) -> &'a mut T {
-        self.0.get_mut()
-    }
-}
-
-#[cfg(not(feature = "sync"))]
-struct Lock<T>(::std::cell::RefCell<T>);
-#[cfg(not(feature = "sync"))]
-impl<T> From<T> for Lock<T> {
-    fn from(val: T) -> Self {
-        Lock(::std::cell::RefCell::new(val))
-    }
-}
-#[cfg(not(feature = "sync"))]
-impl<'a, T: 'a> ILock<'a, T> for Lock<T> {
-    type Guard = ::std::cell::RefMut<'a, T>;
-
-    #[inline]
-    fn lock(&'a self) -> Self::Guard {
-        self.0.borrow_mut()
-    }
-
-    #[inline]
-    fn get_mut(&'a mut self) -> &'a mut T {
-        self.0.get_mut()
-    }
-}
-
 /// The thread-safe implementation of an allocator
 ///
 /// Most allocations should avoid locking.
 pub(crate) struct SimpleAlloc {
     collector_id: Option<CollectorId>,
     small_arenas: SmallArenaList,
+    /// The blocks that small objects are allocated in,
+    /// when the `immix` feature is enabled (instead of the small object arenas)
+    immix: ImmixSpace,
     big_objects: Lock<Vec<BigGcObject>>,
     small_objects: Lock<Vec<*mut GcHeader>>,
     /// Whether the meaning of the mark bit is currently inverted.
@@ -416,6 +862,19 @@ pub(crate) struct SimpleAlloc {
     /// This flips every collection
     mark_inverted: AtomicBool,
     allocated_size: AtomicUsize,
+    /// The portion of `allocated_size` used by big objects
+    big_object_size: AtomicUsize,
+    /// The small objects left over from the last collection, that haven't been swept yet.
+    ///
+    /// See [GcConfig::lazy_sweep]
+    unswept_small_objects: Lock<Vec<*mut GcHeader>>,
+    /// The big objects left over from the last collection, that haven't been swept yet.
+    unswept_big_objects: Lock<Vec<BigGcObject>>,
+    /// The portion of `allocated_size` that is known to be garbage,
+    /// but hasn't been swept yet.
+    unswept_size: AtomicUsize,
+    /// Whether there are (potentially) any objects left to sweep
+    sweeping: AtomicBool,
 }
 #[derive(Debug)]
 struct TargetLayout<H> {
@@ -424,14 +883,20 @@ struct TargetLayout<H> {
     overall_layout: Layout,
 }
 impl SimpleAlloc {
-    fn new() -> SimpleAlloc {
+    fn new(config: &GcConfig) -> SimpleAlloc {
         SimpleAlloc {
             collector_id: None,
             allocated_size: AtomicUsize::new(0),
-            small_arenas: SmallArenaList::new(),
+            big_object_size: AtomicUsize::new(0),
+            small_arenas: SmallArenaList::new(config),
+            immix: ImmixSpace::new(),
             big_objects: Lock::from(Vec::new()),
             small_objects: Lock::from(Vec::new()),
             mark_inverted: AtomicBool::new(false),
+            unswept_small_objects: Lock::from(Vec::new()),
+            unswept_big_objects: Lock::from(Vec::new()),
+            unswept_size: AtomicUsize::new(0),
+            sweeping: AtomicBool::new(false),
         }
     }
     #[inline]
@@ -447,6 +912,19 @@ impl SimpleAlloc {
         self.allocated_size.store(amount, Ordering::Release)
     }
     #[inline]
+    fn big_object_size(&self) -> usize {
+        self.big_object_size.load(Ordering::Acquire)
+    }
+    /// The amount of garbage that is waiting to be swept
+    #[inline]
+    fn unswept_size(&self) -> usize {
+        self.unswept_size.load(Ordering::Acquire)
+    }
+    #[inline]
+    fn is_sweeping(&self) -> bool {
+        self.sweeping.load(Ordering::Relaxed)
+    }
+    #[inline]
     fn mark_inverted(&self) -> bool {
         self.mark_inverted.load(Ordering::Acquire)
     }
@@ -455,13 +933,15 @@ impl SimpleAlloc {
         self.mark_inverted.store(b, Ordering::Release)
     }
 
+    /// Allocate an object, using the context's cache if one is given
     #[inline]
     fn alloc_layout<H>(
         &self,
         header_layout: HeaderLayout<H>,
         value_layout: Layout,
         static_type: &'static GcType,
-    ) -> (*mut H, *mut u8) {
+        cache: Option<&SimpleContextCache>,
+    ) -> Result<(*mut H, *mut u8), GcAllocError> {
         let collector_id_ptr = match self.collector_id {
             Some(ref collector) => collector,
             None => {
@@ -475,8 +955,7 @@ impl SimpleAlloc {
                 }
             }
         };
-        let (mut overall_layout, value_offset) =
-            header_layout.layout().extend(value_layout).unwrap();
+        let (mut overall_layout, value_offset) = header_layout.layout().extend(value_layout)?;
         debug_assert_eq!(
             header_layout.value_offset(value_layout.align()),
             value_offset
@@ -491,12 +970,13 @@ impl SimpleAlloc {
             value_offset,
             overall_layout,
         };
-        let (header, value_ptr) =
-            if let Some(arena) = self.small_arenas.find(target_layout.overall_layout) {
-                unsafe { self.alloc_layout_small(arena, target_layout) }
-            } else {
-                self.alloc_layout_big(target_layout)
-            };
+        let (header, value_ptr) = if immix::fits(target_layout.overall_layout) {
+            unsafe { self.alloc_layout_immix(target_layout, cache) }
+        } else if let Some(arena) = self.small_arenas.find(target_layout.overall_layout) {
+            unsafe { self.alloc_layout_small(arena, target_layout, cache) }
+        } else {
+            self.alloc_layout_big(target_layout)?
+        };
         unsafe {
             header_layout.common_header(header).write(GcHeader::new(
                 static_type,
@@ -506,37 +986,85 @@ impl SimpleAlloc {
                 )),
             ));
         }
-        (header, value_ptr)
+        Ok((header, value_ptr))
     }
     #[inline]
     unsafe fn alloc_layout_small<H>(
         &self,
         arena: &SmallArena,
         target_layout: TargetLayout<H>,
+        cache: Option<&SimpleContextCache>,
     ) -> (*mut H, *mut u8) {
-        let ptr = arena.alloc();
+        let ptr = match cache {
+            Some(cache) => {
+                if self.is_sweeping() && !cache.arenas.has_free(arena) {
+                    // Keep sweeping at the same pace as we refill the cache
+                    self.sweep_batch();
+                }
+                cache.arenas.alloc(arena)
+            }
+            None => {
+                if self.is_sweeping() && !arena.has_free() {
+                    // Try to free up a slot before falling back to fresh memory
+                    self.sweep_batch();
+                }
+                arena.alloc()
+            }
+        };
         debug_assert_eq!(
             ptr.as_ptr() as usize % target_layout.header_layout.layout().align(),
             0
         );
         self.add_allocated_size(target_layout.overall_layout.size());
-        {
-            let mut lock = self.small_objects.lock();
-            lock.push(
-                (ptr.as_ptr() as *mut u8)
-                    .add(target_layout.header_layout.common_header_offset)
-                    .cast(),
-            );
+        let common_header = (ptr.as_ptr() as *mut u8)
+            .add(target_layout.header_layout.common_header_offset)
+            .cast();
+        match cache {
+            Some(cache) => cache.small_objects.borrow_mut().push(common_header),
+            None => self.small_objects.lock().push(common_header),
         }
         (
             ptr.as_ptr().cast(),
             (ptr.as_ptr() as *mut u8).add(target_layout.value_offset),
         )
     }
-    fn alloc_layout_big<H>(&self, target_layout: TargetLayout<H>) -> (*mut H, *mut u8) {
+    #[inline]
+    unsafe fn alloc_layout_immix<H>(
+        &self,
+        target_layout: TargetLayout<H>,
+        cache: Option<&SimpleContextCache>,
+    ) -> (*mut H, *mut u8) {
+        let ptr = self.immix.alloc(
+            target_layout.overall_layout,
+            cache.map(|cache| &cache.immix),
+        );
+        self.add_allocated_size(target_layout.overall_layout.size());
+        let common_header = ptr
+            .as_ptr()
+            .add(target_layout.header_layout.common_header_offset)
+            .cast();
+        match cache {
+            Some(cache) => cache.small_objects.borrow_mut().push(common_header),
+            None => self.small_objects.lock().push(common_header),
+        }
+        (
+            ptr.as_ptr().cast(),
+            ptr.as_ptr().add(target_layout.value_offset),
+        )
+    }
+    fn alloc_layout_big<H>(
+        &self,
+        target_layout: TargetLayout<H>,
+    ) -> Result<(*mut H, *mut u8), GcAllocError> {
+        if self.is_sweeping() {
+            unsafe { self.sweep_batch() };
+        }
         let header: *mut H;
         let value_ptr = unsafe {
             header = std::alloc::alloc(target_layout.overall_layout).cast();
+            if header.is_null() {
+                return Err(GcAllocError::OutOfMemory);
+            }
             (header as *mut u8).add(target_layout.value_offset)
         };
         {
@@ -548,17 +1076,38 @@ impl SimpleAlloc {
                 objs.push(BigGcObject::from_ptr(common_header));
             }
             self.add_allocated_size(target_layout.overall_layout.size());
+            self.big_object_size
+                .fetch_add(target_layout.overall_layout.size(), Ordering::AcqRel);
+        }
+        Ok((header, value_ptr))
+    }
+    /// Flush a context's cache,
+    /// giving back its free slots and registering the objects it allocated
+    unsafe fn flush_cache(&self, cache: &SimpleContextCache) {
+        self.publish_cache(cache);
+        cache.arenas.flush(&self.small_arenas);
+        self.immix.flush(&cache.immix);
+    }
+    /// Register the objects that a context allocated,
+    /// while it keeps its free slots.
+    ///
+    /// Giving back the slots would force the next refill
+    /// to walk the arena's entire free list again.
+    unsafe fn publish_cache(&self, cache: &SimpleContextCache) {
+        let mut new_objects = cache.small_objects.borrow_mut();
+        if !new_objects.is_empty() {
+            self.small_objects.lock().append(&mut new_objects);
         }
-        (header, value_ptr)
     }
     unsafe fn sweep(&self) {
         let mut expected_size = self.allocated_size();
         let mut actual_size = 0;
+        let mut big_object_size = 0;
         // Clear small arenas
         let was_mark_inverted = self.mark_inverted.load(Ordering::SeqCst);
         self.small_objects
             .lock()
-            .extract_if(|&mut common_header| {
+            .extract_if(.., |&mut common_header| {
                 let total_size = (*common_header)
                     .type_info
                     .determine_total_size(common_header);
@@ -580,25 +1129,12 @@ impl SimpleAlloc {
                     }
                 }
             })
-            .for_each(|freed_common_header| {
-                let type_info = (*freed_common_header).type_info;
-                if let Some(func) = type_info.drop_func {
-                    func((*freed_common_header).value());
-                }
-                let overall_layout = type_info.determine_total_layout(freed_common_header);
-                let actual_start = type_info
-                    .header_layout()
-                    .from_common_header(freed_common_header);
-                self.small_arenas
-                    .find(overall_layout)
-                    .unwrap()
-                    .add_free(actual_start)
-            });
+            .for_each(|freed_common_header| self.free_small_object(freed_common_header));
         // Clear large objects
         debug_assert_eq!(was_mark_inverted, self.mark_inverted());
         self.big_objects
             .lock()
-            .extract_if(|big_item| {
+            .extract_if(.., |big_item| {
                 let total_size = big_item
                     .header()
                     .type_info
@@ -623,6 +1159,7 @@ impl SimpleAlloc {
                          * by inverting mark the meaning of the mark bits.
                          */
                         actual_size += total_size;
+                        big_object_size += total_size;
                         false // Keep
                     }
                 }
@@ -636,8 +1173,132 @@ impl SimpleAlloc {
         self.set_mark_inverted(!was_mark_inverted);
         assert_eq!(expected_size, actual_size);
         self.set_allocated_size(actual_size);
+        self.big_object_size
+            .store(big_object_size, Ordering::Release);
+    }
+    /// Drop the specified small object, and return its memory to the arena
+    ///
+    /// Objects allocated in Immix blocks are only dropped,
+    /// since their lines are reclaimed by [ImmixSpace::sweep_lines]
+    unsafe fn free_small_object(&self, common_header: *mut GcHeader) {
+        let type_info = (*common_header).type_info;
+        if let Some(func) = type_info.drop_func {
+            func((*common_header).value());
+        }
+        let overall_layout = type_info.determine_total_layout(common_header);
+        if immix::fits(overall_layout) {
+            return;
+        }
+        let actual_start = type_info.header_layout().from_common_header(common_header);
+        self.small_arenas
+            .find(overall_layout)
+            .unwrap()
+            .add_free(actual_start)
+    }
+    /// Prepare to sweep the heap lazily, after marking has finished.
+    ///
+    /// Instead of sweeping the heap immediately, every existing object is set aside,
+    /// to be swept in batches as the allocator needs more memory.
+    /// Everything left over is swept at the start of the next collection.
+    ///
+    /// The `live_size` is the total size of all the objects that were marked.
+    unsafe fn start_lazy_sweep(&self, live_size: usize) {
+        debug_assert!(!self.is_sweeping());
+        {
+            let mut unswept = self.unswept_small_objects.lock();
+            debug_assert!(unswept.is_empty());
+            std::mem::swap(&mut *unswept, &mut *self.small_objects.lock());
+        }
+        {
+            let mut unswept = self.unswept_big_objects.lock();
+            debug_assert!(unswept.is_empty());
+            std::mem::swap(&mut *unswept, &mut *self.big_objects.lock());
+        }
+        self.unswept_size
+            .store(self.allocated_size() - live_size, Ordering::Release);
+        /*
+         * Flip the meaning of the mark bit, just like an ordinary sweep.
+         * This implicitly resets reachable objects to white,
+         * while the garbage appears black until it's swept.
+         */
+        self.set_mark_inverted(!self.mark_inverted());
+        self.sweeping.store(true, Ordering::Release);
+    }
+    /// Sweep a batch of the objects left over from the last collection,
+    /// returning `false` if there was nothing left to sweep.
+    ///
+    /// This is safe to call concurrently from multiple threads,
+    /// but not while a collection is in progress.
+    #[cold]
+    unsafe fn sweep_batch(&self) -> bool {
+        let small_objects = {
+            let mut unswept = self.unswept_small_objects.lock();
+            let start = unswept.len().saturating_sub(SWEEP_BATCH_SIZE);
+            unswept.split_off(start)
+        };
+        let big_objects = if small_objects.is_empty() {
+            let mut unswept = self.unswept_big_objects.lock();
+            let start = unswept.len().saturating_sub(SWEEP_BATCH_SIZE);
+            unswept.split_off(start)
+        } else {
+            Vec::new()
+        };
+        if small_objects.is_empty() && big_objects.is_empty() {
+            self.sweeping.store(false, Ordering::Release);
+            return false;
+        }
+        let inverted_mark = self.mark_inverted();
+        let mut freed_size = 0;
+        let mut freed_big_object_size = 0;
+        let mut surviving_small_objects = Vec::new();
+        for common_header in small_objects {
+            match (*common_header).raw_mark_state().resolve(inverted_mark) {
+                // Since the mark bit was flipped, garbage appears black
+                MarkState::Black => {
+                    freed_size += (*common_header)
+                        .type_info
+                        .determine_total_size(common_header);
+                    self.free_small_object(common_header);
+                }
+                MarkState::Grey => panic!("All grey objects should've been processed"),
+                MarkState::White => surviving_small_objects.push(common_header),
+            }
+        }
+        let mut surviving_big_objects = Vec::new();
+        for big_item in big_objects {
+            match big_item.header().raw_mark_state().resolve(inverted_mark) {
+                MarkState::Black => {
+                    let total_size = big_item
+                        .header()
+                        .type_info
+                        .determine_total_size(big_item.header.as_ptr());
+                    freed_size += total_size;
+                    freed_big_object_size += total_size;
+                    drop(big_item);
+                }
+                MarkState::Grey => panic!("All grey objects should've been processed"),
+                MarkState::White => surviving_big_objects.push(big_item),
+            }
+        }
+        self.small_objects.lock().extend(surviving_small_objects);
+        self.big_objects.lock().extend(surviving_big_objects);
+        self.allocated_size.fetch_sub(freed_size, Ordering::AcqRel);
+        self.big_object_size
+            .fetch_sub(freed_big_object_size, Ordering::AcqRel);
+        self.unswept_size.fetch_sub(freed_size, Ordering::AcqRel);
+        true
+    }
+    /// Sweep everything left over from the last collection
+    ///
+    /// This must be done before marking can start again.
+    unsafe fn finish_sweep(&self) {
+        if self.is_sweeping() {
+            while self.sweep_batch() {}
+        }
     }
 }
+/// The number of objects swept by each batch of lazy sweeping
+const SWEEP_BATCH_SIZE: usize = 256;
 unsafe impl Send for SimpleAlloc {}
 /// We're careful to be thread safe here
 ///
@@ -657,12 +1318,28 @@ pub struct RawSimpleCollector {
     manager: CollectionManager<Self>,
     /// Tracks object handles
     handle_list: GcHandleList<Self>,
+    /// Tracks objects that need to be finalized
+    finalizers: FinalizerQueue,
+    /// Statistics about previous collections
+    ///
+    /// The fields that describe the current state of the heap
+    /// are filled in when a snapshot is taken.
+    history: Lock<GcStats>,
+    /// The state of an incremental collection that is in progress
+    incremental: Lock<Option<IncrementalMarking>>,
+    /// When the current collection cycle started,
+    /// along with the `total_pause` at that point.
+    cycle_start: Lock<(Instant, Duration)>,
+    /// The threads used for parallel marking
+    #[cfg(feature = "sync")]
+    marking_pool: parallel::MarkingPool,
     config: Arc<GcConfig>,
 }
 
 unsafe impl ::zerogc_context::collector::RawCollectorImpl for RawSimpleCollector {
     type DynTracePtr = NonNull<dyn DynTrace>;
     type Config = GcConfig;
+    type Stats = GcStats;
 
     #[cfg(feature = "multiple-collectors")]
     type Ptr = NonNull<Self>;
@@ -674,6 +1351,8 @@ unsafe impl ::zerogc_context::collector::RawCollectorImpl for RawSimpleCollector
 
     type RawContext = RawContext<Self>;
 
+    type ContextCache = SimpleContextCache;
+
     type RawVec<'gc, T: GcSafe<'gc, CollectorId>> = SimpleVecRepr<'gc, T>;
 
     const SINGLETON: bool = cfg!(not(feature = "multiple-collectors"));
@@ -743,14 +1422,16 @@ unsafe impl ::zerogc_context::collector::RawCollectorImpl for RawSimpleCollector
 
     #[inline(always)]
     unsafe fn gc_write_barrier<'gc, T, V>(
-        _owner: &Gc<'gc, T>,
+        owner: &Gc<'gc, T>,
         _value: &Gc<'gc, V>,
         _field_offset: usize,
     ) where
         T: GcSafe<'gc, CollectorId> + ?Sized,
         V: GcSafe<'gc, CollectorId> + ?Sized,
     {
-        // Simple GC doesn't need write barriers
+        // Only needed for incremental marking
+        let owner = GcHeader::from_value_ptr(owner.as_raw_ptr());
+        (*owner).collector_id().as_ref().heap.write_barrier(owner);
     }
     #[inline]
     fn logger(&self) -> &Logger {
@@ -769,7 +1450,9 @@ unsafe impl ::zerogc_context::collector::RawCollectorImpl for RawSimpleCollector
          * More importantly this is easier for a JIT to implement inline!!!
          * As of this writing cranelift doesn't even seem to support fences :o
          */
-        self.heap.should_collect_relaxed() || self.manager.should_trigger_collection()
+        self.heap.should_collect_relaxed()
+            || self.heap.is_marking()
+            || self.manager.should_trigger_collection()
     }
     #[inline]
     fn allocated_size(&self) -> MemorySize {
@@ -777,10 +1460,37 @@ unsafe impl ::zerogc_context::collector::RawCollectorImpl for RawSimpleCollector
             bytes: self.heap.allocator.allocated_size(),
         }
     }
+    fn stats(&self) -> GcStats {
+        let handle_slots = self.handle_list.slot_stats();
+        let live_bytes = self.heap.allocator.allocated_size();
+        let big_object_bytes = self.heap.allocator.big_object_size();
+        GcStats {
+            live_bytes,
+            threshold: self.heap.threshold.load(Ordering::Acquire),
+            num_handles: handle_slots.live_slots,
+            free_handle_slots: handle_slots.free_slots,
+            small_object_bytes: live_bytes.saturating_sub(big_object_bytes),
+            big_object_bytes,
+            marking_in_progress: self.heap.is_marking(),
+            unswept_bytes: self.heap.allocator.unswept_size(),
+            arena_bytes: self.heap.allocator.small_arenas.reserved_bytes()
+                + self.heap.allocator.immix.reserved_bytes(),
+            ..self.history.lock().clone()
+        }
+    }
     #[inline]
     unsafe fn perform_raw_collection(&self, contexts: &[*mut RawContext<Self>]) {
         self.perform_raw_collection(contexts)
     }
+
+    #[inline]
+    unsafe fn flush_context_cache(&self, cache: &SimpleContextCache) {
+        self.heap.allocator.flush_cache(cache)
+    }
+    #[inline]
+    unsafe fn publish_context_cache(&self, cache: &SimpleContextCache) {
+        self.heap.allocator.publish_cache(cache)
+    }
 }
 #[cfg(feature = "sync")]
 unsafe impl ::zerogc_context::collector::SyncCollector for RawSimpleCollector {}
@@ -833,6 +1543,12 @@ impl RawSimpleCollector {
             manager: CollectionManager::new(),
             heap: GcHeap::new(Arc::clone(&config)),
             handle_list: GcHandleList::new(),
+            finalizers: FinalizerQueue::new(),
+            history: Lock::from(GcStats::default()),
+            incremental: Lock::from(None),
+            cycle_start: Lock::from((Instant::now(), Duration::ZERO)),
+            #[cfg(feature = "sync")]
+            marking_pool: parallel::MarkingPool::new(),
             config,
         }
     }
@@ -840,6 +1556,10 @@ impl RawSimpleCollector {
     #[inline(never)]
     unsafe fn perform_raw_collection(&self, contexts: &[*mut RawContext<Self>]) {
         debug_assert!(self.manager.is_collecting());
+        let start = Instant::now();
+        // Marking can't start until the last collection has been completely swept
+        self.heap.allocator.finish_sweep();
+        debug_assert_eq!(self.heap.allocator.unswept_size(), 0);
         let roots = {
             let mut roots: Vec<*mut dyn DynTrace> = Vec::new();
             for ctx in contexts.iter() {
@@ -857,6 +1577,10 @@ impl RawSimpleCollector {
                 // Make into virtual pointer
                 as *const dyn DynTrace as *mut dyn DynTrace,
             );
+            roots.push(
+                &self.finalizers as *const FinalizerQueue as *const dyn DynTrace
+                    as *mut dyn DynTrace,
+            );
             #[repr(transparent)]
             struct CachedEmptyVec(GcVecHeader);
             unsafe impl DynTrace for CachedEmptyVec {
@@ -873,90 +1597,567 @@ impl RawSimpleCollector {
             roots
         };
         let num_roots = roots.len();
-        let mut task = CollectionTask {
-            config: &*self.config,
-            expected_collector: self.heap.allocator.collector_id.unwrap(),
-            roots,
-            heap: &self.heap,
+        let mut incremental = self.incremental.lock();
+        let budget = match self.config.incremental_budget {
+            // A forced safepoint performs a full collection, unless marking has already started
+            Some(budget)
+                if !cfg!(feature = "implicit-grey-stack")
+                    && (incremental.is_some() || self.heap.should_collect_relaxed()) =>
+            {
+                Some(budget)
+            }
+            _ => None,
+        };
+        let resumed = incremental.is_some();
+        let IncrementalMarking {
+            grey_stack,
+            weak_containers,
+            marked_size,
+        } = incremental.take().unwrap_or_else(|| IncrementalMarking {
             grey_stack: if cfg!(feature = "implicit-grey-stack") {
                 Vec::new()
             } else {
                 Vec::with_capacity(64)
             },
+            weak_containers: Vec::new(),
+            marked_size: 0,
+        });
+        let mut task = CollectionTask {
+            config: &self.config,
+            expected_collector: self.heap.allocator.collector_id.unwrap(),
+            roots,
+            heap: &self.heap,
+            handle_list: &self.handle_list,
+            finalizers: &self.finalizers,
+            #[cfg(feature = "sync")]
+            marking_pool: &self.marking_pool,
+            grey_stack,
+            weak_refs: Vec::new(),
+            ephemerons: Vec::new(),
+            weak_containers,
+            marked_size,
         };
         let original_size = self.heap.allocator.allocated_size();
-        task.run();
-        let updated_size = self.heap.allocator.allocated_size();
+        let finished = task.run(budget, resumed, start);
+        if !finished {
+            *incremental = Some(IncrementalMarking {
+                grey_stack: task.grey_stack,
+                weak_containers: task.weak_containers,
+                marked_size: task.marked_size,
+            });
+        }
+        drop(incremental);
+        if finished {
+            self.handle_list.shrink();
+        }
+        // NOTE: Garbage that is waiting to be swept lazily has already been freed, as far as we're concerned
+        let updated_size =
+            self.heap.allocator.allocated_size() - self.heap.allocator.unswept_size();
+        let pause = start.elapsed();
+        {
+            let mut history = self.history.lock();
+            history.total_pause += pause;
+            history.last_pause = pause;
+            if budget.is_some() {
+                history.incremental_steps += 1;
+            }
+            if finished {
+                history.collections += 1;
+                history.last_freed_bytes = original_size - updated_size;
+                history.total_freed_bytes += (original_size - updated_size) as u64;
+                history.last_num_roots = num_roots;
+                let mut cycle_start = self.cycle_start.lock();
+                let (cycle_started, pause_before) = *cycle_start;
+                let cycle_pause = history.total_pause - pause_before;
+                history.gc_cpu_fraction = update_gc_cpu_fraction(
+                    (history.collections > 1).then_some(history.gc_cpu_fraction),
+                    cycle_pause,
+                    cycle_started.elapsed(),
+                );
+                *cycle_start = (Instant::now(), history.total_pause);
+                if !self.config.always_force_collect {
+                    self.update_threshold(FinishedCollection {
+                        live_bytes: updated_size,
+                        gc_cpu_fraction: history.gc_cpu_fraction,
+                    });
+                }
+            }
+        }
+        if !finished {
+            debug!(
+                self.logger, "Paused incremental marking";
+                "current_thread" => FnValue(|_| ThreadId::current()),
+                "num_roots" => num_roots,
+                "pause" => ?pause,
+            );
+            return;
+        }
         debug!(
             self.logger, "Finished simple GC";
             "current_thread" => FnValue(|_| ThreadId::current()),
             "num_roots" => num_roots,
             "original_size" => %MemorySize { bytes: original_size },
             "memory_freed" => %MemorySize { bytes: original_size - updated_size },
+            "pause" => ?pause,
         );
     }
+    /// Pick the threshold for the next collection, according to the [ThresholdPolicy]
+    fn update_threshold(&self, collection: FinishedCollection) {
+        let mut threshold = self.config.threshold_policy.next_threshold(&collection);
+        if let Some(max_heap_size) = self.config.max_heap_size {
+            threshold = threshold.min(max_heap_size);
+        }
+        self.heap.threshold.store(threshold, Ordering::SeqCst);
+    }
+}
+/// The state of marking that is preserved between the steps of an incremental collection
+///
+/// Weak references (and ephemerons) are never preserved between steps,
+/// since the mutator can change (or even drop) them in the meantime.
+/// Only the objects that contained them are remembered.
+struct IncrementalMarking {
+    grey_stack: Vec<*mut GcHeader>,
+    /// The objects that contained weak references (or ephemerons) when they were traced
+    weak_containers: Vec<*mut GcHeader>,
+    /// The total size of the objects that have been marked so far
+    marked_size: usize,
 }
+
 struct CollectionTask<'a> {
     config: &'a GcConfig,
     expected_collector: CollectorId,
     roots: Vec<*mut dyn DynTrace>,
     heap: &'a GcHeap,
+    /// The handles, including weak handles that need to be cleared
+    handle_list: &'a GcHandleList<RawSimpleCollector>,
+    finalizers: &'a FinalizerQueue,
+    #[cfg(feature = "sync")]
+    marking_pool: &'a parallel::MarkingPool,
     #[cfg_attr(feature = "implicit-grey-stack", allow(dead_code))]
     grey_stack: Vec<*mut GcHeader>,
+    /// The weak references encountered while marking
+    weak_refs: Vec<WeakRef>,
+    /// The ephemerons whose keys haven't (yet) been found reachable
+    ephemerons: Vec<Ephemeron>,
+    /// The objects that contained weak references (or ephemerons) when they were traced
+    ///
+    /// See [CollectionTask::rescan_weak_containers]
+    weak_containers: Vec<*mut GcHeader>,
+    /// The total size of the objects that have been marked
+    marked_size: usize,
 }
 impl<'a> CollectionTask<'a> {
-    fn run(&mut self) {
+    /// Run the collection, returning whether it finished.
+    ///
+    /// If there is a budget, marking is suspended once it runs out
+    /// (unless the marking is already done).
+    /// The collection must then be resumed at the next safepoint.
+    fn run(&mut self, budget: Option<MarkBudget>, resumed: bool, start: Instant) -> bool {
+        if self.lazy_sweep() && !resumed {
+            // The last collection has been completely swept by now
+            self.release_free_chunks();
+        }
+        if self.config.verify_heap && !resumed {
+            unsafe { self::verify::verify_before_mark(self) };
+        }
         // Mark
+        let earlier_weak_containers = std::mem::take(&mut self.weak_containers);
+        self.mark_roots();
+        self.grey_stack.append(&mut self.heap.shaded.lock());
+        let finished_marking = match budget {
+            Some(MarkBudget::Time(limit)) => self.process_grey_stack_until(|traced| {
+                // Checking the time on every object would be too expensive
+                traced % 64 == 0 && start.elapsed() >= limit
+            }),
+            Some(MarkBudget::Work(limit)) => {
+                self.process_grey_stack_until(|traced| traced >= limit)
+            }
+            None => self.process_grey_stack_until(|_| false),
+        };
+        if !finished_marking && !self.heap.should_finish_marking() {
+            /*
+             * Resume at the next safepoint.
+             * The weak references we've found may not be valid by then,
+             * but they'll be found again when the roots (and their containers) are rescanned.
+             */
+            self.weak_refs.clear();
+            self.ephemerons.clear();
+            self.weak_containers.extend(earlier_weak_containers);
+            self.heap.marking.store(true, Ordering::Release);
+            return false;
+        }
+        self.heap.marking.store(false, Ordering::Release);
+        self.rescan_weak_containers(earlier_weak_containers);
+        self.process_grey_stack();
+        // Trace the values of ephemerons with reachable keys
+        self.process_ephemerons();
+        /*
+         * Clear weak references (and ephemeron keys) to unreachable objects.
+         * This has to happen before any of them are resurrected for finalization,
+         * or the weak references could be upgraded after the object became unreachable.
+         */
+        self.process_weak_refs();
+        // Keep objects that need finalization alive for one more cycle
+        self.process_finalizers();
+        if self.config.verify_heap {
+            unsafe { self::verify::verify_after_mark(self) };
+        }
+        // Objects allocated during incremental marking are implicitly marked
+        let live_size =
+            self.marked_size + self.heap.allocated_while_marking.swap(0, Ordering::AcqRel);
+        // Sweep
+        if self.lazy_sweep() {
+            unsafe { self.heap.allocator.start_lazy_sweep(live_size) };
+        } else {
+            unsafe { self.heap.allocator.sweep() };
+            debug_assert_eq!(self.heap.allocator.allocated_size(), live_size);
+            self.release_free_chunks();
+            // NOTE: The world is stopped, so every context's cache has been flushed
+            unsafe {
+                self.heap
+                    .allocator
+                    .immix
+                    .sweep_lines(self.config.retained_arena_bytes)
+            };
+        }
+        if self.config.always_force_collect {
+            assert_eq!(self.heap.threshold.load(Ordering::SeqCst), 0);
+        }
+        true
+    }
+    /// Whether the heap should be swept lazily (see [GcConfig::lazy_sweep])
+    ///
+    /// This is never the case with the `immix` feature,
+    /// since free lines can only be found once all the garbage has been swept.
+    #[inline]
+    fn lazy_sweep(&self) -> bool {
+        self.config.lazy_sweep && !cfg!(feature = "immix")
+    }
+    /// Return the arena chunks that are entirely free to the global allocator,
+    /// according to [GcConfig::retained_arena_bytes]
+    fn release_free_chunks(&self) {
+        if let Some(retained) = self.config.retained_arena_bytes {
+            // NOTE: The world is stopped, so every context's cache has been flushed
+            unsafe {
+                self.heap
+                    .allocator
+                    .small_arenas
+                    .release_free_chunks(retained)
+            };
+        }
+    }
+    /// Mark everything directly referenced by the roots.
+    ///
+    /// The roots aren't protected by write barriers,
+    /// so this needs to be repeated by every step of incremental marking.
+    fn mark_roots(&mut self) {
         for &root in &self.roots {
             let mut visitor = MarkVisitor {
                 expected_collector: self.expected_collector,
                 grey_stack: &mut self.grey_stack,
+                weak_refs: &mut self.weak_refs,
+                ephemerons: &mut self.ephemerons,
+                recorded_edges: None,
                 inverted_mark: self.heap.allocator.mark_inverted(),
+                marked_size: &mut self.marked_size,
             };
             // Dynamically dispatched
             unsafe {
                 (*root).trace(&mut visitor);
             }
         }
+    }
+    /// Trace the objects that contained weak references (or ephemerons)
+    /// in earlier steps of incremental marking, to find those references again.
+    ///
+    /// The mutator may have changed the references since then,
+    /// but only by mutating the objects (turning them grey again).
+    /// Those are traced along with the rest of the grey stack,
+    /// so only the objects that are still black need to be rescanned.
+    fn rescan_weak_containers(&mut self, containers: Vec<*mut GcHeader>) {
+        let inverted_mark = self.heap.allocator.mark_inverted();
+        for obj in containers {
+            unsafe {
+                if (*obj).raw_mark_state().resolve(inverted_mark) != MarkState::Black {
+                    continue;
+                }
+                let mut visitor = MarkVisitor {
+                    expected_collector: self.expected_collector,
+                    grey_stack: &mut self.grey_stack,
+                    weak_refs: &mut self.weak_refs,
+                    ephemerons: &mut self.ephemerons,
+                    recorded_edges: None,
+                    inverted_mark,
+                    marked_size: &mut self.marked_size,
+                };
+                if let Some(trace) = (*obj).type_info.trace_func {
+                    (trace)((*obj).value(), &mut visitor);
+                }
+            }
+        }
+    }
+    /// Trace all the objects remaining on the grey stack
+    ///
+    /// This is a no-op with an implicit grey stack,
+    /// since objects are traced recursively as soon as they're marked.
+    fn process_grey_stack(&mut self) {
+        #[cfg(feature = "sync")]
+        if self.config.marking_threads > 1 {
+            /*
+             * Small heaps aren't worth the overhead of waking up the other threads,
+             * so start marking on this thread.
+             */
+            let limit = parallel::SEQUENTIAL_MARKING_LIMIT;
+            if !self.process_grey_stack_until(|traced| traced >= limit) {
+                self.process_grey_stack_parallel();
+            }
+            return;
+        }
+        let finished = self.process_grey_stack_until(|_| false);
+        debug_assert!(finished);
+    }
+    /// Trace objects from the grey stack until it's empty,
+    /// or the closure says to stop (given the number of objects traced so far).
+    ///
+    /// At least one object is always traced, so marking makes progress.
+    /// Returns whether the grey stack was emptied.
+    fn process_grey_stack_until(&mut self, mut should_stop: impl FnMut(usize) -> bool) -> bool {
         #[cfg(not(feature = "implicit-grey-stack"))]
         unsafe {
             let was_inverted_mark = self.heap.allocator.mark_inverted();
+            let mut traced = 0;
             while let Some(obj) = self.grey_stack.pop() {
+                if traced > 0 && should_stop(traced) {
+                    self.grey_stack.push(obj);
+                    return false;
+                }
+                traced += 1;
                 debug_assert_eq!(
                     (*obj).raw_mark_state().resolve(was_inverted_mark),
                     MarkState::Grey
                 );
+                let num_weak = self.weak_refs.len() + self.ephemerons.len();
                 let mut visitor = MarkVisitor {
                     expected_collector: self.expected_collector,
                     grey_stack: &mut self.grey_stack,
+                    weak_refs: &mut self.weak_refs,
+                    ephemerons: &mut self.ephemerons,
+                    recorded_edges: None,
                     inverted_mark: was_inverted_mark,
+                    marked_size: &mut self.marked_size,
                 };
                 if let Some(trace) = (*obj).type_info.trace_func {
                     (trace)((*obj).value(), &mut visitor);
                 }
+                if self.weak_refs.len() + self.ephemerons.len() != num_weak {
+                    self.weak_containers.push(obj);
+                }
                 // Mark the object black now it's innards have been traced
                 (*obj).update_raw_mark_state(MarkState::Black.to_raw(was_inverted_mark));
             }
         }
-        // Sweep
-        unsafe { self.heap.allocator.sweep() };
-        let updated_size = self.heap.allocator.allocated_size();
-        if self.config.always_force_collect {
-            assert_eq!(self.heap.threshold.load(Ordering::SeqCst), 0);
-        } else {
-            // Update the threshold to be 150% of currently used size
-            self.heap
-                .threshold
-                .store(updated_size + (updated_size / 2), Ordering::SeqCst);
+        #[cfg(feature = "implicit-grey-stack")]
+        let _ = &mut should_stop;
+        true
+    }
+    /// Trace the values of all the ephemerons whose keys are reachable.
+    ///
+    /// Tracing a value can make even more keys reachable,
+    /// so this needs to iterate until it reaches a fixpoint.
+    /// Any ephemerons that remain afterwards have (currently) unreachable keys.
+    /// Those are cleared by [CollectionTask::process_weak_refs].
+    fn process_ephemerons(&mut self) {
+        let inverted_mark = self.heap.allocator.mark_inverted();
+        loop {
+            let mut progress = false;
+            for ephemeron in std::mem::take(&mut self.ephemerons) {
+                let key = match unsafe { ephemeron.key.referent() } {
+                    Some(key) => key,
+                    // The key was cleared since the ephemeron was found
+                    None => continue,
+                };
+                let key_state = unsafe { (*key).raw_mark_state() }.resolve(inverted_mark);
+                if key_state == MarkState::White {
+                    // Still unknown, try again on the next iteration
+                    self.ephemerons.push(ephemeron);
+                    continue;
+                }
+                let mut visitor = MarkVisitor {
+                    expected_collector: self.expected_collector,
+                    grey_stack: &mut self.grey_stack,
+                    weak_refs: &mut self.weak_refs,
+                    ephemerons: &mut self.ephemerons,
+                    recorded_edges: None,
+                    inverted_mark,
+                    marked_size: &mut self.marked_size,
+                };
+                unsafe {
+                    (ephemeron.trace_value)(ephemeron.value, &mut visitor);
+                }
+                progress = true;
+            }
+            if !progress {
+                break;
+            }
+            self.process_grey_stack();
+        }
+    }
+    /// Move any registered objects that are unreachable into the pending queue,
+    /// marking them (and everything they reference) so they survive this collection.
+    ///
+    /// This happens after weak references to the unreachable objects have been cleared.
+    /// Since this can mark even more objects,
+    /// the grey stack and ephemerons need to be processed again afterwards,
+    /// along with any weak references found along the way.
+    fn process_finalizers(&mut self) {
+        let inverted_mark = self.heap.allocator.mark_inverted();
+        let finalizers = self.finalizers;
+        let mut registered = finalizers.registered.lock();
+        let (unreachable, reachable): (Vec<Finalizable>, Vec<Finalizable>) =
+            std::mem::take(&mut *registered)
+                .into_iter()
+                .partition(|entry| unsafe {
+                    (*entry.header).raw_mark_state().resolve(inverted_mark) == MarkState::White
+                });
+        *registered = reachable;
+        drop(registered);
+        if unreachable.is_empty() {
+            return;
+        }
+        let mut visitor = MarkVisitor {
+            expected_collector: self.expected_collector,
+            grey_stack: &mut self.grey_stack,
+            weak_refs: &mut self.weak_refs,
+            ephemerons: &mut self.ephemerons,
+            recorded_edges: None,
+            inverted_mark,
+            marked_size: &mut self.marked_size,
+        };
+        for entry in &unreachable {
+            unsafe { visitor._trace_own_header(entry.header) }
+        }
+        finalizers.pending.lock().extend(unreachable);
+        self.process_grey_stack();
+        self.process_ephemerons();
+        self.process_weak_refs();
+    }
+    /// Clear all weak references (and weak handles) whose referents weren't marked,
+    /// along with the keys of any ephemerons that are still unreachable.
+    ///
+    /// This must happen after marking has finished,
+    /// but before any objects have been swept.
+    fn process_weak_refs(&mut self) {
+        let inverted_mark = self.heap.allocator.mark_inverted();
+        for ephemeron in self.ephemerons.drain(..) {
+            unsafe {
+                let key = ephemeron.key;
+                if let Some(referent) = key.referent() {
+                    if (*referent).raw_mark_state().resolve(inverted_mark) == MarkState::White {
+                        (key.clear_func)(key.slot)
+                    }
+                }
+            }
+        }
+        for weak in self.weak_refs.drain(..) {
+            unsafe {
+                let referent = match weak.referent() {
+                    Some(referent) => referent,
+                    None => continue,
+                };
+                match (*referent).raw_mark_state().resolve(inverted_mark) {
+                    MarkState::White => (weak.clear_func)(weak.slot),
+                    MarkState::Grey => panic!("All grey objects should've been processed"),
+                    MarkState::Black => {}
+                }
+            }
+        }
+        unsafe {
+            self.handle_list.clear_dead_weak(|raw_ptr, type_info| {
+                let header = GcHeader::from_value_ptr(raw_ptr);
+                debug_assert!(std::ptr::eq((*header).type_info, type_info));
+                match (*header).raw_mark_state().resolve(inverted_mark) {
+                    MarkState::White => false,
+                    MarkState::Grey => panic!("All grey objects should've been processed"),
+                    MarkState::Black => true,
+                }
+            });
         }
     }
 }
 
+/// A weak reference that was encountered while marking.
+///
+/// The slot is cleared if its referent turns out to be unreachable.
+///
+/// The slot is only valid until the mutator resumes,
+/// so these are never kept between the steps of incremental marking.
+/// The referent is always read from the slot itself.
+struct WeakRef {
+    /// A pointer to the [GcWeak] itself
+    slot: *mut (),
+    /// Reads the header of the object currently referenced by the slot
+    referent_func: unsafe fn(*mut ()) -> Option<*mut GcHeader>,
+    /// Clears the weak reference in the slot
+    clear_func: unsafe fn(*mut ()),
+}
+impl WeakRef {
+    fn new<T: ?Sized>(slot: &mut GcWeak<'_, T>) -> WeakRef {
+        WeakRef {
+            slot: slot as *mut GcWeak<'_, T> as *mut (),
+            referent_func: WeakRef::referent_of::<T>,
+            clear_func: WeakRef::clear_slot::<T>,
+        }
+    }
+    /// The header of the current referent,
+    /// or `None` if the reference has been cleared.
+    #[inline]
+    unsafe fn referent(&self) -> Option<*mut GcHeader> {
+        (self.referent_func)(self.slot)
+    }
+    unsafe fn referent_of<T: ?Sized>(slot: *mut ()) -> Option<*mut GcHeader> {
+        (*(slot as *mut GcWeak<'static, T>))
+            .as_raw_ptr()
+            .map(|ptr| GcHeader::from_value_ptr(ptr.as_ptr()))
+    }
+    unsafe fn clear_slot<T: ?Sized>(slot: *mut ()) {
+        (*(slot as *mut GcWeak<'static, T>)).clear();
+    }
+}
+
+/// An ephemeron whose key hasn't (yet) been found to be reachable.
+///
+/// The value is traced once the key is marked.
+/// If that never happens, the key is cleared instead.
+struct Ephemeron {
+    /// The weak reference to the key
+    key: WeakRef,
+    /// A pointer to the value
+    value: *mut (),
+    /// Traces the value (once the key is known to be reachable)
+    trace_value: unsafe fn(*mut (), &mut MarkVisitor),
+}
+impl Ephemeron {
+    unsafe fn trace_value<V: Trace>(value: *mut (), visitor: &mut MarkVisitor) {
+        let Ok(()) = (*(value as *mut V)).trace(visitor);
+    }
+}
+
 #[doc(hidden)] // NOTE: Needs be public for RawCollectorImpl
 pub struct MarkVisitor<'a> {
     expected_collector: CollectorId,
     #[cfg_attr(feature = "implicit-grey-stack", allow(dead_code))]
     grey_stack: &'a mut Vec<*mut GcHeader>,
+    /// The weak references that need to be processed after marking
+    weak_refs: &'a mut Vec<WeakRef>,
+    /// The ephemerons whose keys haven't been marked yet
+    ephemerons: &'a mut Vec<Ephemeron>,
+    /// If present, the visitor only records the objects it encounters,
+    /// without marking or tracing them.
+    ///
+    /// Used to inspect the edges of the heap for debugging.
+    recorded_edges: Option<&'a mut Vec<*mut GcHeader>>,
+    /// The total size of the objects marked by this visitor
+    marked_size: &'a mut usize,
     /// If this meaning of the mark bit is currently inverted
     ///
     /// This flips every collection
@@ -965,8 +2166,8 @@ pub struct MarkVisitor<'a> {
 impl<'a> MarkVisitor<'a> {
     fn visit_raw_gc(
         &mut self,
-        obj: &mut GcHeader,
-        trace_func: impl FnOnce(&mut GcHeader, &mut MarkVisitor<'a>),
+        obj: &GcHeader,
+        trace_func: impl FnOnce(&GcHeader, &mut MarkVisitor<'a>),
     ) {
         match obj.raw_mark_state().resolve(self.inverted_mark) {
             MarkState::White => trace_func(obj, self),
@@ -1011,8 +2212,13 @@ unsafe impl GcVisitor for MarkVisitor<'_> {
             /*
              * Check the collectors match. Otherwise we're mutating
              * other people's data.
+             *
+             * When recording edges we don't mutate anything,
+             * and the target may be a dangling pointer the caller is looking for.
              */
-            assert_eq!(*gc.collector_id(), self.expected_collector);
+            if self.recorded_edges.is_none() {
+                assert_eq!(*gc.collector_id(), self.expected_collector);
+            }
             self._trace_own_gc(gc);
             Ok(())
         } else {
@@ -1021,6 +2227,98 @@ unsafe impl GcVisitor for MarkVisitor<'_> {
         }
     }
 
+    unsafe fn trace_weak<'gc, T, Id>(
+        &mut self,
+        weak: &mut zerogc::GcWeak<'gc, T, Id>,
+    ) -> Result<(), Self::Err>
+    where
+        T: ?Sized + GcSafe<'gc, Id>,
+        Id: zerogc::CollectorId,
+    {
+        if TypeId::of::<Id>() == TypeId::of::<crate::CollectorId>() {
+            /*
+             * The TypeIds match, so this cast is safe. See `visit_gc` for details
+             */
+            let weak = std::mem::transmute::<
+                &mut ::zerogc::GcWeak<'gc, T, Id>,
+                &mut ::zerogc::GcWeak<'gc, T, crate::CollectorId>,
+            >(weak);
+            if self.recorded_edges.is_some() {
+                // Weak references aren't real edges
+                return Ok(());
+            }
+            if let Some(gc) = weak.upgrade() {
+                assert_eq!(*gc.collector_id(), self.expected_collector);
+                /*
+                 * Don't mark the referent. Just remember the reference,
+                 * so it can be cleared once marking has finished.
+                 */
+                self.weak_refs.push(WeakRef::new(weak));
+            }
+            Ok(())
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn trace_ephemeron<'gc, K, V, Id>(
+        &mut self,
+        key: &mut zerogc::GcWeak<'gc, K, Id>,
+        value: &mut V,
+    ) -> Result<(), Self::Err>
+    where
+        K: ?Sized + GcSafe<'gc, Id>,
+        V: Trace,
+        Id: zerogc::CollectorId,
+    {
+        if TypeId::of::<Id>() == TypeId::of::<crate::CollectorId>() {
+            /*
+             * The TypeIds match, so this cast is safe. See `visit_gc` for details
+             */
+            let key = std::mem::transmute::<
+                &mut ::zerogc::GcWeak<'gc, K, Id>,
+                &mut ::zerogc::GcWeak<'gc, K, crate::CollectorId>,
+            >(key);
+            let gc = match key.upgrade() {
+                Some(gc) => gc,
+                None => {
+                    // The key is already dead, so the value must never be touched
+                    return Ok(());
+                }
+            };
+            assert_eq!(*gc.collector_id(), self.expected_collector);
+            if self.recorded_edges.is_some() {
+                // Only the value is (potentially) a strong edge
+                return value.trace(self);
+            }
+            let header = GcHeader::from_value_ptr(gc.as_raw_ptr());
+            match (*header).raw_mark_state().resolve(self.inverted_mark) {
+                MarkState::White => {
+                    /*
+                     * We don't know whether the key is reachable yet.
+                     * Defer tracing the value until we do.
+                     */
+                    self.ephemerons.push(Ephemeron {
+                        key: WeakRef::new(key),
+                        value: value as *mut V as *mut (),
+                        trace_value: Ephemeron::trace_value::<V>,
+                    });
+                    Ok(())
+                }
+                MarkState::Grey | MarkState::Black => {
+                    // The key is already known to be reachable
+                    value.trace(self)
+                }
+            }
+        } else {
+            /*
+             * We can't tell whether another collector's key is reachable,
+             * so conservatively treat the value as strongly reachable.
+             */
+            value.trace(self)
+        }
+    }
+
     unsafe fn trace_trait_object<'gc, T, Id>(
         &mut self,
         gc: &mut zerogc::Gc<'gc, T, Id>,
@@ -1121,6 +2419,27 @@ unsafe impl GcVisitor for MarkVisitor<'_> {
     }
 }
 impl MarkVisitor<'_> {
+    /// Record the objects directly referenced by a value,
+    /// without marking or tracing any of them.
+    ///
+    /// This is used to inspect the heap for debugging.
+    unsafe fn record_edges(
+        expected_collector: CollectorId,
+        inverted_mark: bool,
+        edges: &mut Vec<*mut GcHeader>,
+        trace_func: impl FnOnce(&mut MarkVisitor),
+    ) {
+        let mut visitor = MarkVisitor {
+            expected_collector,
+            grey_stack: &mut Vec::new(),
+            weak_refs: &mut Vec::new(),
+            ephemerons: &mut Vec::new(),
+            recorded_edges: Some(edges),
+            inverted_mark,
+            marked_size: &mut 0,
+        };
+        trace_func(&mut visitor);
+    }
     /// Visit a GC type whose [::zerogc::CollectorId] matches our own,
     /// tracing it with the specified closure
     ///
@@ -1135,6 +2454,17 @@ impl MarkVisitor<'_> {
             |_value, visitor| <T as Trace>::trace(&mut *gc.as_raw_ptr(), visitor),
         )
     }
+    /// Visit an object given only its header,
+    /// using the dynamic type information to trace it.
+    unsafe fn _trace_own_header(&mut self, header: *mut GcHeader) {
+        let trace_func = (*header).type_info.trace_func;
+        self._trace_own_gc_with(header, trace_func.is_some(), |val, visitor| {
+            if let Some(func) = trace_func {
+                func(val, visitor)
+            }
+            Ok(())
+        })
+    }
     unsafe fn _trace_own_rawvec<T: Trace>(&mut self, header: *mut GcVecHeader) {
         self._trace_own_gc_with(
             &mut (*header).common_header,
@@ -1161,26 +2491,39 @@ impl MarkVisitor<'_> {
     ) where
         F: FnOnce(*mut c_void, &mut MarkVisitor) -> Result<(), !>,
     {
+        if let Some(ref mut recorded_edges) = self.recorded_edges {
+            recorded_edges.push(header);
+            return;
+        }
         // Verify this again (should be checked by caller)
         debug_assert_eq!(*(*header).collector_id(), self.expected_collector);
-        self.visit_raw_gc(&mut *header, |actual_header, visitor| {
+        self.visit_raw_gc(&*header, |actual_header, visitor| {
             debug_assert_eq!(actual_header as *const _, header as *const _);
             let inverted_mark = visitor.inverted_mark;
             #[allow(unused_variables)]
             let val = (*header).value();
-            if !needs_trace {
-                /*
-                 * We don't need to mark this grey
-                 * It has no internals that need to be traced.
-                 * We can directly move it directly to the black set
-                 */
-                (*header).update_raw_mark_state(MarkState::Black.to_raw(inverted_mark));
+            /*
+             * If we don't need to trace the object's internals,
+             * we can move it directly to the black set.
+             * Otherwise, it needs to be marked grey and pushed onto the grey stack.
+             *
+             * NOTE: With parallel marking, another thread may have claimed the object
+             * since we checked it was white. Only the thread that wins is responsible for it.
+             */
+            let claimed_state = if needs_trace {
+                MarkState::Grey
             } else {
-                /*
-                 * We need to mark this object grey and push it onto the grey stack.
-                 * It will be processed later
-                 */
-                (*header).update_raw_mark_state(MarkState::Grey.to_raw(inverted_mark));
+                MarkState::Black
+            };
+            if !(*header).try_update_raw_mark_state(
+                MarkState::White.to_raw(inverted_mark),
+                claimed_state.to_raw(inverted_mark),
+            ) {
+                return;
+            }
+            *visitor.marked_size += (*header).type_info.determine_total_size(header);
+            immix::mark_object(header as *const GcHeader as *mut GcHeader);
+            if needs_trace {
                 #[cfg(not(feature = "implicit-grey-stack"))]
                 {
                     visitor
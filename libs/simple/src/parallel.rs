@@ -0,0 +1,246 @@
+//! Marks the heap using several threads at once.
+//!
+//! This is enabled by [GcConfig::marking_threads](crate::GcConfig::marking_threads).
+//!
+//! Each thread has its own grey stack, which the others can steal from once they run out of work.
+//! Threads race to claim white objects by atomically updating their mark state,
+//! so each object is only traced once.
+//!
+//! The marking threads are owned by the collector (see [MarkingPool]),
+//! so they're only spawned once instead of at every collection.
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use crossbeam_deque::{Injector, Stealer, Worker};
+
+use crate::layout::GcHeader;
+use crate::{
+    CollectionTask, CollectorId, Ephemeron, ILock, Lock, MarkState, MarkVisitor, WeakRef,
+};
+
+/// The number of objects to mark on a single thread,
+/// before deciding the heap is large enough to spread across several threads.
+pub(crate) const SEQUENTIAL_MARKING_LIMIT: usize = 1024;
+
+/// An object on the grey stack of a marking thread
+struct GreyObject(*mut GcHeader);
+/*
+ * Objects are only sent between marking threads while the world is stopped,
+ * and each one is traced by exactly one thread.
+ */
+unsafe impl Send for GreyObject {}
+unsafe impl Send for WeakRef {}
+unsafe impl Send for Ephemeron {}
+
+/// The weak references and ephemerons found by a single marking thread,
+/// along with the total size of the objects it marked.
+type MarkingResult = (Vec<WeakRef>, Vec<Ephemeron>, usize);
+
+/// A request for a pool thread to help with marking
+struct MarkingJob {
+    shared: *const SharedMarking,
+    local: Worker<GreyObject>,
+}
+/*
+ * The shared state lives on the stack of the collecting thread,
+ * which waits for every job to finish before returning.
+ */
+unsafe impl Send for MarkingJob {}
+
+/// The threads that help the collecting thread with marking.
+///
+/// These are spawned by the first collection that is large enough to need them,
+/// then wait for work until the collector is dropped.
+pub(crate) struct MarkingPool {
+    threads: Lock<Option<PoolThreads>>,
+}
+struct PoolThreads {
+    jobs: Vec<Sender<MarkingJob>>,
+    handles: Vec<JoinHandle<()>>,
+    results: Receiver<thread::Result<MarkingResult>>,
+}
+impl MarkingPool {
+    pub(crate) fn new() -> Self {
+        MarkingPool {
+            threads: Lock::from(None),
+        }
+    }
+}
+impl PoolThreads {
+    fn spawn(num_threads: usize) -> Self {
+        let (result_sender, results) = mpsc::channel();
+        let mut jobs = Vec::with_capacity(num_threads);
+        let mut handles = Vec::with_capacity(num_threads);
+        for _ in 0..num_threads {
+            let (job_sender, job_receiver) = mpsc::channel::<MarkingJob>();
+            let result_sender = result_sender.clone();
+            let handle = thread::Builder::new()
+                .name("zerogc-marking".into())
+                .spawn(move || {
+                    // Exits once the pool is dropped
+                    while let Ok(job) = job_receiver.recv() {
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+                            (*job.shared).run(job.local)
+                        }));
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .expect("Failed to spawn marking thread");
+            jobs.push(job_sender);
+            handles.push(handle);
+        }
+        PoolThreads {
+            jobs,
+            handles,
+            results,
+        }
+    }
+}
+impl Drop for PoolThreads {
+    fn drop(&mut self) {
+        // Disconnecting the channels tells the threads to exit
+        self.jobs.clear();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// The state shared between all the marking threads
+struct SharedMarking {
+    expected_collector: CollectorId,
+    inverted_mark: bool,
+    /// The objects that were already on the grey stack before marking was split up
+    injector: Injector<GreyObject>,
+    stealers: Vec<Stealer<GreyObject>>,
+    /// The number of threads that are still looking for work
+    active: AtomicUsize,
+}
+
+impl CollectionTask<'_> {
+    /// Process the grey stack using [GcConfig::marking_threads](crate::GcConfig::marking_threads) threads
+    ///
+    /// The current thread is used as one of the workers,
+    /// and the rest are taken from the collector's [MarkingPool].
+    pub(crate) fn process_grey_stack_parallel(&mut self) {
+        let num_threads = self.config.marking_threads;
+        debug_assert!(num_threads > 1);
+        let workers: Vec<Worker<GreyObject>> =
+            (0..num_threads).map(|_| Worker::new_lifo()).collect();
+        let shared = SharedMarking {
+            expected_collector: self.expected_collector,
+            inverted_mark: self.heap.allocator.mark_inverted(),
+            injector: Injector::new(),
+            stealers: workers.iter().map(Worker::stealer).collect(),
+            active: AtomicUsize::new(num_threads),
+        };
+        for obj in self.grey_stack.drain(..) {
+            shared.injector.push(GreyObject(obj));
+        }
+        let mut threads = self.marking_pool.threads.lock();
+        let threads = threads.get_or_insert_with(|| PoolThreads::spawn(num_threads - 1));
+        let mut workers = workers.into_iter();
+        let local = workers.next().unwrap();
+        for (job_sender, worker) in threads.jobs.iter().zip(workers) {
+            job_sender
+                .send(MarkingJob {
+                    shared: &shared,
+                    local: worker,
+                })
+                .expect("Marking thread exited");
+        }
+        /*
+         * Even if this thread panics, we have to wait for the others,
+         * since they're still using the shared state.
+         */
+        let mut results = vec![panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+            shared.run(local)
+        }))];
+        for _ in 1..num_threads {
+            results.push(threads.results.recv().expect("Marking thread exited"));
+        }
+        for result in results {
+            match result {
+                Ok((weak_refs, ephemerons, marked_size)) => {
+                    self.weak_refs.extend(weak_refs);
+                    self.ephemerons.extend(ephemerons);
+                    self.marked_size += marked_size;
+                }
+                Err(cause) => panic::resume_unwind(cause),
+            }
+        }
+    }
+}
+
+impl SharedMarking {
+    /// Trace objects until there's no work left on any thread,
+    /// returning the weak references and ephemerons that were found,
+    /// along with the total size of the objects that were marked.
+    unsafe fn run(&self, local: Worker<GreyObject>) -> MarkingResult {
+        let mut grey_stack = Vec::new();
+        let mut weak_refs = Vec::new();
+        let mut ephemerons = Vec::new();
+        let mut marked_size = 0;
+        while let Some(GreyObject(obj)) = self.find_work(&local) {
+            debug_assert_eq!(
+                (*obj).raw_mark_state().resolve(self.inverted_mark),
+                MarkState::Grey
+            );
+            let mut visitor = MarkVisitor {
+                expected_collector: self.expected_collector,
+                grey_stack: &mut grey_stack,
+                weak_refs: &mut weak_refs,
+                ephemerons: &mut ephemerons,
+                recorded_edges: None,
+                inverted_mark: self.inverted_mark,
+                marked_size: &mut marked_size,
+            };
+            if let Some(trace) = (*obj).type_info.trace_func {
+                (trace)((*obj).value(), &mut visitor);
+            }
+            // Mark the object black now it's innards have been traced
+            (*obj).update_raw_mark_state(MarkState::Black.to_raw(self.inverted_mark));
+            // Make the newly found objects available to the other threads
+            for obj in grey_stack.drain(..) {
+                local.push(GreyObject(obj));
+            }
+        }
+        (weak_refs, ephemerons, marked_size)
+    }
+    /// Find another object to trace,
+    /// returning `None` once every thread has run out of work.
+    fn find_work(&self, local: &Worker<GreyObject>) -> Option<GreyObject> {
+        if let Some(obj) = local.pop().or_else(|| self.steal(local)) {
+            return Some(obj);
+        }
+        /*
+         * Objects are only pushed by threads that are still active,
+         * and a thread only becomes inactive once its own stack is empty.
+         * So once every thread is inactive, there's nothing left to steal.
+         */
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        loop {
+            if let Some(obj) = self.steal(local) {
+                self.active.fetch_add(1, Ordering::SeqCst);
+                return Some(obj);
+            }
+            if self.active.load(Ordering::SeqCst) == 0 {
+                return None;
+            }
+            std::thread::yield_now();
+        }
+    }
+    fn steal(&self, local: &Worker<GreyObject>) -> Option<GreyObject> {
+        std::iter::repeat_with(|| {
+            self.injector
+                .steal_batch_and_pop(local)
+                .or_else(|| self.stealers.iter().map(Stealer::steal).collect())
+        })
+        .find(|steal| !steal.is_retry())
+        .and_then(|steal| steal.success())
+    }
+}
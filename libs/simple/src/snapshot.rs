@@ -0,0 +1,178 @@
+//! Snapshots of the heap, for debugging memory usage.
+//!
+//! A [HeapSnapshot] records every object currently allocated in the heap
+//! (both in the small object arenas and as big objects),
+//! along with the outgoing (strong) references found by tracing it.
+//!
+//! ## Format
+//! [HeapSnapshot::write_json] writes the snapshot as a single JSON object,
+//! with one line per heap object so that snapshots are easy to diff:
+//! ```text
+//! {"objects": [
+//! {"address": 94412907421768, "size": 40, "kind": "fixed", "type": "my_vm::Node", "edges": [94412907421816]},
+//! {"address": 94412907421816, "size": 32, "kind": "array", "type": "[u8]", "edges": []}
+//! ]}
+//! ```
+//! Each object has the following fields:
+//! - `address` - The address of the object's value (not its header),
+//!   matching [Gc::as_raw_ptr](zerogc::Gc::as_raw_ptr)
+//! - `size` - The total size of the object in bytes, including its header
+//! - `kind` - Either `fixed`, `array` or `vec`
+//! - `type` - The name of the type, as given by [std::any::type_name]
+//! - `edges` - The addresses of every object this one references.
+//!   Weak references are not included.
+//!
+//! Since addresses are only meaningful for the lifetime of the process,
+//! snapshots are only comparable within the same run.
+use std::io::{self, Write};
+
+use zerogc_context::state::CollectionManager;
+
+use crate::layout::{GcHeader, GcTypeLayout};
+use crate::{ILock, MarkVisitor, SimpleCollector};
+
+/// A snapshot of all the objects in the heap
+#[derive(Clone, Debug)]
+pub struct HeapSnapshot {
+    /// The objects in the heap, in no particular order
+    pub objects: Vec<ObjectSnapshot>,
+}
+impl HeapSnapshot {
+    /// Take a snapshot of every object in the collector's heap.
+    ///
+    /// This includes garbage that hasn't been collected yet.
+    /// However, small objects that a context allocated since its last safepoint
+    /// are still in the context's cache, so they aren't included.
+    ///
+    /// ## Safety
+    /// The heap must not be mutated while the snapshot is taken.
+    /// All other contexts must be frozen or paused at a safepoint,
+    /// and a collection must not be in progress.
+    ///
+    /// Every allocated object must be fully initialized
+    /// (see [GcSimpleAlloc::alloc_uninit](zerogc::GcSimpleAlloc::alloc_uninit)).
+    pub unsafe fn capture(collector: &SimpleCollector) -> HeapSnapshot {
+        let raw = collector.as_raw();
+        debug_assert!(!raw.manager.is_collecting());
+        let allocator = &raw.heap.allocator;
+        // Garbage from previous collections may reference objects that were already freed
+        allocator.finish_sweep();
+        let mut headers: Vec<*mut GcHeader> = allocator.small_objects.lock().clone();
+        headers.extend(
+            allocator
+                .big_objects
+                .lock()
+                .iter()
+                .map(|obj| obj.header.as_ptr()),
+        );
+        let mut edges = Vec::new();
+        let objects = headers
+            .into_iter()
+            .map(|header| {
+                let type_info = (*header).type_info;
+                if let Some(trace_func) = type_info.trace_func {
+                    MarkVisitor::record_edges(
+                        allocator.collector_id.unwrap(),
+                        allocator.mark_inverted(),
+                        &mut edges,
+                        |visitor| trace_func((*header).value(), visitor),
+                    );
+                }
+                ObjectSnapshot {
+                    address: (*header).value() as usize,
+                    size: type_info.determine_total_size(header),
+                    kind: match type_info.layout {
+                        GcTypeLayout::Fixed(_) => ObjectKind::Fixed,
+                        GcTypeLayout::Array { .. } => ObjectKind::Array,
+                        GcTypeLayout::Vec { .. } => ObjectKind::Vec,
+                    },
+                    type_name: type_info.type_name,
+                    edges: edges
+                        .drain(..)
+                        .map(|target| (*target).value() as usize)
+                        .collect(),
+                }
+            })
+            .collect();
+        HeapSnapshot { objects }
+    }
+    /// The total size of all the objects in the snapshot (in bytes)
+    pub fn total_size(&self) -> usize {
+        self.objects.iter().map(|obj| obj.size).sum()
+    }
+    /// Write the snapshot as JSON
+    ///
+    /// See the [module docs](self) for a description of the format.
+    pub fn write_json<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "{{\"objects\": [")?;
+        for (index, obj) in self.objects.iter().enumerate() {
+            if index > 0 {
+                write!(out, ",")?;
+            }
+            write!(
+                out,
+                "\n{{\"address\": {}, \"size\": {}, \"kind\": \"{}\", \"type\": \"",
+                obj.address,
+                obj.size,
+                obj.kind.name()
+            )?;
+            write_json_escaped(&mut out, obj.type_name)?;
+            write!(out, "\", \"edges\": [")?;
+            for (index, edge) in obj.edges.iter().enumerate() {
+                if index > 0 {
+                    write!(out, ", ")?;
+                }
+                write!(out, "{}", edge)?;
+            }
+            write!(out, "]}}")?;
+        }
+        writeln!(out, "\n]}}")
+    }
+}
+
+/// A snapshot of a single object in the heap
+#[derive(Clone, Debug)]
+pub struct ObjectSnapshot {
+    /// The address of the object's value
+    pub address: usize,
+    /// The total size of the object (in bytes), including its header
+    pub size: usize,
+    /// The kind of object
+    pub kind: ObjectKind,
+    /// The name of the object's type
+    pub type_name: &'static str,
+    /// The addresses of the objects this object references
+    pub edges: Vec<usize>,
+}
+
+/// The kind of an object in the heap
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ObjectKind {
+    /// An object with a fixed size
+    Fixed,
+    /// An array, allocated as a [GcArray](crate::GcArray)
+    Array,
+    /// A vector, allocated as a [GcVec](crate::GcVec)
+    Vec,
+}
+impl ObjectKind {
+    fn name(self) -> &'static str {
+        match self {
+            ObjectKind::Fixed => "fixed",
+            ObjectKind::Array => "array",
+            ObjectKind::Vec => "vec",
+        }
+    }
+}
+
+fn write_json_escaped<W: Write>(out: &mut W, s: &str) -> io::Result<()> {
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    Ok(())
+}
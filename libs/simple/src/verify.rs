@@ -0,0 +1,201 @@
+//! Verifies the integrity of the heap during a collection.
+//!
+//! This is enabled by [GcConfig::verify_heap](crate::GcConfig::verify_heap),
+//! and is intended to catch broken `Trace` implementations
+//! before they turn into use-after-free bugs.
+//!
+//! The heap is verified twice. Once before marking,
+//! so that marking never follows a dangling pointer into freed memory,
+//! and once after marking, to check that the marks are consistent.
+use std::collections::HashSet;
+
+use crate::layout::GcHeader;
+use crate::{CollectionTask, ILock, MarkState, MarkVisitor};
+
+/// Verify the heap before marking, panicking if it's corrupted.
+///
+/// This checks that:
+/// 1. Every object belongs to the collector being collected
+/// 2. The free lists of the small object arenas aren't corrupted,
+///    and don't overlap with any allocated objects
+/// 3. Everything reachable from the roots is an allocated object,
+///    and not a dangling pointer into freed memory
+///
+/// ## Safety
+/// Assumes the world is stopped, and that marking hasn't started yet.
+pub(crate) unsafe fn verify_before_mark(task: &CollectionTask) {
+    if let Err(cause) = try_verify_before_mark(task) {
+        panic!("Heap verification failed: {}", cause)
+    }
+}
+
+/// Verify the heap after marking, panicking if it's corrupted.
+///
+/// This checks that:
+/// 1. No objects are left in the grey set
+/// 2. Every object that is reachable from the roots is marked
+///
+/// ## Safety
+/// Assumes the world is stopped, and that marking has finished
+/// (but sweeping hasn't started yet).
+pub(crate) unsafe fn verify_after_mark(task: &CollectionTask) {
+    if let Err(cause) = try_verify_after_mark(task) {
+        panic!("Heap verification failed: {}", cause)
+    }
+}
+
+/// Every object currently allocated in the heap (including garbage)
+unsafe fn allocated_objects(task: &CollectionTask) -> HashSet<*mut GcHeader> {
+    let allocator = &task.heap.allocator;
+    let mut objects: HashSet<*mut GcHeader> =
+        allocator.small_objects.lock().iter().copied().collect();
+    objects.extend(
+        allocator
+            .big_objects
+            .lock()
+            .iter()
+            .map(|obj| obj.header.as_ptr()),
+    );
+    objects
+}
+
+/// Invoke the closure on every object directly referenced by the roots
+unsafe fn for_each_root_edge(
+    task: &CollectionTask,
+    mut func: impl FnMut(usize, *mut GcHeader) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut edges = Vec::new();
+    for (index, &root) in task.roots.iter().enumerate() {
+        MarkVisitor::record_edges(
+            task.expected_collector,
+            task.heap.allocator.mark_inverted(),
+            &mut edges,
+            |visitor| (*root).trace(visitor),
+        );
+        for target in edges.drain(..) {
+            func(index, target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Invoke the closure on every object directly referenced by the specified object
+unsafe fn for_each_edge(
+    task: &CollectionTask,
+    header: *mut GcHeader,
+    mut func: impl FnMut(*mut GcHeader) -> Result<(), String>,
+) -> Result<(), String> {
+    if let Some(trace_func) = (*header).type_info.trace_func {
+        let mut edges = Vec::new();
+        MarkVisitor::record_edges(
+            task.expected_collector,
+            task.heap.allocator.mark_inverted(),
+            &mut edges,
+            |visitor| trace_func((*header).value(), visitor),
+        );
+        for target in edges {
+            func(target)?;
+        }
+    }
+    Ok(())
+}
+
+unsafe fn try_verify_before_mark(task: &CollectionTask) -> Result<(), String> {
+    let objects = allocated_objects(task);
+    for &header in &objects {
+        if *(*header).collector_id() != task.expected_collector {
+            return Err(format!(
+                "Object {:p} ({}) belongs to a different collector",
+                header,
+                (*header).type_info.type_name
+            ));
+        }
+    }
+    let mut small_object_starts = HashSet::new();
+    for &header in task.heap.allocator.small_objects.lock().iter() {
+        small_object_starts.insert(
+            (*header)
+                .type_info
+                .header_layout()
+                .from_common_header(header) as *mut u8,
+        );
+    }
+    let mut overlapping_slot = None;
+    task.heap.allocator.small_arenas.verify_free_lists(|slot| {
+        if small_object_starts.contains(&slot) {
+            overlapping_slot = Some(slot);
+        }
+    })?;
+    if let Some(slot) = overlapping_slot {
+        return Err(format!("Free slot {:p} is also an allocated object", slot));
+    }
+    // Walk everything reachable from the roots, without following dangling pointers
+    let mut visited = HashSet::new();
+    let mut pending = Vec::new();
+    for_each_root_edge(task, |index, target| {
+        if !objects.contains(&target) {
+            return Err(format!(
+                "Root #{} references {:p}, which isn't an allocated object",
+                index, target
+            ));
+        }
+        if visited.insert(target) {
+            pending.push(target);
+        }
+        Ok(())
+    })?;
+    while let Some(header) = pending.pop() {
+        for_each_edge(task, header, |target| {
+            if !objects.contains(&target) {
+                return Err(format!(
+                    "Object {:p} ({}) references {:p}, which isn't an allocated object",
+                    header,
+                    (*header).type_info.type_name,
+                    target
+                ));
+            }
+            if visited.insert(target) {
+                pending.push(target);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+unsafe fn try_verify_after_mark(task: &CollectionTask) -> Result<(), String> {
+    let inverted_mark = task.heap.allocator.mark_inverted();
+    let objects = allocated_objects(task);
+    let check_marked =
+        |target: *mut GcHeader| match (*target).raw_mark_state().resolve(inverted_mark) {
+            MarkState::Black => Ok(()),
+            state => Err(format!(
+                "{:p} ({}) is reachable, but is {:?} after marking",
+                target,
+                (*target).type_info.type_name,
+                state
+            )),
+        };
+    /*
+     * Every object reachable from the roots is marked if
+     * the roots only reference marked objects,
+     * and marked objects only reference other marked objects.
+     */
+    for_each_root_edge(task, |_, target| check_marked(target))?;
+    for &header in &objects {
+        match (*header).raw_mark_state().resolve(inverted_mark) {
+            MarkState::White => {
+                // Unreachable objects are about to be freed
+            }
+            MarkState::Grey => {
+                return Err(format!(
+                    "Object {:p} ({}) is still grey after marking",
+                    header,
+                    (*header).type_info.type_name
+                ))
+            }
+            MarkState::Black => for_each_edge(task, header, check_marked)?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use slog::Logger;
+
+use zerogc::{safepoint, safepoint_force, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.initial_threshold = 16 * 1024;
+    config.verify_heap = true; // Catch any objects the collector doesn't know about
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Node<'gc> {
+    val: usize,
+    next: Option<Gc<'gc, Node<'gc>>>,
+}
+
+#[test]
+fn threads_allocate_concurrently() {
+    const THREADS: usize = 4;
+    const LEN: usize = 1000;
+    let collector = test_collector();
+    std::thread::scope(|scope| {
+        for _ in 0..THREADS {
+            let collector = &collector;
+            scope.spawn(move || {
+                let mut context = collector.create_context();
+                let mut list: Option<Gc<Node>> = None;
+                for val in 0..LEN {
+                    let node = context.alloc(Node { val, next: list });
+                    // Garbage, which is freed by the next collection
+                    context.alloc(Node { val, next: None });
+                    list = safepoint!(context, Some(node));
+                }
+                let mut node = list;
+                for expected in (0..LEN).rev() {
+                    assert_eq!(node.unwrap().val, expected);
+                    node = node.unwrap().next;
+                }
+                assert!(node.is_none());
+            });
+        }
+    });
+    let stats = collector.stats();
+    assert!(stats.collections > 0);
+    assert!(stats.total_freed_bytes > 0);
+}
+
+#[test]
+fn dropped_context_flushes_cache() {
+    let collector = test_collector();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let context = collector.create_context();
+            for val in 0..100 {
+                context.alloc(Node { val, next: None });
+            }
+        });
+    });
+    let allocated = collector.stats().live_bytes;
+    assert!(allocated > 0);
+    // The objects from the dead context must still be swept
+    let mut context = collector.create_context();
+    safepoint_force!(context, ());
+    let stats = context.system().stats();
+    assert_eq!(stats.last_freed_bytes, allocated);
+    assert_eq!(stats.live_bytes, 0);
+}
+
+#[test]
+fn safepoints_keep_cached_slots() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    // Leave a long free list behind
+    for val in 0..200_000 {
+        context.alloc(Node { val, next: None });
+    }
+    safepoint_force!(context, ());
+    let before = context.system().stats();
+    /*
+     * Refilling the cache takes the arena's entire free list.
+     * If every safepoint gave it back, each one would have to walk the whole list again.
+     */
+    let mut list: Option<Gc<Node>> = None;
+    for val in 0..20_000 {
+        list = safepoint!(context, Some(context.alloc(Node { val, next: list })));
+    }
+    let after = context.system().stats();
+    assert_eq!(after.collections, before.collections);
+    assert_eq!(after.arena_bytes, before.arena_bytes);
+    let mut node = list;
+    for expected in (0..20_000).rev() {
+        assert_eq!(node.unwrap().val, expected);
+        node = node.unwrap().next;
+    }
+    assert!(node.is_none());
+}
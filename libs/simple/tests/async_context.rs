@@ -0,0 +1,167 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+
+use slog::Logger;
+
+use zerogc::prelude::*;
+use zerogc::safepoint_force;
+use zerogc_derive::Trace;
+
+use zerogc_simple::{
+    safepoint_await, CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector,
+};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.initial_threshold = usize::MAX; // Only collect when forced
+    config.verify_heap = true;
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+struct ThreadWaker(Thread);
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// A minimal executor, so we don't need to depend on tokio
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+/// Returns `Pending` once, like a real I/O future
+#[derive(Default)]
+struct YieldNow {
+    yielded: bool,
+}
+impl Future for YieldNow {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            Poll::Ready(())
+        } else {
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// Force a collection from a different thread
+fn collect_elsewhere(collector: &SimpleCollector) {
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut context = collector.create_context();
+            safepoint_force!(context, ());
+        });
+    });
+}
+
+fn assert_send<T: Send>(val: T) -> T {
+    val
+}
+
+#[test]
+fn collect_while_awaiting() {
+    let collector = test_collector();
+    // Only ever suspends inside `safepoint_await!`
+    let mut context = unsafe { collector.create_async_context() };
+    let other = &collector;
+    let task = assert_send(async move {
+        let root: Gc<Dummy> = context.alloc(Dummy { val: 42 });
+        // Garbage
+        context.alloc(Dummy { val: 7 });
+        let (root, ()) = safepoint_await!(context, root, async {
+            YieldNow::default().await;
+            collect_elsewhere(other);
+        });
+        assert_eq!(root.val, 42);
+        context.system().stats()
+    });
+    // Run the task on a different thread than the one that created it
+    let stats = std::thread::scope(|scope| scope.spawn(|| block_on(task)).join().unwrap());
+    assert_eq!(stats.collections, 1);
+    assert!(stats.last_freed_bytes > 0);
+}
+
+#[test]
+fn polled_on_two_threads() {
+    let collector = test_collector();
+    // Only ever suspends inside `safepoint_await!`
+    let mut context = unsafe { collector.create_async_context() };
+    let mut task = Box::pin(assert_send(async move {
+        let root: Gc<Dummy> = context.alloc(Dummy { val: 42 });
+        // Garbage
+        context.alloc(Dummy { val: 7 });
+        let (root, ()) = safepoint_await!(context, root, YieldNow::default());
+        assert_eq!(root.val, 42);
+        // The context is active again, and collects on the new thread
+        let root: Gc<Dummy> = safepoint_force!(context, root);
+        root.val
+    }));
+    // The first poll freezes the context before yielding
+    let task = std::thread::scope(|scope| {
+        scope
+            .spawn(move || {
+                let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+                assert!(task
+                    .as_mut()
+                    .poll(&mut Context::from_waker(&waker))
+                    .is_pending());
+                task
+            })
+            .join()
+            .unwrap()
+    });
+    collect_elsewhere(&collector);
+    assert_eq!(collector.stats().collections, 1);
+    // The second poll unfreezes it on a different thread
+    let val = std::thread::scope(|scope| scope.spawn(|| block_on(task)).join().unwrap());
+    assert_eq!(val, 42);
+    assert_eq!(collector.stats().collections, 2);
+}
+
+#[test]
+fn cancelled_while_frozen() {
+    let collector = test_collector();
+    // Only ever suspends inside `safepoint_await!`
+    let mut context = unsafe { collector.create_async_context() };
+    {
+        let task = async {
+            let root = context.alloc(Dummy { val: 1 });
+            safepoint_await!(context, root, std::future::pending::<()>());
+        };
+        let mut task = Box::pin(task);
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        assert!(task
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+        // The context is frozen, so other threads don't wait for it
+        collect_elsewhere(&collector);
+        // Dropping the task unfreezes the context
+    }
+    assert_eq!(collector.stats().collections, 1);
+    let root = context.alloc(Dummy { val: 2 });
+    let root: Gc<Dummy> = safepoint_force!(context, root);
+    assert_eq!(root.val, 2);
+    assert_eq!(collector.stats().collections, 2);
+}
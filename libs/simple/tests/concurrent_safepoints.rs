@@ -0,0 +1,51 @@
+use slog::Logger;
+
+use zerogc::{safepoint_force, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.verify_heap = true; // Catch any objects that were missed
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Node<'gc> {
+    val: usize,
+    next: Option<Gc<'gc, Node<'gc>>>,
+}
+
+/// Every thread forces a collection at every safepoint,
+/// so they constantly race to join (or start) the next collection.
+///
+/// This used to deadlock, or join a collection that had already finished.
+#[test]
+fn threads_force_collections() {
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 200;
+    let collector = test_collector();
+    std::thread::scope(|scope| {
+        for thread in 0..THREADS {
+            let collector = &collector;
+            scope.spawn(move || {
+                let mut context = collector.create_context();
+                let mut list: Option<Gc<Node>> = None;
+                // Stagger the threads, so some of them exit while others still collect
+                for val in 0..(ROUNDS + thread * 10) {
+                    let node = context.alloc(Node { val, next: list });
+                    list = safepoint_force!(context, Some(node));
+                }
+                let mut len = 0;
+                let mut node = list;
+                while let Some(current) = node {
+                    node = current.next;
+                    len += 1;
+                }
+                assert_eq!(len, ROUNDS + thread * 10);
+            });
+        }
+    });
+}
@@ -0,0 +1,74 @@
+use slog::Logger;
+
+use zerogc::hash_map::GcWeakKeyMap;
+use zerogc::safepoint;
+use zerogc::GcSimpleAlloc;
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug, Eq, PartialEq)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+type SideTable<'gc> = GcWeakKeyMap<'gc, Dummy, Gc<'gc, Dummy>, SimpleCollectorId>;
+
+#[test]
+fn dead_keys() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let mut table: SideTable = GcWeakKeyMap::new_in(&context);
+    let live = context.alloc(Dummy { val: 1 });
+    let dead = context.alloc(Dummy { val: 2 });
+    table.insert(live, context.alloc(Dummy { val: 10 }));
+    table.insert(dead, context.alloc(Dummy { val: 20 }));
+    // The value refers back to its own key, but that doesn't keep it alive
+    table.insert(context.alloc(Dummy { val: 3 }), dead);
+    assert_eq!(table.get(live).map(|v| v.val), Some(10));
+    assert_eq!(table.get(dead).map(|v| v.val), Some(20));
+    assert_eq!(table.len(), 3);
+    let (mut table, live): (SideTable, Gc<Dummy>) = safepoint!(context, (table, live));
+    assert_eq!(table.get(live).map(|v| v.val), Some(10));
+    assert_eq!(table.keys().collect::<Vec<_>>(), vec![live]);
+    assert_eq!(table.values().map(|v| v.val).collect::<Vec<_>>(), vec![10]);
+    // The dead entries are only removed once they're pruned
+    assert_eq!(table.len(), 3);
+    table.prune();
+    assert_eq!(table.len(), 1);
+    assert_eq!(table.remove(live).map(|v| v.val), Some(10));
+    assert!(table.is_empty());
+}
+
+#[test]
+fn ephemeron_chain() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let mut table: SideTable = GcWeakKeyMap::new_in(&context);
+    let first = context.alloc(Dummy { val: 1 });
+    let second = context.alloc(Dummy { val: 2 });
+    let third = context.alloc(Dummy { val: 3 });
+    /*
+     * The second key is only reachable through the value of the first entry.
+     * Insert it first, so that it's deferred until the first entry is traced.
+     */
+    table.insert(third, context.alloc(Dummy { val: 30 }));
+    table.insert(second, third);
+    table.insert(first, second);
+    let (table, first): (SideTable, Gc<Dummy>) = safepoint!(context, (table, first));
+    let second = *table.get(first).unwrap();
+    assert_eq!(second.val, 2);
+    let third = *table.get(second).unwrap();
+    assert_eq!(third.val, 3);
+    assert_eq!(table.get(third).map(|v| v.val), Some(30));
+    // Once the first key dies, the whole chain dies with it
+    let table: SideTable = safepoint!(context, table);
+    assert_eq!(table.iter().count(), 0);
+}
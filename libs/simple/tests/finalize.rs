@@ -0,0 +1,91 @@
+use slog::Logger;
+
+use zerogc::{safepoint, GcFinalize, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, GcWeak, SimpleCollector};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct FileHandle<'gc> {
+    fd: i32,
+    path: Gc<'gc, Dummy>,
+}
+
+#[derive(Trace, Copy, Clone, Debug, Eq, PartialEq)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[test]
+fn finalize_unreachable() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let path = context.alloc(Dummy { val: 7 });
+    let file = context.alloc(FileHandle { fd: 3, path });
+    context.register_finalizer(file);
+    // Still reachable, so nothing should be finalized
+    let file: Gc<FileHandle> = safepoint!(context, file);
+    assert_eq!(context.drain_finalized().count(), 0);
+    // Now it's unreachable, so it should be queued for finalization
+    safepoint!(context, ());
+    // It was kept alive (along with everything it references)
+    let finalized = context.drain_finalized().collect::<Vec<_>>();
+    assert_eq!(finalized.len(), 1);
+    let finalized = finalized[0];
+    assert!(!finalized.is::<Dummy>());
+    let file = finalized.downcast::<FileHandle>().unwrap();
+    assert_eq!(file.fd, 3);
+    assert_eq!(file.path.val, 7);
+    // Objects are only finalized once
+    safepoint!(context, ());
+    assert_eq!(context.drain_finalized().count(), 0);
+}
+
+#[test]
+fn weak_cleared_before_finalization() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let path = context.alloc(Dummy { val: 7 });
+    let file = context.alloc(FileHandle { fd: 3, path });
+    context.register_finalizer(file);
+    let (weak_file, weak_path): (GcWeak<FileHandle>, GcWeak<Dummy>) =
+        safepoint!(context, (file.downgrade(), path.downgrade()));
+    /*
+     * Both objects became unreachable, so the weak references are cleared,
+     * even though the objects are kept alive for finalization.
+     */
+    assert!(weak_file.is_cleared());
+    assert!(weak_path.is_cleared());
+    let finalized = context.drain_finalized().collect::<Vec<_>>();
+    assert_eq!(finalized.len(), 1);
+    let file = finalized[0].downcast::<FileHandle>().unwrap();
+    assert_eq!(file.path.val, 7);
+}
+
+#[test]
+fn finalize_pending() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let first = context.alloc(Dummy { val: 1 });
+    let second = context.alloc(Dummy { val: 2 });
+    context.register_finalizer(first);
+    context.register_finalizer(second);
+    safepoint!(context, ());
+    // Until they are drained, objects remain alive across safepoints
+    safepoint!(context, ());
+    safepoint!(context, ());
+    let mut values = context
+        .drain_finalized()
+        .map(|obj| obj.downcast::<Dummy>().unwrap().val)
+        .collect::<Vec<_>>();
+    values.sort_unstable();
+    assert_eq!(values, vec![1, 2]);
+}
@@ -0,0 +1,33 @@
+use slog::Logger;
+
+use zerogc::{safepoint, safepoint_force, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector};
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[test]
+fn force_collect() {
+    // The default threshold is far too large for these allocations to trigger a collection
+    let collector = SimpleCollector::with_config(
+        GcConfig::default(),
+        Logger::root(::slog::Discard, ::slog::o!()),
+    );
+    let mut context = collector.into_context();
+    let kept = context.alloc(Dummy { val: 1 });
+    context.alloc(Dummy { val: 2 });
+    let kept: Gc<Dummy> = safepoint!(context, kept);
+    assert_eq!(context.system().stats().collections, 0);
+    let live_bytes = context.system().stats().live_bytes;
+    let kept: Gc<Dummy> = safepoint_force!(context, kept);
+    assert_eq!(kept.val, 1);
+    let stats = context.system().stats();
+    assert_eq!(stats.collections, 1);
+    assert!(stats.last_freed_bytes > 0);
+    assert_eq!(stats.live_bytes, live_bytes - stats.last_freed_bytes);
+}
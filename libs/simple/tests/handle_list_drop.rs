@@ -0,0 +1,45 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+
+use slog::Logger;
+
+use zerogc::prelude::*;
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, GcConfig, SimpleCollector};
+
+/// Overwrites memory as soon as it's freed,
+/// so reading it afterwards gives garbage instead of the old value.
+struct PoisonFreed;
+unsafe impl GlobalAlloc for PoisonFreed {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ptr.write_bytes(0xA5, layout.size());
+        System.dealloc(ptr, layout)
+    }
+}
+#[global_allocator]
+static ALLOC: PoisonFreed = PoisonFreed;
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[test]
+fn dropping_collector_frees_buckets() {
+    let collector = SimpleCollector::with_config(
+        GcConfig::default(),
+        Logger::root(::slog::Discard, ::slog::o!()),
+    );
+    let context = collector.into_context();
+    // Enough handles for several buckets
+    let handles = (0..10_000)
+        .map(|val| context.alloc(Dummy { val }).create_handle())
+        .collect::<Vec<_>>();
+    drop(handles);
+    // Frees every bucket, without touching the freed ones
+    drop(context);
+}
@@ -0,0 +1,90 @@
+use slog::Logger;
+
+use zerogc::prelude::*;
+use zerogc::safepoint_force;
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, GcConfig, SimpleCollector};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.initial_threshold = usize::MAX; // Only collect when forced
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[test]
+fn free_buckets_released() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let mut handles = (0..10_000)
+        .map(|val| context.alloc(Dummy { val }).create_handle())
+        .collect::<Vec<_>>();
+    let before = context.system().stats();
+    assert_eq!(before.num_handles, 10_000);
+    // Only keep the oldest handles
+    handles.truncate(10);
+    assert!(context.system().stats().free_handle_slots >= 9_990);
+    safepoint_force!(context, ());
+    let after = context.system().stats();
+    assert_eq!(after.num_handles, 10);
+    // Only the first bucket is left
+    assert!(after.free_handle_slots < 64);
+    for (val, handle) in handles.iter().enumerate() {
+        assert_eq!(handle.bind_to(&context).val, val);
+    }
+    // New handles reuse the free slots
+    let more = (0..after.free_handle_slots)
+        .map(|val| context.alloc(Dummy { val }).create_handle())
+        .collect::<Vec<_>>();
+    let stats = context.system().stats();
+    assert_eq!(stats.num_handles, 10 + more.len());
+    assert_eq!(stats.free_handle_slots, 0);
+    for (val, handle) in more.iter().enumerate() {
+        assert_eq!(handle.bind_to(&context).val, val);
+    }
+}
+
+#[test]
+fn dropping_clone_keeps_handle() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let handle = context.alloc(Dummy { val: 42 }).create_handle();
+    drop(handle.clone());
+    assert_eq!(context.system().stats().num_handles, 1);
+    // The slot must not be handed out to another handle
+    let other = context.alloc(Dummy { val: 7 }).create_handle();
+    safepoint_force!(context, ());
+    assert_eq!(handle.bind_to(&context).val, 42);
+    assert_eq!(other.bind_to(&context).val, 7);
+    drop(handle);
+    assert_eq!(context.system().stats().num_handles, 1);
+}
+
+#[test]
+#[cfg(feature = "sync")]
+fn dropped_while_collecting() {
+    let collector = test_collector();
+    let mut context = collector.create_context();
+    let handles = (0..10_000)
+        .map(|val| context.alloc(Dummy { val }).create_handle())
+        .collect::<Vec<_>>();
+    let dropper = std::thread::spawn(move || {
+        for handle in handles {
+            drop(handle);
+        }
+    });
+    while !dropper.is_finished() {
+        safepoint_force!(context, ());
+    }
+    dropper.join().unwrap();
+    safepoint_force!(context, ());
+    let stats = context.system().stats();
+    assert_eq!(stats.num_handles, 0);
+    assert_eq!(stats.free_handle_slots, 0);
+}
@@ -0,0 +1,101 @@
+use slog::Logger;
+
+use zerogc::{safepoint, GcAllocError, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector};
+
+fn test_collector(max_heap_size: usize) -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.max_heap_size = Some(max_heap_size);
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Big {
+    values: [u64; 16],
+}
+
+#[test]
+fn limit_exceeded() {
+    let collector = test_collector(1024);
+    let mut context = collector.into_context();
+    let mut last = None;
+    loop {
+        match context.try_alloc(Big { values: [7; 16] }) {
+            Ok(big) => last = Some(big),
+            Err(cause) => {
+                assert_eq!(cause, GcAllocError::HeapLimitExceeded);
+                break;
+            }
+        }
+    }
+    assert!(context.try_alloc_slice_copy(&[0u8; 512]).is_err());
+    // Only the last object survives, so a collection frees enough room
+    let last: Gc<Big> = safepoint!(context, last.unwrap());
+    assert_eq!(last.values, [7; 16]);
+    let big = context.try_alloc(Big { values: [3; 16] }).unwrap();
+    assert_eq!(big.values, [3; 16]);
+    let vec = context.try_alloc_vec_with_capacity::<u64>(4).unwrap();
+    assert_eq!(vec.capacity(), 4);
+}
+
+#[test]
+fn retry_after_safepoint() {
+    let collector = test_collector(4096);
+    let mut context = collector.into_context();
+    let mut failures = 0;
+    for i in 0..1000 {
+        // Nothing is kept alive, so retrying after a collection always succeeds
+        let big = match context.try_alloc(Big { values: [i; 16] }) {
+            Ok(big) => big,
+            Err(GcAllocError::HeapLimitExceeded) => {
+                failures += 1;
+                safepoint!(context, ());
+                context.try_alloc(Big { values: [i; 16] }).unwrap()
+            }
+            Err(cause) => panic!("Unexpected error: {}", cause),
+        };
+        assert_eq!(big.values, [i; 16]);
+    }
+    assert!(failures > 0);
+    assert_eq!(context.system().stats().collections, failures);
+}
+
+#[test]
+fn live_objects_over_limit() {
+    let collector = test_collector(1024);
+    let mut context = collector.into_context();
+    let mut live = context.alloc_vec_with_capacity::<Gc<Big>>(64);
+    while let Ok(big) = context.try_alloc(Big { values: [1; 16] }) {
+        live.push(big);
+    }
+    let live = safepoint!(context, live);
+    // Everything is still reachable, so the retry fails too
+    assert_eq!(
+        context.try_alloc(Big { values: [2; 16] }).unwrap_err(),
+        GcAllocError::HeapLimitExceeded
+    );
+    assert!(live.iter().all(|big| big.values == [1; 16]));
+}
+
+#[test]
+fn array_size_overflow() {
+    let collector = test_collector(1024);
+    let context = collector.into_context();
+    // The size doesn't even fit in a `Layout`, so this fails without collecting
+    assert_eq!(
+        context
+            .try_alloc_vec_with_capacity::<u64>(usize::MAX)
+            .unwrap_err(),
+        GcAllocError::SizeOverflow
+    );
+    unsafe {
+        assert_eq!(
+            context.try_alloc_uninit_slice::<u64>(usize::MAX).unwrap_err(),
+            GcAllocError::SizeOverflow
+        );
+    }
+    assert_eq!(context.system().stats().collections, 0);
+}
@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use slog::Logger;
+
+use zerogc::{safepoint, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[test]
+fn collection_hooks() {
+    let collector = test_collector();
+    let started = Arc::new(AtomicU64::new(0));
+    let live_before = Arc::new(AtomicUsize::new(0));
+    let finished = Arc::new(AtomicU64::new(0));
+    let freed = Arc::new(AtomicUsize::new(0));
+    {
+        let started = Arc::clone(&started);
+        let live_before = Arc::clone(&live_before);
+        collector.on_collection_start(move |id, stats| {
+            assert_eq!(stats.collections, started.fetch_add(1, Ordering::SeqCst));
+            assert_eq!(id, stats.collections);
+            live_before.store(stats.live_bytes, Ordering::SeqCst);
+        });
+    }
+    {
+        let finished = Arc::clone(&finished);
+        let freed = Arc::clone(&freed);
+        collector.on_collection_end(move |id, stats| {
+            assert_eq!(id, finished.fetch_add(1, Ordering::SeqCst));
+            assert_eq!(stats.collections, id + 1);
+            freed.store(stats.last_freed_bytes, Ordering::SeqCst);
+        });
+    }
+    let mut context = collector.into_context();
+    let kept = context.alloc(Dummy { val: 1 });
+    context.alloc(Dummy { val: 2 });
+    let kept: Gc<Dummy> = safepoint!(context, kept);
+    assert_eq!(kept.val, 1);
+    assert_eq!(started.load(Ordering::SeqCst), 1);
+    assert_eq!(finished.load(Ordering::SeqCst), 1);
+    assert!(live_before.load(Ordering::SeqCst) > 0);
+    assert!(freed.load(Ordering::SeqCst) > 0);
+    let kept: Gc<Dummy> = safepoint!(context, kept);
+    assert_eq!(kept.val, 1);
+    assert_eq!(started.load(Ordering::SeqCst), 2);
+    assert_eq!(finished.load(Ordering::SeqCst), 2);
+    assert_eq!(freed.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,105 @@
+use slog::Logger;
+
+use zerogc::{safepoint, safepoint_force, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{
+    CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector, SimpleCollectorContext,
+};
+
+fn test_collector(retained_arena_bytes: Option<usize>) -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.initial_threshold = usize::MAX; // Only collect when forced
+    config.retained_arena_bytes = retained_arena_bytes;
+    config.verify_heap = true;
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Node<'gc> {
+    val: usize,
+    next: Option<Gc<'gc, Node<'gc>>>,
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Medium {
+    data: [u64; 24],
+}
+
+fn alloc_list<'gc>(context: &'gc SimpleCollectorContext, len: usize) -> Gc<'gc, Node<'gc>> {
+    let mut head = context.alloc(Node { val: 0, next: None });
+    for val in 1..len {
+        head = context.alloc(Node {
+            val,
+            next: Some(head),
+        });
+    }
+    head
+}
+
+fn check_list(head: Gc<Node>, len: usize) {
+    let mut node = Some(head);
+    for expected in (0..len).rev() {
+        assert_eq!(node.unwrap().val, expected);
+        node = node.unwrap().next;
+    }
+    assert!(node.is_none());
+}
+
+#[test]
+fn mixed_sizes_are_adjacent() {
+    let collector = test_collector(None);
+    let context = collector.into_context();
+    let first = context.alloc(Node { val: 0, next: None });
+    let medium = context.alloc(Medium { data: [7; 24] });
+    let second = context.alloc(Node { val: 1, next: None });
+    let first_addr = unsafe { first.as_raw_ptr() } as usize;
+    let medium_addr = unsafe { medium.as_raw_ptr() } as usize;
+    let second_addr = unsafe { second.as_raw_ptr() } as usize;
+    // Each object directly follows the last one (and its header)
+    assert!(first_addr < medium_addr && medium_addr < second_addr);
+    assert!(medium_addr - first_addr <= 2 * std::mem::size_of::<Node>() + 64);
+    assert!(second_addr - medium_addr <= std::mem::size_of::<Medium>() + 64);
+    assert_eq!(medium.data, [7; 24]);
+}
+
+#[test]
+fn reuses_free_lines() {
+    let collector = test_collector(None);
+    let mut context = collector.into_context();
+    let mut live = alloc_list(&context, 100);
+    let mut reserved = None;
+    for _ in 0..5 {
+        alloc_list(&context, 10_000);
+        live = safepoint_force!(context, live);
+        let stats = context.system().stats();
+        assert!(stats.last_freed_bytes > 0);
+        // The lines freed by each collection are reused by the next one
+        match reserved {
+            None => reserved = Some(stats.arena_bytes),
+            Some(reserved) => assert_eq!(stats.arena_bytes, reserved),
+        }
+        check_list(live, 100);
+    }
+}
+
+#[test]
+fn releases_free_blocks() {
+    let collector = test_collector(Some(0));
+    let mut context = collector.into_context();
+    let live = alloc_list(&context, 100);
+    alloc_list(&context, 100_000);
+    let live: Gc<Node> = safepoint!(context, live);
+    let before = context.system().stats();
+    let live: Gc<Node> = safepoint_force!(context, live);
+    let after = context.system().stats();
+    assert!(after.arena_bytes < before.arena_bytes / 2);
+    check_list(live, 100);
+    // The surviving objects can be mixed with new ones
+    let more = alloc_list(&context, 1000);
+    let (live, more): (Gc<Node>, Gc<Node>) = safepoint_force!(context, (live, more));
+    check_list(live, 100);
+    check_list(more, 1000);
+}
@@ -0,0 +1,297 @@
+#![feature(
+    arbitrary_self_types, // Required for the setters on Gc refs
+)]
+// Recursive marking can't be interrupted
+#![cfg(not(feature = "implicit-grey-stack"))]
+use slog::Logger;
+
+use zerogc::hash_map::GcWeakKeyMap;
+use zerogc::prelude::*;
+use zerogc::safepoint_force;
+use zerogc_derive::Trace;
+
+use zerogc_simple::{
+    CollectorId as SimpleCollectorId, Gc, GcConfig, GcVec, GcWeak, MarkBudget, SimpleCollector,
+    SimpleCollectorContext,
+};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.initial_threshold = 4096;
+    config.incremental_budget = Some(MarkBudget::Work(1)); // Trace a single object at each step
+    config.verify_heap = true; // Catch any black objects that reference white ones
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace)]
+#[zerogc(collector_ids(SimpleCollectorId))]
+struct Node<'gc> {
+    val: usize,
+    #[zerogc(mutable(public))]
+    next: GcCell<Option<Gc<'gc, Node<'gc>>>>,
+    #[zerogc(mutable(public))]
+    extra: GcCell<Option<Gc<'gc, Node<'gc>>>>,
+}
+
+#[derive(Trace, Copy, Clone)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Cache<'gc> {
+    entry: GcWeak<'gc, Node<'gc>>,
+}
+
+type Table<'gc> = GcWeakKeyMap<'gc, Node<'gc>, Gc<'gc, Node<'gc>>, SimpleCollectorId>;
+
+fn alloc_node<'gc>(context: &'gc SimpleCollectorContext, val: usize) -> Gc<'gc, Node<'gc>> {
+    context.alloc(Node {
+        val,
+        next: GcCell::new(None),
+        extra: GcCell::new(None),
+    })
+}
+
+/// Allocate a list of nodes `0..len`, where the last node has an `extra` node with the value `len`
+fn alloc_list<'gc>(context: &'gc SimpleCollectorContext, len: usize) -> Gc<'gc, Node<'gc>> {
+    let last = alloc_node(context, len - 1);
+    last.set_extra(Some(alloc_node(context, len)));
+    let mut head = last;
+    for val in (0..len - 1).rev() {
+        let node = alloc_node(context, val);
+        node.set_next(Some(head));
+        head = node;
+    }
+    head
+}
+
+fn last_node<'gc>(head: Gc<'gc, Node<'gc>>) -> Gc<'gc, Node<'gc>> {
+    let mut node = head;
+    while let Some(next) = node.next.get() {
+        node = next;
+    }
+    node
+}
+
+fn check_list<'gc>(head: Gc<'gc, Node<'gc>>, len: usize) {
+    let mut node = Some(head);
+    for expected in 0..len {
+        let current = node.unwrap();
+        assert_eq!(current.val, expected);
+        node = current.next.get();
+    }
+    assert!(node.is_none());
+}
+
+/// Exceed the threshold with garbage, so the next safepoint starts marking
+fn fill_threshold(context: &SimpleCollectorContext) {
+    context.alloc_slice_copy(&[0u8; 4096]);
+}
+
+#[test]
+fn marks_incrementally() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let head = alloc_list(&context, 16);
+    fill_threshold(&context);
+    let mut head: Gc<Node> = safepoint!(context, head);
+    let stats = context.system().stats();
+    assert!(stats.marking_in_progress);
+    assert_eq!(stats.collections, 0);
+    assert_eq!(stats.incremental_steps, 1);
+    let mut steps = 1;
+    while context.system().stats().marking_in_progress {
+        head = safepoint!(context, head);
+        steps += 1;
+    }
+    assert!(steps > 1);
+    let stats = context.system().stats();
+    assert_eq!(stats.collections, 1);
+    assert_eq!(stats.incremental_steps, steps);
+    assert!(stats.last_freed_bytes >= 4096);
+    assert!(stats.last_pause <= stats.total_pause);
+    check_list(head, 16);
+    assert_eq!(last_node(head).extra.get().unwrap().val, 16);
+}
+
+#[test]
+fn setter_barrier() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let head = alloc_list(&context, 8);
+    fill_threshold(&context);
+    // Only the head has been traced so far
+    let mut head: Gc<Node> = safepoint!(context, head);
+    assert!(context.system().stats().marking_in_progress);
+    // Move the (unmarked) extra node into the head, which is already black
+    let last = last_node(head);
+    let extra = last.extra.get().unwrap();
+    head.set_extra(Some(extra));
+    last.set_extra(None);
+    while context.system().stats().marking_in_progress {
+        head = safepoint!(context, head);
+    }
+    assert_eq!(context.system().stats().collections, 1);
+    check_list(head, 8);
+    assert_eq!(head.extra.get().unwrap().val, 8);
+}
+
+#[test]
+fn vec_barrier() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let head = alloc_list(&context, 8);
+    let mut vec: GcVec<Gc<Node>> = context.alloc_vec_with_capacity(4);
+    vec.push(alloc_node(&context, 100));
+    fill_threshold(&context);
+    // The vector is traced before the list
+    let (mut head, mut vec): (Gc<Node>, GcVec<Gc<Node>>) = safepoint!(context, (head, vec));
+    assert!(context.system().stats().marking_in_progress);
+    let last = last_node(head);
+    vec.push(last.extra.get().unwrap());
+    last.set_extra(None);
+    // A new object is allocated while marking is in progress
+    vec.push(alloc_node(&context, 200));
+    while context.system().stats().marking_in_progress {
+        let roots = safepoint!(context, (head, vec));
+        head = roots.0;
+        vec = roots.1;
+    }
+    assert_eq!(context.system().stats().collections, 1);
+    check_list(head, 8);
+    let values: Vec<usize> = vec.iter().map(|node| node.val).collect();
+    assert_eq!(values, vec![100, 8, 200]);
+}
+
+/// Store an unmarked node into a vector that has already been traced, using `store`
+fn check_vec_barrier(store: for<'gc> fn(&mut GcVec<'gc, Gc<'gc, Node<'gc>>>, Gc<'gc, Node<'gc>>)) {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let head = alloc_list(&context, 8);
+    let mut vec: GcVec<Gc<Node>> = context.alloc_vec_with_capacity(2);
+    vec.push(alloc_node(&context, 100));
+    vec.push(alloc_node(&context, 101));
+    fill_threshold(&context);
+    // The vector is traced before the list
+    let (mut head, mut vec): (Gc<Node>, GcVec<Gc<Node>>) = safepoint!(context, (head, vec));
+    assert!(context.system().stats().marking_in_progress);
+    let last = last_node(head);
+    store(&mut vec, last.extra.get().unwrap());
+    last.set_extra(None);
+    while context.system().stats().marking_in_progress {
+        let roots = safepoint!(context, (head, vec));
+        head = roots.0;
+        vec = roots.1;
+    }
+    assert_eq!(context.system().stats().collections, 1);
+    check_list(head, 8);
+    assert!(vec.iter().any(|node| node.val == 8));
+}
+
+#[test]
+fn vec_set_barrier() {
+    check_vec_barrier(|vec, node| vec.set(0, node));
+}
+
+#[test]
+fn vec_replace_barrier() {
+    check_vec_barrier(|vec, node| {
+        assert_eq!(vec.replace(1, node).val, 101);
+    });
+}
+
+#[test]
+fn vec_extend_barrier() {
+    check_vec_barrier(|vec, node| {
+        vec.pop();
+        vec.extend_from_slice(&[node]);
+    });
+}
+
+#[test]
+fn vec_slice_barrier() {
+    check_vec_barrier(|vec, node| vec.as_mut_slice()[1] = node);
+}
+
+#[test]
+fn vec_grow_barrier() {
+    // The vector is already full, so it's copied into a new one
+    check_vec_barrier(|vec, node| vec.push(node));
+}
+
+#[test]
+fn weak_refs() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let head = alloc_list(&context, 8);
+    let dead = context.alloc(Cache {
+        entry: alloc_node(&context, 100).downgrade(),
+    });
+    let live = context.alloc(Cache {
+        entry: head.downgrade(),
+    });
+    fill_threshold(&context);
+    let (mut head, mut dead, mut live): (Gc<Node>, Gc<Cache>, Gc<Cache>) =
+        safepoint!(context, (head, dead, live));
+    assert!(context.system().stats().marking_in_progress);
+    while context.system().stats().marking_in_progress {
+        let roots = safepoint!(context, (head, dead, live));
+        head = roots.0;
+        dead = roots.1;
+        live = roots.2;
+    }
+    assert!(dead.entry.is_cleared());
+    assert!(std::ptr::eq(&*live.entry.upgrade().unwrap(), &*head));
+    check_list(head, 8);
+}
+
+#[test]
+fn remove_entries_while_marking() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let head = alloc_list(&context, 16);
+    let mut table: Table = GcWeakKeyMap::new_in(&context);
+    // The keys are at the end of the list, so they're still unmarked when the table is traced
+    let last = last_node(head);
+    let extra = last.extra.get().unwrap();
+    table.insert(last, alloc_node(&context, 100));
+    table.insert(extra, alloc_node(&context, 200));
+    let cache = context.alloc(Cache {
+        entry: table.get(extra).unwrap().downgrade(),
+    });
+    fill_threshold(&context);
+    let (mut head, mut table, mut cache): (Gc<Node>, Table, Gc<Cache>) =
+        safepoint!(context, (head, table, cache));
+    for _ in 0..3 {
+        let roots = safepoint!(context, (head, table, cache));
+        head = roots.0;
+        table = roots.1;
+        cache = roots.2;
+    }
+    assert!(context.system().stats().marking_in_progress);
+    // The removed value is only referenced by the (stale) slot of its entry
+    let extra = last_node(head).extra.get().unwrap();
+    assert_eq!(table.remove(extra).map(|v| v.val), Some(200));
+    while context.system().stats().marking_in_progress {
+        let roots = safepoint!(context, (head, table, cache));
+        head = roots.0;
+        table = roots.1;
+        cache = roots.2;
+    }
+    assert_eq!(context.system().stats().collections, 1);
+    assert!(cache.entry.is_cleared());
+    check_list(head, 16);
+    assert_eq!(table.len(), 1);
+    assert_eq!(table.get(last_node(head)).map(|v| v.val), Some(100));
+}
+
+#[test]
+fn forced_collection() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let head = alloc_list(&context, 8);
+    // Below the threshold, so this performs a full collection
+    let head: Gc<Node> = safepoint_force!(context, head);
+    let stats = context.system().stats();
+    assert!(!stats.marking_in_progress);
+    assert_eq!(stats.collections, 1);
+    assert_eq!(stats.incremental_steps, 0);
+    check_list(head, 8);
+}
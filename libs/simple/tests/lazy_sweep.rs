@@ -0,0 +1,100 @@
+// Lazy sweeping isn't supported by the Immix allocator
+#![cfg(not(feature = "immix"))]
+use std::cell::Cell;
+
+use slog::Logger;
+
+use zerogc::{safepoint, GcAllocError, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector};
+
+fn test_collector(config: GcConfig) -> SimpleCollector {
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace)]
+#[zerogc(collector_ids(SimpleCollectorId), unsafe_skip_drop)]
+struct Tracked {
+    val: usize,
+}
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        DROP_COUNT.with(|count| count.set(count.get() + 1));
+    }
+}
+thread_local! {
+    static DROP_COUNT: Cell<usize> = const { Cell::new(0) };
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Big {
+    values: [u64; 16],
+}
+
+#[test]
+fn sweeps_lazily() {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    config.lazy_sweep = true;
+    config.verify_heap = true;
+    let collector = test_collector(config);
+    let mut context = collector.into_context();
+    let kept = context.alloc(Tracked { val: 1 });
+    for val in 0..1000 {
+        context.alloc(Tracked { val });
+    }
+    let before = context.system().stats();
+    let kept: Gc<Tracked> = safepoint!(context, kept);
+    let after = context.system().stats();
+    // The garbage has been found, but not freed yet
+    assert_eq!(DROP_COUNT.with(Cell::get), 0);
+    assert_eq!(after.collections, 1);
+    assert_eq!(after.live_bytes, before.live_bytes);
+    assert!(after.unswept_bytes > 0);
+    assert_eq!(after.last_freed_bytes, after.unswept_bytes);
+    // Allocating sweeps (some of) the garbage to make room
+    let garbage = context.alloc(Tracked { val: 2 });
+    assert_eq!(garbage.val, 2);
+    let swept = DROP_COUNT.with(Cell::get);
+    assert!(swept > 0 && swept < 1000, "Swept {} objects", swept);
+    let stats = context.system().stats();
+    assert!(stats.unswept_bytes < after.unswept_bytes);
+    assert!(stats.live_bytes < after.live_bytes);
+    // The next collection finishes sweeping before marking
+    let kept: Gc<Tracked> = safepoint!(context, kept);
+    assert_eq!(DROP_COUNT.with(Cell::get), 1000);
+    assert_eq!(kept.val, 1);
+    let stats = context.system().stats();
+    assert_eq!(stats.collections, 2);
+    assert_eq!(stats.last_freed_bytes, stats.unswept_bytes);
+    assert!(stats.unswept_bytes > 0);
+}
+
+#[test]
+fn heap_limit() {
+    let mut config = GcConfig::default();
+    config.max_heap_size = Some(4096);
+    config.lazy_sweep = true;
+    let collector = test_collector(config);
+    let mut context = collector.into_context();
+    let mut last = None;
+    loop {
+        match context.try_alloc(Big { values: [7; 16] }) {
+            Ok(big) => last = Some(big),
+            Err(cause) => {
+                assert_eq!(cause, GcAllocError::HeapLimitExceeded);
+                break;
+            }
+        }
+    }
+    let last: Gc<Big> = safepoint!(context, last.unwrap());
+    assert!(context.system().stats().unswept_bytes > 0);
+    // Garbage that is waiting to be swept doesn't count against the limit
+    for _ in 0..8 {
+        let big = context.try_alloc(Big { values: [3; 16] }).unwrap();
+        assert_eq!(big.values, [3; 16]);
+    }
+    assert_eq!(last.values, [7; 16]);
+}
@@ -0,0 +1,97 @@
+#![feature(
+    arbitrary_self_types, // Required for the setters on Gc refs
+)]
+use slog::Logger;
+
+use zerogc::prelude::*;
+use zerogc_derive::Trace;
+
+use zerogc_simple::{
+    CollectorId as SimpleCollectorId, Gc, GcConfig, GcWeak, SimpleCollector, SimpleCollectorContext,
+};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    config.marking_threads = 4;
+    config.verify_heap = true; // Catch any objects that were missed
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace)]
+#[zerogc(collector_ids(SimpleCollectorId))]
+struct Tree<'gc> {
+    val: usize,
+    #[zerogc(mutable(public))]
+    children: GcCell<Option<(Gc<'gc, Tree<'gc>>, Gc<'gc, Tree<'gc>>)>>,
+    /// Shared between many nodes, so several threads race to mark it
+    shared: Gc<'gc, Leaf>,
+    weak: GcWeak<'gc, Leaf>,
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Leaf {
+    val: usize,
+}
+
+fn build_tree<'gc>(
+    context: &'gc SimpleCollectorContext,
+    depth: u32,
+    val: usize,
+    shared: Gc<'gc, Leaf>,
+) -> Gc<'gc, Tree<'gc>> {
+    let tree = context.alloc(Tree {
+        val,
+        children: GcCell::new(None),
+        shared,
+        weak: context.alloc(Leaf { val }).downgrade(),
+    });
+    if depth > 0 {
+        let left = build_tree(context, depth - 1, val * 2, shared);
+        let right = build_tree(context, depth - 1, val * 2 + 1, shared);
+        tree.set_children(Some((left, right)));
+    }
+    tree
+}
+
+fn check_tree(tree: &Tree, depth: u32, val: usize, shared: *const Leaf) -> usize {
+    assert_eq!(tree.val, val);
+    assert!(std::ptr::eq(&*tree.shared, shared));
+    assert!(tree.weak.is_cleared());
+    match tree.children.get() {
+        Some((left, right)) => {
+            assert!(depth > 0);
+            1 + check_tree(&left, depth - 1, val * 2, shared)
+                + check_tree(&right, depth - 1, val * 2 + 1, shared)
+        }
+        None => {
+            assert_eq!(depth, 0);
+            1
+        }
+    }
+}
+
+#[test]
+fn parallel_mark() {
+    const DEPTH: u32 = 12;
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let shared = context.alloc(Leaf { val: 42 });
+    let tree = build_tree(&context, DEPTH, 1, shared);
+    // Garbage, which must be freed
+    build_tree(&context, 4, 1, shared);
+    let before = context.system().stats();
+    let tree: Gc<Tree> = safepoint!(context, tree);
+    let after = context.system().stats();
+    assert_eq!(after.collections, 1);
+    assert!(after.last_freed_bytes > 0);
+    assert!(after.live_bytes < before.live_bytes);
+    let num_nodes = check_tree(&tree, DEPTH, 1, &*tree.shared);
+    assert_eq!(num_nodes, (1 << (DEPTH + 1)) - 1);
+    assert_eq!(tree.shared.val, 42);
+    // Everything should survive a second collection too
+    let tree: Gc<Tree> = safepoint!(context, tree);
+    assert_eq!(check_tree(&tree, DEPTH, 1, &*tree.shared), num_nodes);
+    assert_eq!(context.system().stats().last_freed_bytes, 0);
+}
@@ -0,0 +1,58 @@
+use std::sync::mpsc;
+
+use slog::Logger;
+
+use zerogc::prelude::*;
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, GcConfig, GcWeak, SimpleCollector};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug, Eq, PartialEq)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[test]
+fn pinned_survives() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let obj = context.alloc(Dummy { val: 42 });
+    let pinned = obj.pin();
+    let address = pinned.as_raw_ptr();
+    assert_eq!(address, obj.value() as *const Dummy as *mut Dummy);
+    // Nothing else references the object, but the pin keeps it alive
+    let weak: GcWeak<Dummy> = safepoint!(context, obj.downgrade());
+    assert_eq!(weak.upgrade().map(|obj| obj.val), Some(42));
+    assert_eq!(pinned.as_raw_ptr(), address);
+    assert_eq!(unsafe { (*address).val }, 42);
+    // Once the pin is released, it is collected like normal
+    drop(pinned);
+    let weak: GcWeak<Dummy> = safepoint!(context, weak);
+    assert!(weak.is_cleared());
+}
+
+#[test]
+fn pinned_array() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let buffer = context.alloc_slice_copy(b"foreign buffer");
+    let pinned = buffer.pin();
+    let (sender, receiver) = mpsc::channel::<()>();
+    // Simulate foreign code that asynchronously reads the buffer
+    let reader = std::thread::spawn(move || {
+        receiver.recv().unwrap();
+        let bytes = pinned.as_raw_ptr();
+        assert_eq!(unsafe { &*bytes }, b"foreign buffer");
+    });
+    safepoint!(context, ());
+    safepoint!(context, ());
+    sender.send(()).unwrap();
+    reader.join().unwrap();
+}
@@ -0,0 +1,98 @@
+use slog::Logger;
+
+use zerogc::{safepoint, safepoint_force, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{
+    CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector, SimpleCollectorContext,
+};
+
+fn test_collector(retained_arena_bytes: Option<usize>, lazy_sweep: bool) -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.initial_threshold = usize::MAX; // Only collect when forced
+    config.retained_arena_bytes = retained_arena_bytes;
+    config.lazy_sweep = lazy_sweep;
+    config.verify_heap = true; // Catch any corruption of the free lists
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Node<'gc> {
+    val: usize,
+    next: Option<Gc<'gc, Node<'gc>>>,
+}
+
+fn alloc_list<'gc>(context: &'gc SimpleCollectorContext, len: usize) -> Gc<'gc, Node<'gc>> {
+    let mut head = context.alloc(Node { val: 0, next: None });
+    for val in 1..len {
+        head = context.alloc(Node {
+            val,
+            next: Some(head),
+        });
+    }
+    head
+}
+
+fn check_list(head: Gc<Node>, len: usize) {
+    let mut node = Some(head);
+    for expected in (0..len).rev() {
+        assert_eq!(node.unwrap().val, expected);
+        node = node.unwrap().next;
+    }
+    assert!(node.is_none());
+}
+
+#[test]
+fn releases_free_chunks() {
+    let collector = test_collector(Some(0), false);
+    let mut context = collector.into_context();
+    let live = alloc_list(&context, 100);
+    // A burst of garbage
+    alloc_list(&context, 100_000);
+    let live: Gc<Node> = safepoint!(context, live);
+    let before = context.system().stats();
+    assert!(before.arena_bytes >= 100_000 * 32);
+    let live: Gc<Node> = safepoint_force!(context, live);
+    let after = context.system().stats();
+    assert!(
+        after.arena_bytes < before.arena_bytes / 2,
+        "Only released {} of {} bytes",
+        before.arena_bytes - after.arena_bytes,
+        before.arena_bytes
+    );
+    check_list(live, 100);
+    // The arenas can grow again afterwards
+    let more = alloc_list(&context, 1000);
+    let (live, more): (Gc<Node>, Gc<Node>) = safepoint_force!(context, (live, more));
+    check_list(live, 100);
+    check_list(more, 1000);
+}
+
+#[test]
+fn retains_free_chunks() {
+    let collector = test_collector(None, false);
+    let mut context = collector.into_context();
+    alloc_list(&context, 100_000);
+    safepoint!(context, ());
+    let before = context.system().stats();
+    safepoint_force!(context, ());
+    let after = context.system().stats();
+    assert!(after.last_freed_bytes > 0);
+    assert_eq!(after.arena_bytes, before.arena_bytes);
+}
+
+#[test]
+#[cfg_attr(feature = "immix", ignore)]
+fn released_after_lazy_sweep() {
+    let collector = test_collector(Some(0), true);
+    let mut context = collector.into_context();
+    alloc_list(&context, 100_000);
+    safepoint!(context, ());
+    let before = context.system().stats();
+    safepoint_force!(context, ());
+    // Nothing can be released until the garbage has been swept
+    assert_eq!(context.system().stats().arena_bytes, before.arena_bytes);
+    safepoint_force!(context, ());
+    assert!(context.system().stats().arena_bytes < before.arena_bytes / 2);
+}
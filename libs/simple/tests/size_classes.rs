@@ -0,0 +1,89 @@
+use slog::Logger;
+
+use zerogc::{safepoint, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, GcStats, SimpleCollector};
+
+fn test_collector(config: GcConfig) -> SimpleCollector {
+    let mut config = config;
+    config.initial_threshold = usize::MAX; // Only collect when forced
+    config.verify_heap = true; // Catch any objects the collector doesn't know about
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+/// An object that's about 300 bytes (including its header)
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Medium<'gc> {
+    data: [u64; 34],
+    next: Option<Gc<'gc, Medium<'gc>>>,
+}
+
+fn alloc_medium(config: GcConfig) -> GcStats {
+    let mut context = test_collector(config).into_context();
+    let mut list: Option<Gc<Medium>> = None;
+    for val in 0..100 {
+        list = Some(context.alloc(Medium {
+            data: [val; 34],
+            next: list,
+        }));
+    }
+    let list: Option<Gc<Medium>> = safepoint!(context, list);
+    let mut node = list;
+    for val in (0..100).rev() {
+        assert_eq!(node.unwrap().data, [val; 34]);
+        node = node.unwrap().next;
+    }
+    assert!(node.is_none());
+    context.system().stats()
+}
+
+#[test]
+fn default_size_classes() {
+    let sizes = GcConfig::default().size_classes;
+    let word_size = std::mem::size_of::<usize>();
+    let words: Vec<usize> = sizes.iter().map(|size| size / word_size).collect();
+    assert_eq!(words, [2, 3, 4, 5, 6, 7, 8, 10, 12, 14, 16, 20, 24, 28, 32]);
+    // No more than 25% of a slot is ever wasted
+    for window in sizes.windows(2) {
+        let smallest_fit = window[0] + word_size;
+        assert!((window[1] - smallest_fit) * 4 <= window[1]);
+    }
+}
+
+#[test]
+#[cfg_attr(feature = "immix", ignore)]
+fn big_objects_by_default() {
+    let stats = alloc_medium(GcConfig::default());
+    assert!(stats.big_object_bytes >= 100 * std::mem::size_of::<Medium>());
+}
+
+#[test]
+#[cfg_attr(not(feature = "small-object-arenas"), ignore)]
+fn larger_small_objects() {
+    let mut config = GcConfig::default();
+    config.size_classes = GcConfig::size_classes_up_to(512);
+    assert_eq!(config.size_classes.last(), Some(&512));
+    let stats = alloc_medium(config);
+    assert_eq!(stats.big_object_bytes, 0);
+    assert!(stats.small_object_bytes >= 100 * std::mem::size_of::<Medium>());
+}
+
+#[test]
+#[cfg_attr(not(feature = "small-object-arenas"), ignore)]
+fn custom_size_classes() {
+    let mut config = GcConfig::default();
+    config.size_classes = vec![16, 40, 304];
+    config.arena_chunk_size = 4096;
+    assert_eq!(alloc_medium(config).big_object_bytes, 0);
+}
+
+#[test]
+#[cfg_attr(not(feature = "small-object-arenas"), ignore)]
+#[should_panic(expected = "Size classes must be sorted")]
+fn unsorted_size_classes() {
+    let mut config = GcConfig::default();
+    config.size_classes = vec![40, 16];
+    test_collector(config);
+}
@@ -0,0 +1,66 @@
+use slog::Logger;
+
+use zerogc::{safepoint, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::snapshot::{HeapSnapshot, ObjectKind};
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcArray, SimpleCollector};
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Node<'gc> {
+    value: usize,
+    next: Option<Gc<'gc, Node<'gc>>>,
+    data: GcArray<'gc, u8>,
+}
+
+#[test]
+fn snapshot_edges() {
+    let collector = SimpleCollector::with_logger(Logger::root(::slog::Discard, ::slog::o!()));
+    let mut context = collector.into_context();
+    let data = context.alloc_slice_copy(b"hello");
+    let first = context.alloc(Node {
+        value: 1,
+        next: None,
+        data,
+    });
+    let second = context.alloc(Node {
+        value: 2,
+        next: Some(first),
+        data,
+    });
+    let second: Gc<Node> = safepoint!(context, second);
+    let snapshot = unsafe { HeapSnapshot::capture(context.system()) };
+    let find = |address: usize| {
+        snapshot
+            .objects
+            .iter()
+            .find(|obj| obj.address == address)
+            .unwrap()
+    };
+    let second_obj = find(second.value() as *const Node as usize);
+    assert_eq!(second_obj.kind, ObjectKind::Fixed);
+    assert!(second_obj.type_name.contains("Node"));
+    assert_eq!(
+        second_obj.edges,
+        vec![
+            second.next.unwrap().value() as *const Node as usize,
+            second.data.as_raw_ptr() as usize
+        ]
+    );
+    let data_obj = find(second.data.as_raw_ptr() as usize);
+    assert_eq!(data_obj.kind, ObjectKind::Array);
+    assert_eq!(data_obj.type_name, "[u8]");
+    assert!(data_obj.edges.is_empty());
+    assert!(snapshot.total_size() >= second_obj.size + data_obj.size);
+
+    let mut json = Vec::new();
+    snapshot.write_json(&mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.starts_with("{\"objects\": ["));
+    assert_eq!(json.lines().count(), snapshot.objects.len() + 2);
+    assert!(json.contains(&format!(
+        "{{\"address\": {}, \"size\": {}, \"kind\": \"array\", \"type\": \"[u8]\", \"edges\": []}}",
+        data_obj.address, data_obj.size
+    )));
+}
@@ -0,0 +1,57 @@
+use slog::Logger;
+
+use zerogc::{safepoint, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[test]
+fn collection_stats() {
+    let collector = test_collector();
+    let initial = collector.stats();
+    assert_eq!(initial.collections, 0);
+    assert_eq!(initial.live_bytes, 0);
+    let mut context = collector.into_context();
+    let kept = context.alloc(Dummy { val: 1 });
+    context.alloc(Dummy { val: 2 });
+    context.alloc_slice_copy(&[0u8; 4096]);
+    let stats = context.system().stats();
+    if !cfg!(feature = "immix") {
+        assert!(stats.big_object_bytes >= 4096);
+    }
+    if cfg!(feature = "small-object-arenas") {
+        assert!(stats.small_object_bytes > 0);
+    }
+    assert_eq!(
+        stats.live_bytes,
+        stats.small_object_bytes + stats.big_object_bytes
+    );
+    let kept: Gc<Dummy> = safepoint!(context, kept);
+    assert_eq!(kept.val, 1);
+    let after = context.system().stats();
+    assert_eq!(after.collections, 1);
+    if cfg!(feature = "small-object-arenas") {
+        assert_eq!(after.big_object_bytes, 0);
+    }
+    assert!(after.live_bytes > 0);
+    assert_eq!(after.last_freed_bytes, stats.live_bytes - after.live_bytes);
+    assert_eq!(after.total_freed_bytes, after.last_freed_bytes as u64);
+    assert_eq!(after.total_pause, after.last_pause);
+    assert!(after.last_num_roots > 0);
+    assert_eq!(after.num_handles, 0);
+    let handle = kept.create_handle();
+    assert_eq!(context.system().stats().num_handles, 1);
+    drop(handle);
+}
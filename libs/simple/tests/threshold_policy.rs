@@ -0,0 +1,145 @@
+use slog::Logger;
+
+use zerogc::{safepoint, safepoint_force, GcContext, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{
+    CollectorId as SimpleCollectorId, FinishedCollection, Gc, GcConfig, HeapGrowth,
+    SimpleCollector, ThresholdPolicy,
+};
+
+const MB: usize = 1024 * 1024;
+
+fn finished(live_bytes: usize, gc_cpu_fraction: f64) -> FinishedCollection {
+    FinishedCollection {
+        live_bytes,
+        gc_cpu_fraction,
+    }
+}
+
+#[test]
+fn heap_growth() {
+    let policy = HeapGrowth {
+        target_gc_cpu_fraction: None,
+        ..HeapGrowth::default()
+    };
+    assert_eq!(policy.next_threshold(&finished(16 * MB, 0.0)), 32 * MB);
+    // Small heaps are never collected too often
+    assert_eq!(policy.next_threshold(&finished(0, 0.0)), 4 * MB);
+    assert_eq!(policy.next_threshold(&finished(MB, 0.0)), 4 * MB);
+    let policy = HeapGrowth {
+        growth_factor: 0.5,
+        ..policy
+    };
+    assert_eq!(policy.next_threshold(&finished(16 * MB, 0.0)), 24 * MB);
+}
+
+#[test]
+fn max_heap_size() {
+    let policy = HeapGrowth {
+        max_heap_size: Some(40 * MB),
+        target_gc_cpu_fraction: None,
+        ..HeapGrowth::default()
+    };
+    assert_eq!(policy.next_threshold(&finished(16 * MB, 0.0)), 32 * MB);
+    assert_eq!(policy.next_threshold(&finished(32 * MB, 0.0)), 40 * MB);
+    // Past the limit, the heap is still given a little room to grow
+    assert_eq!(policy.next_threshold(&finished(64 * MB, 0.0)), 68 * MB);
+}
+
+#[test]
+fn target_gc_cpu_fraction() {
+    let policy = HeapGrowth {
+        target_gc_cpu_fraction: Some(0.1),
+        ..HeapGrowth::default()
+    };
+    // Cheap collections use the plain growth factor
+    assert_eq!(policy.next_threshold(&finished(16 * MB, 0.01)), 32 * MB);
+    // Expensive collections make the heap grow faster
+    assert_eq!(policy.next_threshold(&finished(16 * MB, 0.2)), 48 * MB);
+    assert_eq!(policy.next_threshold(&finished(16 * MB, 1.0)), 144 * MB);
+}
+
+struct FixedThreshold(usize);
+impl ThresholdPolicy for FixedThreshold {
+    fn next_threshold(&self, _collection: &FinishedCollection) -> usize {
+        self.0
+    }
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Node<'gc> {
+    val: usize,
+    next: Option<Gc<'gc, Node<'gc>>>,
+}
+
+#[test]
+fn custom_policy() {
+    let mut config = GcConfig::default();
+    config.initial_threshold = usize::MAX;
+    config.threshold_policy = Box::new(FixedThreshold(1024));
+    let collector =
+        SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()));
+    let mut context = collector.into_context();
+    safepoint_force!(context, ());
+    let stats = context.system().stats();
+    assert_eq!(stats.collections, 1);
+    assert_eq!(stats.threshold, 1024);
+    assert!((0.0..=1.0).contains(&stats.gc_cpu_fraction));
+    // Past the new threshold, so the next safepoint collects
+    let mut list: Option<Gc<Node>> = None;
+    for val in 0..100 {
+        list = Some(context.alloc(Node { val, next: list }));
+    }
+    let list: Option<Gc<Node>> = safepoint!(context, list);
+    assert_eq!(context.system().stats().collections, 2);
+    assert_eq!(list.unwrap().val, 99);
+}
+
+#[test]
+fn default_threshold() {
+    let collector = SimpleCollector::with_config(
+        GcConfig::default(),
+        Logger::root(::slog::Discard, ::slog::o!()),
+    );
+    let mut context = collector.into_context();
+    // Startup allocations don't trigger any collections
+    let mut list: Option<Gc<Node>> = None;
+    for val in 0..1000 {
+        list = Some(context.alloc(Node { val, next: list }));
+        list = safepoint!(context, list);
+    }
+    let stats = context.system().stats();
+    assert_eq!(stats.collections, 0);
+    assert_eq!(stats.threshold, HeapGrowth::default().min_heap_size);
+    safepoint_force!(context, list);
+    assert_eq!(
+        context.system().stats().threshold,
+        HeapGrowth::default().min_heap_size
+    );
+}
+
+#[test]
+fn eager_preset() {
+    let policy = HeapGrowth::EAGER;
+    assert_eq!(policy.next_threshold(&finished(16 * MB, 0.0)), 24 * MB);
+    // Neither small heaps nor expensive collections change anything
+    assert_eq!(policy.next_threshold(&finished(0, 0.0)), 0);
+    assert_eq!(policy.next_threshold(&finished(16 * MB, 1.0)), 24 * MB);
+    let collector = SimpleCollector::with_config(
+        GcConfig::eager(),
+        Logger::root(::slog::Discard, ::slog::o!()),
+    );
+    let mut context = collector.into_context();
+    assert_eq!(context.system().stats().threshold, 2048);
+    // The same startup allocations collect over and over again
+    let mut list: Option<Gc<Node>> = None;
+    for val in 0..1000 {
+        list = Some(context.alloc(Node { val, next: list }));
+        list = safepoint!(context, list);
+    }
+    let stats = context.system().stats();
+    assert!(stats.collections > 5);
+    assert!(stats.threshold < stats.live_bytes * 2);
+}
@@ -1,6 +1,6 @@
 use core::cell::Cell;
 
-use zerogc::{safepoint, trait_object_trace, DynTrace, GcSimpleAlloc, Trace};
+use zerogc::{dyn_trace, safepoint, trait_object_trace, DynTrace, GcSimpleAlloc, Trace};
 
 use slog::Logger;
 use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector};
@@ -85,3 +85,46 @@ fn foo_bar() {
         "Expected Bar to be dropped"
     );
 }
+
+#[dyn_trace(collector_id = SimpleCollectorId)]
+trait Node<'gc> {
+    fn eval(&self) -> i32;
+}
+#[derive(Trace)]
+#[zerogc(collector_ids(SimpleCollectorId))]
+struct Literal {
+    val: i32,
+}
+impl<'gc> Node<'gc> for Literal {
+    fn eval(&self) -> i32 {
+        self.val
+    }
+}
+#[derive(Trace)]
+#[zerogc(collector_ids(SimpleCollectorId))]
+struct Add<'gc> {
+    left: Gc<'gc, dyn Node<'gc> + 'gc>,
+    right: Gc<'gc, dyn Node<'gc> + 'gc>,
+}
+impl<'gc> Node<'gc> for Add<'gc> {
+    fn eval(&self) -> i32 {
+        self.left.eval() + self.right.eval()
+    }
+}
+
+#[test]
+fn alloc_dyn() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let left = context.alloc_dyn::<_, dyn Node<'_> + '_>(Literal { val: 1 });
+    let right = context.alloc_dyn::<_, dyn Node<'_> + '_>(Add {
+        left: context.alloc_dyn(Literal { val: 2 }),
+        right: context.alloc_dyn(Literal { val: 3 }),
+    });
+    let root: Gc<dyn Node<'_> + '_> = context.alloc_dyn(Add { left, right });
+    assert_eq!(root.eval(), 6);
+    // The children should be traced through the trait object
+    let root = safepoint!(context, root);
+    let _garbage = context.alloc(Literal { val: 4 });
+    assert_eq!(root.eval(), 6);
+}
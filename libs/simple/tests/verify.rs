@@ -0,0 +1,95 @@
+use std::panic::AssertUnwindSafe;
+
+use slog::Logger;
+
+use zerogc::{safepoint, GcSimpleAlloc};
+use zerogc_derive::Trace;
+
+use zerogc_simple::{
+    CollectorId as SimpleCollectorId, Gc, GcArray, GcConfig, GcWeak, SimpleCollector,
+};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    config.verify_heap = true;
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Node<'gc> {
+    value: usize,
+    next: Option<Gc<'gc, Node<'gc>>>,
+    children: GcArray<'gc, Gc<'gc, Node<'gc>>>,
+    weak: Option<GcWeak<'gc, Node<'gc>>>,
+}
+
+/// A node whose `Trace` implementation "forgets" about its child
+#[derive(Trace)]
+#[zerogc(collector_ids(SimpleCollectorId))]
+struct BrokenNode<'gc> {
+    #[zerogc(unsafe_skip_trace)]
+    child: Gc<'gc, Node<'gc>>,
+}
+
+fn leaf<'gc>(
+    context: &'gc zerogc_simple::SimpleCollectorContext,
+    value: usize,
+) -> Gc<'gc, Node<'gc>> {
+    let leaf = context.alloc(Node {
+        value,
+        next: None,
+        children: context.alloc_slice_copy(&[]),
+        weak: None,
+    });
+    leaf
+}
+
+#[test]
+fn verify_valid_heap() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let mut head = leaf(&context, 0);
+    for value in 1..100 {
+        let children = [leaf(&context, value), head];
+        // Garbage, which is freed immediately
+        leaf(&context, value);
+        head = context.alloc(Node {
+            value,
+            next: Some(head),
+            children: context.alloc_slice_copy(&children),
+            weak: Some(children[0].downgrade()),
+        });
+        if value % 10 == 0 {
+            head = safepoint!(context, head);
+        }
+    }
+    let head: Gc<Node> = safepoint!(context, head);
+    assert_eq!(head.value, 99);
+    assert_eq!(head.children.len(), 2);
+}
+
+#[test]
+fn verify_broken_trace() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let err = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        let child = leaf(&context, 1);
+        let broken = context.alloc(BrokenNode { child });
+        // The child is incorrectly freed here
+        let broken: Gc<BrokenNode> = safepoint!(context, broken);
+        // Rooting the dangling pointer must be caught before marking follows it
+        let child = broken.child;
+        safepoint!(context, child);
+    }))
+    .unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert!(
+        msg.starts_with("Heap verification failed: Root #0 references"),
+        "{}",
+        msg
+    );
+    // The collection never finished, so the context can't be dropped
+    std::mem::forget(context);
+}
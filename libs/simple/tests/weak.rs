@@ -0,0 +1,64 @@
+use slog::Logger;
+
+use zerogc::safepoint;
+use zerogc::GcSimpleAlloc;
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, GcWeak, SimpleCollector};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.always_force_collect = true; // Force collections for predictability
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Cache<'gc> {
+    name: usize,
+    entry: GcWeak<'gc, Dummy>,
+}
+
+#[derive(Trace, Copy, Clone, Debug, Eq, PartialEq)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[test]
+fn weak_cleared() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let target = context.alloc(Dummy { val: 42 });
+    let cache = context.alloc(Cache {
+        name: 1,
+        entry: target.downgrade(),
+    });
+    // Both are still reachable, so the weak reference is preserved
+    let (cache, target): (Gc<Cache>, Gc<Dummy>) = safepoint!(context, (cache, target));
+    assert_eq!(cache.entry.upgrade().map(|gc| gc.val), Some(42));
+    assert_eq!(
+        cache.entry.upgrade().unwrap().value() as *const _,
+        target.value() as *const _
+    );
+    // Only the cache is reachable, so the weak reference must be cleared
+    let cache: Gc<Cache> = safepoint!(context, cache);
+    assert_eq!(cache.name, 1);
+    assert!(cache.entry.is_cleared());
+    assert!(cache.entry.upgrade().is_none());
+}
+
+#[test]
+fn weak_roots() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let first = context.alloc(Dummy { val: 1 });
+    let second = context.alloc(Dummy { val: 2 });
+    // Weak references can be roots themselves
+    let (weak_first, weak_second, second): (GcWeak<Dummy>, GcWeak<Dummy>, Gc<Dummy>) =
+        safepoint!(context, (first.downgrade(), second.downgrade(), second));
+    assert!(weak_first.is_cleared());
+    assert_eq!(weak_second.upgrade(), Some(second));
+    let weak_second: GcWeak<Dummy> = safepoint!(context, weak_second);
+    assert!(weak_second.upgrade().is_none());
+}
@@ -0,0 +1,71 @@
+use slog::Logger;
+
+use zerogc::prelude::*;
+use zerogc::safepoint_force;
+use zerogc_derive::Trace;
+
+use zerogc_simple::{CollectorId as SimpleCollectorId, Gc, GcConfig, SimpleCollector};
+
+fn test_collector() -> SimpleCollector {
+    let mut config = GcConfig::default();
+    config.initial_threshold = usize::MAX; // Only collect when forced
+    config.verify_heap = true;
+    SimpleCollector::with_config(config, Logger::root(::slog::Discard, ::slog::o!()))
+}
+
+#[derive(Trace, Copy, Clone, Debug)]
+#[zerogc(copy, collector_ids(SimpleCollectorId))]
+struct Dummy {
+    val: usize,
+}
+
+#[test]
+fn cleared_when_unreachable() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let weak = context.alloc(Dummy { val: 42 }).create_weak_handle();
+    assert!(!weak.is_cleared());
+    assert_eq!(weak.bind_to(&context).unwrap().val, 42);
+    safepoint_force!(context, ());
+    assert!(weak.is_cleared());
+    assert!(weak.bind_to(&context).is_none());
+    assert_eq!(weak.use_critical(|value| value.val), None);
+    // The slot is only freed once the handle is dropped
+    assert_eq!(context.system().stats().num_handles, 1);
+    drop(weak);
+    assert_eq!(context.system().stats().num_handles, 0);
+}
+
+#[test]
+fn kept_by_other_references() {
+    let collector = test_collector();
+    let mut context = collector.into_context();
+    let value = context.alloc(Dummy { val: 1 });
+    let weak = value.create_weak_handle();
+    let value: Gc<Dummy> = safepoint_force!(context, value);
+    assert_eq!(value.val, 1);
+    assert_eq!(weak.bind_to(&context).unwrap().val, 1);
+    // A strong handle is enough to keep it alive
+    let strong = value.create_handle();
+    safepoint_force!(context, ());
+    assert_eq!(weak.clone().bind_to(&context).unwrap().val, 1);
+    drop(strong);
+    safepoint_force!(context, ());
+    assert!(weak.is_cleared());
+}
+
+#[test]
+#[cfg(feature = "sync")]
+fn used_from_other_thread() {
+    let collector = test_collector();
+    let context = collector.create_context();
+    let value = context.alloc(Dummy { val: 7 });
+    let weak = value.create_weak_handle();
+    let weak = std::thread::spawn(move || {
+        assert_eq!(weak.use_critical(|value| value.val), Some(7));
+        weak
+    })
+    .join()
+    .unwrap();
+    assert_eq!(weak.bind_to(&context).unwrap().val, 7);
+}
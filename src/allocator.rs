@@ -29,21 +29,22 @@ unsafe impl<'gc, C: GcSimpleAlloc> Allocator for GcAllocWrapper<'gc, C> {
     fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
         unsafe {
             let ptr: *mut u8 = match layout.align() {
-                1 => self.0.alloc_uninit_slice::<u8>(layout.size()),
+                1 => self.0.try_alloc_uninit_slice::<u8>(layout.size()),
                 2 => self
                     .0
-                    .alloc_uninit_slice::<u16>((layout.size() + 1) / 2)
-                    .cast(),
+                    .try_alloc_uninit_slice::<u16>((layout.size() + 1) / 2)
+                    .map(|ptr| ptr.cast()),
                 4 => self
                     .0
-                    .alloc_uninit_slice::<u32>((layout.size() + 3) / 4)
-                    .cast(),
+                    .try_alloc_uninit_slice::<u32>((layout.size() + 3) / 4)
+                    .map(|ptr| ptr.cast()),
                 8 => self
                     .0
-                    .alloc_uninit_slice::<u64>((layout.size() + 7) / 8)
-                    .cast(),
+                    .try_alloc_uninit_slice::<u64>((layout.size() + 7) / 8)
+                    .map(|ptr| ptr.cast()),
                 _ => return Err(AllocError),
-            };
+            }
+            .map_err(|_| AllocError)?;
             Ok(NonNull::new_unchecked(core::ptr::slice_from_raw_parts_mut(
                 ptr,
                 layout.size(),
@@ -8,7 +8,7 @@ use core::ptr::NonNull;
 use core::slice::SliceIndex;
 use core::str;
 
-use crate::{CollectorId, Gc, GcRebrand, GcSafe};
+use crate::{CollectorId, Gc, GcRebrand, GcSafe, PinCollectorId};
 use zerogc_derive::{unsafe_gc_impl, Trace};
 
 use self::repr::GcArrayPtr;
@@ -146,6 +146,24 @@ impl<'gc, T, Id: CollectorId> GcArray<'gc, T, Id> {
         }
     }
 }
+impl<'gc, T: GcSafe<'gc, Id>, Id: CollectorId> GcArray<'gc, T, Id> {
+    /// Pin this array in place,
+    /// guaranteeing it won't be moved or freed for as long as the pin is held.
+    ///
+    /// This allows the array's memory to be passed to foreign code
+    /// without copying it out first.
+    ///
+    /// See [Gc::pin] for details.
+    #[inline]
+    pub fn pin(&self) -> Id::Pinned<[T::Branded]>
+    where
+        Id: PinCollectorId,
+        T: GcRebrand<'static, Id>,
+        T::Branded: Sized,
+    {
+        Id::pin_array(*self)
+    }
+}
 /// If the underlying type is `Sync`, it's safe
 /// to share garbage collected references between threads.
 ///
@@ -12,7 +12,10 @@ mod alloc;
 mod handle;
 mod layout;
 
-use crate::{CollectorId, GcContext, GcSafe, GcSimpleAlloc, GcSystem, Trace};
+use crate::{
+    CollectorId, GcContext, GcFinalize, GcFinalized, GcRebrand, GcSafe, GcSimpleAlloc, GcSystem,
+    NonMovingCollectorId, Trace,
+};
 use std::alloc::Layout;
 use std::cell::{Cell, OnceCell};
 use std::ptr::NonNull;
@@ -102,6 +105,12 @@ pub fn leaked<'gc, T: GcSafe<'gc, EpsilonCollectorId> + 'static>(value: T) -> Gc
 ///
 /// **WARNING**: This never actually collects any garbage
 pub type Gc<'gc, T> = crate::Gc<'gc, T, EpsilonCollectorId>;
+/// A [weak reference](`crate::GcWeak`)
+/// that uses the [epsilon collector](EpsilonSystem)
+///
+/// **WARNING**: This never actually collects any garbage,
+/// so weak references are never cleared.
+pub type GcWeak<'gc, T> = crate::GcWeak<'gc, T, EpsilonCollectorId>;
 /// A [garbage collected array](`crate::array::GcArray`)
 /// that uses the [epsilon collector](EpsilonSystem)
 ///
@@ -134,6 +143,11 @@ unsafe impl GcContext for EpsilonContext {
         // safepoints are a nop in our system
     }
 
+    #[inline]
+    unsafe fn unchecked_force_safepoint<T: Trace>(&self, _value: &mut &mut T) {
+        // we never collect, even if asked to
+    }
+
     unsafe fn freeze(&mut self) {
         unimplemented!()
     }
@@ -202,7 +216,7 @@ impl Drop for State {
             while let Some(header) = ptr {
                 let header_layout = layout::EpsilonHeader::LAYOUT;
                 let desired_align = header.as_ref().type_info.layout.align();
-                let padding = header_layout.padding_needed_for(desired_align);
+                let padding = header_layout.padding_needed_for(core::ptr::Alignment::new(desired_align).unwrap());
                 let value_ptr = (header.as_ptr() as *const u8)
                     .add(header_layout.size())
                     .add(padding);
@@ -395,6 +409,25 @@ unsafe impl GcSimpleAlloc for EpsilonContext {
         }
     }
 }
+/// The epsilon collector never collects anything,
+/// so nothing will ever be finalized.
+unsafe impl GcFinalize for EpsilonContext {
+    type DrainFinalized<'gc> = core::iter::Empty<GcFinalized<'gc, EpsilonCollectorId>>;
+
+    #[inline]
+    fn register_finalizer<'gc, T>(&'gc self, _gc: Gc<'gc, T>)
+    where
+        T: GcSafe<'gc, EpsilonCollectorId> + GcRebrand<'static, EpsilonCollectorId>,
+        <T as GcRebrand<'static, EpsilonCollectorId>>::Branded: 'static,
+    {
+        // Nothing is ever collected
+    }
+
+    #[inline]
+    fn drain_finalized<'gc>(&'gc self) -> Self::DrainFinalized<'gc> {
+        core::iter::empty()
+    }
+}
 
 /// The id for an [EpsilonSystem]
 ///
@@ -458,6 +491,9 @@ unsafe impl CollectorId for EpsilonCollectorId {
     }
 }
 
+/// Objects are never freed, let alone moved
+unsafe impl NonMovingCollectorId for EpsilonCollectorId {}
+
 #[cfg(test)]
 mod test {
     use super::*;
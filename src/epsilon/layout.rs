@@ -282,7 +282,7 @@ unsafe impl<'gc, T: GcSafe<'gc, EpsilonCollectorId>> IGcVec<'gc, T> for EpsilonR
     #[inline]
     pub unsafe fn as_ptr(&self) -> *const T {
         const LAYOUT: Layout = Layout::new::<EpsilonVecHeader>();
-        let offset = LAYOUT.size() + LAYOUT.padding_needed_for(core::mem::align_of::<T>());
+        let offset = LAYOUT.size() + LAYOUT.padding_needed_for(core::ptr::Alignment::of::<T>());
         (self.header() as *const u8).add(offset) as *const T
     }
 
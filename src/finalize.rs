@@ -0,0 +1,144 @@
+//! Post-mortem finalization of garbage collected objects.
+//!
+//! Objects can be registered with a [GcFinalize] context.
+//! Once a registered object becomes unreachable,
+//! the collector keeps it alive for one more cycle
+//! and hands it back to the mutator through [GcFinalize::drain_finalized].
+//!
+//! This is "post-mortem" finalization, so the object is never
+//! resurrected behind the program's back.
+//! Finalization is entirely up to the mutator,
+//! which is free to do whatever cleanup is needed (like closing file handles).
+//!
+//! Just like Java's weak references, weak references to an object
+//! are cleared as soon as it becomes unreachable, *before* it's queued for finalization.
+//! So a [GcWeak](crate::GcWeak) can never be upgraded to an object that's waiting to be finalized
+//! (unless the weak reference is itself only reachable through a finalized object).
+use core::any::TypeId;
+use core::fmt::{self, Debug, Formatter};
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use crate::{CollectorId, Gc, GcContext, GcRebrand, GcSafe};
+
+/// A [GcContext] that supports post-mortem finalization.
+///
+/// Some garbage collectors (like the epsilon collector)
+/// never collect anything, so implementing this is optional.
+///
+/// ## Safety
+/// Objects handed back by [GcFinalize::drain_finalized]
+/// must still be valid for the `'gc` lifetime of the context.
+pub unsafe trait GcFinalize: GcContext {
+    /// An iterator over the objects that have been finalized
+    type DrainFinalized<'gc>: Iterator<Item = GcFinalized<'gc, Self::Id>>
+    where
+        Self: 'gc;
+    /// Register the specified object for finalization.
+    ///
+    /// Once the object becomes unreachable, it will be kept alive for one more cycle
+    /// and queued for [draining](GcFinalize::drain_finalized).
+    ///
+    /// Each registration is finalized (at most) once.
+    /// If the object needs to be finalized again,
+    /// it needs to be explicitly re-registered.
+    fn register_finalizer<'gc, T>(&'gc self, gc: Gc<'gc, T, Self::Id>)
+    where
+        T: GcSafe<'gc, Self::Id> + GcRebrand<'static, Self::Id>,
+        <T as GcRebrand<'static, Self::Id>>::Branded: 'static;
+    /// Take all the objects that have been found unreachable since the last call.
+    ///
+    /// Once drained, the objects are no longer kept alive by the collector.
+    /// They must be rooted like any other object in order to survive the next safepoint.
+    fn drain_finalized<'gc>(&'gc self) -> Self::DrainFinalized<'gc>;
+}
+
+/// A type-erased object that has been queued for finalization.
+///
+/// Use [GcFinalized::downcast] to recover the original [Gc] pointer.
+pub struct GcFinalized<'gc, Id: CollectorId> {
+    value: NonNull<()>,
+    type_id: TypeId,
+    type_name: &'static str,
+    marker: PhantomData<Gc<'gc, (), Id>>,
+}
+impl<'gc, Id: CollectorId> GcFinalized<'gc, Id> {
+    /// Erase the type of the specified garbage collected pointer
+    #[inline]
+    pub fn new<T>(gc: Gc<'gc, T, Id>) -> Self
+    where
+        T: GcSafe<'gc, Id> + GcRebrand<'static, Id>,
+        <T as GcRebrand<'static, Id>>::Branded: 'static,
+    {
+        GcFinalized {
+            value: unsafe { NonNull::new_unchecked(gc.as_raw_ptr() as *mut ()) },
+            type_id: TypeId::of::<<T as GcRebrand<'static, Id>>::Branded>(),
+            type_name: core::any::type_name::<T>(),
+            marker: PhantomData,
+        }
+    }
+    /// Change the lifetime of this object.
+    ///
+    /// Collectors use this to store the object in between collections.
+    ///
+    /// ## Safety
+    /// The object must actually be valid for the new lifetime.
+    #[inline]
+    pub unsafe fn rebrand<'new_gc>(self) -> GcFinalized<'new_gc, Id> {
+        GcFinalized {
+            value: self.value,
+            type_id: self.type_id,
+            type_name: self.type_name,
+            marker: PhantomData,
+        }
+    }
+    /// A raw pointer to the underlying value
+    #[inline]
+    pub fn as_raw_ptr(&self) -> *mut () {
+        self.value.as_ptr()
+    }
+    /// The name of the original type, as given by [core::any::type_name]
+    #[inline]
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+    /// Check if the underlying object has the specified type
+    #[inline]
+    pub fn is<T>(&self) -> bool
+    where
+        T: GcSafe<'gc, Id> + GcRebrand<'static, Id>,
+        <T as GcRebrand<'static, Id>>::Branded: 'static,
+    {
+        self.type_id == TypeId::of::<<T as GcRebrand<'static, Id>>::Branded>()
+    }
+    /// Attempt to downcast into the original [Gc] pointer,
+    /// returning `Err(self)` if the types don't match.
+    #[inline]
+    pub fn downcast<T>(self) -> Result<Gc<'gc, T, Id>, Self>
+    where
+        T: GcSafe<'gc, Id> + GcRebrand<'static, Id>,
+        <T as GcRebrand<'static, Id>>::Branded: 'static,
+    {
+        if self.is::<T>() {
+            // SAFETY: The types only differ in their lifetimes
+            Ok(unsafe { Gc::from_raw(self.value.cast::<T>()) })
+        } else {
+            Err(self)
+        }
+    }
+}
+impl<'gc, Id: CollectorId> Copy for GcFinalized<'gc, Id> {}
+impl<'gc, Id: CollectorId> Clone for GcFinalized<'gc, Id> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<'gc, Id: CollectorId> Debug for GcFinalized<'gc, Id> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GcFinalized")
+            .field("type_name", &self.type_name)
+            .field("value", &self.value)
+            .finish()
+    }
+}
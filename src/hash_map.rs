@@ -1,14 +1,19 @@
 //! A garbage collected HashMap implementation
 //!
-//! Right now, the only implementation
+//! Right now, the main implementation
 //! is [GcIndexMap]. It is a garbage collected
 //! version of [indexmap::IndexMap](https://docs.rs/indexmap/1.7.0/indexmap/map/struct.IndexMap.html).
 //!
+//! There is also [GcWeakKeyMap], whose entries only
+//! live as long as their keys are reachable.
+//!
 //! In the future, unordered maps may be possible
 //! (although they'll likely require much more work).
 pub mod indexmap;
+pub mod weak;
 
 /// The default hasher for garbage collected maps.
 pub type DefaultHasher = ahash::RandomState;
 
 pub use self::indexmap::GcIndexMap;
+pub use self::weak::GcWeakKeyMap;
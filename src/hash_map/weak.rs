@@ -0,0 +1,368 @@
+//! Contains the implementation of [GcWeakKeyMap]
+
+use core::hash::BuildHasher;
+use core::mem;
+
+use hashbrown::raw::RawTable;
+
+use zerogc_derive::unsafe_gc_impl;
+
+use crate::prelude::*;
+use crate::{GcWeak, NonMovingCollectorId, SimpleAllocCollectorId};
+
+/// A garbage collected hashmap with weak keys (also known as an "ephemeron table").
+///
+/// An entry only lives as long as its key is reachable from somewhere else.
+/// The value is only considered reachable through the key,
+/// so a value that refers back to its own key doesn't keep the entry alive.
+///
+/// Once the key is collected, the entry is effectively removed from the map.
+/// Dead entries are lazily pruned (see [GcWeakKeyMap::prune]).
+///
+/// Keys are compared by identity (their address), not by value.
+/// Addresses are only stable if the collector never moves objects,
+/// so this requires a [NonMovingCollectorId].
+///
+/// Like [GcIndexMap](super::GcIndexMap), this uses the `RawTable` from `hashbrown`
+/// to map hashes to indices in a garbage collected vector of entries.
+pub struct GcWeakKeyMap<
+    'gc,
+    K: ?Sized + GcSafe<'gc, Id>,
+    V: GcSafe<'gc, Id>,
+    Id: SimpleAllocCollectorId + NonMovingCollectorId,
+    S: BuildHasher = super::DefaultHasher,
+> {
+    /// indices mapping from the key's hash to the index of its entry
+    ///
+    /// NOTE: Just like [GcIndexMap](super::GcIndexMap),
+    /// this uses `std::alloc` instead of the garbage collector.
+    indices: RawTable<usize>,
+    /// a garbage collected vector of entries (in no particular order)
+    entries: GcVec<'gc, Bucket<'gc, K, V, Id>, Id>,
+    /// The hasher used to hash key addresses
+    hasher: S,
+}
+unsafe impl<
+        'gc,
+        K: ?Sized + GcSafe<'gc, Id>,
+        V: GcSafe<'gc, Id>,
+        Id: SimpleAllocCollectorId + NonMovingCollectorId,
+        S: BuildHasher,
+    > crate::ImplicitWriteBarrier for GcWeakKeyMap<'gc, K, V, Id, S>
+{
+}
+impl<
+        'gc,
+        K: ?Sized + GcSafe<'gc, Id>,
+        V: GcSafe<'gc, Id>,
+        Id: SimpleAllocCollectorId + NonMovingCollectorId,
+        S: BuildHasher,
+    > GcWeakKeyMap<'gc, K, V, Id, S>
+{
+    /// Allocate a new map inside the specified collector
+    #[inline]
+    pub fn new_in(ctx: &'gc Id::Context) -> Self
+    where
+        S: Default,
+    {
+        Self::with_capacity_in(0, ctx)
+    }
+    /// Allocate a new map with the specified capacity,
+    /// inside of the specified collector
+    #[inline]
+    pub fn with_capacity_in(capacity: usize, ctx: &'gc Id::Context) -> Self
+    where
+        S: Default,
+    {
+        Self::with_capacity_and_hasher_in(capacity, Default::default(), ctx)
+    }
+    /// Allocate a new map with the specified capacity and hasher,
+    /// inside of the specified collector
+    #[inline]
+    pub fn with_capacity_and_hasher_in(capacity: usize, hasher: S, ctx: &'gc Id::Context) -> Self {
+        GcWeakKeyMap {
+            indices: RawTable::with_capacity(capacity),
+            entries: GcVec::with_capacity_in(capacity, ctx),
+            hasher,
+        }
+    }
+    /// Allocate a new map with the specified hasher,
+    /// inside the specified collector
+    #[inline]
+    pub fn with_hasher_in(hasher: S, ctx: &'gc Id::Context) -> Self {
+        Self::with_capacity_and_hasher_in(0, hasher, ctx)
+    }
+    /// Return the number of entries in the map.
+    ///
+    /// This includes entries whose keys have been collected,
+    /// but that haven't been [pruned](GcWeakKeyMap::prune) yet.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// Check if the map is empty.
+    ///
+    /// See [GcWeakKeyMap::len] for details.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Check if the map contains an entry for the specified key
+    #[inline]
+    pub fn contains_key(&self, key: Gc<'gc, K, Id>) -> bool {
+        self.get_index_of(key).is_some()
+    }
+    /// Return a reference to the value associated with the specified key,
+    /// or `None` if it isn't present in the map.
+    pub fn get(&self, key: Gc<'gc, K, Id>) -> Option<&V> {
+        self.get_index_of(key)
+            .map(|index| &self.entries[index].value)
+    }
+    /// Return a mutable reference to the value associated with the specified key,
+    /// or `None` if it isn't present in the map.
+    pub fn get_mut(&mut self, key: Gc<'gc, K, Id>) -> Option<&mut V> {
+        self.get_index_of(key)
+            .map(move |index| &mut self.entries[index].value)
+    }
+    /// Insert a key value pair into the map, returning the previous value (if any).
+    ///
+    /// If the map needs to grow, this will [prune](GcWeakKeyMap::prune) dead entries first.
+    pub fn insert(&mut self, key: Gc<'gc, K, Id>, value: V) -> Option<V> {
+        match self.get_index_of(key) {
+            Some(i) => Some(mem::replace(&mut self.entries[i].value, value)),
+            None => {
+                if self.entries.len() == self.entries.capacity() {
+                    // Try to make room before we grow
+                    self.prune();
+                }
+                let hash = self.hash(key);
+                let i = self.entries.len();
+                let entries = &self.entries;
+                self.indices.insert(hash, i, |&i| entries[i].hash);
+                self.entries.push(Bucket {
+                    hash,
+                    key: key.downgrade(),
+                    value,
+                });
+                None
+            }
+        }
+    }
+    /// Remove the entry associated with `key`, and return its value.
+    ///
+    /// This does **not** preserve ordering,
+    /// just like [GcIndexMap::swap_remove](super::GcIndexMap::swap_remove).
+    pub fn remove(&mut self, key: Gc<'gc, K, Id>) -> Option<V> {
+        self.get_index_of(key)
+            .map(|index| self.swap_remove_index(index).value)
+    }
+    /// Remove all the entries whose keys have been collected.
+    ///
+    /// This happens implicitly whenever the map needs to grow.
+    pub fn prune(&mut self) {
+        let mut index = 0;
+        while index < self.entries.len() {
+            if self.entries[index].key.is_cleared() {
+                /*
+                 * NOTE: The value may refer to dead objects.
+                 * Dropping it is fine, because its destructor is trusted
+                 * not to touch any garbage collected memory.
+                 */
+                drop(self.swap_remove_index(index));
+            } else {
+                index += 1;
+            }
+        }
+    }
+    /// Return the index of the entry with the specified key
+    fn get_index_of(&self, key: Gc<'gc, K, Id>) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            let hash = self.hash(key);
+            let address = key_address(key);
+            let entries = &self.entries;
+            self.indices
+                .get(hash, |&i| {
+                    // NOTE: Cleared keys never match
+                    entries[i]
+                        .key
+                        .upgrade()
+                        .is_some_and(|other| key_address(other) == address)
+                })
+                .copied()
+        }
+    }
+    /// Remove the entry at the specified index,
+    /// correcting the index of the entry that gets moved into its place.
+    fn swap_remove_index(&mut self, index: usize) -> Bucket<'gc, K, V, Id> {
+        let hash = self.entries[index].hash;
+        self.indices
+            .remove_entry(hash, move |&i| i == index)
+            .expect("index not found");
+        let entry = self.entries.swap_remove(index);
+        /*
+         * correct the index that points to the moved entry
+         * It was at 'self.len()', now it's at 'index'
+         */
+        if let Some(moved) = self.entries.get(index) {
+            let last = self.entries.len();
+            *self
+                .indices
+                .get_mut(moved.hash, move |&i| i == last)
+                .expect("index not found") = index;
+        }
+        entry
+    }
+    fn hash(&self, key: Gc<'gc, K, Id>) -> u64 {
+        self.hasher.hash_one(key_address(key))
+    }
+    /// Iterate over the live entries in the map
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, 'gc, K, V, Id> {
+        Iter(self.entries.iter())
+    }
+    /// Iterate over the live keys in the map
+    #[inline]
+    pub fn keys(&self) -> Keys<'_, 'gc, K, V, Id> {
+        Keys(self.iter())
+    }
+    /// Iterate over the values of the live entries in the map
+    #[inline]
+    pub fn values(&self) -> Values<'_, 'gc, K, V, Id> {
+        Values(self.iter())
+    }
+    /// Return the context implicitly associated with this map
+    ///
+    /// See also: [GcVec::context]
+    #[inline]
+    pub fn context(&self) -> &'gc Id::Context {
+        self.entries.context()
+    }
+}
+
+/// An iterator over the live entries of a [GcWeakKeyMap]
+///
+/// Entries whose keys have been collected are skipped.
+pub struct Iter<'a, 'gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: CollectorId>(
+    core::slice::Iter<'a, Bucket<'gc, K, V, Id>>,
+);
+impl<'a, 'gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: CollectorId> Iterator
+    for Iter<'a, 'gc, K, V, Id>
+{
+    type Item = (Gc<'gc, K, Id>, &'a V);
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0
+            .by_ref()
+            .find_map(|bucket| bucket.key.upgrade().map(|key| (key, &bucket.value)))
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.0.size_hint().1)
+    }
+}
+impl<'a, 'gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: CollectorId>
+    core::iter::FusedIterator for Iter<'a, 'gc, K, V, Id>
+{
+}
+
+/// An iterator over the live keys of a [GcWeakKeyMap]
+pub struct Keys<'a, 'gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: CollectorId>(
+    Iter<'a, 'gc, K, V, Id>,
+);
+impl<'a, 'gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: CollectorId> Iterator
+    for Keys<'a, 'gc, K, V, Id>
+{
+    type Item = Gc<'gc, K, Id>;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(key, _)| key)
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+impl<'a, 'gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: CollectorId>
+    core::iter::FusedIterator for Keys<'a, 'gc, K, V, Id>
+{
+}
+/// An iterator over the values of the live entries in a [GcWeakKeyMap]
+pub struct Values<'a, 'gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: CollectorId>(
+    Iter<'a, 'gc, K, V, Id>,
+);
+impl<'a, 'gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: CollectorId> Iterator
+    for Values<'a, 'gc, K, V, Id>
+{
+    type Item = &'a V;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(_, value)| value)
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+impl<'a, 'gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: CollectorId>
+    core::iter::FusedIterator for Values<'a, 'gc, K, V, Id>
+{
+}
+
+unsafe_gc_impl!(
+    target => GcWeakKeyMap<'gc, K, V, Id, S>,
+    params => ['gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: SimpleAllocCollectorId + NonMovingCollectorId, S: BuildHasher],
+    bounds => {
+        GcSafe => { where S: 'static },
+        Trace => { where S: 'static },
+        TraceImmutable => never,
+        TrustedDrop => { where V: TrustedDrop, S: 'static },
+        GcRebrand => {
+            where K: GcRebrand<'new_gc, Id>, V: GcRebrand<'new_gc, Id>, S: 'static, V::Branded: Sized }
+    },
+    branded_type => GcWeakKeyMap<'new_gc, K::Branded, V::Branded, Id, S>,
+    NEEDS_TRACE => true,
+    NEEDS_DROP => core::mem::needs_drop::<Self>(),
+    null_trace => never,
+    trace_mut => |self, visitor| {
+        visitor.trace(&mut self.entries)
+    },
+    collector_id => Id
+);
+
+/// The address of the key, used for hashing and comparison
+#[inline]
+fn key_address<'gc, K: ?Sized + GcSafe<'gc, Id>, Id: CollectorId>(key: Gc<'gc, K, Id>) -> usize {
+    unsafe { key.as_raw_ptr() as *mut u8 as usize }
+}
+
+/// An entry in the map.
+///
+/// This is traced as an ephemeron,
+/// so the value is only traced if the key is reachable.
+struct Bucket<'gc, K: ?Sized, V, Id: CollectorId> {
+    hash: u64,
+    key: GcWeak<'gc, K, Id>,
+    value: V,
+}
+unsafe_gc_impl!(
+    target => Bucket<'gc, K, V, Id>,
+    params => ['gc, K: ?Sized + GcSafe<'gc, Id>, V: GcSafe<'gc, Id>, Id: CollectorId],
+    bounds => {
+        GcSafe => { where V: GcSafe<'gc, Id> },
+        Trace => { where V: Trace },
+        TraceImmutable => never,
+        TrustedDrop => { where V: TrustedDrop },
+        GcRebrand => {
+            where K: GcRebrand<'new_gc, Id>, V: GcRebrand<'new_gc, Id>, V::Branded: Sized }
+    },
+    branded_type => Bucket<'new_gc, K::Branded, V::Branded, Id>,
+    NEEDS_TRACE => true,
+    NEEDS_DROP => <V as Trace>::NEEDS_DROP,
+    null_trace => never,
+    trace_mut => |self, visitor| {
+        unsafe { visitor.trace_ephemeron(&mut self.key, &mut self.value) }
+    },
+    collector_id => Id
+);
@@ -12,7 +12,7 @@
 )]
 #![cfg_attr(feature = "error", backtrace)]
 #![cfg_attr(feature = "allocator-api", feature(allocator_api))]
-#![feature(maybe_uninit_slice)]
+#![feature(maybe_uninit_slice, ptr_alignment_type)]
 #![feature(new_uninit)]
 #![deny(missing_docs)]
 #![allow(
@@ -50,6 +50,7 @@ extern crate self as zerogc;
  */
 #[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::vec::Vec;
+use core::alloc::{Layout, LayoutError};
 use core::cmp::Ordering;
 use core::fmt::{self, Debug, Display, Formatter};
 use core::hash::{Hash, Hasher};
@@ -60,10 +61,12 @@ use core::ptr::{DynMetadata, NonNull, Pointee};
 
 use vec::raw::GcRawVec;
 use zerogc_derive::unsafe_gc_impl;
-pub use zerogc_derive::{NullTrace, Trace};
+pub use zerogc_derive::{dyn_trace, NullTrace, Trace};
 
 pub use crate::array::GcArray;
+pub use crate::finalize::{GcFinalize, GcFinalized};
 use crate::vec::GcVec;
+pub use crate::weak::GcWeak;
 
 #[macro_use] // We have macros in here!
 #[cfg(feature = "serde1")]
@@ -79,10 +82,12 @@ pub mod cell;
 pub mod epsilon;
 #[cfg(feature = "errors")]
 pub mod errors;
+pub mod finalize;
 #[cfg(feature = "hashmap-impl")]
 pub mod hash_map;
 pub mod prelude;
 pub mod vec;
+pub mod weak;
 
 /// Invoke the closure with a temporary [GcContext],
 /// then perform a safepoint afterwards.
@@ -209,6 +214,39 @@ macro_rules! safepoint {
     };
 }
 
+/// Unconditionally perform a garbage collection,
+/// rebinding the specified root just like [safepoint!].
+///
+/// A regular safepoint only collects once the collector decides it's necessary.
+/// This is useful when a collection is needed at a specific point,
+/// like in tests or right before a latency-sensitive section.
+///
+/// Collectors that never collect (like the epsilon collector)
+/// are free to treat this the same as a regular safepoint.
+///
+/// ## Example
+/// ```
+/// # use ::zerogc::safepoint_force;
+/// # let mut context = zerogc::epsilon::EpsilonSystem::leak().new_context();
+/// let root = zerogc::epsilon::leaked(String::from("potato"));
+/// let root = safepoint_force!(context, root);
+/// assert_eq!(*root, "potato");
+/// ```
+///
+/// ## Safety
+/// This macro is completely safe, although it expands to unsafe code internally.
+#[macro_export]
+macro_rules! safepoint_force {
+    ($context:ident, $value:expr) => {
+        unsafe {
+            use $crate::GcContext;
+            let mut erased = $context.rebrand_static($value);
+            $context.force_safepoint(&mut &mut erased);
+            $context.rebrand_self(erased)
+        }
+    };
+}
+
 /// Indicate its safe to begin a garbage collection (like [safepoint!])
 /// and then "freeze" the specified context.
 ///
@@ -343,6 +381,30 @@ pub unsafe trait GcContext: Sized {
         self.unchecked_safepoint(root)
     }
 
+    /// Unconditionally perform a garbage collection, freeing
+    /// all objects that aren't reachable from the specified root.
+    ///
+    /// This is a less safe version of [GcContext::force_safepoint]
+    /// that doesn't explicitly mark this context as "mutated".
+    ///
+    /// ## Safety
+    /// The same as [GcContext::unchecked_safepoint].
+    unsafe fn unchecked_force_safepoint<T: Trace>(&self, root: &mut &mut T);
+
+    /// Unconditionally perform a garbage collection, freeing
+    /// all objects that aren't reachable from the specified root.
+    ///
+    /// This is what the [safepoint_force!] macro expands to internally.
+    /// Unlike [GcContext::basic_safepoint], the collection is performed
+    /// regardless of whether the collector thinks it's necessary.
+    ///
+    /// ## Safety
+    /// The same as [GcContext::basic_safepoint].
+    #[inline]
+    unsafe fn force_safepoint<T: Trace>(&mut self, root: &mut &mut T) {
+        self.unchecked_force_safepoint(root)
+    }
+
     /// Inform the garbage collection system we are at a safepoint
     /// and are ready for a potential garbage collection.
     ///
@@ -443,6 +505,81 @@ pub unsafe trait GcContext: Sized {
     /// Get the id of this context
     fn id(&self) -> Self::Id;
 }
+/// An error that occurs when a garbage collector fails to allocate memory.
+///
+/// This is returned by the fallible `try_alloc*` methods on [GcSimpleAlloc].
+/// The infallible variants panic instead (or abort if the system is out of memory).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum GcAllocError {
+    /// The allocation would exceed the collector's configured heap limit.
+    ///
+    /// Allocation never triggers a collection by itself,
+    /// since that requires a safepoint. Instead, the heap size includes any
+    /// garbage allocated since the last collection.
+    /// After this error, callers should invoke a safepoint (like [safepoint!])
+    /// and retry the allocation. It only fails again if the live objects
+    /// really don't fit beneath the limit.
+    HeapLimitExceeded,
+    /// The underlying allocator is out of memory
+    OutOfMemory,
+    /// The size of the requested object overflowed
+    /// (for example, an array with an absurdly large length).
+    ///
+    /// Unlike [GcAllocError::HeapLimitExceeded], retrying never helps.
+    SizeOverflow,
+}
+impl GcAllocError {
+    /// Handle a failure in one of the infallible allocation methods,
+    /// by panicking (or aborting if the system is out of memory).
+    ///
+    /// This is intended for implementations of [GcSimpleAlloc].
+    #[cold]
+    #[track_caller]
+    pub fn fail(self, layout: Layout) -> ! {
+        match self {
+            #[cfg(feature = "alloc")]
+            GcAllocError::OutOfMemory => alloc::alloc::handle_alloc_error(layout),
+            _ => panic!("Failed to allocate {:?}: {}", layout, self),
+        }
+    }
+    /// Handle a failure to allocate an array of the specified length,
+    /// in one of the infallible allocation methods.
+    ///
+    /// Unlike [GcAllocError::fail], this handles lengths
+    /// whose size overflows a [Layout].
+    #[cold]
+    #[track_caller]
+    pub fn fail_array<T>(self, len: usize) -> ! {
+        match Layout::array::<T>(len) {
+            Ok(layout) => self.fail(layout),
+            Err(_) => panic!(
+                "Failed to allocate [{}; {}]: {}",
+                core::any::type_name::<T>(),
+                len,
+                self
+            ),
+        }
+    }
+}
+impl From<LayoutError> for GcAllocError {
+    #[inline]
+    fn from(_cause: LayoutError) -> Self {
+        GcAllocError::SizeOverflow
+    }
+}
+impl Display for GcAllocError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            GcAllocError::HeapLimitExceeded => f.write_str("Exceeded the maximum heap size"),
+            GcAllocError::OutOfMemory => f.write_str("Out of memory"),
+            GcAllocError::SizeOverflow => f.write_str("The requested size overflowed"),
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for GcAllocError {}
+
 /// A simple interface to allocating from a [GcContext].
 ///
 /// Some garbage collectors implement more complex interfaces,
@@ -456,6 +593,24 @@ pub unsafe trait GcSimpleAlloc: GcContext {
     unsafe fn alloc_uninit<'gc, T>(&'gc self) -> *mut T
     where
         T: GcSafe<'gc, Self::Id>;
+    /// Attempt to allocate room for a object, but don't finish initializing it.
+    ///
+    /// Returns an error if the allocation fails,
+    /// instead of panicking like [GcSimpleAlloc::alloc_uninit].
+    ///
+    /// The default implementation never fails.
+    /// Collectors that can (for example, those with a heap limit) should override this.
+    ///
+    /// ## Safety
+    /// The object **must** be initialized by the time the next collection
+    /// rolls around, so that the collector can properly trace it
+    #[inline]
+    unsafe fn try_alloc_uninit<'gc, T>(&'gc self) -> Result<*mut T, GcAllocError>
+    where
+        T: GcSafe<'gc, Self::Id>,
+    {
+        Ok(self.alloc_uninit::<T>())
+    }
     /// Allocate the specified object in this garbage collector,
     /// binding it to the lifetime of this collector.
     ///
@@ -479,6 +634,39 @@ pub unsafe trait GcSimpleAlloc: GcContext {
             Gc::from_raw(NonNull::new_unchecked(ptr))
         }
     }
+    /// Attempt to allocate the specified object in this garbage collector,
+    /// returning an error if allocation fails.
+    ///
+    /// See [GcSimpleAlloc::alloc] for details.
+    #[inline]
+    fn try_alloc<'gc, T>(&'gc self, value: T) -> Result<Gc<'gc, T, Self::Id>, GcAllocError>
+    where
+        T: GcSafe<'gc, Self::Id>,
+    {
+        unsafe {
+            let ptr = self.try_alloc_uninit::<T>()?;
+            ptr.write(value);
+            Ok(Gc::from_raw(NonNull::new_unchecked(ptr)))
+        }
+    }
+    /// Allocate the specified object,
+    /// immediately coercing it into a dynamically dispatched trait object.
+    ///
+    /// The trait object must implement [DynTrace],
+    /// typically by using the [dyn_trace] attribute (or the [trait_object_trace!] macro).
+    /// The collector will then trace it through [GcVisitor::trace_trait_object],
+    /// using the runtime type information of the original object.
+    #[inline]
+    fn alloc_dyn<'gc, T, U>(&'gc self, value: T) -> Gc<'gc, U, Self::Id>
+    where
+        T: GcSafe<'gc, Self::Id> + Unsize<U>,
+        U: ?Sized
+            + GcSafe<'gc, Self::Id>
+            + Pointee<Metadata = DynMetadata<U>>
+            + DynTrace<'gc, Self::Id>,
+    {
+        self.alloc::<T>(value)
+    }
     /// Allocate a [GcString](`crate::array::GcString`), copied from the specified source
     #[inline]
     fn alloc_str<'gc>(&'gc self, src: &str) -> array::GcString<'gc, Self::Id> {
@@ -486,6 +674,17 @@ pub unsafe trait GcSimpleAlloc: GcContext {
         // SAFETY: Guaranteed by the original `src`
         unsafe { array::GcString::from_utf8_unchecked(bytes) }
     }
+    /// Attempt to allocate a [GcString](`crate::array::GcString`),
+    /// returning an error if allocation fails.
+    #[inline]
+    fn try_alloc_str<'gc>(
+        &'gc self,
+        src: &str,
+    ) -> Result<array::GcString<'gc, Self::Id>, GcAllocError> {
+        let bytes = self.try_alloc_slice_copy(src.as_bytes())?;
+        // SAFETY: Guaranteed by the original `src`
+        Ok(unsafe { array::GcString::from_utf8_unchecked(bytes) })
+    }
 
     /// Wrap the specified error in a dynamically dispatched [GcError](`self::errors::GcError`)
     ///
@@ -515,6 +714,21 @@ pub unsafe trait GcSimpleAlloc: GcContext {
     unsafe fn alloc_uninit_slice<'gc, T>(&'gc self, len: usize) -> *mut T
     where
         T: GcSafe<'gc, Self::Id>;
+    /// Attempt to allocate a slice with the specified length,
+    /// whose memory is uninitialized.
+    ///
+    /// Returns an error if the allocation fails.
+    /// The default implementation never fails (see [GcSimpleAlloc::try_alloc_uninit]).
+    ///
+    /// ## Safety
+    /// See [GcSimpleAlloc::alloc_uninit_slice]
+    #[inline]
+    unsafe fn try_alloc_uninit_slice<'gc, T>(&'gc self, len: usize) -> Result<*mut T, GcAllocError>
+    where
+        T: GcSafe<'gc, Self::Id>,
+    {
+        Ok(self.alloc_uninit_slice::<T>(len))
+    }
     /// Allocate a slice, copied from the specified input
     fn alloc_slice_copy<'gc, T>(&'gc self, src: &[T]) -> GcArray<'gc, T, Self::Id>
     where
@@ -526,6 +740,24 @@ pub unsafe trait GcSimpleAlloc: GcContext {
             GcArray::from_raw_ptr(NonNull::new_unchecked(res_ptr), src.len())
         }
     }
+    /// Attempt to allocate a slice copied from the specified input,
+    /// returning an error if allocation fails.
+    fn try_alloc_slice_copy<'gc, T>(
+        &'gc self,
+        src: &[T],
+    ) -> Result<GcArray<'gc, T, Self::Id>, GcAllocError>
+    where
+        T: GcSafe<'gc, Self::Id> + Copy,
+    {
+        unsafe {
+            let res_ptr = self.try_alloc_uninit_slice::<T>(src.len())?;
+            res_ptr.copy_from_nonoverlapping(src.as_ptr(), src.len());
+            Ok(GcArray::from_raw_ptr(
+                NonNull::new_unchecked(res_ptr),
+                src.len(),
+            ))
+        }
+    }
     /// Allocate an array, taking ownership of the values in
     /// the specified vec.
     #[inline]
@@ -605,6 +837,20 @@ pub unsafe trait GcSimpleAlloc: GcContext {
     ) -> <Self::Id as CollectorId>::RawVec<'gc, T>
     where
         T: GcSafe<'gc, Self::Id>;
+    /// Attempt to create a new [GcRawVec] with the specified capacity,
+    /// returning an error if allocation fails.
+    ///
+    /// The default implementation never fails (see [GcSimpleAlloc::try_alloc_uninit]).
+    #[inline]
+    fn try_alloc_raw_vec_with_capacity<'gc, T>(
+        &'gc self,
+        capacity: usize,
+    ) -> Result<<Self::Id as CollectorId>::RawVec<'gc, T>, GcAllocError>
+    where
+        T: GcSafe<'gc, Self::Id>,
+    {
+        Ok(self.alloc_raw_vec_with_capacity::<T>(capacity))
+    }
     /// Create a new [GcVec] with zero initial length,
     /// with an implicit reference to this [GcContext].
     #[inline]
@@ -623,6 +869,19 @@ pub unsafe trait GcSimpleAlloc: GcContext {
     {
         unsafe { crate::vec::GcVec::from_raw(self.alloc_raw_vec_with_capacity::<T>(capacity)) }
     }
+    /// Attempt to allocate a new [GcVec] with the specified capacity,
+    /// returning an error if allocation fails.
+    #[inline]
+    fn try_alloc_vec_with_capacity<'gc, T>(
+        &'gc self,
+        capacity: usize,
+    ) -> Result<GcVec<'gc, T, Self::Id>, GcAllocError>
+    where
+        T: GcSafe<'gc, Self::Id>,
+    {
+        let raw = self.try_alloc_raw_vec_with_capacity::<T>(capacity)?;
+        Ok(unsafe { crate::vec::GcVec::from_raw(raw) })
+    }
 }
 /// The internal representation of a frozen context
 ///
@@ -674,6 +933,69 @@ pub unsafe trait HandleCollectorId: CollectorId {
         T: GcSafe<'gc, Self> + GcRebrand<'static, Self> + ?Sized;
 }
 
+/// A [CollectorId] that supports pinning objects in place,
+/// using [GcPinned] handles.
+///
+/// Not all collectors necessarily support pinning.
+pub unsafe trait PinCollectorId: HandleCollectorId {
+    /// The type of [GcPinned] for this collector.
+    ///
+    /// Just like [HandleCollectorId::Handle],
+    /// this is parameterized by the *erased* type.
+    type Pinned<T>: GcPinned<T, System = Self::System, Id = Self>
+    where
+        T: GcSafe<'static, Self> + ?Sized;
+
+    /// Pin the specified object in place.
+    ///
+    /// NOTE: Users should only use from [Gc::pin].
+    #[doc(hidden)]
+    fn pin<'gc, T>(gc: Gc<'gc, T, Self>) -> Self::Pinned<T::Branded>
+    where
+        T: GcSafe<'gc, Self> + GcRebrand<'static, Self> + ?Sized;
+
+    /// Pin the specified array in place.
+    ///
+    /// NOTE: Users should only use from [GcArray::pin].
+    #[doc(hidden)]
+    fn pin_array<'gc, T>(array: GcArray<'gc, T, Self>) -> Self::Pinned<[T::Branded]>
+    where
+        T: GcSafe<'gc, Self> + GcRebrand<'static, Self>,
+        T::Branded: Sized;
+}
+
+/// A [CollectorId] that supports [GcWeakHandle]s,
+/// which reference an object without keeping it alive.
+///
+/// Not all collectors necessarily support weak handles.
+pub unsafe trait WeakHandleCollectorId: HandleCollectorId {
+    /// The type of [GcWeakHandle] for this collector.
+    ///
+    /// Just like [HandleCollectorId::Handle],
+    /// this is parameterized by the *erased* type.
+    type WeakHandle<T>: GcWeakHandle<T, System = Self::System, Id = Self>
+    where
+        T: GcSafe<'static, Self> + ?Sized;
+
+    /// Create a weak handle to the specified GC pointer
+    ///
+    /// NOTE: Users should only use from [Gc::create_weak_handle].
+    #[doc(hidden)]
+    fn create_weak_handle<'gc, T>(gc: Gc<'gc, T, Self>) -> Self::WeakHandle<T::Branded>
+    where
+        T: GcSafe<'gc, Self> + GcRebrand<'static, Self> + ?Sized;
+}
+
+/// A [CollectorId] whose objects are never moved once they've been allocated.
+///
+/// The address of an object is a stable identity,
+/// which can be hashed and compared across collections
+/// (see [GcWeakKeyMap](crate::hash_map::GcWeakKeyMap)).
+///
+/// ## Safety
+/// The collector must never relocate a live object.
+pub unsafe trait NonMovingCollectorId: CollectorId {}
+
 /// Uniquely identifies the collector in case there are
 /// multiple collectors.
 ///
@@ -832,6 +1154,34 @@ impl<'gc, T: GcSafe<'gc, Id> + ?Sized, Id: CollectorId> Gc<'gc, T, Id> {
     {
         Id::create_handle(*self)
     }
+    /// Pin this object in place,
+    /// guaranteeing it won't be moved or freed for as long as the pin is held.
+    ///
+    /// Unlike [Gc::as_raw_ptr], the address of a pinned object
+    /// remains valid across safepoints (and threads).
+    ///
+    /// Requires that the collector [supports pinning](`PinCollectorId`)
+    #[inline]
+    pub fn pin(&self) -> Id::Pinned<T::Branded>
+    where
+        Id: PinCollectorId,
+        T: GcRebrand<'static, Id>,
+    {
+        Id::pin(*self)
+    }
+    /// Create a [GcWeakHandle] referencing this object,
+    /// which can be used without a context (and sent between threads)
+    /// but doesn't keep the object alive.
+    ///
+    /// Requires that the collector [supports weak handles](`WeakHandleCollectorId`)
+    #[inline]
+    pub fn create_weak_handle(&self) -> Id::WeakHandle<T::Branded>
+    where
+        Id: WeakHandleCollectorId,
+        T: GcRebrand<'static, Id>,
+    {
+        Id::create_weak_handle(*self)
+    }
 
     /// Get a reference to the system
     ///
@@ -871,6 +1221,12 @@ impl<'gc, T: ?Sized, Id: CollectorId> Gc<'gc, T, Id> {
     pub fn collector_id(&self) -> &'_ Id {
         Id::from_gc_ptr(self)
     }
+    /// Create a [GcWeak] reference to this object,
+    /// which doesn't keep it alive.
+    #[inline]
+    pub const fn downgrade(&self) -> GcWeak<'gc, T, Id> {
+        GcWeak::new(*self)
+    }
 }
 
 /// Double-indirection is completely safe
@@ -1095,6 +1451,74 @@ pub unsafe trait GcHandle<T: GcSafe<'static, Self::Id> + ?Sized>:
         T: GcRebrand<'new_gc, Self::Id>;
 }
 
+/// A [GcHandle] that also pins its object in place.
+///
+/// For as long as a pin is held, the object will never be moved
+/// or freed by the collector (even by a moving collector).
+/// This makes it possible to pass garbage collected memory to foreign code,
+/// which may continue to use it asynchronously across safepoints.
+///
+/// Pins are created using [Gc::pin] and [GcArray::pin].
+pub unsafe trait GcPinned<T: GcSafe<'static, Self::Id> + ?Sized>: GcHandle<T> {
+    /// A raw pointer to the pinned object.
+    ///
+    /// This is guaranteed to remain valid (and never change)
+    /// for as long as this pin is held.
+    ///
+    /// Mutating the object through this pointer is only safe
+    /// if it has interior mutability (and nothing else is accessing it).
+    fn as_raw_ptr(&self) -> *mut T;
+}
+
+/// A weak handle to a garbage collected object,
+/// which doesn't keep it alive.
+///
+/// Just like a [GcHandle], this can be used without a context
+/// and referenced across safepoints (or threads).
+/// However, the object may be collected once it's no longer reachable
+/// from anywhere else. After that, the handle is cleared
+/// and all attempts to access it return `None`.
+///
+/// Weak handles are created using [Gc::create_weak_handle].
+///
+/// ## Tracing
+/// Weak handles are never roots of a collection.
+/// They're cleared by the collector itself,
+/// so it's safe to implement [NullTrace] for them.
+pub unsafe trait GcWeakHandle<T: GcSafe<'static, Self::Id> + ?Sized>:
+    Sized + Clone + NullTrace + for<'gc> GcSafe<'gc, Self::Id>
+{
+    /// The type of the system used with this handle
+    type System: GcSystem<Id = Self::Id>;
+    /// The type of [CollectorId] used with this sytem
+    type Id: CollectorId;
+
+    /// Access this handle inside the closure,
+    /// unless its object has already been collected.
+    ///
+    /// Just like [GcHandle::use_critical], this **blocks collections**
+    /// for as long as the closure is in use.
+    fn use_critical<R>(&self, func: impl FnOnce(&T) -> R) -> Option<R>;
+
+    /// Associate this handle with the specified context,
+    /// unless its object has already been collected.
+    ///
+    /// The resulting [Gc] is a regular (strong) reference,
+    /// so the object stays alive for as long as it's used as a root.
+    fn bind_to<'new_gc>(
+        &self,
+        context: &'new_gc <Self::System as GcSystem>::Context,
+    ) -> Option<Gc<'new_gc, <T as GcRebrand<'new_gc, Self::Id>>::Branded, Self::Id>>
+    where
+        T: GcRebrand<'new_gc, Self::Id>;
+
+    /// Check if this handle has been cleared,
+    /// because its object has already been collected.
+    ///
+    /// Once a handle has been cleared, it stays that way.
+    fn is_cleared(&self) -> bool;
+}
+
 /// Safely trigger a write barrier before
 /// writing to a garbage collected value.
 ///
@@ -1516,6 +1940,69 @@ pub unsafe trait GcVisitor: Sized {
         T: GcSafe<'gc, Id>,
         Id: CollectorId;
 
+    /// Visit a weak reference to a garbage collected object.
+    ///
+    /// Unlike [GcVisitor::trace_gc], this must **not** keep the referent alive.
+    /// If the referent turns out to be unreachable,
+    /// the collector must [clear](GcWeak::clear) the reference
+    /// before the object is freed.
+    ///
+    /// The default implementation does nothing,
+    /// which is only correct for visitors that never free objects.
+    ///
+    /// ## Safety
+    /// Undefined behavior if the weak reference isn't properly visited.
+    #[inline]
+    unsafe fn trace_weak<'gc, T, Id>(
+        &mut self,
+        weak: &mut GcWeak<'gc, T, Id>,
+    ) -> Result<(), Self::Err>
+    where
+        T: ?Sized + GcSafe<'gc, Id>,
+        Id: CollectorId,
+    {
+        let _ = weak;
+        Ok(())
+    }
+
+    /// Visit an ephemeron: a key-value pair,
+    /// where the value is only reachable if the key is.
+    ///
+    /// The key is held weakly (just like [GcVisitor::trace_weak]),
+    /// and the value must **not** be traced until the key is known to be reachable
+    /// through some path that doesn't go through the value.
+    /// If the key turns out to be unreachable, it must be cleared
+    /// and the value must not be traced at all.
+    ///
+    /// ## Safety
+    /// Undefined behavior if the ephemeron isn't properly visited.
+    ///
+    /// Once the key has been cleared, the value may refer to dead objects
+    /// and must never be accessed again.
+    ///
+    /// The default implementation visits the key with [GcVisitor::trace_weak],
+    /// and then conservatively traces the value strongly (unless the key has already been cleared).
+    /// This keeps the value alive even if the key is dead,
+    /// so it's only suitable for visitors that don't free anything based on ephemerons.
+    #[inline]
+    unsafe fn trace_ephemeron<'gc, K, V, Id>(
+        &mut self,
+        key: &mut GcWeak<'gc, K, Id>,
+        value: &mut V,
+    ) -> Result<(), Self::Err>
+    where
+        K: ?Sized + GcSafe<'gc, Id>,
+        V: Trace,
+        Id: CollectorId,
+    {
+        if key.is_cleared() {
+            // The key is already dead, so the value must never be touched
+            return Ok(());
+        }
+        self.trace_weak(key)?;
+        self.trace::<V>(value)
+    }
+
     /// Visit a garbage collected trait object.
     ///
     /// ## Safety
@@ -8,7 +8,10 @@
 // macros
 pub use crate::{freeze_context, safepoint, safepoint_recurse, unfreeze_context};
 // Basic collector types
-pub use crate::{Gc, GcContext, GcHandle, GcSimpleAlloc, GcSystem, GcVisitor, HandleCollectorId};
+pub use crate::{
+    Gc, GcContext, GcHandle, GcPinned, GcSimpleAlloc, GcSystem, GcVisitor, GcWeakHandle,
+    HandleCollectorId, NonMovingCollectorId, PinCollectorId, WeakHandleCollectorId,
+};
 // Traits for user code to implement
 pub use crate::{GcRebrand, GcSafe, NullTrace, Trace, TraceImmutable, TrustedDrop};
 // TODO: Should this trait be auto-imported???
@@ -195,6 +195,7 @@ impl<'gc, T: GcSafe<'gc, Id>, Id: CollectorId> GcVec<'gc, T, Id> {
     /// while another reference is in use (because there are no other references)
     #[inline]
     pub fn as_mut_slice(&mut self) -> &'_ mut [T] {
+        self.write_barrier();
         unsafe { core::slice::from_raw_parts_mut(self.as_mut_raw().as_mut_ptr(), self.len()) }
     }
     /// Get a reference to the underlying [GcRawVec](`zerogc::vec::raw::GcRawVec`),
@@ -364,6 +365,17 @@ unsafe impl<'gc, T: GcSafe<'gc, Id>, Id: CollectorId> IGcVec<'gc, T> for GcVec<'
         self.as_raw().as_ptr()
     }
 
+    #[inline]
+    unsafe fn as_mut_ptr(&mut self) -> *mut T {
+        self.as_mut_raw().as_mut_ptr()
+    }
+
+    /// NOTE: Forwarded to the raw vector, which may need to trigger write barriers
+    #[inline]
+    fn write_barrier(&mut self) {
+        unsafe { self.as_mut_raw().write_barrier() }
+    }
+
     #[inline]
     pub fn context(&self) -> &'gc Id::Context {
         unsafe { self.as_raw().context() }
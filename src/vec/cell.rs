@@ -121,8 +121,17 @@ unsafe impl<'gc, T: GcSafe<'gc, Id>, Id: SimpleAllocCollectorId> IGcVec<'gc, T>
         self.inner.borrow().context()
     }
 
+    #[inline]
+    pub unsafe fn as_mut_ptr(&mut self) -> *mut T {
+        IGcVec::as_mut_ptr(&mut *self.inner.borrow_mut())
+    }
+
+    #[inline]
+    pub fn write_barrier(&mut self) {
+        self.inner.borrow_mut().write_barrier();
+    }
+
     // Default methods:
-    pub unsafe fn as_mut_ptr(&mut self) -> *mut T;
     pub fn replace(&mut self, index: usize, val: T) -> T;
     pub fn set(&mut self, index: usize, val: T);
     pub fn extend_from_slice(&mut self, src: &[T])
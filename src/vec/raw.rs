@@ -132,8 +132,8 @@ pub unsafe trait IGcVec<'gc, T: GcSafe<'gc, Self::Id>>: Sized + Extend<T> {
     #[inline]
     fn push(&mut self, val: T) {
         let old_len = self.len();
-        // TODO: Write barriers
         self.reserve(1);
+        self.write_barrier();
         unsafe {
             // NOTE: This implicitly calls `borrow_mut` if we are a `GcVecCell`
             self.as_mut_ptr().add(old_len).write(val);
@@ -173,6 +173,7 @@ pub unsafe trait IGcVec<'gc, T: GcSafe<'gc, Self::Id>>: Sized + Extend<T> {
     fn swap_remove(&mut self, index: usize) -> T {
         let len = self.len();
         assert!(index < len);
+        self.write_barrier();
         unsafe {
             let last = core::ptr::read(self.as_ptr().add(len - 1));
             let hole = self.as_mut_ptr().add(index);
@@ -188,7 +189,7 @@ pub unsafe trait IGcVec<'gc, T: GcSafe<'gc, Self::Id>>: Sized + Extend<T> {
     {
         let old_len = self.len();
         self.reserve(src.len());
-        // TODO: Write barriers?
+        self.write_barrier();
         unsafe {
             self.as_mut_ptr()
                 .add(old_len)
@@ -214,6 +215,7 @@ pub unsafe trait IGcVec<'gc, T: GcSafe<'gc, Self::Id>>: Sized + Extend<T> {
     #[inline]
     fn set(&mut self, index: usize, val: T) {
         assert!(index < self.len());
+        self.write_barrier();
         unsafe {
             *self.as_mut_ptr().add(index) = val;
         }
@@ -226,7 +228,8 @@ pub unsafe trait IGcVec<'gc, T: GcSafe<'gc, Self::Id>>: Sized + Extend<T> {
     #[inline]
     fn replace(&mut self, index: usize, val: T) -> T {
         assert!(index < self.len());
-        unsafe { core::ptr::replace(self.as_mut_ptr(), val) }
+        self.write_barrier();
+        unsafe { core::ptr::replace(self.as_mut_ptr().add(index), val) }
     }
     /// Get a pointer to this vector's
     /// underling data.
@@ -248,10 +251,20 @@ pub unsafe trait IGcVec<'gc, T: GcSafe<'gc, Self::Id>>: Sized + Extend<T> {
     ///
     /// Undefined behavior if the elements
     /// are mutated while there are multiple outstanding references.
+    ///
+    /// This doesn't trigger a write barrier by itself,
+    /// so [IGcVec::write_barrier] must be called before storing through the pointer.
     #[inline]
     unsafe fn as_mut_ptr(&mut self) -> *mut T {
         self.as_ptr() as *mut T
     }
+    /// Notify the collector that the elements are about to be mutated.
+    ///
+    /// Every method that stores into the vector calls this first,
+    /// so collectors can override it to trigger a write barrier.
+    /// By default, this does nothing.
+    #[inline]
+    fn write_barrier(&mut self) {}
     /// Get a slice of this vector's elements.
     ///
     /// ## Safety
@@ -307,7 +320,7 @@ where
         .max(requested_capacity);
     // Just allocate a new one, copying from the old
     let mut new_mem = V::with_capacity_in(new_capacity, vec.context());
-    // TODO: Write barriers
+    new_mem.write_barrier();
     unsafe {
         new_mem
             .as_mut_ptr()
@@ -0,0 +1,152 @@
+//! Weak references to garbage collected objects.
+//!
+//! A [GcWeak] refers to a garbage collected object
+//! without keeping it alive.
+//! Once the object becomes unreachable (through strong references),
+//! the collector will clear the weak reference before the object is freed.
+//!
+//! This is useful for caches, which shouldn't keep their values alive.
+use core::fmt::{self, Debug, Formatter};
+use core::ptr::NonNull;
+
+use crate::{
+    CollectorId, Gc, GcDirectBarrier, GcRebrand, GcSafe, GcVisitor, Trace, TrustedDrop,
+};
+
+/// A weak reference to a garbage collected object.
+///
+/// This doesn't keep its referent alive.
+/// If the referent is found to be unreachable during a collection,
+/// the collector will clear this reference (before freeing the object).
+/// This also happens before the referent is kept alive for [finalization](crate::finalize),
+/// so a cleared reference never comes back.
+///
+/// In between safepoints, no objects are collected.
+/// So once a weak reference has been [upgraded](GcWeak::upgrade),
+/// the resulting [Gc] pointer is valid for the entire `'gc` lifetime,
+/// just like any other garbage collected pointer.
+///
+/// ## Safety
+/// This is a `repr(transparent)` wrapper around `Option<Gc<'gc, T, Id>>`.
+/// A cleared reference is represented by a null pointer.
+#[repr(transparent)]
+pub struct GcWeak<'gc, T: ?Sized, Id: CollectorId> {
+    value: Option<Gc<'gc, T, Id>>,
+}
+impl<'gc, T: ?Sized, Id: CollectorId> GcWeak<'gc, T, Id> {
+    /// Create a weak reference to the specified object
+    #[inline]
+    pub const fn new(gc: Gc<'gc, T, Id>) -> Self {
+        GcWeak { value: Some(gc) }
+    }
+    /// Create a weak reference that has already been cleared.
+    ///
+    /// It can never be upgraded.
+    #[inline]
+    pub const fn cleared() -> Self {
+        GcWeak { value: None }
+    }
+    /// Attempt to upgrade this into a strong reference.
+    ///
+    /// Returns `None` if the referent has already been collected.
+    #[inline]
+    pub const fn upgrade(&self) -> Option<Gc<'gc, T, Id>> {
+        self.value
+    }
+    /// Check if this weak reference has been cleared
+    /// (because its referent has already been collected).
+    #[inline]
+    pub const fn is_cleared(&self) -> bool {
+        self.value.is_none()
+    }
+    /// Clear this weak reference,
+    /// so that it no longer refers to anything.
+    ///
+    /// Collectors use this to clear references to unreachable objects.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.value = None;
+    }
+    /// A raw pointer to the referent,
+    /// or `None` if the reference has been cleared.
+    #[inline]
+    pub fn as_raw_ptr(&self) -> Option<NonNull<T>> {
+        self.value
+            .map(|gc| unsafe { NonNull::new_unchecked(gc.as_raw_ptr()) })
+    }
+}
+impl<'gc, T: ?Sized, Id: CollectorId> From<Gc<'gc, T, Id>> for GcWeak<'gc, T, Id> {
+    #[inline]
+    fn from(gc: Gc<'gc, T, Id>) -> Self {
+        GcWeak::new(gc)
+    }
+}
+/// Double-indirection is completely safe
+unsafe impl<'gc, T: ?Sized + GcSafe<'gc, Id>, Id: CollectorId> TrustedDrop for GcWeak<'gc, T, Id> {}
+unsafe impl<'gc, T: ?Sized + GcSafe<'gc, Id>, Id: CollectorId> GcSafe<'gc, Id>
+    for GcWeak<'gc, T, Id>
+{
+    #[inline]
+    unsafe fn trace_inside_gc<V>(gc: &mut Gc<'gc, Self, Id>, visitor: &mut V) -> Result<(), V::Err>
+    where
+        V: GcVisitor,
+    {
+        // Double indirection is fine. It's just a `Sized` type
+        visitor.trace_gc(gc)
+    }
+}
+/// Rebrand
+unsafe impl<'gc, 'new_gc, T, Id> GcRebrand<'new_gc, Id> for GcWeak<'gc, T, Id>
+where
+    T: GcSafe<'gc, Id> + ?Sized + GcRebrand<'new_gc, Id>,
+    Id: CollectorId,
+    Self: Trace,
+{
+    type Branded = GcWeak<'new_gc, <T as GcRebrand<'new_gc, Id>>::Branded, Id>;
+}
+unsafe impl<'gc, T: ?Sized + GcSafe<'gc, Id>, Id: CollectorId> Trace for GcWeak<'gc, T, Id> {
+    // We need tracing (so that we can be cleared)
+    const NEEDS_TRACE: bool = true;
+    // we never need to be dropped because we are `Copy`
+    const NEEDS_DROP: bool = false;
+
+    #[inline]
+    fn trace<V: GcVisitor>(&mut self, visitor: &mut V) -> Result<(), V::Err> {
+        unsafe { visitor.trace_weak(self) }
+    }
+}
+/// Storing a weak reference into an object triggers
+/// the same write barrier as storing the referent itself.
+///
+/// The collector may need to find (and clear) the reference later,
+/// for example when the owner is old and the referent is young.
+/// A cleared reference has nothing to clear, so it never needs a barrier.
+unsafe impl<'gc, O, V, Id> GcDirectBarrier<'gc, Gc<'gc, O, Id>> for GcWeak<'gc, V, Id>
+where
+    O: GcSafe<'gc, Id> + 'gc,
+    V: GcSafe<'gc, Id> + 'gc,
+    Id: CollectorId,
+{
+    #[inline]
+    unsafe fn write_barrier(&self, owner: &Gc<'gc, O, Id>, field_offset: usize) {
+        if let Some(ref value) = self.value {
+            Id::gc_write_barrier(owner, value, field_offset)
+        }
+    }
+}
+// We can be copied freely :)
+impl<'gc, T: ?Sized, Id: CollectorId> Copy for GcWeak<'gc, T, Id> {}
+impl<'gc, T: ?Sized, Id: CollectorId> Clone for GcWeak<'gc, T, Id> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<'gc, T: ?Sized + GcSafe<'gc, Id> + Debug, Id: CollectorId> Debug for GcWeak<'gc, T, Id> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Some(ref gc) => f.debug_tuple("GcWeak").field(&gc.value()).finish(),
+            None => f.write_str("GcWeak(<cleared>)"),
+        }
+    }
+}
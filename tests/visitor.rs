@@ -0,0 +1,81 @@
+#![feature(ptr_metadata)]
+use std::convert::Infallible;
+use std::ptr::{DynMetadata, Pointee};
+
+use zerogc::epsilon::{EpsilonCollectorId, EpsilonSystem};
+use zerogc::vec::raw::GcRawVec;
+use zerogc::{CollectorId, DynTrace, Gc, GcArray, GcSafe, GcSimpleAlloc, GcVisitor, GcWeak};
+
+/// A visitor that only counts the (strong) references it visits,
+/// relying on the default implementations for everything else.
+#[derive(Default)]
+struct CountingVisitor {
+    strong: usize,
+}
+unsafe impl GcVisitor for CountingVisitor {
+    type Err = Infallible;
+
+    unsafe fn trace_gc<'gc, T, Id>(&mut self, _gc: &mut Gc<'gc, T, Id>) -> Result<(), Infallible>
+    where
+        T: GcSafe<'gc, Id>,
+        Id: CollectorId,
+    {
+        self.strong += 1;
+        Ok(())
+    }
+
+    unsafe fn trace_trait_object<'gc, T, Id>(
+        &mut self,
+        _gc: &mut Gc<'gc, T, Id>,
+    ) -> Result<(), Infallible>
+    where
+        T: ?Sized + GcSafe<'gc, Id> + Pointee<Metadata = DynMetadata<T>> + DynTrace<'gc, Id>,
+        Id: CollectorId,
+    {
+        self.strong += 1;
+        Ok(())
+    }
+
+    unsafe fn trace_vec<'gc, T, V>(&mut self, _raw: &mut V) -> Result<(), Infallible>
+    where
+        T: GcSafe<'gc, V::Id>,
+        V: GcRawVec<'gc, T>,
+    {
+        self.strong += 1;
+        Ok(())
+    }
+
+    unsafe fn trace_array<'gc, T, Id>(
+        &mut self,
+        _array: &mut GcArray<'gc, T, Id>,
+    ) -> Result<(), Infallible>
+    where
+        T: GcSafe<'gc, Id>,
+        Id: CollectorId,
+    {
+        self.strong += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn default_ephemeron_skips_dead_values() {
+    let sys = EpsilonSystem::leak();
+    let ctx = sys.new_context();
+    let key = ctx.alloc(1i32);
+    let mut value: Gc<'_, i32, EpsilonCollectorId> = ctx.alloc(2i32);
+    let mut visitor = CountingVisitor::default();
+    unsafe {
+        visitor
+            .trace_ephemeron(&mut GcWeak::new(key), &mut value)
+            .unwrap();
+    }
+    // A live key conservatively keeps the value alive
+    assert_eq!(visitor.strong, 1);
+    let mut dead_key = GcWeak::<i32, EpsilonCollectorId>::cleared();
+    unsafe {
+        visitor.trace_ephemeron(&mut dead_key, &mut value).unwrap();
+    }
+    // Once the key is dead, the value must never be touched
+    assert_eq!(visitor.strong, 1);
+}